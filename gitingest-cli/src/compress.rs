@@ -0,0 +1,108 @@
+use anyhow::Result;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use zip::ZipWriter;
+use zip::write::SimpleFileOptions;
+
+/// Compressed containers the digest can be written into, picked by the
+/// output file's extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputArchive {
+    Zip,
+    TarGz,
+}
+
+impl OutputArchive {
+    const EXTENSIONS: &[(&str, OutputArchive)] =
+        &[(".zip", OutputArchive::Zip), (".tar.gz", OutputArchive::TarGz), (".tgz", OutputArchive::TarGz)];
+
+    pub fn for_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        Self::EXTENSIONS.iter().find(|(extension, _)| name.ends_with(extension)).map(|(_, archive)| *archive)
+    }
+
+    /// The output's file name without the archive extension, which names
+    /// the entries inside it.
+    pub fn stem(path: &Path) -> String {
+        let name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+        let lower = name.to_lowercase();
+        Self::EXTENSIONS
+            .iter()
+            .find(|(extension, _)| lower.ends_with(extension))
+            .map_or(name.to_string(), |(extension, _)| name[..name.len() - extension.len()].to_string())
+    }
+}
+
+/// An archive being written. The digest is streamed into its first entry;
+/// further entries are added by [`ArchiveWriter::finish`].
+pub enum ArchiveWriter {
+    Zip(Box<ZipWriter<BufWriter<File>>>),
+    /// Tar headers need each entry's size up front, so the digest is
+    /// spooled to a temporary file and packed once it is complete.
+    TarGz { output: File, entry: String, spool: BufWriter<File> },
+}
+
+impl ArchiveWriter {
+    /// Creates the archive at `path` with the digest entry `entry` open
+    /// for writing.
+    pub fn create(path: &Path, archive: OutputArchive, entry: &str) -> Result<Self> {
+        let output = File::create(path)?;
+        Ok(match archive {
+            OutputArchive::Zip => {
+                let mut zip = ZipWriter::new(BufWriter::new(output));
+                zip.start_file(entry, SimpleFileOptions::default())?;
+                ArchiveWriter::Zip(Box::new(zip))
+            }
+            OutputArchive::TarGz => {
+                let spool = BufWriter::new(tempfile::tempfile()?);
+                ArchiveWriter::TarGz { output, entry: entry.to_string(), spool }
+            }
+        })
+    }
+
+    /// Where the digest is written.
+    pub fn digest(&mut self) -> &mut dyn Write {
+        match self {
+            ArchiveWriter::Zip(zip) => zip.as_mut(),
+            ArchiveWriter::TarGz { spool, .. } => spool,
+        }
+    }
+
+    /// Closes the digest entry, adds `extra` entries after it, and
+    /// completes the archive.
+    pub fn finish(self, extra: &[(String, Vec<u8>)]) -> Result<()> {
+        match self {
+            ArchiveWriter::Zip(mut zip) => {
+                for (name, content) in extra {
+                    zip.start_file(name.as_str(), SimpleFileOptions::default())?;
+                    zip.write_all(content)?;
+                }
+                zip.finish()?.flush()?;
+            }
+            ArchiveWriter::TarGz { output, entry, spool } => {
+                let mut spool = spool.into_inner().map_err(|e| e.into_error())?;
+                let size = spool.seek(SeekFrom::End(0))?;
+                spool.rewind()?;
+
+                let mut tar = tar::Builder::new(GzEncoder::new(BufWriter::new(output), Compression::default()));
+                tar.append_data(&mut file_header(size), &entry, spool)?;
+                for (name, content) in extra {
+                    tar.append_data(&mut file_header(content.len() as u64), name, content.as_slice())?;
+                }
+                tar.into_inner()?.finish()?.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn file_header(size: u64) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(size);
+    header.set_mode(0o644);
+    header.set_mtime(std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs()));
+    header
+}
@@ -1,53 +1,698 @@
-use clap::{Parser, ValueEnum};
-use gitingest::{AppConfig, IngestService, IngestRequest, DownloadFormat, UrlParser};
-use std::path::PathBuf;
+use clap::{Parser, Subcommand, ValueEnum};
+use gitingest::{archive_input, archive_stem, formats, format_file_size, AppConfig, CloneCache, CloneMode, ContentFormat, IngestFailure, IngestService, IngestRequest, DownloadFormat, KnownHostsPolicy, MissingFilePolicy, PatternMatcher, PatternService, PatternType, SortOrder, UrlParser, read_token_file};
+use std::collections::HashSet;
+use std::io::{BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use anyhow::Result;
+use compress::{ArchiveWriter, OutputArchive};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+mod compress;
+
+/// Exit code for runs interrupted by SIGINT/SIGTERM, matching shell convention.
+const EXIT_CANCELLED: i32 = 130;
+
+/// Exit codes for failures a caller can act on; anything else exits with 1.
+const EXIT_REPOSITORY_NOT_FOUND: i32 = 3;
+const EXIT_BRANCH_NOT_FOUND: i32 = 4;
+const EXIT_AUTHENTICATION_REQUIRED: i32 = 5;
+const EXIT_RATE_LIMITED: i32 = 6;
+const EXIT_HOST_NOT_ALLOWED: i32 = 7;
+const EXIT_HOST_KEY_VERIFICATION_FAILED: i32 = 8;
+
+/// How long cleanup may take after the first signal before exiting anyway.
+const CLEANUP_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Output file currently being written, removed if the run is interrupted.
+type PartialOutput = Arc<Mutex<Option<PathBuf>>>;
 
 #[derive(Parser)]
 #[command(name = "gitingest")]
 #[command(about = "A fast Git repository ingestion and analysis tool")]
 #[command(version = env!("CARGO_PKG_VERSION"))]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
-    #[arg(help = "Git repository URL or path")]
-    input: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+    
+    #[arg(required = true, help = "Git repository URL, a local directory, or a local .zip, .tar.gz or .tgz archive")]
+    input: Option<String>,
     
     #[arg(short, long, value_enum, default_value = "text", help = "Output format")]
     format: OutputFormat,
     
-    #[arg(short, long, help = "Output file path")]
+    #[arg(short, long, help = "Output file path (use - for stdout); a .zip, .tar.gz or .tgz name writes a compressed archive")]
     output: Option<PathBuf>,
     
+    #[arg(long, conflicts_with_all = ["output", "split_by_dir"], help = "Write the digest to stdout")]
+    stdout: bool,
+    
+    #[arg(long, help = "Don't print the completion summary to stderr")]
+    no_summary_line: bool,
+    
+    #[arg(long, help = "Add a <name>.stats.json entry with the run's statistics to .zip, .tar.gz and .tgz outputs")]
+    stats_sidecar: bool,
+    
     #[arg(long, help = "Include patterns (comma-separated)")]
     include: Option<String>,
     
     #[arg(long, help = "Exclude patterns (comma-separated)")]
     exclude: Option<String>,
     
+    #[arg(long, value_enum, requires = "pattern", help = "Whether --pattern includes or excludes matching files")]
+    pattern_type: Option<PatternTypeArg>,
+    
+    #[arg(long, help = "Glob pattern applied according to --pattern-type (default: exclude)")]
+    pattern: Option<String>,
+    
+    #[arg(long, help = "Branch to clone instead of the default branch")]
+    branch: Option<String>,
+    
+    #[arg(long, conflicts_with_all = ["files", "api"], help = "Ingest only the files changed since this branch, tag or commit")]
+    base_ref: Option<String>,
+    
+    #[arg(long, help = "Access token for private repositories (defaults to GITHUB_TOKEN, GITLAB_TOKEN or BITBUCKET_TOKEN by host, then the git credential helper)")]
+    token: Option<String>,
+    
+    #[arg(long, value_name = "PATH", conflicts_with = "token", help = "Read the access token from PATH, keeping it out of shell history and ps")]
+    token_file: Option<PathBuf>,
+    
+    #[arg(long, value_name = "PATH", help = "Private key for SSH remotes, such as a deploy key (defaults to GITINGEST_SSH_KEY)")]
+    ssh_key: Option<PathBuf>,
+    
+    #[arg(long, value_enum, value_name = "POLICY", help = "How SSH remotes' host keys are checked (defaults to GITINGEST_SSH_KNOWN_HOSTS, then ssh's configuration)")]
+    ssh_known_hosts: Option<KnownHostsArg>,
+    
     #[arg(long, help = "Maximum file size in bytes")]
     max_file_size: Option<u64>,
     
+    #[arg(long, value_name = "BYTES", help = "Truncate each file's content beyond BYTES (defaults to MAX_CONTENT_SIZE)")]
+    max_content_size: Option<u64>,
+    
+    #[arg(long, value_name = "BYTES", help = "Leave out the remaining files once the digest's contents reach BYTES (defaults to MAX_OUTPUT_BYTES)")]
+    max_output_bytes: Option<u64>,
+    
+    #[arg(long, value_name = "N", help = "Skip the content of files with more than N lines (e.g. minified bundles)")]
+    max_file_lines: Option<u64>,
+    
     #[arg(long, help = "Maximum number of files")]
     max_files: Option<usize>,
     
+    #[arg(long, value_name = "N", help = "Walk only N levels of the tree, 1 being the root's own files (defaults to MAX_DIRECTORY_DEPTH)")]
+    max_depth: Option<u32>,
+    
+    #[arg(long, value_name = "PATH", help = "Ingest only the repo-relative paths listed in PATH, one per line (use - for stdin)")]
+    files: Option<PathBuf>,
+    
+    #[arg(long, value_enum, requires = "files", default_value = "warn", help = "What to do with listed files that don't exist")]
+    missing: MissingArg,
+    
+    #[arg(long, help = "Don't apply the built-in excludes (build output, media, editor files)")]
+    no_default_ignore: bool,
+    
+    #[arg(long, help = "Clone into a temporary directory even when CACHE_DIR is set")]
+    no_cache: bool,
+    
+    #[arg(long, conflicts_with = "no_cache", help = "Reuse a cached clone, from CACHE_DIR or else TEMP_DIR/cache")]
+    cache: bool,
+    
+    #[arg(long, conflicts_with_all = ["files", "include_submodules"], help = "Fetch only the matching files under the URL's subpath through the GitHub API instead of cloning")]
+    api: bool,
+    
+    #[arg(long, help = "Put the root license file into the digest in full, even if it would be excluded")]
+    include_license_text: bool,
+    
+    #[arg(long, help = "Clone the full history and record when each file was last committed")]
+    with_timestamps: bool,
+    
+    #[arg(long, conflicts_with = "split_by_dir", help = "List the files that would be ingested and their sizes without reading them")]
+    dry_run: bool,
+    
+    #[arg(long, value_enum, help = "How much of the repository to clone (default: shallow); blobless lists files like --dry-run without checking them out")]
+    clone_mode: Option<CloneModeArg>,
+    
+    #[arg(long, value_name = "N", help = "Drop file contents that don't fit in N tokens; omitted files stay in the tree")]
+    max_tokens: Option<usize>,
+    
+    #[arg(long, value_enum, help = "Order of the file contents (default: importance; listed --files keep their order)")]
+    sort: Option<SortArg>,
+    
+    #[arg(long, value_name = "TEMPLATE", help = "Header line of each file's section; {path} is replaced by the file's path (default: \"{path}:\")")]
+    file_header: Option<String>,
+    
+    #[arg(long, value_name = "TEXT", help = "Line under each file's header (default: 48 '='; empty for none)")]
+    file_separator: Option<String>,
+    
+    #[arg(long, help = "Wrap file contents in code fences tagged with their language")]
+    fence: bool,
+    
+    #[arg(long, help = "Prefix each line of file contents with its line number")]
+    line_numbers: bool,
+    
+    #[arg(long, value_name = "CHARS", help = "Cut lines of file contents longer than CHARS characters")]
+    max_line_width: Option<usize>,
+    
+    #[arg(long, value_name = "DEPTH", help = "Write one output file per directory at DEPTH into the output directory")]
+    split_by_dir: Option<usize>,
+    
+    #[arg(long, help = "Recursively clone submodules")]
+    include_submodules: bool,
+    
+    #[arg(long, value_enum, default_value = "text", help = "How failures are reported on stderr")]
+    error_format: ErrorFormat,
+    
     #[arg(short, long, help = "Enable verbose logging")]
     verbose: bool,
 }
 
+#[derive(Subcommand)]
+enum Command {
+    /// Manage the clone cache in CACHE_DIR
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    /// Print the directory structure without reading file contents
+    Tree(TreeArgs),
+    /// Check include/exclude patterns, and preview which paths they match, without cloning
+    CheckPatterns(CheckPatternsArgs),
+    /// Ingest every repository listed in a file, several at a time, into one digest each
+    Batch(BatchArgs),
+}
+
+#[derive(clap::Args)]
+struct BatchArgs {
+    #[arg(long, value_name = "PATH", help = "File listing one repository URL or archive per line (- for stdin); # starts a comment")]
+    input: PathBuf,
+    
+    #[arg(short, long, value_enum, default_value = "text", help = "Output format")]
+    format: OutputFormat,
+    
+    #[arg(long, value_name = "DIR", default_value = "digests", help = "Directory to write one <owner>_<name> digest per repository into")]
+    output_dir: PathBuf,
+    
+    #[arg(long, value_name = "N", default_value_t = 4, help = "Repositories ingested at once")]
+    parallel: usize,
+    
+    #[arg(long, help = "Include patterns (comma-separated)")]
+    include: Option<String>,
+    
+    #[arg(long, help = "Exclude patterns (comma-separated)")]
+    exclude: Option<String>,
+    
+    #[arg(long, help = "Access token for every repository (defaults to GITHUB_TOKEN, GITLAB_TOKEN or BITBUCKET_TOKEN by host, then the git credential helper)")]
+    token: Option<String>,
+    
+    #[arg(long, value_name = "PATH", conflicts_with = "token", help = "Read the access token from PATH, keeping it out of shell history and ps")]
+    token_file: Option<PathBuf>,
+    
+    #[arg(long, value_name = "PATH", help = "Private key for SSH remotes, such as a deploy key (defaults to GITINGEST_SSH_KEY)")]
+    ssh_key: Option<PathBuf>,
+    
+    #[arg(long, value_enum, value_name = "POLICY", help = "How SSH remotes' host keys are checked (defaults to GITINGEST_SSH_KNOWN_HOSTS, then ssh's configuration)")]
+    ssh_known_hosts: Option<KnownHostsArg>,
+}
+
+#[derive(clap::Args)]
+struct CheckPatternsArgs {
+    #[arg(help = "Repo-relative paths to match against the patterns; a trailing / marks a directory")]
+    paths: Vec<String>,
+    
+    #[arg(long, help = "Include patterns (comma-separated)")]
+    include: Option<String>,
+    
+    #[arg(long, help = "Exclude patterns (comma-separated)")]
+    exclude: Option<String>,
+    
+    #[arg(long, help = "Don't apply the built-in excludes (build output, media, editor files)")]
+    no_default_ignore: bool,
+}
+
+#[derive(clap::Args)]
+struct TreeArgs {
+    #[arg(help = "Git repository URL or path")]
+    input: String,
+    
+    #[arg(long, help = "Include patterns (comma-separated)")]
+    include: Option<String>,
+    
+    #[arg(long, help = "Exclude patterns (comma-separated)")]
+    exclude: Option<String>,
+    
+    #[arg(long, help = "Branch to clone instead of the default branch")]
+    branch: Option<String>,
+    
+    #[arg(long, help = "Access token for private repositories (defaults to GITHUB_TOKEN, GITLAB_TOKEN or BITBUCKET_TOKEN by host, then the git credential helper)")]
+    token: Option<String>,
+    
+    #[arg(long, value_name = "PATH", conflicts_with = "token", help = "Read the access token from PATH, keeping it out of shell history and ps")]
+    token_file: Option<PathBuf>,
+    
+    #[arg(long, value_name = "PATH", help = "Private key for SSH remotes, such as a deploy key (defaults to GITINGEST_SSH_KEY)")]
+    ssh_key: Option<PathBuf>,
+    
+    #[arg(long, value_enum, value_name = "POLICY", help = "How SSH remotes' host keys are checked (defaults to GITINGEST_SSH_KNOWN_HOSTS, then ssh's configuration)")]
+    ssh_known_hosts: Option<KnownHostsArg>,
+    
+    #[arg(long, help = "Maximum number of files")]
+    max_files: Option<usize>,
+    
+    #[arg(long, value_name = "N", help = "Walk only N levels of the tree, 1 being the root's own files (defaults to MAX_DIRECTORY_DEPTH)")]
+    max_depth: Option<u32>,
+    
+    #[arg(long, help = "Annotate files with their size")]
+    sizes: bool,
+    
+    #[arg(long, conflicts_with = "sizes", help = "List the files from a blobless clone instead of checking them out, without their sizes")]
+    blobless: bool,
+}
+
+#[derive(Subcommand)]
+enum CacheAction {
+    /// List cached clones
+    Ls,
+    /// Remove every cached clone
+    Clear,
+}
+
 #[derive(Clone, Copy, ValueEnum)]
 enum OutputFormat {
     Json,
+    /// JSON with per-file entries and a nested tree
+    JsonStructured,
     Text,
     Markdown,
+    /// XML-tagged digest for LLM prompts
+    Xml,
+    /// One JSON object per line: metadata first, then one line per file
+    Jsonl,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ErrorFormat {
+    /// A single `error: ...` line
+    Text,
+    /// The failure as a JSON object with phase, timings and warnings
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum MissingArg {
+    Warn,
+    Error,
+    Skip,
+}
+
+impl From<MissingArg> for MissingFilePolicy {
+    fn from(missing: MissingArg) -> Self {
+        match missing {
+            MissingArg::Warn => MissingFilePolicy::Warn,
+            MissingArg::Error => MissingFilePolicy::Error,
+            MissingArg::Skip => MissingFilePolicy::Skip,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SortArg {
+    /// READMEs, docs and manifests first; tests and generated files last
+    Importance,
+    /// By repo-relative path
+    Path,
+    /// Smallest files first
+    SizeAsc,
+}
+
+impl From<SortArg> for SortOrder {
+    fn from(sort: SortArg) -> Self {
+        match sort {
+            SortArg::Importance => SortOrder::Importance,
+            SortArg::Path => SortOrder::Path,
+            SortArg::SizeAsc => SortOrder::SizeAsc,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum CloneModeArg {
+    /// Every commit of the branch
+    Full,
+    /// Only the latest commit
+    Shallow,
+    /// The latest commit's file list and sizes, without file contents
+    Blobless,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum KnownHostsArg {
+    /// Only connect to hosts already in known_hosts
+    Strict,
+    /// Add unknown hosts to known_hosts, but refuse a changed key
+    AcceptNew,
+    /// Don't check host keys
+    Off,
+}
+
+impl From<KnownHostsArg> for KnownHostsPolicy {
+    fn from(policy: KnownHostsArg) -> Self {
+        match policy {
+            KnownHostsArg::Strict => KnownHostsPolicy::Strict,
+            KnownHostsArg::AcceptNew => KnownHostsPolicy::AcceptNew,
+            KnownHostsArg::Off => KnownHostsPolicy::Off,
+        }
+    }
+}
+
+impl From<CloneModeArg> for CloneMode {
+    fn from(mode: CloneModeArg) -> Self {
+        match mode {
+            CloneModeArg::Full => CloneMode::Full,
+            CloneModeArg::Shallow => CloneMode::Shallow,
+            CloneModeArg::Blobless => CloneMode::Blobless,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum PatternTypeArg {
+    Include,
+    Exclude,
+}
+
+impl From<PatternTypeArg> for PatternType {
+    fn from(pattern_type: PatternTypeArg) -> Self {
+        match pattern_type {
+            PatternTypeArg::Include => PatternType::Include,
+            PatternTypeArg::Exclude => PatternType::Exclude,
+        }
+    }
 }
 
 impl From<OutputFormat> for DownloadFormat {
     fn from(format: OutputFormat) -> Self {
         match format {
             OutputFormat::Json => DownloadFormat::Json,
+            OutputFormat::JsonStructured => DownloadFormat::JsonStructured,
             OutputFormat::Text => DownloadFormat::Text,
             OutputFormat::Markdown => DownloadFormat::Markdown,
+            OutputFormat::Xml => DownloadFormat::Xml,
+            OutputFormat::Jsonl => DownloadFormat::JsonLines,
+        }
+    }
+}
+
+/// The repository's name, an archive's file stem or a local directory's
+/// name, for naming outputs.
+fn input_name(input: &str) -> Option<String> {
+    if let Some(archive) = archive_input(input) {
+        return Some(archive_stem(&archive));
+    }
+    if let Some(dir) = UrlParser::local_directory(input) {
+        return dir.file_name().map(|name| name.to_string_lossy().into_owned());
+    }
+    UrlParser::parse_git_url(input).ok().map(|repo| repo.name)
+}
+
+/// Splits a comma-separated option into trimmed patterns.
+fn split_patterns(patterns: Option<String>) -> Option<Vec<String>> {
+    patterns.map(|s| s.split(',').map(|s| s.trim().to_string()).collect())
+}
+
+/// Reads a file list, skipping blank lines and `#` comments.
+fn read_file_list(path: &Path) -> Result<Vec<String>> {
+    let content = if path == Path::new("-") {
+        std::io::read_to_string(std::io::stdin())?
+    } else {
+        std::fs::read_to_string(path)?
+    };
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+/// `--token`, or the contents of `--token-file`.
+fn resolve_token(token: Option<String>, token_file: Option<&Path>) -> Result<Option<String>> {
+    match token {
+        Some(token) => Ok(Some(token)),
+        None => Ok(token_file.map(read_token_file).transpose()?),
+    }
+}
+
+async fn wait_for_shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {},
+                    _ = terminate.recv() => {},
+                }
+            },
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+fn remove_partial_output(partial_output: &PartialOutput) {
+    if let Some(path) = partial_output.lock().unwrap().take() {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// The first signal cancels the ingestion and gives it time to clean up;
+/// a second signal (or an expired grace period) exits immediately.
+fn install_signal_handler(cancel: CancellationToken, partial_output: PartialOutput) {
+    tokio::spawn(async move {
+        wait_for_shutdown_signal().await;
+        eprintln!("Cancelling... press Ctrl-C again to force exit");
+        cancel.cancel();
+        
+        tokio::select! {
+            _ = wait_for_shutdown_signal() => {},
+            _ = tokio::time::sleep(CLEANUP_GRACE_PERIOD) => {},
+        }
+        remove_partial_output(&partial_output);
+        std::process::exit(EXIT_CANCELLED);
+    });
+}
+
+fn exit_cancelled(phase: &str, start_time: Instant, partial_output: &PartialOutput) -> ! {
+    remove_partial_output(partial_output);
+    eprintln!(
+        "Ingestion cancelled during {} phase after {:.1}s",
+        phase,
+        start_time.elapsed().as_secs_f64()
+    );
+    std::process::exit(EXIT_CANCELLED);
+}
+
+fn exit_failed(
+    failure: &IngestFailure,
+    error_format: ErrorFormat,
+    start_time: Instant,
+    partial_output: &PartialOutput,
+) -> ! {
+    if failure.error_code == "cancelled" {
+        // A digest closed off with a trailer is still worth keeping
+        if let Some(incomplete) = &failure.incomplete_output
+            && let Some(path) = partial_output.lock().unwrap().take()
+        {
+            eprintln!(
+                "Ingestion cancelled after {:.1}s; kept partial digest in {} ({}/{} files)",
+                start_time.elapsed().as_secs_f64(),
+                path.display(),
+                incomplete.files_written,
+                incomplete.files_total
+            );
+            std::process::exit(EXIT_CANCELLED);
+        }
+        exit_cancelled(&failure.phase.to_string(), start_time, partial_output);
+    }
+    remove_partial_output(partial_output);
+    let (exit_code, hint) = match failure.error_code.as_str() {
+        "repository_not_found" => (
+            EXIT_REPOSITORY_NOT_FOUND,
+            Some("check the URL; private repositories also need --token"),
+        ),
+        "branch_not_found" => (EXIT_BRANCH_NOT_FOUND, Some("check the branch or tag name")),
+        "authentication_required" => (
+            EXIT_AUTHENTICATION_REQUIRED,
+            Some("pass an access token with --token or set GITHUB_TOKEN, GITLAB_TOKEN or BITBUCKET_TOKEN; SSH remotes need a key, from --ssh-key or GITINGEST_SSH_KEY"),
+        ),
+        "rate_limited" => (
+            EXIT_RATE_LIMITED,
+            Some("wait for the limit to reset, or pass --token for a higher limit"),
+        ),
+        "host_not_allowed" => (
+            EXIT_HOST_NOT_ALLOWED,
+            Some("add the host to ALLOWED_HOSTS, or set it empty to allow any host"),
+        ),
+        "host_key_verification_failed" => (
+            EXIT_HOST_KEY_VERIFICATION_FAILED,
+            Some("add the host's key to ~/.ssh/known_hosts (see ssh-keyscan), or pass --ssh-known-hosts accept-new"),
+        ),
+        _ => (1, None),
+    };
+    match error_format {
+        ErrorFormat::Text => {
+            eprintln!("error: {}", failure);
+            if let Some(hint) = hint {
+                eprintln!("hint: {}", hint);
+            }
+        }
+        ErrorFormat::Json => match serde_json::to_string(failure) {
+            Ok(json) => eprintln!("{}", json),
+            Err(_) => eprintln!("error: {}", failure),
+        },
+    }
+    std::process::exit(exit_code);
+}
+
+async fn run_cache_command(action: CacheAction, config: &AppConfig) -> Result<()> {
+    let Some(cache_dir) = &config.cache_dir else {
+        anyhow::bail!("no clone cache configured; set CACHE_DIR");
+    };
+    let cache = CloneCache::new(cache_dir);
+    
+    match action {
+        CacheAction::Ls => {
+            for entry in cache.entries()? {
+                println!("{}\t{}", format_file_size(entry.size_bytes), entry.key);
+            }
+        },
+        CacheAction::Clear => {
+            let removed = cache.clear().await?;
+            println!("Removed {} cached clone(s) from {}", removed, cache.root().display());
+        },
+    }
+    
+    Ok(())
+}
+
+async fn run_tree_command(args: TreeArgs, config: &AppConfig) -> Result<()> {
+    let request = IngestRequest {
+        input_text: args.input,
+        include_patterns: split_patterns(args.include),
+        exclude_patterns: split_patterns(args.exclude),
+        branch: args.branch,
+        token: resolve_token(args.token, args.token_file.as_deref())?,
+        max_files: args.max_files,
+        max_directory_depth: args.max_depth,
+        clone_mode: args.blobless.then_some(CloneMode::Blobless),
+        ssh_key_path: args.ssh_key,
+        ssh_known_hosts_policy: args.ssh_known_hosts.map(Into::into),
+        ..Default::default()
+    };
+    let (tree, stats) = IngestService::scan_only(request, config, args.sizes).await?;
+    print!("{}", tree);
+    match args.blobless {
+        true => eprintln!("{} files", stats.files_analyzed),
+        false => eprintln!("{} files, {}", stats.files_analyzed, format_file_size(stats.total_size_bytes)),
+    }
+    
+    Ok(())
+}
+
+async fn run_batch_command(args: BatchArgs, config: &AppConfig) -> Result<()> {
+    let inputs = read_file_list(&args.input)?;
+    let format: DownloadFormat = args.format.into();
+    let include_patterns = split_patterns(args.include);
+    let exclude_patterns = split_patterns(args.exclude);
+    let token = resolve_token(args.token, args.token_file.as_deref())?;
+    std::fs::create_dir_all(&args.output_dir)?;
+    
+    let mut stems = HashSet::new();
+    let paths: Vec<PathBuf> = inputs
+        .iter()
+        .map(|input| args.output_dir.join(format!("{}.{}", batch_stem(input, &mut stems), formats::extension(&format))))
+        .collect();
+    let jobs = inputs
+        .iter()
+        .zip(&paths)
+        .map(|(input, path)| {
+            let request = IngestRequest {
+                input_text: input.clone(),
+                download_format: Some(format.clone()),
+                include_patterns: include_patterns.clone(),
+                exclude_patterns: exclude_patterns.clone(),
+                token: token.clone(),
+                ssh_key_path: args.ssh_key.clone(),
+                ssh_known_hosts_policy: args.ssh_known_hosts.map(Into::into),
+                ..Default::default()
+            };
+            (request, path.clone())
+        })
+        .collect();
+    
+    let results = IngestService::ingest_many_to_files(jobs, config, args.parallel).await;
+    let mut failed = 0;
+    for ((input, path), result) in inputs.iter().zip(&paths).zip(results) {
+        match result {
+            Ok(summary) => eprintln!("{}", summary.completion_line(&path.display().to_string())),
+            Err(err) => {
+                failed += 1;
+                eprintln!("error: {}: {}", input, err);
+            }
         }
     }
+    if failed > 0 {
+        anyhow::bail!("{} of {} repositories failed", failed, inputs.len());
+    }
+    
+    Ok(())
+}
+
+/// `<owner>_<name>` for a repository URL, or the archive's stem, made
+/// unique among the names already in `taken`. The slashes of a nested
+/// GitLab namespace become underscores too.
+fn batch_stem(input: &str, taken: &mut HashSet<String>) -> String {
+    let stem = match archive_input(input) {
+        Some(archive) => archive_stem(&archive),
+        None if UrlParser::local_directory(input).is_some() => input_name(input).unwrap_or_else(|| "output".to_string()),
+        None => UrlParser::parse_git_url(input)
+            .map(|repo| format!("{}_{}", repo.owner.replace('/', "_"), repo.name))
+            .unwrap_or_else(|_| "output".to_string()),
+    };
+    let mut unique = stem.clone();
+    let mut n = 1;
+    while !taken.insert(unique.clone()) {
+        n += 1;
+        unique = format!("{}_{}", stem, n);
+    }
+    unique
+}
+
+fn run_check_patterns_command(args: CheckPatternsArgs) -> Result<()> {
+    let include = split_patterns(args.include).unwrap_or_default();
+    let exclude = split_patterns(args.exclude).unwrap_or_default();
+    PatternService::validate_patterns(&include, &exclude)?;
+    eprintln!("{} include and {} exclude pattern(s) are valid", include.len(), exclude.len());
+    
+    let mut matcher = if args.no_default_ignore {
+        PatternMatcher::without_default_excludes()
+    } else {
+        PatternMatcher::default()
+    };
+    matcher.include_patterns.extend(include);
+    matcher.exclude_patterns.extend(exclude);
+    for (path, included) in PatternService::dry_match(&matcher, &args.paths)? {
+        println!("{}\t{}", if included { "included" } else { "excluded" }, path);
+    }
+    
+    Ok(())
 }
 
 #[tokio::main]
@@ -57,94 +702,204 @@ async fn main() -> Result<()> {
     let log_level = if cli.verbose { "debug" } else { "info" };
     tracing_subscriber::fmt()
         .with_env_filter(format!("gitingest={},gitingest_cli={}", log_level, log_level))
+        .with_writer(std::io::stderr)
         .init();
     
     dotenv::dotenv().ok();
-    let config = AppConfig::from_env()?;
+    let mut config = AppConfig::from_env()?;
+    // The local disk is the user's own here, unlike on a shared server
+    if std::env::var_os("ALLOW_LOCAL_INPUTS").is_none() {
+        config.allow_local_inputs = true;
+    }
+    
+    let input = match cli.command {
+        Some(Command::Cache { action }) => return run_cache_command(action, &config).await,
+        Some(Command::Tree(args)) => return run_tree_command(args, &config).await,
+        Some(Command::CheckPatterns(args)) => return run_check_patterns_command(args),
+        Some(Command::Batch(args)) => return run_batch_command(args, &config).await,
+        None => cli.input.expect("clap requires an input without a subcommand"),
+    };
+    
+    let id = Uuid::new_v4();
+    let start_time = Instant::now();
+    let cancel = CancellationToken::new();
+    let partial_output: PartialOutput = Arc::new(Mutex::new(None));
+    install_signal_handler(cancel.clone(), partial_output.clone());
     
     // Main repository ingestion logic
     let mut request = IngestRequest {
-        input_text: cli.input.clone(),
+        input_text: input.clone(),
         download_format: Some(cli.format.into()),
-        include_patterns: cli.include.map(|s| s.split(',').map(|s| s.trim().to_string()).collect()),
-        exclude_patterns: cli.exclude.map(|s| s.split(',').map(|s| s.trim().to_string()).collect()),
+        include_patterns: split_patterns(cli.include),
+        exclude_patterns: split_patterns(cli.exclude),
         max_file_size: cli.max_file_size,
+        max_content_size: cli.max_content_size,
+        max_output_bytes: cli.max_output_bytes,
+        max_file_lines: cli.max_file_lines,
         max_files: cli.max_files,
-        pattern_type: None,
-        pattern: None,
-        token: None,
-        branch: None,
-        include_submodules: None,
+        max_directory_depth: cli.max_depth,
+        pattern_type: cli.pattern_type.map(Into::into),
+        pattern: cli.pattern,
+        token: resolve_token(cli.token, cli.token_file.as_deref())?,
+        branch: cli.branch,
+        base_ref: cli.base_ref,
+        include_submodules: Some(cli.include_submodules),
+        files: cli.files.as_deref().map(read_file_list).transpose()?,
+        missing_files: Some(cli.missing.into()),
+        max_tokens: cli.max_tokens,
+        no_default_ignore: Some(cli.no_default_ignore),
+        no_cache: Some(cli.no_cache),
+        use_cache: cli.cache.then_some(true),
+        api: Some(cli.api),
+        dry_run: Some(cli.dry_run),
+        include_license_text: Some(cli.include_license_text),
+        with_timestamps: Some(cli.with_timestamps),
+        clone_mode: cli.clone_mode.map(Into::into),
+        ssh_key_path: cli.ssh_key,
+        ssh_known_hosts_policy: cli.ssh_known_hosts.map(Into::into),
+        sort: cli.sort.map(Into::into),
+        content_format: (cli.file_header.is_some() || cli.file_separator.is_some() || cli.fence).then(|| {
+            let default = ContentFormat::default();
+            ContentFormat {
+                header: cli.file_header.unwrap_or(default.header),
+                separator: cli.file_separator.unwrap_or(default.separator),
+                fence: cli.fence,
+                ..default
+            }
+        }),
+        line_numbers: Some(cli.line_numbers),
+        max_line_width: cli.max_line_width,
     };
     
+    // Piped output goes to stdout unless a file was explicitly requested
+    let to_stdout = cli.stdout
+        || cli.output.as_deref() == Some(Path::new("-"))
+        || (cli.output.is_none() && cli.split_by_dir.is_none() && !std::io::stdout().is_terminal());
+    
+    if let Some(depth) = cli.split_by_dir {
+        if to_stdout {
+            anyhow::bail!("--split-by-dir writes a directory and cannot be combined with stdout output");
+        }
+        
+        let output_dir = cli.output.unwrap_or_else(|| match input_name(&input) {
+            Some(name) => PathBuf::from(format!("{}-digest", name)),
+            None => PathBuf::from("output-digest"),
+        });
+        
+        tracing::info!("Starting split ingestion of: {} (id: {})", input, id);
+        
+        match IngestService::try_process_repository_split(request, &config, id, depth, &output_dir, cancel).await {
+            Ok(manifest) => {
+                let index_path = output_dir.join("index.json");
+                std::fs::write(&index_path, serde_json::to_string_pretty(&manifest)?)?;
+                println!(
+                    "✅ {} parts written to: {} (index: {})",
+                    manifest.parts.len(),
+                    output_dir.display(),
+                    index_path.display()
+                );
+            },
+            Err(failure) => exit_failed(&failure, cli.error_format, start_time, &partial_output),
+        }
+        
+        return Ok(());
+    }
+    
     // Generate automatic filename if no output is specified
-    let output_path = if let Some(output_path) = cli.output {
+    let output_path = if to_stdout {
+        None
+    } else if let Some(output_path) = cli.output {
         // If output filename is provided but doesn't match the format, adjust format based on extension
         if let Some(ext) = output_path.extension().and_then(|e| e.to_str()) {
             let format_from_ext = match ext {
                 "txt" => DownloadFormat::Text,
                 "md" => DownloadFormat::Markdown,
+                "json" if matches!(cli.format, OutputFormat::JsonStructured) => DownloadFormat::JsonStructured,
                 "json" => DownloadFormat::Json,
+                "xml" => DownloadFormat::Xml,
+                "jsonl" => DownloadFormat::JsonLines,
                 _ => cli.format.into(),
             };
             request.download_format = Some(format_from_ext);
         }
-        output_path
+        Some(output_path)
     } else {
-        // Parse repository URL to extract name for automatic filename
-        match UrlParser::parse_git_url(&cli.input) {
-            Ok(repo) => {
-                let extension = match cli.format {
-                    OutputFormat::Text => "txt",
-                    OutputFormat::Markdown => "md", 
-                    OutputFormat::Json => "json",
-                };
-                PathBuf::from(format!("{}.{}", repo.name, extension))
+        // Name the file after the repository or archive, falling back to a
+        // generic name if the input can't be parsed
+        let extension = formats::extension(&cli.format.into());
+        match input_name(&input) {
+            Some(name) => Some(PathBuf::from(format!("{}.{}", name, extension))),
+            None => Some(PathBuf::from(format!("output.{}", extension))),
+        }
+    };
+    
+    tracing::info!("Starting ingestion of: {} (id: {})", input, id);
+    
+    // Files are streamed as the digest is produced; stdout gets the
+    // rendered digest only once ingestion has succeeded
+    if let Some(output_path) = &output_path
+        && let Some(archive) = OutputArchive::for_path(output_path)
+    {
+        *partial_output.lock().unwrap() = Some(output_path.clone());
+        let stem = OutputArchive::stem(output_path);
+        let format = request.download_format.clone().unwrap_or_else(|| cli.format.into());
+        let mut writer = ArchiveWriter::create(output_path, archive, &format!("{}.{}", stem, formats::extension(&format)))?;
+        
+        match IngestService::try_process_repository_to_writer(request, &config, id, writer.digest(), cancel).await {
+            Ok(summary) => {
+                let mut extra = Vec::new();
+                if cli.stats_sidecar {
+                    extra.push((format!("{}.stats.json", stem), serde_json::to_vec_pretty(&summary)?));
+                }
+                writer.finish(&extra)?;
+                partial_output.lock().unwrap().take();
+                if !cli.no_summary_line {
+                    eprintln!("{}", summary.completion_line(&output_path.display().to_string()));
+                }
             },
-            Err(_) => {
-                // Fallback to generic name if URL parsing fails
-                let extension = match cli.format {
-                    OutputFormat::Text => "txt",
-                    OutputFormat::Markdown => "md",
-                    OutputFormat::Json => "json", 
-                };
-                PathBuf::from(format!("output.{}", extension))
+            Err(failure) => {
+                // A partial digest is only worth keeping in a complete archive
+                if failure.incomplete_output.is_some() {
+                    let _ = writer.finish(&[]);
+                }
+                exit_failed(&failure, cli.error_format, start_time, &partial_output)
             }
         }
-    };
+        
+        return Ok(());
+    }
     
-    tracing::info!("Starting ingestion of: {}", cli.input);
-    
-    match IngestService::process_repository(request.clone(), &config).await {
-        Ok(response) => {
-            let content = match request.download_format.unwrap_or(DownloadFormat::Text) {
-                DownloadFormat::Json => serde_json::to_string_pretty(&response)?,
-                DownloadFormat::Text => format!(
-                    "Repository: {}\nSummary:\n{}\n\nDirectory Structure:\n{}\n\nFile Contents:\n{}",
-                    response.short_repo_url,
-                    response.summary,
-                    response.tree,
-                    response.content
-                ),
-                DownloadFormat::Markdown => format!(
-                    "# Repository: {}\n\n## Summary\n{}\n\n## Directory Structure\n```\n{}\n```\n\n## File Contents\n{}",
-                    response.short_repo_url,
-                    response.summary,
-                    response.tree,
-                    response.content
-                ),
-            };
-            
-            std::fs::write(&output_path, content)?;
-            println!("✅ Output written to: {}", output_path.display());
-            
-            tracing::info!("✅ Ingestion completed successfully");
-        },
-        Err(err) => {
-            tracing::error!("❌ Ingestion failed: {:?}", err);
-            std::process::exit(1);
+    if let Some(output_path) = output_path {
+        *partial_output.lock().unwrap() = Some(output_path.clone());
+        let mut writer = BufWriter::new(std::fs::File::create(&output_path)?);
+        
+        match IngestService::try_process_repository_to_writer(request, &config, id, &mut writer, cancel).await {
+            Ok(summary) => {
+                partial_output.lock().unwrap().take();
+                if !cli.no_summary_line {
+                    eprintln!("{}", summary.completion_line(&output_path.display().to_string()));
+                }
+                
+                tracing::info!("✅ Ingestion completed successfully");
+            },
+            Err(failure) => exit_failed(&failure, cli.error_format, start_time, &partial_output),
         }
+        
+        return Ok(());
+    }
+    
+    let mut content = Vec::new();
+    match IngestService::try_process_repository_to_writer(request, &config, id, &mut content, cancel).await {
+        Ok(summary) => {
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(&content)?;
+            stdout.flush()?;
+            if !cli.no_summary_line {
+                eprintln!("{}", summary.completion_line("stdout"));
+            }
+        },
+        Err(failure) => exit_failed(&failure, cli.error_format, start_time, &partial_output),
     }
     
     Ok(())
-}
\ No newline at end of file
+}
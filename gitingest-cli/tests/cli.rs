@@ -0,0 +1,436 @@
+use std::path::Path;
+use std::process::Command;
+use tempfile::TempDir;
+
+fn create_local_repo(dir: &Path) {
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(dir.join("README.md"), "# Fixture").unwrap();
+    std::fs::write(dir.join("src/main.rs"), "fn main() {}").unwrap();
+    for args in [vec!["init", "-q"], vec!["add", "."], vec!["commit", "-qm", "initial"]] {
+        let status = Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .arg("-C")
+            .arg(dir)
+            .args(&args)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+}
+
+fn gitingest() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_gitingest"))
+}
+
+#[test]
+fn test_output_dash_writes_digest_to_stdout() {
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path().join("owner").join("repo");
+    create_local_repo(&repo_path);
+
+    let output = gitingest()
+        .arg(format!("file://{}", repo_path.display()))
+        .args(["--output", "-"])
+        .current_dir(temp_dir.path())
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("Repository: owner/repo\n"));
+    assert!(stdout.contains("src/main.rs:"));
+    assert!(stdout.contains("fn main() {}"));
+    assert!(!stdout.contains("INFO"));
+    assert!(!stdout.contains("Output written"));
+    assert!(!temp_dir.path().join("repo.txt").exists());
+}
+
+#[test]
+fn test_local_inputs_can_be_turned_off() {
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path().join("owner").join("repo");
+    create_local_repo(&repo_path);
+
+    let output = gitingest()
+        .arg(format!("file://{}", repo_path.display()))
+        .args(["--output", "-"])
+        .env("ALLOW_LOCAL_INPUTS", "false")
+        .current_dir(temp_dir.path())
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("ALLOW_LOCAL_INPUTS"));
+}
+
+#[test]
+fn test_completion_summary_goes_to_stderr() {
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path().join("owner").join("repo");
+    create_local_repo(&repo_path);
+    let url = format!("file://{}", repo_path.display());
+
+    let output = gitingest()
+        .args([url.as_str(), "--output", "repo.txt"])
+        .current_dir(temp_dir.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let line = stderr.lines().find(|line| line.starts_with("✅")).unwrap();
+    assert!(line.starts_with("✅ repo.txt — 2 files, "));
+    assert!(line.contains("tokens"));
+    assert!(line.contains("(clone "));
+
+    let quiet = gitingest()
+        .args([url.as_str(), "--stdout", "--no-summary-line"])
+        .current_dir(temp_dir.path())
+        .output()
+        .unwrap();
+    assert!(quiet.status.success());
+    assert!(String::from_utf8(quiet.stdout).unwrap().starts_with("Repository: owner/repo\n"));
+    assert!(!String::from_utf8(quiet.stderr).unwrap().contains("✅"));
+}
+
+#[test]
+fn test_dry_run_prints_tree_without_content() {
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path().join("owner").join("repo");
+    create_local_repo(&repo_path);
+
+    let output = gitingest()
+        .arg(format!("file://{}", repo_path.display()))
+        .args(["--stdout", "--dry-run", "--no-summary-line"])
+        .current_dir(temp_dir.path())
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Files processed: 2\n"));
+    assert!(stdout.contains("main.rs"));
+    assert!(!stdout.contains("fn main() {}"));
+}
+
+#[test]
+fn test_tree_prints_structure_only() {
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path().join("owner").join("repo");
+    create_local_repo(&repo_path);
+    let url = format!("file://{}", repo_path.display());
+
+    let output = gitingest()
+        .args(["tree", url.as_str(), "--sizes", "--exclude", "README.md"])
+        .current_dir(temp_dir.path())
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("└── repo/\n"));
+    assert!(stdout.contains("src/\n"));
+    assert!(stdout.contains("main.rs (12 B)\n"));
+    assert!(!stdout.contains("README.md"));
+    assert!(!stdout.contains("fn main() {}"));
+    assert!(std::fs::read_dir(temp_dir.path()).unwrap().count() == 1);
+}
+
+#[test]
+fn test_tree_max_files_caps_the_listing() {
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path().join("owner").join("repo");
+    create_local_repo(&repo_path);
+    let url = format!("file://{}", repo_path.display());
+
+    let output = gitingest().args(["tree", url.as_str(), "--max-files", "3"]).output().unwrap();
+
+    // The root and src/ directories take two of the three entries
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!(stdout.contains("README.md") && stdout.contains("main.rs")), "{}", stdout);
+    assert!(String::from_utf8(output.stderr).unwrap().lines().any(|line| line.starts_with("1 files, ")));
+}
+
+#[test]
+fn test_check_patterns_reports_invalid_pattern_and_previews_paths() {
+    let output = gitingest()
+        .args(["check-patterns", "--include", "src/**,*.md", "--exclude", "src/gen/"])
+        .args(["src/main.rs", "src/gen/api.rs", "README.md", "build.rs"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "included\tsrc/main.rs\nexcluded\tsrc/gen/api.rs\nincluded\tREADME.md\nexcluded\tbuild.rs\n"
+    );
+
+    let output = gitingest()
+        .args(["check-patterns", "--include", "src/**,src/[a-z"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().contains("Invalid include pattern 'src/[a-z'"));
+}
+
+#[test]
+fn test_token_file_is_read_and_conflicts_with_token() {
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path().join("owner").join("repo");
+    create_local_repo(&repo_path);
+    let url = format!("file://{}", repo_path.display());
+    let token_file = temp_dir.path().join("token");
+    std::fs::write(&token_file, "ghp_fromfile\n").unwrap();
+    let token_file = token_file.to_str().unwrap();
+
+    let output = gitingest().args(["tree", url.as_str(), "--token-file", token_file]).output().unwrap();
+    assert!(output.status.success());
+
+    let missing = temp_dir.path().join("missing");
+    let output = gitingest()
+        .args(["tree", url.as_str(), "--token-file", missing.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().contains("Can't read token file"));
+
+    let output = gitingest()
+        .args(["tree", url.as_str(), "--token", "ghp_x", "--token-file", token_file])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_batch_writes_one_digest_per_repository_and_reports_failures() {
+    let temp_dir = TempDir::new().unwrap();
+    let repos: Vec<String> = ["owner/repo", "other/tool"]
+        .iter()
+        .map(|path| {
+            let repo_path = temp_dir.path().join(path);
+            create_local_repo(&repo_path);
+            format!("file://{}", repo_path.display())
+        })
+        .collect();
+    let missing = format!("file://{}/nobody/missing", temp_dir.path().display());
+    std::fs::write(
+        temp_dir.path().join("repos.txt"),
+        format!("# audit\n{}\n{}\n{}\n", repos[0], missing, repos[1]),
+    )
+    .unwrap();
+
+    let output = gitingest()
+        .args(["batch", "--input", "repos.txt", "--format", "json", "--output-dir", "out", "--parallel", "2"])
+        .current_dir(temp_dir.path())
+        .output()
+        .unwrap();
+
+    // One failure fails the run, but the other digests are still written
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(&format!("error: {}: ", missing)), "{}", stderr);
+    assert!(stderr.contains("1 of 3 repositories failed"), "{}", stderr);
+    let mut written: Vec<String> = std::fs::read_dir(temp_dir.path().join("out"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    written.sort();
+    assert_eq!(written, ["other_tool.json", "owner_repo.json"]);
+    let digest = std::fs::read_to_string(temp_dir.path().join("out/owner_repo.json")).unwrap();
+    assert!(digest.contains("\"short_repo_url\": \"owner/repo\""), "{}", digest);
+}
+
+#[test]
+fn test_stdout_failure_exits_non_zero_without_output() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let output = gitingest()
+        .arg(format!("file://{}/missing/repo", temp_dir.path().display()))
+        .arg("--stdout")
+        .current_dir(temp_dir.path())
+        .output()
+        .unwrap();
+
+    // A missing repository has its own exit code and a hint
+    assert_eq!(output.status.code(), Some(3));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("error: Repository not found: "));
+    assert!(stderr.contains("hint: check the URL"));
+    assert!(!stderr.contains("❌"));
+}
+
+#[test]
+fn test_xml_extension_selects_xml_format() {
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path().join("owner").join("repo");
+    create_local_repo(&repo_path);
+
+    let output = gitingest()
+        .arg(format!("file://{}", repo_path.display()))
+        .args(["--output", "digest.xml"])
+        .current_dir(temp_dir.path())
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let xml = std::fs::read_to_string(temp_dir.path().join("digest.xml")).unwrap();
+    assert!(xml.starts_with("<repository name=\"owner/repo\""));
+    assert!(xml.contains("<file path=\"src/main.rs\" language=\"rust\"><![CDATA[fn main() {}]]></file>"));
+}
+
+#[test]
+fn test_text_output_file_is_streamed_digest() {
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path().join("owner").join("repo");
+    create_local_repo(&repo_path);
+
+    let output = gitingest()
+        .arg(format!("file://{}", repo_path.display()))
+        .args(["--output", "digest.txt"])
+        .current_dir(temp_dir.path())
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let digest = std::fs::read_to_string(temp_dir.path().join("digest.txt")).unwrap();
+    assert!(digest.starts_with("Repository: owner/repo\nSummary:\n"));
+    assert!(digest.contains("\n\nFile Contents:\n"));
+    // README leads the content, source follows
+    assert!(digest.contains("File Contents:\nREADME.md:\n"));
+    assert!(digest.ends_with("fn main() {}\n\n"));
+}
+
+#[test]
+fn test_archive_input_names_the_output_after_the_archive() {
+    let temp_dir = TempDir::new().unwrap();
+    let source = temp_dir.path().join("upload");
+    std::fs::create_dir_all(source.join("src")).unwrap();
+    std::fs::write(source.join("src/main.rs"), "fn main() {}").unwrap();
+    let status = Command::new("tar")
+        .args(["-czf", "upload.tgz", "upload"])
+        .current_dir(temp_dir.path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let output = gitingest().args(["upload.tgz", "--stdout"]).current_dir(temp_dir.path()).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let digest = String::from_utf8(output.stdout).unwrap();
+    assert!(digest.starts_with("Repository: upload\nSummary:\nRepository: upload.tgz\n"));
+    assert!(digest.contains("src/main.rs:\n"));
+    assert!(digest.contains("fn main() {}"));
+
+    let split = gitingest().args(["upload.tgz", "--split-by-dir", "1"]).current_dir(temp_dir.path()).output().unwrap();
+    assert!(split.status.success());
+    assert!(temp_dir.path().join("upload-digest/index.json").is_file());
+}
+
+#[test]
+fn test_compressed_outputs_hold_the_digest() {
+    use std::io::Read;
+
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path().join("owner").join("repo");
+    create_local_repo(&repo_path);
+    let url = format!("file://{}", repo_path.display());
+    for output in ["digest.txt", "digest.zip", "digest.tar.gz"] {
+        let mut command = gitingest();
+        command.args([url.as_str(), "--output", output]).current_dir(temp_dir.path());
+        if output != "digest.txt" {
+            command.arg("--stats-sidecar");
+        }
+        assert!(command.output().unwrap().status.success(), "{}", output);
+    }
+    let digest = std::fs::read(temp_dir.path().join("digest.txt")).unwrap();
+
+    let mut zip = zip::ZipArchive::new(std::fs::File::open(temp_dir.path().join("digest.zip")).unwrap()).unwrap();
+    let mut zipped = Vec::new();
+    zip.by_name("digest.txt").unwrap().read_to_end(&mut zipped).unwrap();
+    assert_eq!(zipped, digest);
+    let mut stats = String::new();
+    zip.by_name("digest.stats.json").unwrap().read_to_string(&mut stats).unwrap();
+    assert!(stats.contains("\"files_analyzed\": 2"));
+
+    let tarball = std::fs::File::open(temp_dir.path().join("digest.tar.gz")).unwrap();
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(tarball));
+    let mut entries = Vec::new();
+    for entry in archive.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content).unwrap();
+        entries.push((entry.path().unwrap().display().to_string(), content));
+    }
+    assert_eq!(entries[0], ("digest.txt".to_string(), digest));
+    assert_eq!(entries[1].0, "digest.stats.json");
+}
+
+#[test]
+fn test_failed_ingestion_removes_output_file() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let output = gitingest()
+        .arg(format!("file://{}/missing/repo", temp_dir.path().display()))
+        .args(["--output", "digest.txt"])
+        .current_dir(temp_dir.path())
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(!temp_dir.path().join("digest.txt").exists());
+}
+
+#[test]
+fn test_error_format_json_reports_structured_failure() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let output = gitingest()
+        .arg(format!("file://{}/missing/repo", temp_dir.path().display()))
+        .args(["--stdout", "--error-format", "json"])
+        .current_dir(temp_dir.path())
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let failure: serde_json::Value = serde_json::from_str(stderr.lines().last().unwrap()).unwrap();
+    assert_eq!(failure["status"], "failed");
+    assert_eq!(failure["phase"], "clone");
+    assert_eq!(failure["error_code"], "repository_not_found");
+    assert!(failure["timings"]["total_ms"].is_u64());
+    assert!(failure["message"].as_str().unwrap().contains("missing/repo"));
+}
+
+#[test]
+fn test_cache_ls_and_clear() {
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path().join("owner").join("repo");
+    create_local_repo(&repo_path);
+    let cache_dir = temp_dir.path().join("cache");
+
+    let output = gitingest()
+        .arg(format!("file://{}", repo_path.display()))
+        .arg("--stdout")
+        .env("CACHE_DIR", &cache_dir)
+        .current_dir(temp_dir.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let ls = gitingest().args(["cache", "ls"]).env("CACHE_DIR", &cache_dir).output().unwrap();
+    assert!(ls.status.success());
+    assert!(String::from_utf8(ls.stdout).unwrap().contains("localhost/owner/repo/_default"));
+
+    let clear = gitingest().args(["cache", "clear"]).env("CACHE_DIR", &cache_dir).output().unwrap();
+    assert!(clear.status.success());
+    assert!(String::from_utf8(clear.stdout).unwrap().starts_with("Removed 1 cached clone(s)"));
+    assert!(!cache_dir.exists());
+}
+
+#[test]
+fn test_cache_command_requires_cache_dir() {
+    let output = gitingest().args(["cache", "ls"]).env_remove("CACHE_DIR").output().unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().contains("CACHE_DIR"));
+}
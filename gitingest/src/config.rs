@@ -1,20 +1,122 @@
+use crate::error::GitingestError;
+use crate::models::KnownHostsPolicy;
+use crate::utils::{display_host, normalize_host, read_token_file, GitCredentials, Platform};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// What to do when a request names a branch that differs from the one
+/// embedded in its URL (e.g. `/tree/develop` with `--branch main`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BranchConflictPolicy {
+    /// Use the explicitly requested branch.
+    #[default]
+    PreferFlag,
+    /// Use the branch from the URL.
+    PreferUrl,
+    /// Fail the request.
+    Error,
+}
+
+impl FromStr for BranchConflictPolicy {
+    type Err = GitingestError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "prefer_flag" => Ok(Self::PreferFlag),
+            "prefer_url" => Ok(Self::PreferUrl),
+            "error" => Ok(Self::Error),
+            other => Err(GitingestError::ConfigError(format!(
+                "Unknown branch conflict policy '{}' (expected prefer_flag, prefer_url or error)",
+                other
+            ))),
+        }
+    }
+}
+
+/// Concurrent file scans per available core in auto mode; scans mostly
+/// wait on the disk rather than the CPU.
+const AUTO_FILES_PER_CORE: usize = 32;
+
+/// Bounds of the auto-tuned concurrency limit.
+const AUTO_CONCURRENCY_RANGE: (usize, usize) = (16, 1000);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
     pub host: String,
     pub port: u16,
     pub max_file_size: u64,
+    /// Files with more lines than this are listed without their content.
+    pub max_file_lines: u64,
+    pub max_content_size: u64,
+    /// Bytes of file contents a digest holds before the remaining files
+    /// are left out.
+    pub max_output_bytes: u64,
     pub max_files: usize,
     pub max_total_size: u64,
     pub max_directory_depth: u32,
     pub default_timeout: u64,
     pub temp_dir: String,
     pub github_token: Option<String>,
+    pub gitlab_token: Option<String>,
+    pub bitbucket_token: Option<String>,
+    /// Base URL of the GitHub REST API, for `api` fetches.
+    pub github_api_url: String,
+    /// Hosts repositories may be cloned from, each covering its subdomains
+    /// too; empty allows any host. See [`AppConfig::check_host_allowed`].
     pub allowed_hosts: Vec<String>,
+    /// Refuse anything but `https://` repository URLs: `ssh://`, `git://`
+    /// and `file://` remotes as well as local directories and archives.
+    pub https_only: bool,
+    /// Accept `file://` URLs, local directories and archives, which read
+    /// from the server's own disk. Off by default, so that whoever can submit a URL
+    /// can't read every repository the server can.
+    pub allow_local_inputs: bool,
+    /// Don't ask the host whether a repository exists before cloning it,
+    /// for air-gapped setups whose hosts have no reachable API.
+    pub skip_existence_check: bool,
+    /// The platform of self-hosted instances, by [`normalize_host`] name.
+    /// [`Platform::for_host`] only knows the public instances, so a GitLab
+    /// server at `gitlab.example.com` needs an entry as much as a GitHub
+    /// Enterprise server at `git.example.com` does.
+    pub host_overrides: HashMap<String, Platform>,
+    /// The username tokens are sent under, by [`normalize_host`] name, for
+    /// self-hosted instances that want one other than their platform's
+    /// (see [`GitCredentials::for_platform`]). A `username:token` token
+    /// still names its own.
+    pub token_usernames: HashMap<String, String>,
+    /// Keep the case of owner and repository names as given, even on
+    /// platforms that ignore it; see [`crate::UrlParser::parse_git_url_with`].
+    pub preserve_repo_case: bool,
+    /// Files scanned at once; 0 derives it from the available parallelism
+    /// (see [`AppConfig::effective_concurrent_file_limit`]).
     pub concurrent_file_limit: usize,
+    /// Files per scan batch; 0 matches the concurrency limit.
     pub batch_size: usize,
+    pub branch_conflict_policy: BranchConflictPolicy,
+    pub clone_max_retries: u32,
+    pub clone_base_delay_ms: u64,
+    /// Keep clones here and refresh them instead of cloning every run.
+    pub cache_dir: Option<String>,
+    /// Cached clones unused for this long are re-cloned.
+    pub cache_ttl_secs: Option<u64>,
+    /// Least recently used clones are evicted beyond this many bytes.
+    pub cache_max_bytes: Option<u64>,
+    /// Download github.com repositories as tarballs before falling back to
+    /// `git clone`.
+    pub prefer_archive_download: bool,
+    /// Include small files with a binary extension (`.dat`, `.bin`, ...)
+    /// when their content is plainly UTF-8 text.
+    pub rescue_binary_text: bool,
+    /// Private key SSH remotes are cloned with, such as a deploy key,
+    /// unless a request names its own.
+    pub ssh_key_path: Option<PathBuf>,
+    /// How SSH remotes' host keys are checked unless a request says;
+    /// `None` leaves it to ssh's own configuration.
+    pub ssh_known_hosts_policy: Option<KnownHostsPolicy>,
 }
 
 impl Default for AppConfig {
@@ -23,19 +125,43 @@ impl Default for AppConfig {
             host: "0.0.0.0".to_string(),
             port: 8080,
             max_file_size: u64::MAX,
+            max_file_lines: u64::MAX,
+            max_content_size: 100_000,
+            max_output_bytes: u64::MAX,
             max_files: usize::MAX,
             max_total_size: u64::MAX,
             max_directory_depth: u32::MAX,
             default_timeout: 60,
             temp_dir: "/tmp/fast-gitingest".to_string(),
             github_token: None,
+            gitlab_token: None,
+            bitbucket_token: None,
+            github_api_url: "https://api.github.com".to_string(),
             allowed_hosts: vec![
                 "github.com".to_string(),
                 "gitlab.com".to_string(),
                 "bitbucket.org".to_string(),
+                "codeberg.org".to_string(),
+                "git.sr.ht".to_string(),
             ],
-            concurrent_file_limit: 1000,
-            batch_size: 500,
+            https_only: false,
+            allow_local_inputs: false,
+            skip_existence_check: false,
+            host_overrides: HashMap::new(),
+            token_usernames: HashMap::new(),
+            preserve_repo_case: false,
+            concurrent_file_limit: 0,
+            batch_size: 0,
+            branch_conflict_policy: BranchConflictPolicy::default(),
+            clone_max_retries: 3,
+            clone_base_delay_ms: 500,
+            cache_dir: None,
+            cache_ttl_secs: None,
+            cache_max_bytes: None,
+            prefer_archive_download: false,
+            rescue_binary_text: true,
+            ssh_key_path: None,
+            ssh_known_hosts_policy: None,
         }
     }
 }
@@ -56,6 +182,18 @@ impl AppConfig {
             config.max_file_size = max_file_size.parse()?;
         }
 
+        if let Ok(max_file_lines) = env::var("MAX_FILE_LINES") {
+            config.max_file_lines = max_file_lines.parse()?;
+        }
+
+        if let Ok(max_content_size) = env::var("MAX_CONTENT_SIZE") {
+            config.max_content_size = max_content_size.parse()?;
+        }
+
+        if let Ok(max_output_bytes) = env::var("MAX_OUTPUT_BYTES") {
+            config.max_output_bytes = max_output_bytes.parse()?;
+        }
+
         if let Ok(max_files) = env::var("MAX_FILES") {
             config.max_files = max_files.parse()?;
         }
@@ -76,15 +214,42 @@ impl AppConfig {
             config.temp_dir = temp_dir;
         }
 
-        config.github_token = env::var("GITHUB_TOKEN").ok();
+        config.github_token = token_from_env("GITHUB_TOKEN")?;
+        config.gitlab_token = token_from_env("GITLAB_TOKEN")?;
+        config.bitbucket_token = token_from_env("BITBUCKET_TOKEN")?;
+
+        if let Ok(github_api_url) = env::var("GITHUB_API_URL") {
+            config.github_api_url = github_api_url;
+        }
 
         if let Ok(allowed_hosts) = env::var("ALLOWED_HOSTS") {
             config.allowed_hosts = allowed_hosts
                 .split(',')
                 .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
                 .collect();
         }
 
+        if let Ok(https_only) = env::var("HTTPS_ONLY") {
+            config.https_only = https_only.parse()?;
+        }
+
+        if let Ok(allow_local_inputs) = env::var("ALLOW_LOCAL_INPUTS") {
+            config.allow_local_inputs = allow_local_inputs.parse()?;
+        }
+
+        if let Ok(skip_existence_check) = env::var("SKIP_EXISTENCE_CHECK") {
+            config.skip_existence_check = skip_existence_check.parse()?;
+        }
+
+        if let Ok(host_overrides) = env::var("HOST_OVERRIDES") {
+            config.host_overrides = parse_host_overrides(&host_overrides)?;
+        }
+
+        if let Ok(token_usernames) = env::var("TOKEN_USERNAMES") {
+            config.token_usernames = parse_token_usernames(&token_usernames)?;
+        }
+
         if let Ok(concurrent_file_limit) = env::var("CONCURRENT_FILE_LIMIT") {
             config.concurrent_file_limit = concurrent_file_limit.parse()?;
         }
@@ -93,6 +258,311 @@ impl AppConfig {
             config.batch_size = batch_size.parse()?;
         }
 
+        if let Ok(branch_conflict_policy) = env::var("BRANCH_CONFLICT_POLICY") {
+            config.branch_conflict_policy = branch_conflict_policy.parse()?;
+        }
+
+        if let Ok(clone_max_retries) = env::var("CLONE_MAX_RETRIES") {
+            config.clone_max_retries = clone_max_retries.parse()?;
+        }
+
+        if let Ok(clone_base_delay_ms) = env::var("CLONE_BASE_DELAY_MS") {
+            config.clone_base_delay_ms = clone_base_delay_ms.parse()?;
+        }
+
+        if let Ok(cache_dir) = env::var("CACHE_DIR") {
+            config.cache_dir = Some(cache_dir);
+        }
+
+        if let Ok(cache_ttl_secs) = env::var("CACHE_TTL_SECS") {
+            config.cache_ttl_secs = Some(cache_ttl_secs.parse()?);
+        }
+
+        if let Ok(cache_max_bytes) = env::var("CACHE_MAX_BYTES") {
+            config.cache_max_bytes = Some(cache_max_bytes.parse()?);
+        }
+
+        if let Ok(preserve_repo_case) = env::var("PRESERVE_REPO_CASE") {
+            config.preserve_repo_case = preserve_repo_case.parse()?;
+        }
+
+        if let Ok(prefer_archive_download) = env::var("PREFER_ARCHIVE_DOWNLOAD") {
+            config.prefer_archive_download = prefer_archive_download.parse()?;
+        }
+
+        if let Ok(rescue_binary_text) = env::var("RESCUE_BINARY_TEXT") {
+            config.rescue_binary_text = rescue_binary_text.parse()?;
+        }
+
+        if let Ok(ssh_key_path) = env::var("GITINGEST_SSH_KEY") {
+            config.ssh_key_path = Some(PathBuf::from(ssh_key_path));
+        }
+
+        if let Ok(ssh_known_hosts_policy) = env::var("GITINGEST_SSH_KNOWN_HOSTS") {
+            config.ssh_known_hosts_policy = Some(ssh_known_hosts_policy.parse()?);
+        }
+
         Ok(config)
     }
-}
\ No newline at end of file
+
+    /// `concurrent_file_limit`, or one derived from
+    /// [`std::thread::available_parallelism`] when it is 0.
+    pub fn effective_concurrent_file_limit(&self) -> usize {
+        let parallelism = std::thread::available_parallelism().map_or(1, |n| n.get());
+        resolve_concurrency(self.concurrent_file_limit, parallelism)
+    }
+
+    /// `batch_size`, or the effective concurrency limit when it is 0.
+    pub fn effective_batch_size(&self) -> usize {
+        match self.batch_size {
+            0 => self.effective_concurrent_file_limit(),
+            batch_size => batch_size,
+        }
+    }
+
+    /// `host`'s platform, taking [`AppConfig::host_overrides`] into account.
+    pub fn platform_for_host(&self, host: &str) -> Platform {
+        Platform::for_host_in(host, &self.host_overrides)
+    }
+
+    /// The credentials `token` stands for on `host`: those of its platform,
+    /// taking [`AppConfig::host_overrides`] into account, under the
+    /// username [`AppConfig::token_usernames`] sets for it, if any.
+    pub fn credentials_for_host(&self, host: &str, token: &str) -> GitCredentials {
+        let mut credentials = GitCredentials::for_platform(self.platform_for_host(host), token);
+        let host = normalize_host(host);
+        if !token.contains(':')
+            && let Some(username) = self
+                .token_usernames
+                .iter()
+                .find_map(|(name, username)| (normalize_host(name) == host).then_some(username))
+        {
+            credentials.username = username.clone();
+        }
+        credentials
+    }
+
+    /// `token` in the form git and the host's API take it for `host`: as
+    /// is when [`GitCredentials::for_host`] finds the right username from
+    /// the host's name alone, else as the `username:token` pair of
+    /// [`AppConfig::credentials_for_host`].
+    pub fn qualify_token(&self, host: &str, token: &str) -> String {
+        let credentials = self.credentials_for_host(host, token);
+        if credentials == GitCredentials::for_host(host, token) {
+            return token.to_string();
+        }
+        format!("{}:{}", credentials.username, credentials.password)
+    }
+
+    /// Where clones are cached for a request with the given `use_cache`:
+    /// `cache_dir`, or `<temp_dir>/cache` for a request that opts in
+    /// without one being configured. `None` when the request opts out, or
+    /// doesn't opt in and no `cache_dir` is set.
+    pub fn clone_cache_dir(&self, use_cache: Option<bool>) -> Option<PathBuf> {
+        match (use_cache, &self.cache_dir) {
+            (Some(false), _) => None,
+            (_, Some(cache_dir)) => Some(PathBuf::from(cache_dir)),
+            (Some(true), None) => Some(Path::new(&self.temp_dir).join("cache")),
+            (None, None) => None,
+        }
+    }
+
+    /// The configured token for `host`'s platform, used when a request
+    /// doesn't carry its own.
+    pub fn token_for_host(&self, host: &str) -> Option<&str> {
+        match self.platform_for_host(host) {
+            Platform::GitHub => self.github_token.as_deref(),
+            Platform::GitLab => self.gitlab_token.as_deref(),
+            Platform::Bitbucket => self.bitbucket_token.as_deref(),
+            Platform::Gitea | Platform::SourceHut | Platform::AzureDevOps | Platform::Other => None,
+        }
+    }
+
+    /// Fails unless repositories may be cloned from `host`: it is one of
+    /// the [`AppConfig::allowed_hosts`] or a subdomain of one, or has a
+    /// [`AppConfig::host_overrides`] entry. Both sides are compared in
+    /// their [`normalize_host`] form, so case and unicode versus punycode
+    /// don't matter. An empty list allows any host.
+    pub fn check_host_allowed(&self, host: &str) -> crate::error::Result<()> {
+        let host = normalize_host(host);
+        let allowed = self.allowed_hosts.is_empty()
+            || self.host_overrides.keys().any(|name| normalize_host(name) == host)
+            || self.allowed_hosts.iter().any(|allowed| {
+                let allowed = normalize_host(allowed);
+                host == allowed || host.strip_suffix(&allowed).is_some_and(|prefix| prefix.ends_with('.'))
+            });
+        match allowed {
+            true => Ok(()),
+            false => Err(GitingestError::HostNotAllowed(format!(
+                "{} is not one of the allowed hosts ({})",
+                display_host(&host),
+                self.allowed_hosts.join(", ")
+            ))),
+        }
+    }
+}
+
+/// Parses `host=platform` pairs separated by commas, as in
+/// `git.example.com=github,code.example.com=gitlab`.
+fn parse_host_overrides(value: &str) -> crate::error::Result<HashMap<String, Platform>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (host, platform) = pair.split_once('=').ok_or_else(|| {
+                GitingestError::ConfigError(format!("Host override '{}' is not of the form host=platform", pair))
+            })?;
+            Ok((normalize_host(host), platform.trim().parse()?))
+        })
+        .collect()
+}
+
+/// Parses `host=username` pairs separated by commas, as in
+/// `git.example.com=deploy,code.example.com=ci-bot`.
+fn parse_token_usernames(value: &str) -> crate::error::Result<HashMap<String, String>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (host, username) = pair.split_once('=').ok_or_else(|| {
+                GitingestError::ConfigError(format!("Token username '{}' is not of the form host=username", pair))
+            })?;
+            Ok((normalize_host(host), username.trim().to_string()))
+        })
+        .collect()
+}
+
+/// The token in `name`, else the contents of the file named by `<name>_FILE`.
+fn token_from_env(name: &str) -> crate::error::Result<Option<String>> {
+    if let Ok(token) = env::var(name) {
+        return Ok(Some(token));
+    }
+    env::var(format!("{}_FILE", name))
+        .ok()
+        .map(|path| read_token_file(Path::new(&path)))
+        .transpose()
+}
+
+/// `configured` if set, else [`AUTO_FILES_PER_CORE`] per core within
+/// [`AUTO_CONCURRENCY_RANGE`].
+fn resolve_concurrency(configured: usize, parallelism: usize) -> usize {
+    if configured > 0 {
+        return configured;
+    }
+    let (min, max) = AUTO_CONCURRENCY_RANGE;
+    (parallelism * AUTO_FILES_PER_CORE).clamp(min, max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_concurrency() {
+        assert_eq!(resolve_concurrency(250, 8), 250);
+        assert_eq!(resolve_concurrency(1, 64), 1);
+        assert_eq!(resolve_concurrency(0, 8), 8 * AUTO_FILES_PER_CORE);
+        assert_eq!(resolve_concurrency(0, 0), AUTO_CONCURRENCY_RANGE.0);
+        assert_eq!(resolve_concurrency(0, 256), AUTO_CONCURRENCY_RANGE.1);
+
+        let auto = AppConfig::default();
+        assert!(auto.effective_concurrent_file_limit() >= AUTO_CONCURRENCY_RANGE.0);
+        assert_eq!(auto.effective_batch_size(), auto.effective_concurrent_file_limit());
+        let explicit = AppConfig { concurrent_file_limit: 100, batch_size: 50, ..AppConfig::default() };
+        assert_eq!((explicit.effective_concurrent_file_limit(), explicit.effective_batch_size()), (100, 50));
+    }
+
+    #[test]
+    fn test_host_overrides() {
+        let overrides = parse_host_overrides("Git.Example.com=github, code.example.com = gitlab,").unwrap();
+        assert_eq!(overrides.len(), 2);
+        let config = AppConfig {
+            host_overrides: overrides,
+            github_token: Some("github".to_string()),
+            gitlab_token: Some("gitlab".to_string()),
+            ..AppConfig::default()
+        };
+        assert_eq!(config.platform_for_host("git.example.com"), Platform::GitHub);
+        assert_eq!(config.token_for_host("git.example.com"), Some("github"));
+        assert_eq!(config.token_for_host("code.example.com"), Some("gitlab"));
+        assert_eq!(config.token_for_host("gitlab.com"), Some("gitlab"));
+        assert_eq!(config.token_for_host("other.example.com"), None);
+        assert_eq!(config.token_for_host("gitlab.attacker.example"), None);
+
+        assert!(parse_host_overrides("git.example.com").is_err());
+        assert!(parse_host_overrides("git.example.com=gogs").is_err());
+    }
+
+    #[test]
+    fn test_clone_cache_dir() {
+        let config = AppConfig { temp_dir: "/tmp/gi".to_string(), ..AppConfig::default() };
+        assert_eq!(config.clone_cache_dir(None), None);
+        assert_eq!(config.clone_cache_dir(Some(true)), Some(PathBuf::from("/tmp/gi/cache")));
+
+        let config = AppConfig { cache_dir: Some("/var/cache/gi".to_string()), ..config };
+        assert_eq!(config.clone_cache_dir(None), Some(PathBuf::from("/var/cache/gi")));
+        assert_eq!(config.clone_cache_dir(Some(true)), Some(PathBuf::from("/var/cache/gi")));
+        assert_eq!(config.clone_cache_dir(Some(false)), None);
+    }
+
+    #[test]
+    fn test_token_usernames() {
+        let config = AppConfig {
+            host_overrides: parse_host_overrides("code.example.com=gitlab").unwrap(),
+            token_usernames: parse_token_usernames("Git.Example.com=deploy, ").unwrap(),
+            ..AppConfig::default()
+        };
+
+        // Tokens whose username the host name gives away stay as they are
+        assert_eq!(config.qualify_token("github.com", "ghp_abc"), "ghp_abc");
+        assert_eq!(config.qualify_token("gitlab.com", "glpat-abc"), "glpat-abc");
+        assert_eq!(config.qualify_token("dev.azure.com", "pat"), "pat");
+        // An overridden platform or a configured username is spelled out
+        assert_eq!(config.qualify_token("code.example.com", "glpat-abc"), "oauth2:glpat-abc");
+        assert_eq!(config.qualify_token("git.example.com", "secret"), "deploy:secret");
+        assert_eq!(config.credentials_for_host("git.example.com", "secret").username, "deploy");
+        // A token that names its own username keeps it
+        assert_eq!(config.qualify_token("git.example.com", "ci:secret"), "ci:secret");
+
+        assert!(parse_token_usernames("git.example.com").is_err());
+    }
+
+    #[test]
+    fn test_allowed_hosts() {
+        let config = AppConfig::default();
+        for host in ["github.com", "GitHub.com", "gist.github.com", "gitlab.com."] {
+            assert!(config.check_host_allowed(host).is_ok(), "{}", host);
+        }
+        for host in ["evil-github.com", "github.com.evil.net", "169.254.169.254", "localhost"] {
+            let err = config.check_host_allowed(host).unwrap_err();
+            assert!(matches!(err, GitingestError::HostNotAllowed(_)), "{}", host);
+            assert!(err.to_string().contains("github.com, gitlab.com"), "{}", err);
+        }
+
+        // Hosts configured with an override are allowed as well
+        let config = AppConfig {
+            host_overrides: parse_host_overrides("git.example.com=gitlab").unwrap(),
+            ..AppConfig::default()
+        };
+        assert!(config.check_host_allowed("git.example.com").is_ok());
+
+        let config = AppConfig { allowed_hosts: Vec::new(), ..AppConfig::default() };
+        assert!(config.check_host_allowed("git.internal").is_ok());
+
+        // Internationalized names match in unicode or punycode, either way
+        let config = AppConfig { allowed_hosts: vec!["Büro.Example".to_string()], ..AppConfig::default() };
+        for host in ["git.xn--bro-hoa.example", "git.büro.example", "XN--BRO-HOA.EXAMPLE"] {
+            assert!(config.check_host_allowed(host).is_ok(), "{}", host);
+        }
+        let err = config.check_host_allowed("git.xn--bro-hoa.test").unwrap_err();
+        assert!(err.to_string().starts_with("Host not allowed: git.büro.test is not"), "{}", err);
+        let config = AppConfig {
+            host_overrides: parse_host_overrides("git.büro.example=gitlab").unwrap(),
+            ..AppConfig::default()
+        };
+        assert!(config.check_host_allowed("git.xn--bro-hoa.example").is_ok());
+        assert_eq!(config.platform_for_host("git.xn--bro-hoa.example"), Platform::GitLab);
+    }
+}
@@ -6,9 +6,24 @@ pub enum GitingestError {
     #[error("Repository not found: {0}")]
     RepositoryNotFound(String),
     
+    #[error("Branch not found: {0}")]
+    BranchNotFound(String),
+    
+    #[error("Authentication required: {0}")]
+    AuthenticationRequired(String),
+    
+    #[error("Host key verification failed: {0}")]
+    HostKeyVerificationFailed(String),
+    
+    #[error("Rate limit exceeded: {0}")]
+    RateLimited(String),
+    
     #[error("Invalid repository URL: {0}")]
     InvalidRepositoryUrl(String),
     
+    #[error("Host not allowed: {0}")]
+    HostNotAllowed(String),
+    
     #[error("Git operation failed: {0}")]
     GitOperationFailed(String),
     
@@ -18,6 +33,7 @@ pub enum GitingestError {
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
     
+    #[cfg(feature = "remote")]
     #[error("HTTP client error: {0}")]
     HttpError(#[from] reqwest::Error),
     
@@ -42,10 +58,43 @@ pub enum GitingestError {
     #[error("Validation error: {0}")]
     ValidationError(String),
     
+    #[error("Operation cancelled during {0}")]
+    Cancelled(String),
+    
     #[error("Internal server error: {0}")]
     InternalError(String),
 }
 
+impl GitingestError {
+    /// Stable, machine-readable name for the kind of error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            GitingestError::RepositoryNotFound(_) => "repository_not_found",
+            GitingestError::BranchNotFound(_) => "branch_not_found",
+            GitingestError::AuthenticationRequired(_) => "authentication_required",
+            GitingestError::HostKeyVerificationFailed(_) => "host_key_verification_failed",
+            GitingestError::RateLimited(_) => "rate_limited",
+            GitingestError::InvalidRepositoryUrl(_) => "invalid_repository_url",
+            GitingestError::HostNotAllowed(_) => "host_not_allowed",
+            GitingestError::GitOperationFailed(_) => "git_operation_failed",
+            GitingestError::FileSystemError(_) => "file_system_error",
+            GitingestError::IoError(_) => "io_error",
+            #[cfg(feature = "remote")]
+            GitingestError::HttpError(_) => "http_error",
+            GitingestError::JsonError(_) => "json_error",
+            GitingestError::UrlParseError(_) => "url_parse_error",
+            GitingestError::PatternError(_) => "pattern_error",
+            GitingestError::TokenValidationError(_) => "token_validation_error",
+            GitingestError::TimeoutError(_) => "timeout",
+            GitingestError::ConfigError(_) => "config_error",
+            GitingestError::ValidationError(_) => "validation_error",
+            GitingestError::Cancelled(_) => "cancelled",
+            GitingestError::InternalError(_) => "internal_error",
+        }
+    }
+}
+
+#[cfg(feature = "git")]
 impl From<git2::Error> for GitingestError {
     fn from(err: git2::Error) -> Self {
         GitingestError::GitOperationFailed(err.to_string())
@@ -68,9 +117,26 @@ impl Serialize for GitingestError {
         use serde::ser::SerializeStruct;
         let mut state = serializer.serialize_struct("GitingestError", 2)?;
         state.serialize_field("error", &self.to_string())?;
-        state.serialize_field("type", &format!("{:?}", self))?;
+        state.serialize_field("type", self.code())?;
         state.end()
     }
 }
 
-pub type Result<T> = std::result::Result<T, GitingestError>;
\ No newline at end of file
+pub type Result<T> = std::result::Result<T, GitingestError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialized_type_is_the_error_code() {
+        let err = GitingestError::AuthenticationRequired("https://github.com/owner/repo".to_string());
+        assert_eq!(
+            serde_json::to_value(&err).unwrap(),
+            serde_json::json!({
+                "error": "Authentication required: https://github.com/owner/repo",
+                "type": "authentication_required",
+            })
+        );
+    }
+}
@@ -0,0 +1,308 @@
+//! Renders an [`IngestResponse`] into the digest formats written by the CLI
+//! and the language bindings.
+
+use crate::error::Result;
+use crate::models::{DownloadFormat, FileEntry, IncompleteOutput, IngestResponse};
+use crate::schema::{IncompleteLineDto, MetadataLineDto, ResponseDto, StatusDto};
+use std::fmt::Write;
+
+/// Stands in for `content` when a JSON digest is rendered around a
+/// streamed content string. NULs never appear in real content.
+const CONTENT_PLACEHOLDER: &str = "\u{0}gitingest-content\u{0}";
+
+pub fn render(response: &IngestResponse, format: &DownloadFormat) -> Result<String> {
+    Ok(match format {
+        DownloadFormat::Text => render_text(response),
+        DownloadFormat::Markdown => render_markdown(response),
+        DownloadFormat::Json | DownloadFormat::JsonStructured => serde_json::to_string_pretty(&ResponseDto::from(response))?,
+        DownloadFormat::Xml => render_xml(response),
+        DownloadFormat::JsonLines => {
+            let mut lines = Vec::new();
+            write_json_lines_metadata(&mut lines, response)?;
+            String::from_utf8(lines).expect("JSON is UTF-8") + &response.content
+        }
+    })
+}
+
+/// File extension conventionally used for `format`.
+pub fn extension(format: &DownloadFormat) -> &'static str {
+    match format {
+        DownloadFormat::Text => "txt",
+        DownloadFormat::Markdown => "md",
+        DownloadFormat::Json | DownloadFormat::JsonStructured => "json",
+        DownloadFormat::Xml => "xml",
+        DownloadFormat::JsonLines => "jsonl",
+    }
+}
+
+/// Writes everything a text or markdown digest has before the file
+/// contents, so the contents can be streamed after it. Other formats have
+/// no prelude.
+pub fn write_prelude(
+    writer: &mut dyn std::io::Write,
+    format: &DownloadFormat,
+    short_repo_url: &str,
+    summary: &str,
+    tree: &str,
+) -> std::io::Result<()> {
+    match format {
+        DownloadFormat::Text => write!(
+            writer,
+            "Repository: {}\nSummary:\n{}\n\nDirectory Structure:\n{}\n\nFile Contents:\n",
+            short_repo_url, summary, tree
+        ),
+        DownloadFormat::Markdown => write!(
+            writer,
+            "# Repository: {}\n\n## Summary\n{}\n\n## Directory Structure\n```\n{}\n```\n\n## File Contents\n",
+            short_repo_url, summary, tree
+        ),
+        DownloadFormat::Json | DownloadFormat::JsonStructured | DownloadFormat::Xml | DownloadFormat::JsonLines => Ok(()),
+    }
+}
+
+/// Writes the first line of a `jsonl` digest, whose content is then
+/// streamed as one line per file.
+pub fn write_json_lines_metadata(writer: &mut dyn std::io::Write, response: &IngestResponse) -> Result<()> {
+    serde_json::to_writer(&mut *writer, &MetadataLineDto::from(response))?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+/// Appends the marker that closes off a text, markdown or `jsonl` digest
+/// whose contents stopped early.
+pub fn write_incomplete_trailer(
+    writer: &mut dyn std::io::Write,
+    format: &DownloadFormat,
+    incomplete: &IncompleteOutput,
+) -> std::io::Result<()> {
+    if matches!(format, DownloadFormat::JsonLines) {
+        serde_json::to_writer(&mut *writer, &IncompleteLineDto::from(*incomplete))?;
+        return writeln!(writer);
+    }
+    writeln!(writer, "\n{}", incomplete)
+}
+
+/// Renders a JSON digest as the text before and after the characters of
+/// its `content` string, ignoring `response.content`. Writing the head,
+/// the content escaped with [`JsonStringWriter`], then the tail gives the
+/// same document as [`render`], while the content is streamed. With
+/// `incomplete`, the status is `failed` and an `incomplete` object says
+/// how far the content got.
+pub fn render_json_around_content(
+    response: &IngestResponse,
+    incomplete: Option<IncompleteOutput>,
+) -> Result<(String, String)> {
+    let mut dto = ResponseDto::from(response);
+    dto.content = CONTENT_PLACEHOLDER;
+    if let Some(incomplete) = incomplete {
+        dto.status = StatusDto::Failed;
+        dto.incomplete = Some(incomplete.into());
+    }
+    let rendered = serde_json::to_string_pretty(&dto)?;
+    let placeholder = serde_json::to_string(CONTENT_PLACEHOLDER)?;
+    let placeholder = &placeholder[1..placeholder.len() - 1];
+    let (head, tail) = rendered
+        .split_once(placeholder)
+        .expect("rendered JSON contains the content placeholder");
+    Ok((head.to_string(), tail.to_string()))
+}
+
+/// Writes text through to `inner` escaped for a JSON string, without the
+/// surrounding quotes. A UTF-8 sequence split across writes is held back
+/// until it is complete.
+pub struct JsonStringWriter<'a> {
+    inner: &'a mut dyn std::io::Write,
+    pending: Vec<u8>,
+}
+
+impl<'a> JsonStringWriter<'a> {
+    pub fn new(inner: &'a mut dyn std::io::Write) -> Self {
+        Self { inner, pending: Vec::new() }
+    }
+}
+
+impl std::io::Write for JsonStringWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(text) => text.len(),
+            // An incomplete sequence at the end may still be completed
+            Err(err) if err.error_len().is_none() => err.valid_up_to(),
+            Err(_) => {
+                let text = String::from_utf8_lossy(&self.pending).into_owned();
+                self.pending = text.into_bytes();
+                self.pending.len()
+            }
+        };
+        let text = std::str::from_utf8(&self.pending[..valid]).expect("prefix is valid UTF-8");
+        let escaped = serde_json::to_string(text).map_err(std::io::Error::other)?;
+        self.inner.write_all(&escaped.as_bytes()[1..escaped.len() - 1])?;
+        self.pending.drain(..valid);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn render_text(response: &IngestResponse) -> String {
+    render_with_prelude(response, &DownloadFormat::Text)
+}
+
+fn render_markdown(response: &IngestResponse) -> String {
+    render_with_prelude(response, &DownloadFormat::Markdown)
+}
+
+fn render_with_prelude(response: &IngestResponse, format: &DownloadFormat) -> String {
+    let mut output = Vec::new();
+    // Writing into a Vec cannot fail
+    let _ = write_prelude(&mut output, format, &response.short_repo_url, &response.summary, &response.tree);
+    output.extend_from_slice(response.content.as_bytes());
+    String::from_utf8_lossy(&output).into_owned()
+}
+
+/// Wraps each file in `<file>` with its contents in CDATA. Responses built
+/// without per-file entries fall back to a single `<content>` element.
+fn render_xml(response: &IngestResponse) -> String {
+    let mut xml = String::new();
+    let _ = writeln!(
+        xml,
+        "<repository name=\"{}\" url=\"{}\">",
+        escape_attribute(&response.short_repo_url),
+        escape_attribute(&response.repo_url)
+    );
+    let _ = writeln!(xml, "<summary>\n{}\n</summary>", escape_text(&response.summary));
+    let _ = writeln!(
+        xml,
+        "<directory_structure>\n{}</directory_structure>",
+        escape_text(&response.tree)
+    );
+
+    match &response.files {
+        Some(files) => {
+            xml.push_str("<files>\n");
+            for file in files {
+                write_xml_file(&mut xml, file);
+            }
+            xml.push_str("</files>\n");
+        }
+        None => {
+            let _ = writeln!(xml, "<content>{}</content>", cdata(&response.content));
+        }
+    }
+
+    xml.push_str("</repository>\n");
+    xml
+}
+
+fn write_xml_file(xml: &mut String, file: &FileEntry) {
+    let path = escape_attribute(&file.relative_path);
+    match (&file.content, file.skip_reason.as_deref()) {
+        (Some(content), _) => {
+            let _ = write!(xml, "<file path=\"{}\"", path);
+            if let Some(language) = &file.language {
+                let _ = write!(xml, " language=\"{}\"", escape_attribute(language));
+            }
+            if file.truncated {
+                xml.push_str(" truncated=\"true\"");
+            }
+            let _ = writeln!(xml, ">{}</file>", cdata(content));
+        }
+        // Files the text digest reports as unreadable; binary, excluded
+        // and symlinked files are left out as they are there
+        (None, Some("unreadable")) => {
+            let _ = writeln!(xml, "<file path=\"{}\" skip_reason=\"unreadable\"/>", path);
+        }
+        (None, _) => {}
+    }
+}
+
+/// Wraps `text` in CDATA, splitting any `]]>` so it cannot end the section.
+fn cdata(text: &str) -> String {
+    format!("<![CDATA[{}]]>", text.replace("]]>", "]]]]><![CDATA[>"))
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn escape_attribute(text: &str) -> String {
+    escape_text(text).replace('"', "&quot;").replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::IngestStatus;
+    use uuid::Uuid;
+
+    fn response(files: Option<Vec<FileEntry>>) -> IngestResponse {
+        IngestResponse {
+            id: Uuid::nil(),
+            repo_url: "https://github.com/owner/repo".to_string(),
+            short_repo_url: "owner/repo".to_string(),
+            summary: "Repository: owner/repo\nFiles processed: 1".to_string(),
+            digest_url: None,
+            tree: "└── repo/\n    └── a<b>.rs\n".to_string(),
+            content: "a<b>.rs:\n====\n</file>\n\n".to_string(),
+            status: IngestStatus::Completed,
+            truncated: false,
+            files,
+            file_tree: None,
+            language_breakdown: Vec::new(),
+            license: None,
+        }
+    }
+
+    fn entry(path: &str, content: Option<&str>, skip_reason: Option<&str>) -> FileEntry {
+        FileEntry {
+            relative_path: path.to_string(),
+            size: content.map_or(0, |c| c.len() as u64),
+            language: Some("rust".to_string()),
+            content: content.map(str::to_string),
+            truncated: false,
+            skip_reason: skip_reason.map(str::to_string),
+            tokens: None,
+        }
+    }
+
+    #[test]
+    fn test_xml_wraps_files_and_escapes_structure() {
+        let files = vec![
+            entry("a<b>.rs", Some("fn f() {} // </file> ]]> done"), None),
+            entry("bad.rs", None, Some("unreadable")),
+            entry("logo.png", None, Some("binary")),
+        ];
+        let xml = render(&response(Some(files)), &DownloadFormat::Xml).unwrap();
+
+        assert!(xml.starts_with("<repository name=\"owner/repo\" url=\"https://github.com/owner/repo\">\n"));
+        assert!(xml.contains("<summary>\nRepository: owner/repo\nFiles processed: 1\n</summary>"));
+        assert!(xml.contains("<directory_structure>\n└── repo/\n    └── a&lt;b&gt;.rs\n</directory_structure>"));
+        assert!(xml.contains(
+            "<file path=\"a&lt;b&gt;.rs\" language=\"rust\"><![CDATA[fn f() {} // </file> ]]]]><![CDATA[> done]]></file>"
+        ));
+        assert!(xml.contains("<file path=\"bad.rs\" skip_reason=\"unreadable\"/>"));
+        assert!(!xml.contains("logo.png"));
+        assert!(xml.ends_with("</files>\n</repository>\n"));
+    }
+
+    #[test]
+    fn test_xml_without_entries_falls_back_to_content() {
+        let xml = render(&response(None), &DownloadFormat::Xml).unwrap();
+        assert!(xml.contains("<content><![CDATA[a<b>.rs:\n====\n</file>\n\n]]></content>"));
+    }
+
+    #[test]
+    fn test_text_and_markdown_layout() {
+        let response = response(None);
+
+        let text = render(&response, &DownloadFormat::Text).unwrap();
+        assert!(text.starts_with("Repository: owner/repo\nSummary:\n"));
+        assert!(text.contains("\n\nDirectory Structure:\n"));
+
+        let markdown = render(&response, &DownloadFormat::Markdown).unwrap();
+        assert!(markdown.starts_with("# Repository: owner/repo\n\n## Summary\n"));
+        assert!(markdown.contains("## Directory Structure\n```\n"));
+    }
+}
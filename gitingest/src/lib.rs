@@ -1,12 +1,15 @@
 pub mod config;
 pub mod error;
+pub mod formats;
 pub mod models;
+pub mod schema;
 pub mod services;
 pub mod utils;
 
-pub use config::AppConfig;
+pub use config::{AppConfig, BranchConflictPolicy};
 pub use error::GitingestError;
 pub use models::*;
+pub use schema::SCHEMA_VERSION;
 pub use services::*;
 pub use utils::*;
 
@@ -1,12 +1,26 @@
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct IngestRequest {
     pub input_text: String,
     pub max_file_size: Option<u64>,
+    /// Truncate each file's content beyond this many bytes; defaults to
+    /// [`crate::AppConfig::max_content_size`].
+    pub max_content_size: Option<u64>,
+    /// Stop writing file contents once the digest's content would pass
+    /// this many bytes; defaults to [`crate::AppConfig::max_output_bytes`].
+    pub max_output_bytes: Option<u64>,
+    /// Skip the content of files with more lines than this, such as
+    /// minified bundles; they stay in the tree.
+    pub max_file_lines: Option<u64>,
     pub max_files: Option<usize>,
+    /// Levels of the tree to walk, 1 being the root's own files; defaults
+    /// to [`crate::AppConfig::max_directory_depth`].
+    pub max_directory_depth: Option<u32>,
     pub pattern_type: Option<PatternType>,
     pub pattern: Option<String>,
     pub include_patterns: Option<Vec<String>>,
@@ -15,6 +29,248 @@ pub struct IngestRequest {
     pub branch: Option<String>,
     pub include_submodules: Option<bool>,
     pub download_format: Option<DownloadFormat>,
+    /// Repo-relative paths to ingest instead of scanning the whole tree.
+    /// Listed files bypass pattern and gitignore filtering and keep their
+    /// order in the digest.
+    pub files: Option<Vec<String>>,
+    pub missing_files: Option<MissingFilePolicy>,
+    /// Token budget for file contents; files that don't fit are listed in
+    /// the tree but their content is omitted.
+    pub max_tokens: Option<usize>,
+    /// Skip the built-in excludes (build output, media, editor files).
+    /// `.git` is still excluded, and `.gitignore`/`.gitingestignore` still
+    /// apply.
+    pub no_default_ignore: Option<bool>,
+    /// Clone into a temporary directory even when a clone cache is
+    /// configured.
+    pub no_cache: Option<bool>,
+    /// Reuse a cached clone, refreshed with a shallow fetch, from
+    /// `cache_dir` or, when that isn't set, `<temp_dir>/cache`; see
+    /// [`AppConfig::clone_cache_dir`]. Defaults to whether `cache_dir` is
+    /// set; `no_cache` wins.
+    ///
+    /// [`AppConfig::clone_cache_dir`]: crate::AppConfig::clone_cache_dir
+    pub use_cache: Option<bool>,
+    /// List the files that would be ingested without reading them: the
+    /// digest has the tree and stats but an empty `content`, and tokens
+    /// are estimated from file sizes.
+    pub dry_run: Option<bool>,
+    /// Put the root license file into the digest in full, even when
+    /// patterns, size limits or the token budget would leave it out. A
+    /// scan narrowed to a subpath only names it in the summary.
+    pub include_license_text: Option<bool>,
+    /// Fetch only the matching files under the URL's subpath through the
+    /// GitHub API instead of cloning. github.com only; can't be combined
+    /// with submodules or listed files.
+    pub api: Option<bool>,
+    /// Order of the files in the content section; the tree stays
+    /// alphabetical. Defaults to [`SortOrder::Importance`], except that
+    /// listed [`IngestRequest::files`] keep their order unless this is set.
+    pub sort: Option<SortOrder>,
+    /// Layout of each file's section in the content; defaults to
+    /// [`ContentFormat::markdown`] for markdown downloads and to
+    /// [`ContentFormat::default`] otherwise.
+    pub content_format: Option<ContentFormat>,
+    /// Number each line of the content; sets
+    /// [`ContentFormat::line_numbers`].
+    pub line_numbers: Option<bool>,
+    /// Cut content lines longer than this many characters; sets
+    /// [`ContentFormat::max_line_width`].
+    pub max_line_width: Option<usize>,
+    /// Ingest only the files that differ between this ref and the one
+    /// being ingested. Deleted files are listed in the tree without
+    /// content; can't be combined with listed files, API fetches or
+    /// archives.
+    pub base_ref: Option<String>,
+    /// Clone the full history and record when each file was last
+    /// committed, listing the most recently changed files in the summary.
+    /// Has no effect on API fetches, archives and shallow clones.
+    pub with_timestamps: Option<bool>,
+    /// How much of the repository to clone; defaults to
+    /// [`CloneMode::Shallow`]. A [`CloneMode::Blobless`] clone lists the
+    /// tree like a dry run without fetching file contents or sizes.
+    pub clone_mode: Option<CloneMode>,
+    /// Private key to authenticate SSH remotes with, instead of the
+    /// agent's and `~/.ssh`'s; defaults to [`AppConfig::ssh_key_path`].
+    ///
+    /// [`AppConfig::ssh_key_path`]: crate::AppConfig::ssh_key_path
+    pub ssh_key_path: Option<PathBuf>,
+    /// How SSH remotes' host keys are checked; defaults to
+    /// [`AppConfig::ssh_known_hosts_policy`], then to ssh's own settings.
+    ///
+    /// [`AppConfig::ssh_known_hosts_policy`]: crate::AppConfig::ssh_known_hosts_policy
+    pub ssh_known_hosts_policy: Option<KnownHostsPolicy>,
+}
+
+/// How each file's section of the content is laid out. The default is a
+/// `path:` header over a line of 48 `=`, with the body as is.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContentFormat {
+    /// First line of a section; `{path}` stands for the repo-relative path.
+    pub header: String,
+    /// Line written under the header; left out when empty.
+    pub separator: String,
+    /// Wrap bodies in a fenced code block tagged with the language guessed
+    /// from the file extension.
+    pub fence: bool,
+    /// Prefix each line of a body with its right-aligned number and `|`.
+    pub line_numbers: bool,
+    /// Cut body lines longer than this many characters, noting how many
+    /// were left out, so minified files don't swamp the digest.
+    pub max_line_width: Option<usize>,
+    /// Write each file as a one-line JSON object instead of a header over
+    /// its body, as [`DownloadFormat::JsonLines`] does; `header`,
+    /// `separator` and `fence` don't apply.
+    pub json_lines: bool,
+}
+
+impl Default for ContentFormat {
+    fn default() -> Self {
+        Self {
+            header: "{path}:".to_string(),
+            separator: "=".repeat(48),
+            fence: false,
+            line_numbers: false,
+            max_line_width: None,
+            json_lines: false,
+        }
+    }
+}
+
+impl ContentFormat {
+    /// Markdown sections: a `### path` heading over a fenced code block.
+    pub fn markdown() -> Self {
+        Self { header: "### {path}".to_string(), separator: String::new(), fence: true, ..Self::default() }
+    }
+
+    /// `content` with the line numbers and line cuts this format asks for.
+    fn render_body<'a>(&self, content: &'a str) -> std::borrow::Cow<'a, str> {
+        if !self.line_numbers && self.max_line_width.is_none() {
+            return std::borrow::Cow::Borrowed(content);
+        }
+
+        let line_count = content.lines().count();
+        let number_width = line_count.to_string().len();
+        let mut body = String::with_capacity(content.len() + line_count * (number_width + 3));
+        for (i, line) in content.lines().enumerate() {
+            if self.line_numbers {
+                body.push_str(&format!("{:>width$} |", i + 1, width = number_width));
+                if !line.is_empty() {
+                    body.push(' ');
+                }
+            }
+            match self.max_line_width {
+                Some(max_width) if line.chars().count() > max_width => {
+                    let kept: String = line.chars().take(max_width).collect();
+                    let cut = line.chars().count() - max_width;
+                    body.push_str(&format!("{}… [{} more characters]", kept, cut));
+                }
+                _ => body.push_str(line),
+            }
+            body.push('\n');
+        }
+        if !content.ends_with('\n') {
+            body.pop();
+        }
+        std::borrow::Cow::Owned(body)
+    }
+
+    fn write_header(&self, writer: &mut dyn Write, relative_path: &str, note: Option<&str>) -> std::io::Result<()> {
+        let header = self.header.replace("{path}", relative_path);
+        match note {
+            Some(note) => writeln!(writer, "{} {}", header, note)?,
+            None => writeln!(writer, "{}", header)?,
+        }
+        if !self.separator.is_empty() {
+            writeln!(writer, "{}", self.separator)?;
+        }
+        Ok(())
+    }
+}
+
+/// A code fence longer than any run of backticks in `content`, so the
+/// content can't close it early.
+fn code_fence(content: &str) -> String {
+    let longest_run = content
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    "`".repeat(longest_run.max(2) + 1)
+}
+
+/// Order in which file contents are written; see
+/// [`crate::utils::sort_for_content`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortOrder {
+    /// READMEs, top-level docs and manifests first, then source files by
+    /// depth and size, with tests and generated or vendored files last.
+    #[default]
+    Importance,
+    /// By repo-relative path.
+    Path,
+    /// Smallest files first.
+    SizeAsc,
+}
+
+/// How much of a repository a clone fetches.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CloneMode {
+    /// Every commit of the branch.
+    Full,
+    /// Only the latest commit.
+    #[default]
+    Shallow,
+    /// Only the latest commit's trees, without file contents and without
+    /// checking anything out; files are listed with `git ls-tree`. No blob
+    /// is fetched, so file sizes are unknown.
+    Blobless,
+}
+
+/// How ssh checks the host key of an SSH remote, as its
+/// `StrictHostKeyChecking` option.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KnownHostsPolicy {
+    /// Only connect to hosts already in `known_hosts`.
+    #[default]
+    Strict,
+    /// Add unknown hosts to `known_hosts`, but refuse a changed key.
+    AcceptNew,
+    /// Don't check host keys at all, nor record them.
+    Off,
+}
+
+impl std::str::FromStr for KnownHostsPolicy {
+    type Err = crate::GitingestError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "strict" => Ok(Self::Strict),
+            "accept-new" => Ok(Self::AcceptNew),
+            "off" => Ok(Self::Off),
+            other => Err(crate::GitingestError::ConfigError(format!(
+                "Unknown known hosts policy '{}' (expected strict, accept-new or off)",
+                other
+            ))),
+        }
+    }
+}
+
+/// How to handle entries of [`IngestRequest::files`] that don't exist.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MissingFilePolicy {
+    /// Log a warning and report the count in the summary.
+    #[default]
+    Warn,
+    /// Fail the request.
+    Error,
+    /// Ignore missing paths.
+    Skip,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,6 +281,17 @@ pub enum DownloadFormat {
     Markdown,
     #[serde(rename = "json")]
     Json,
+    /// JSON with a per-file array and a nested tree instead of
+    /// preformatted `tree`/`content` strings only.
+    #[serde(rename = "json-structured")]
+    JsonStructured,
+    /// XML-tagged digest for LLM prompts, one `<file>` element per file.
+    #[serde(rename = "xml")]
+    Xml,
+    /// One compact JSON object per line: a metadata line, then one line
+    /// per file with content, so a consumer never holds the whole digest.
+    #[serde(rename = "jsonl")]
+    JsonLines,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,6 +312,202 @@ pub struct IngestResponse {
     pub tree: String,
     pub content: String,
     pub status: IngestStatus,
+    /// Set when files were left out of `content` to keep it under
+    /// [`IngestRequest::max_output_bytes`]; the tree still lists them.
+    #[serde(default)]
+    pub truncated: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files: Option<Vec<FileEntry>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_tree: Option<TreeEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub language_breakdown: Vec<LanguageStat>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<LicenseInfo>,
+}
+
+/// Result of a streamed ingestion: everything but the digest itself, which
+/// has already been written out.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestSummary {
+    pub id: Uuid,
+    pub repo_url: String,
+    pub short_repo_url: String,
+    pub summary: String,
+    pub stats: ProcessingStats,
+    #[serde(default)]
+    pub timings: PhaseTimings,
+    /// See [`IngestResponse::truncated`].
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+impl IngestSummary {
+    /// One-line, human-readable summary of the run for `label` (usually the
+    /// output file); see [`crate::utils::completion_line`].
+    pub fn completion_line(&self, label: &str) -> String {
+        crate::utils::completion_line(label, &self.stats, &self.timings)
+    }
+}
+
+impl IngestResponse {
+    /// Renders the response as the digest written for `format`; see
+    /// [`crate::formats::render`].
+    pub fn render(&self, format: &DownloadFormat) -> crate::error::Result<String> {
+        crate::formats::render(self, format)
+    }
+}
+
+/// Stage of an ingestion, as reported by [`IngestFailure`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IngestPhase {
+    Parse,
+    Clone,
+    Scan,
+    Content,
+}
+
+impl std::fmt::Display for IngestPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            IngestPhase::Parse => "parse",
+            IngestPhase::Clone => "clone",
+            IngestPhase::Scan => "scan",
+            IngestPhase::Content => "content",
+        })
+    }
+}
+
+/// Progress of a running ingestion, sent to the channel passed to
+/// [`crate::IngestService::process_repository_with_progress`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum IngestEvent {
+    /// The ingestion entered `phase`.
+    Phase { phase: IngestPhase },
+    /// The walk found `total` files to scan.
+    FilesDiscovered { total: usize },
+    /// `scanned` of the `total` discovered files have been scanned; sent
+    /// after each batch.
+    FilesScanned { scanned: usize, total: usize },
+}
+
+/// Durations of the phases that finished before an ingestion ended.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PhaseTimings {
+    pub clone_ms: Option<u64>,
+    pub scan_ms: Option<u64>,
+    pub content_ms: Option<u64>,
+    pub total_ms: u64,
+}
+
+/// A failed ingestion, with as much as was known when it stopped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestFailure {
+    pub id: Uuid,
+    pub repo_url: String,
+    pub status: IngestStatus,
+    pub phase: IngestPhase,
+    /// Stable identifier for the error kind; see [`crate::GitingestError::code`].
+    pub error_code: String,
+    pub message: String,
+    pub timings: PhaseTimings,
+    pub warnings: Vec<String>,
+    /// Set when the run stopped while writing content and the output so
+    /// far was closed off with an incomplete marker rather than discarded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub incomplete_output: Option<IncompleteOutput>,
+}
+
+/// How far [`FileNode::write_content_async`] got.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ContentWriteOutcome {
+    pub files_written: usize,
+    /// Files left out to stay under `max_output_bytes`.
+    pub files_omitted: usize,
+    /// Set when writing stopped at `max_output_bytes`.
+    pub truncated: bool,
+}
+
+/// How far an interrupted digest got.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IncompleteOutput {
+    pub files_written: usize,
+    pub files_total: usize,
+}
+
+impl std::fmt::Display for IncompleteOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[INGESTION INCOMPLETE: stopped after {}/{} files]",
+            self.files_written, self.files_total
+        )
+    }
+}
+
+impl std::fmt::Display for IngestFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for IngestFailure {}
+
+/// One file of a structured digest. `content` is `None` when the file was
+/// not read, with `skip_reason` saying why.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub relative_path: String,
+    pub size: u64,
+    pub language: Option<String>,
+    pub content: Option<String>,
+    pub truncated: bool,
+    pub skip_reason: Option<String>,
+    /// Estimated tokens in `content`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<usize>,
+}
+
+/// Serializable mirror of a scanned [`FileNode`] tree.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TreeEntry {
+    pub name: String,
+    pub path: String,
+    #[serde(rename = "type")]
+    pub node_type: FileNodeType,
+    pub size: u64,
+    pub has_content: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_target: Option<PathBuf>,
+    /// Unix time of the file's last commit; see
+    /// [`IngestRequest::with_timestamps`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<i64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<TreeEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SplitManifest {
+    pub id: Uuid,
+    pub repo_url: String,
+    pub short_repo_url: String,
+    pub summary: String,
+    pub depth: usize,
+    pub parts: Vec<DirectoryPart>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectoryPart {
+    /// Directory path relative to the repository root, or `_root`.
+    pub directory: String,
+    /// Output file path relative to the split output directory.
+    pub output: PathBuf,
+    pub files: usize,
+    pub total_size_bytes: u64,
+    pub estimated_tokens: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -62,23 +525,261 @@ pub enum IngestStatus {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Repository {
     pub url: String,
+    /// Lowercase ASCII, internationalized names in punycode; see
+    /// [`crate::utils::normalize_host`].
     pub host: String,
     pub owner: String,
     pub name: String,
-    pub branch: Option<String>,
-    pub commit: Option<String>,
+    #[serde(flatten)]
+    pub git_ref: GitRef,
     pub subpath: String,
+    /// What `subpath` names, when the URL says: `blob/` URLs point at a
+    /// file, `tree/` URLs and `src/` URLs with a trailing slash at a
+    /// directory.
+    #[serde(default)]
+    pub target_kind: Option<TargetKind>,
+}
+
+impl Repository {
+    /// [`Repository::host`], which is kept in ASCII, as people write it:
+    /// `git.büro.example` rather than `git.xn--bro-hoa.example`.
+    pub fn display_host(&self) -> String {
+        crate::utils::display_host(&self.host)
+    }
+}
+
+/// What a repository is checked out at. It is (de)serialized as the
+/// `branch`, `tag`, `commit` and `pull_request` fields, so the JSON shape
+/// of [`Repository`] and [`CloneConfig`] stays what it was.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "RefFields", into = "RefFields")]
+pub enum GitRef {
+    /// Whatever the remote's `HEAD` names.
+    #[default]
+    DefaultBranch,
+    Branch(String),
+    Tag(String),
+    /// A full or abbreviated commit id.
+    Commit(String),
+    /// The head of a pull request, `refs/pull/<n>/head`.
+    PullRequest(u64),
+}
+
+impl GitRef {
+    /// The name `git clone --branch` takes: a branch's or a tag's.
+    pub fn branch_name(&self) -> Option<&str> {
+        match self {
+            GitRef::Branch(name) | GitRef::Tag(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// The ref to fetch, or to ask an API for; `None` for the default
+    /// branch.
+    pub fn fetch_ref(&self) -> Option<String> {
+        match self {
+            GitRef::DefaultBranch => None,
+            GitRef::Branch(name) | GitRef::Tag(name) | GitRef::Commit(name) => Some(name.clone()),
+            GitRef::PullRequest(number) => Some(format!("refs/pull/{}/head", number)),
+        }
+    }
+}
+
+impl std::fmt::Display for GitRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GitRef::DefaultBranch => f.write_str("the default branch"),
+            GitRef::Branch(name) | GitRef::Tag(name) | GitRef::Commit(name) => f.write_str(name),
+            GitRef::PullRequest(number) => write!(f, "pull request #{}", number),
+        }
+    }
+}
+
+/// The serialized form of [`GitRef`].
+#[derive(Default, Serialize, Deserialize)]
+struct RefFields {
+    #[serde(default)]
+    branch: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tag: Option<String>,
+    #[serde(default)]
+    commit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pull_request: Option<u64>,
+}
+
+impl From<RefFields> for GitRef {
+    fn from(fields: RefFields) -> Self {
+        match fields {
+            RefFields { commit: Some(commit), .. } => GitRef::Commit(commit),
+            RefFields { pull_request: Some(number), .. } => GitRef::PullRequest(number),
+            RefFields { tag: Some(tag), .. } => GitRef::Tag(tag),
+            RefFields { branch: Some(branch), .. } => GitRef::Branch(branch),
+            _ => GitRef::DefaultBranch,
+        }
+    }
+}
+
+impl From<GitRef> for RefFields {
+    fn from(git_ref: GitRef) -> Self {
+        match git_ref {
+            GitRef::DefaultBranch => RefFields::default(),
+            GitRef::Branch(branch) => RefFields { branch: Some(branch), ..Default::default() },
+            GitRef::Tag(tag) => RefFields { tag: Some(tag), ..Default::default() },
+            GitRef::Commit(commit) => RefFields { commit: Some(commit), ..Default::default() },
+            GitRef::PullRequest(number) => RefFields { pull_request: Some(number), ..Default::default() },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TargetKind {
+    #[serde(rename = "file")]
+    File,
+    #[serde(rename = "directory")]
+    Directory,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CloneConfig {
     pub url: String,
     pub local_path: PathBuf,
-    pub branch: Option<String>,
-    pub commit: Option<String>,
+    /// Branches and tags are cloned with `--branch`; commits and pull
+    /// request heads are fetched into the clone and checked out.
+    #[serde(flatten)]
+    pub git_ref: GitRef,
     pub subpath: String,
     pub include_submodules: bool,
     pub token: Option<String>,
+    /// Check out only these repo-relative paths.
+    #[serde(default)]
+    pub sparse_paths: Option<Vec<String>>,
+    #[serde(default)]
+    pub clone_mode: CloneMode,
+    /// Download a tarball snapshot (github.com only) before falling back
+    /// to `git clone`.
+    #[serde(default)]
+    pub prefer_archive: bool,
+    /// Private key, and host key checking, for SSH remotes; ssh's own
+    /// settings apply where these are unset and to other remotes.
+    #[serde(default)]
+    pub ssh_key_path: Option<PathBuf>,
+    #[serde(default)]
+    pub ssh_known_hosts_policy: Option<KnownHostsPolicy>,
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    #[serde(skip)]
+    pub cancel: CancellationToken,
+}
+
+/// How git authenticates to SSH remotes: the private key to offer and how
+/// host keys are checked. Neither set leaves both to ssh's configuration.
+#[derive(Debug, Clone, Default)]
+pub struct SshAuth {
+    pub key_path: Option<PathBuf>,
+    pub known_hosts_policy: Option<KnownHostsPolicy>,
+}
+
+impl SshAuth {
+    pub fn of(config: &CloneConfig) -> Self {
+        Self {
+            key_path: config.ssh_key_path.clone(),
+            known_hosts_policy: config.ssh_known_hosts_policy,
+        }
+    }
+
+    /// Fails when `url` is an SSH remote and the key doesn't exist, which
+    /// ssh would only warn about before failing to authenticate. Other
+    /// remotes never use the key.
+    pub fn validate(&self, url: &str) -> crate::error::Result<()> {
+        match &self.key_path {
+            Some(key_path) if crate::utils::UrlParser::is_ssh_url(url) && !key_path.is_file() => Err(crate::GitingestError::ValidationError(format!(
+                "SSH key {} doesn't exist",
+                key_path.display()
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// How often a clone that failed with a transient error is retried. The
+/// n-th retry waits `base_delay_ms * 2^(n-1)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay_ms: 500,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `retry`, counting from 1.
+    pub fn delay(&self, retry: u32) -> std::time::Duration {
+        let factor = 1u64.checked_shl(retry.saturating_sub(1)).unwrap_or(u64::MAX);
+        std::time::Duration::from_millis(self.base_delay_ms.saturating_mul(factor))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CloneOutcome {
+    /// Submodules that could not be cloned and were left as empty directories.
+    pub skipped_submodules: usize,
+    /// Full id of the commit checked out, when there is a `.git` to ask.
+    pub commit: Option<String>,
+    /// A cached clone was refreshed instead of cloning afresh.
+    pub cached: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScanConfig {
+    pub max_file_size: u64,
+    /// Files with more lines than this are listed but their content is
+    /// skipped.
+    pub max_file_lines: u64,
+    pub max_files: usize,
+    /// Depth of the walk below the root; files directly in the root are at
+    /// depth 1. Excluded directories are not descended into at all.
+    pub max_depth: u32,
+    pub concurrent_limit: usize,
+    pub batch_size: usize,
+    /// Include files with a binary extension whose content is plainly text;
+    /// see [`crate::utils::BINARY_RESCUE_MAX_SIZE`].
+    pub rescue_binary_text: bool,
+    pub cancel: CancellationToken,
+    /// Receives scan progress, if anyone is listening.
+    pub events: Option<mpsc::Sender<IngestEvent>>,
+}
+
+impl ScanConfig {
+    /// Sends `event` to [`ScanConfig::events`]; a closed channel is ignored.
+    pub async fn emit(&self, event: IngestEvent) {
+        if let Some(events) = &self.events {
+            let _ = events.send(event).await;
+        }
+    }
+}
+
+impl Default for ScanConfig {
+    fn default() -> Self {
+        Self {
+            max_file_size: u64::MAX,
+            max_file_lines: u64::MAX,
+            max_files: usize::MAX,
+            max_depth: u32::MAX,
+            concurrent_limit: 1000,
+            batch_size: 500,
+            rescue_binary_text: true,
+            cancel: CancellationToken::new(),
+            events: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -91,65 +792,438 @@ pub struct FileNode {
     pub has_content: bool, // Uses lazy loading - content loaded on demand
     pub children: Vec<FileNode>,
     pub depth: u32,
+    pub link_target: Option<PathBuf>, // Raw target of a symlink, never followed, or a submodule's URL
+    pub budget_omitted: bool, // Listed in the tree, but content dropped to fit max_tokens
+    pub text_rescued: bool, // Binary extension, but the content sniffed as text
+    pub tokens: Option<usize>, // Estimated tokens in the content, once counted
+    pub pinned: bool, // Content kept in full despite filters, limits and the token budget
+    pub over_line_limit: Option<u64>, // Content skipped for having more lines than this
+    pub deleted: bool, // Removed since the diff's base ref; listed without content
+    pub renamed_from: Option<String>, // Path in the diff's base ref, for renamed files
+    pub last_modified: Option<i64>, // Unix time of the last commit touching the file, when asked for
 }
 
-use std::io::Write;
+use std::io::{Read, Write};
 
 pub trait ContentWriter {
-    fn write_content(&self, writer: &mut dyn Write) -> std::io::Result<()>;
+    /// Writes the node's file contents, truncating any single file larger
+    /// than `max_content_size` bytes.
+    fn write_content(&self, writer: &mut dyn Write, max_content_size: u64) -> std::io::Result<()> {
+        self.write_content_with_entries(writer, max_content_size, None)
+    }
+
+    /// Like [`ContentWriter::write_content`], additionally recording a
+    /// [`FileEntry`] per file into `entries` from the same read.
+    fn write_content_with_entries(
+        &self,
+        writer: &mut dyn Write,
+        max_content_size: u64,
+        entries: Option<&mut Vec<FileEntry>>,
+    ) -> std::io::Result<()> {
+        self.write_content_formatted(writer, max_content_size, &ContentFormat::default(), entries)
+    }
+
+    /// Like [`ContentWriter::write_content_with_entries`], laying each
+    /// file's section out as `format` says.
+    fn write_content_formatted(
+        &self,
+        writer: &mut dyn Write,
+        max_content_size: u64,
+        format: &ContentFormat,
+        entries: Option<&mut Vec<FileEntry>>,
+    ) -> std::io::Result<()>;
 }
 
 impl ContentWriter for FileNode {
-    fn write_content(&self, writer: &mut dyn Write) -> std::io::Result<()> {
-        if self.node_type == FileNodeType::File && self.has_content {
-            writeln!(writer, "{}:", self.relative_path)?;
-            writeln!(writer, "{}", "=".repeat(48))?;
-            
-            if self.size > 100_000 {
-                writeln!(writer, "[Large file content truncated - {} bytes]\n", self.size)?;
+    fn write_content_formatted(
+        &self,
+        writer: &mut dyn Write,
+        max_content_size: u64,
+        format: &ContentFormat,
+        mut entries: Option<&mut Vec<FileEntry>>,
+    ) -> std::io::Result<()> {
+        match self.node_type {
+            FileNodeType::Directory => {
+                for child in &self.children {
+                    child.write_content_formatted(writer, max_content_size, format, entries.as_deref_mut())?;
+                }
+            }
+            FileNodeType::File => {
+                let max_content_size = self.content_limit(max_content_size);
+                let content = self.includes_content().then(|| {
+                    if self.size > max_content_size {
+                        read_truncated(&self.path, max_content_size)
+                    } else {
+                        std::fs::read_to_string(&self.path)
+                    }
+                });
+                self.write_section(writer, max_content_size, format, content, entries)?;
+            }
+            FileNodeType::Symlink => self.write_section(writer, max_content_size, format, None, entries)?,
+            FileNodeType::Submodule => {}
+        }
+        Ok(())
+    }
+}
+
+impl FileNode {
+    /// Async counterpart of [`ContentWriter::write_content_formatted`]
+    /// that reads file bodies with `tokio::fs`, keeping up to `read_ahead`
+    /// reads in flight. Files are written in `order`, or in tree order when
+    /// it is `None`, in which case the output is identical to the
+    /// synchronous writer.
+    ///
+    /// `writer` is flushed after every file, so a reader tailing the output
+    /// only ever sees whole file sections. Once `cancel` fires, writing
+    /// stops before the next file. A section that would take the output
+    /// past `max_output_bytes` (counted before any escaping) isn't written;
+    /// the remaining files are left out and a footer says how many.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn write_content_async(
+        &self,
+        writer: &mut dyn Write,
+        max_content_size: u64,
+        max_output_bytes: u64,
+        read_ahead: usize,
+        order: Option<SortOrder>,
+        format: &ContentFormat,
+        cancel: Option<&CancellationToken>,
+        mut entries: Option<&mut Vec<FileEntry>>,
+    ) -> std::io::Result<ContentWriteOutcome> {
+        use futures::stream::{self, StreamExt};
+
+        let mut leaves = Vec::new();
+        self.collect_leaves(&mut leaves);
+        if let Some(order) = order {
+            crate::utils::sort_for_content(&mut leaves, order);
+        }
+        let files_total = leaves.iter().filter(|node| node.node_type == FileNodeType::File).count();
+
+        let mut reads = stream::iter(leaves.into_iter().map(|node| async move {
+            let content = if node.node_type == FileNodeType::File && node.includes_content() {
+                Some(node.read_content_async(max_content_size).await)
             } else {
-                match std::fs::read_to_string(&self.path) {
-                    Ok(content) => {
-                        write!(writer, "{}\n\n", content)?;
+                None
+            };
+            (node, content)
+        }))
+        .buffered(read_ahead.max(1));
+
+        let mut outcome = ContentWriteOutcome::default();
+        let mut bytes_written = 0u64;
+        let mut section = Vec::new();
+        while let Some((node, content)) = reads.next().await {
+            if cancel.is_some_and(CancellationToken::is_cancelled) {
+                break;
+            }
+            // Each section is rendered whole first, so it can be held back
+            // rather than cut off partway
+            section.clear();
+            let entries_before = entries.as_deref().map_or(0, Vec::len);
+            node.write_section(&mut section, max_content_size, format, content, entries.as_deref_mut())?;
+            if bytes_written.saturating_add(section.len() as u64) > max_output_bytes {
+                if let Some(entries) = entries.as_deref_mut() {
+                    entries.truncate(entries_before);
+                }
+                outcome.files_omitted = files_total - outcome.files_written;
+                outcome.truncated = true;
+                if format.json_lines {
+                    let line = crate::schema::TruncatedLineDto { max_output_bytes, files_omitted: outcome.files_omitted };
+                    serde_json::to_writer(&mut *writer, &line)?;
+                    writeln!(writer)?;
+                } else {
+                    writeln!(
+                        writer,
+                        "[digest truncated at {} bytes; {} files omitted]",
+                        max_output_bytes, outcome.files_omitted
+                    )?;
+                }
+                writer.flush()?;
+                break;
+            }
+            writer.write_all(&section)?;
+            writer.flush()?;
+            bytes_written += section.len() as u64;
+            if node.node_type == FileNodeType::File {
+                outcome.files_written += 1;
+            }
+        }
+        Ok(outcome)
+    }
+
+    /// Reads the file's body as it appears in the digest, truncated to
+    /// `max_content_size` bytes.
+    pub async fn read_content_async(&self, max_content_size: u64) -> std::io::Result<String> {
+        let max_content_size = self.content_limit(max_content_size);
+        if self.size > max_content_size {
+            read_truncated_async(&self.path, max_content_size).await
+        } else {
+            tokio::fs::read_to_string(&self.path).await
+        }
+    }
+
+    /// Whether this file's body belongs in the digest.
+    pub fn includes_content(&self) -> bool {
+        self.has_content && !self.budget_omitted
+    }
+
+    /// The size this file's body is truncated to; pinned files are never
+    /// truncated.
+    pub fn content_limit(&self, max_content_size: u64) -> u64 {
+        if self.pinned { u64::MAX } else { max_content_size }
+    }
+
+    /// Marker under a truncated body, naming the limit it was cut at; the
+    /// shown bytes can fall short of it so a character isn't split.
+    fn truncation_note(&self, content: &str, max_content_size: u64) -> String {
+        format!(
+            "[Large file content truncated at max_content_size of {} bytes - showing {} of {} bytes]",
+            max_content_size,
+            content.len(),
+            self.size
+        )
+    }
+
+    /// Files and symlinks beneath this node, in output order.
+    fn collect_leaves<'a>(&'a self, leaves: &mut Vec<&'a FileNode>) {
+        match self.node_type {
+            FileNodeType::Directory => {
+                for child in &self.children {
+                    child.collect_leaves(leaves);
+                }
+            }
+            FileNodeType::File | FileNodeType::Symlink => leaves.push(self),
+            FileNodeType::Submodule => {}
+        }
+    }
+
+    /// Writes one file's section of the digest from its already-read
+    /// `content` (`None` if it was not read), and records its entry.
+    fn write_section(
+        &self,
+        writer: &mut dyn Write,
+        max_content_size: u64,
+        format: &ContentFormat,
+        content: Option<std::io::Result<String>>,
+        entries: Option<&mut Vec<FileEntry>>,
+    ) -> std::io::Result<()> {
+        let truncated = self.size > self.content_limit(max_content_size);
+
+        if format.json_lines {
+            let content = content.map(|content| content.map(|content| format.render_body(&content).into_owned()));
+            let entry = self.file_entry(truncated, content);
+            if let Some(content) = &entry.content {
+                let line = crate::schema::FileLineDto {
+                    path: &entry.relative_path,
+                    size: entry.size,
+                    language: entry.language.as_deref(),
+                    content,
+                    truncated: entry.truncated,
+                };
+                serde_json::to_writer(&mut *writer, &line)?;
+                writeln!(writer)?;
+            }
+            if let Some(entries) = entries {
+                entries.push(entry);
+            }
+            return Ok(());
+        }
+
+        if let Some(content) = &content {
+            let note = match &self.renamed_from {
+                Some(old_path) => Some(format!("[renamed from {}]", old_path)),
+                None => self.text_rescued.then(|| "[binary extension, detected as text]".to_string()),
+            };
+            format.write_header(writer, &self.relative_path, note.as_deref())?;
+            match content {
+                Ok(content) if format.fence => {
+                    let body = format.render_body(content);
+                    let fence = code_fence(&body);
+                    let language = crate::utils::guess_language(&self.path).unwrap_or_default();
+                    writeln!(writer, "{}{}", fence, language)?;
+                    write!(writer, "{}", body)?;
+                    if !body.is_empty() && !body.ends_with('\n') {
+                        writeln!(writer)?;
                     }
-                    Err(_) => {
-                        writeln!(writer, "[Error reading file content]\n")?;
+                    writeln!(writer, "{}", fence)?;
+                    if truncated {
+                        writeln!(writer, "{}", self.truncation_note(content, max_content_size))?;
                     }
+                    writeln!(writer)?;
+                }
+                Ok(content) if truncated => {
+                    writeln!(writer, "{}", format.render_body(content))?;
+                    writeln!(writer, "{}\n", self.truncation_note(content, max_content_size))?;
+                }
+                Ok(content) => {
+                    write!(writer, "{}\n\n", format.render_body(content))?;
+                }
+                Err(_) => {
+                    writeln!(writer, "[Error reading file content]\n")?;
                 }
             }
-        } else if self.node_type == FileNodeType::Directory {
-            for child in &self.children {
-                child.write_content(writer)?;
-            }
+        } else if let Some(max_lines) = self.over_line_limit {
+            format.write_header(writer, &self.relative_path, None)?;
+            writeln!(writer, "[File excluded: exceeds {} lines]\n", max_lines)?;
+        }
+
+        if let Some(entries) = entries {
+            entries.push(self.file_entry(truncated, content));
         }
         Ok(())
     }
+
+    /// This file's per-file entry, from its already-read `content`.
+    fn file_entry(&self, truncated: bool, content: Option<std::io::Result<String>>) -> FileEntry {
+        let (content, skip_reason) = match content {
+            _ if self.node_type == FileNodeType::Symlink => (None, Some("symlink")),
+            _ if self.deleted => (None, Some("deleted")),
+            _ if self.budget_omitted => (None, Some("token_budget")),
+            _ if self.over_line_limit.is_some() => (None, Some("too_many_lines")),
+            Some(Ok(content)) => (Some(content), None),
+            Some(Err(_)) => (None, Some("unreadable")),
+            None if crate::utils::is_binary_file(&self.path) => (None, Some("binary")),
+            None => (None, Some("excluded")),
+        };
+        let is_file = self.node_type == FileNodeType::File;
+        FileEntry {
+            relative_path: self.relative_path.clone(),
+            size: self.size,
+            language: is_file
+                .then(|| crate::utils::guess_language(&self.path))
+                .flatten()
+                .map(str::to_string),
+            truncated: truncated && content.is_some(),
+            tokens: content.is_some().then_some(self.tokens).flatten(),
+            content,
+            skip_reason: skip_reason.map(str::to_string),
+        }
+    }
+}
+
+/// Reads at most `limit` bytes of a file, backing off to the last complete
+/// UTF-8 character so a multi-byte sequence is never split.
+fn read_truncated(path: &std::path::Path, limit: u64) -> std::io::Result<String> {
+    let mut buffer = Vec::new();
+    std::fs::File::open(path)?.take(limit).read_to_end(&mut buffer)?;
+    Ok(decode_truncated(&buffer))
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+async fn read_truncated_async(path: &std::path::Path, limit: u64) -> std::io::Result<String> {
+    use tokio::io::AsyncReadExt;
+
+    let mut buffer = Vec::new();
+    tokio::fs::File::open(path).await?.take(limit).read_to_end(&mut buffer).await?;
+    Ok(decode_truncated(&buffer))
+}
+
+fn decode_truncated(buffer: &[u8]) -> String {
+    let valid_len = match std::str::from_utf8(buffer) {
+        Ok(_) => buffer.len(),
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        Err(_) => buffer.len(),
+    };
+
+    String::from_utf8_lossy(&buffer[..valid_len]).into_owned()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum FileNodeType {
     Directory,
     File,
     Symlink,
+    /// A submodule that wasn't cloned, listed with its URL as
+    /// [`FileNode::link_target`] instead of as an empty directory.
+    Submodule,
 }
 
-#[derive(Debug, Clone)]
+/// What a scan found: every file seen, and why those left out of the
+/// digest's content were skipped.
+#[derive(Debug, Clone, Default)]
 pub struct FileSystemStats {
     pub total_files: usize,
     pub total_size: u64,
     pub processed_files: usize,
     pub skipped_files: usize,
+    pub skipped: SkippedFiles,
 }
 
-impl Default for FileSystemStats {
-    fn default() -> Self {
-        Self {
-            total_files: 0,
-            total_size: 0,
-            processed_files: 0,
-            skipped_files: 0,
+impl FileSystemStats {
+    /// Records a scanned file as processed or skipped.
+    pub fn record(&mut self, node: &FileNode, max_file_size: u64) {
+        self.total_files += 1;
+        self.total_size += node.size;
+        if node.node_type != FileNodeType::File {
+            return;
+        }
+        if node.has_content {
+            self.processed_files += 1;
+        } else if node.size > max_file_size {
+            self.skip(|skipped| skipped.too_large += 1);
+        } else if node.over_line_limit.is_some() {
+            self.skip(|skipped| skipped.too_many_lines += 1);
+        } else if crate::utils::is_binary_file(&node.path) {
+            self.skip(|skipped| skipped.binary += 1);
+        } else {
+            self.skip(|skipped| skipped.excluded += 1);
         }
     }
+
+    /// Counts one skipped file under the reason `count` increments.
+    pub fn skip(&mut self, count: impl FnOnce(&mut SkippedFiles)) {
+        count(&mut self.skipped);
+        self.skipped_files += 1;
+    }
+}
+
+/// Files left out of a digest's content, by reason.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkippedFiles {
+    pub binary: usize,
+    /// Matched an exclude or ignore pattern, or not an include pattern.
+    pub excluded: usize,
+    /// Larger than `max_file_size`.
+    pub too_large: usize,
+    /// More lines than `max_file_lines`.
+    #[serde(default)]
+    pub too_many_lines: usize,
+    /// Couldn't be inspected, e.g. for lack of permissions.
+    pub read_error: usize,
+    /// Found after `max_files` was reached.
+    pub over_file_limit: usize,
+}
+
+impl SkippedFiles {
+    pub fn total(&self) -> usize {
+        self.binary + self.excluded + self.too_large + self.too_many_lines + self.read_error + self.over_file_limit
+    }
+}
+
+/// Lists the non-zero reasons, e.g. `12 binary, 3 too large, 1 unreadable`.
+impl std::fmt::Display for SkippedFiles {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reasons = [
+            (self.binary, "binary"),
+            (self.excluded, "excluded"),
+            (self.too_large, "too large"),
+            (self.too_many_lines, "too many lines"),
+            (self.read_error, "unreadable"),
+            (self.over_file_limit, "over file limit"),
+        ];
+        let mut first = true;
+        for (count, reason) in reasons {
+            if count == 0 {
+                continue;
+            }
+            if !first {
+                f.write_str(", ")?;
+            }
+            write!(f, "{} {}", count, reason)?;
+            first = false;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -166,6 +1240,102 @@ pub struct ProcessingStats {
     pub total_size_bytes: u64,
     pub estimated_tokens: Option<usize>,
     pub processing_time_ms: u64,
+    #[serde(default)]
+    pub skipped: SkippedFiles,
+    /// Per-language totals, largest first.
+    #[serde(default)]
+    pub language_breakdown: Vec<LanguageStat>,
+    /// Set when only the changes against [`IngestRequest::base_ref`] were
+    /// ingested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<DiffStats>,
+}
+
+/// How a file differs between the base ref and the ingested one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    Deleted,
+    Renamed,
+}
+
+/// A file that differs between two refs, by its repo-relative path in
+/// the ingested ref (or in the base ref, once deleted).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangedFile {
+    pub path: String,
+    pub kind: ChangeKind,
+    /// The path in the base ref, for renames.
+    pub old_path: Option<String>,
+}
+
+/// A file of a [`CloneMode::Blobless`] clone, as listed by `git ls-tree`,
+/// by its repo-relative path.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListedFile {
+    pub path: String,
+    /// `None` when the blob wasn't fetched to size it.
+    pub size: Option<u64>,
+    pub symlink: bool,
+}
+
+/// The refs of a diff ingestion and how many files changed between them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffStats {
+    pub base_ref: String,
+    pub head_ref: String,
+    pub added: usize,
+    pub modified: usize,
+    pub deleted: usize,
+    pub renamed: usize,
+}
+
+impl DiffStats {
+    pub fn new(base_ref: &str, head_ref: &str, changes: &[ChangedFile]) -> Self {
+        let count = |kind| changes.iter().filter(|change| change.kind == kind).count();
+        Self {
+            base_ref: base_ref.to_string(),
+            head_ref: head_ref.to_string(),
+            added: count(ChangeKind::Added),
+            modified: count(ChangeKind::Modified),
+            deleted: count(ChangeKind::Deleted),
+            renamed: count(ChangeKind::Renamed),
+        }
+    }
+}
+
+/// A repository's root license file and its SPDX identifier, or
+/// [`crate::utils::UNRECOGNIZED_LICENSE`] when the text matches none of
+/// the known licenses.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LicenseInfo {
+    pub spdx_id: String,
+    /// Repo-relative path of the license file.
+    pub path: String,
+}
+
+/// Files, bytes and tokens of one language in a digest. Languages are
+/// named by [`crate::utils::language_name`], with `Other` for the rest.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LanguageStat {
+    pub language: String,
+    pub files: usize,
+    pub total_size_bytes: u64,
+    pub tokens: usize,
+    /// Fraction of all counted tokens, from 0 to 1.
+    pub token_share: f64,
+    /// Fraction of all counted bytes, from 0 to 1.
+    pub size_share: f64,
+}
+
+/// How a digest was fitted into [`IngestRequest::max_tokens`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenBudgetOutcome {
+    pub max_tokens: usize,
+    pub used_tokens: usize,
+    pub omitted_files: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -185,6 +1355,17 @@ impl Default for PatternMatcher {
     }
 }
 
+impl PatternMatcher {
+    /// A matcher without the built-in excludes, apart from `.git`.
+    pub fn without_default_excludes() -> Self {
+        Self {
+            include_patterns: Vec::new(),
+            exclude_patterns: vec![".git".to_string()],
+            gitignore_patterns: Vec::new(),
+        }
+    }
+}
+
 fn default_exclude_patterns() -> Vec<String> {
     vec![
         // Version control
@@ -230,4 +1411,185 @@ fn default_exclude_patterns() -> Vec<String> {
         "*.mp3".to_string(),
         "*.wav".to_string(),
     ]
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn file_node(dir: &TempDir, name: &str, content: &[u8]) -> FileNode {
+        let path = dir.path().join(name);
+        std::fs::write(&path, content).unwrap();
+        FileNode {
+            name: name.to_string(),
+            path,
+            relative_path: name.to_string(),
+            node_type: FileNodeType::File,
+            size: content.len() as u64,
+            has_content: true,
+            children: Vec::new(),
+            depth: 0,
+            link_target: None,
+            budget_omitted: false,
+            text_rescued: false,
+            tokens: None,
+            pinned: false,
+            over_line_limit: None,
+            deleted: false,
+            renamed_from: None,
+            last_modified: None,
+        }
+    }
+
+    #[test]
+    fn test_git_ref_keeps_the_repository_json_shape() {
+        let repository: Repository = serde_json::from_str(
+            r#"{"url":"u","host":"h","owner":"o","name":"n","branch":"main","commit":null,"subpath":""}"#,
+        )
+        .unwrap();
+        assert_eq!(repository.git_ref, GitRef::Branch("main".to_string()));
+
+        let json = serde_json::to_value(Repository { git_ref: GitRef::Tag("v1.0".to_string()), ..repository }).unwrap();
+        assert_eq!((json["tag"].as_str(), json["branch"].is_null(), json["commit"].is_null()), (Some("v1.0"), true, true));
+        assert!(json.get("pull_request").is_none());
+        let round_trip: Repository = serde_json::from_value(json).unwrap();
+        assert_eq!(round_trip.git_ref, GitRef::Tag("v1.0".to_string()));
+
+        for git_ref in [GitRef::DefaultBranch, GitRef::Commit("3f2a9bc".to_string()), GitRef::PullRequest(7)] {
+            let json = serde_json::to_string(&git_ref).unwrap();
+            assert_eq!(serde_json::from_str::<GitRef>(&json).unwrap(), git_ref, "{}", json);
+        }
+    }
+
+    fn render(node: &FileNode, max_content_size: u64) -> String {
+        let mut output = Vec::new();
+        node.write_content(&mut output, max_content_size).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_content_at_limit_is_not_truncated() {
+        let temp_dir = TempDir::new().unwrap();
+        let node = file_node(&temp_dir, "exact.txt", &[b'a'; 64]);
+
+        let output = render(&node, 64);
+        assert!(output.contains(&"a".repeat(64)));
+        assert!(!output.contains("truncated"));
+    }
+
+    #[test]
+    fn test_content_over_limit_is_truncated() {
+        let temp_dir = TempDir::new().unwrap();
+        let node = file_node(&temp_dir, "over.txt", &[b'a'; 65]);
+
+        let output = render(&node, 64);
+        assert!(output.contains(&"a".repeat(64)));
+        assert!(!output.contains(&"a".repeat(65)));
+        assert!(output.contains("[Large file content truncated at max_content_size of 64 bytes - showing 64 of 65 bytes]"));
+    }
+
+    #[test]
+    fn test_truncation_does_not_split_multibyte_characters() {
+        let temp_dir = TempDir::new().unwrap();
+        let node = file_node(&temp_dir, "unicode.txt", "aé€".as_bytes());
+
+        // "aé" is 3 bytes, the euro sign would need 3 more
+        let output = render(&node, 4);
+        assert!(output.contains("aé\n"));
+        assert!(output.contains("truncated at max_content_size of 4 bytes - showing 3 of 6 bytes"));
+    }
+
+    fn render_formatted(node: &FileNode, max_content_size: u64, format: &ContentFormat) -> String {
+        let mut output = Vec::new();
+        node.write_content_formatted(&mut output, max_content_size, format, None).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_default_content_format() {
+        let temp_dir = TempDir::new().unwrap();
+        let node = file_node(&temp_dir, "main.rs", b"fn main() {}");
+
+        let expected = format!("main.rs:\n{}\nfn main() {{}}\n\n", "=".repeat(48));
+        assert_eq!(render(&node, 1024), expected);
+        assert_eq!(render_formatted(&node, 1024, &ContentFormat::default()), expected);
+    }
+
+    #[test]
+    fn test_markdown_content_format_fences_with_language() {
+        let temp_dir = TempDir::new().unwrap();
+        let node = file_node(&temp_dir, "main.rs", b"fn main() {}\n");
+        assert_eq!(
+            render_formatted(&node, 1024, &ContentFormat::markdown()),
+            "### main.rs\n```rust\nfn main() {}\n```\n\n"
+        );
+
+        // Backticks in the content get a longer fence; unknown extensions an untagged one
+        let node = file_node(&temp_dir, "notes.unknown", b"```sh\nls\n```");
+        assert_eq!(
+            render_formatted(&node, 1024, &ContentFormat::markdown()),
+            "### notes.unknown\n````\n```sh\nls\n```\n````\n\n"
+        );
+
+        let custom = ContentFormat { header: "<<< {path} >>>".to_string(), separator: String::new(), ..ContentFormat::default() };
+        assert_eq!(render_formatted(&node, 1024, &custom), "<<< notes.unknown >>>\n```sh\nls\n```\n\n");
+    }
+
+    #[test]
+    fn test_line_numbers_and_line_width() {
+        let temp_dir = TempDir::new().unwrap();
+        let lines: Vec<String> = (1..=10).map(|i| if i == 5 { String::new() } else { format!("line {}", i) }).collect();
+        let node = file_node(&temp_dir, "main.rs", lines.join("\n").as_bytes());
+
+        let numbered = ContentFormat { line_numbers: true, ..ContentFormat::default() };
+        let output = render_formatted(&node, 1024, &numbered);
+        assert!(output.contains(&format!("{}\n 1 | line 1\n 2 | line 2\n", "=".repeat(48))), "{}", output);
+        assert!(output.contains("\n 5 |\n"));
+        assert!(output.ends_with("\n10 | line 10\n\n"));
+
+        let node = file_node(&temp_dir, "bundle.js", format!("short\n{}\n", "x".repeat(30)).as_bytes());
+        let cut = ContentFormat { line_numbers: true, max_line_width: Some(10), ..ContentFormat::markdown() };
+        assert_eq!(
+            render_formatted(&node, 1024, &cut),
+            "### bundle.js\n```javascript\n1 | short\n2 | xxxxxxxxxx… [20 more characters]\n```\n\n"
+        );
+    }
+
+    #[test]
+    fn test_entries_are_collected_alongside_content() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut skipped = file_node(&temp_dir, "image.png", &[0x89, b'P']);
+        skipped.has_content = false;
+        let root = FileNode {
+            name: "root".to_string(),
+            path: temp_dir.path().to_path_buf(),
+            relative_path: String::new(),
+            node_type: FileNodeType::Directory,
+            size: 0,
+            has_content: false,
+            children: vec![file_node(&temp_dir, "main.rs", b"fn main() {}"), skipped],
+            depth: 0,
+            link_target: None,
+            budget_omitted: false,
+            text_rescued: false,
+            tokens: None,
+            pinned: false,
+            over_line_limit: None,
+            deleted: false,
+            renamed_from: None,
+            last_modified: None,
+        };
+
+        let mut output = Vec::new();
+        let mut entries = Vec::new();
+        root.write_content_with_entries(&mut output, 1024, Some(&mut entries)).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].relative_path, "main.rs");
+        assert_eq!(entries[0].language.as_deref(), Some("rust"));
+        assert_eq!(entries[0].content.as_deref(), Some("fn main() {}"));
+        assert_eq!(entries[1].content, None);
+        assert_eq!(entries[1].skip_reason.as_deref(), Some("binary"));
+        assert!(String::from_utf8(output).unwrap().contains("fn main() {}"));
+    }
+}
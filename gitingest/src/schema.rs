@@ -0,0 +1,333 @@
+//! Wire format of the JSON digests (`json`, `json-structured` and `jsonl`).
+//!
+//! The JSON output is built from these types rather than by serializing
+//! [`IngestResponse`] directly, so internal models can be renamed or
+//! reordered without changing what consumers parse. Every field name is
+//! pinned explicitly. Any change to the serialized shape must bump
+//! [`SCHEMA_VERSION`] and update the golden files under `tests/golden`.
+
+use crate::models::{
+    FileEntry, FileNodeType, IncompleteOutput, IngestResponse, IngestStatus, LanguageStat, LicenseInfo, TreeEntry,
+};
+use serde::Serialize;
+use uuid::Uuid;
+
+/// Version of the JSON digest schema, emitted as `schema_version`.
+pub const SCHEMA_VERSION: u32 = 9;
+
+#[derive(Debug, Serialize)]
+pub struct ResponseDto<'a> {
+    #[serde(rename = "schema_version")]
+    pub schema_version: u32,
+    #[serde(rename = "id")]
+    pub id: Uuid,
+    #[serde(rename = "repo_url")]
+    pub repo_url: &'a str,
+    #[serde(rename = "short_repo_url")]
+    pub short_repo_url: &'a str,
+    #[serde(rename = "summary")]
+    pub summary: &'a str,
+    #[serde(rename = "digest_url")]
+    pub digest_url: Option<&'a str>,
+    #[serde(rename = "tree")]
+    pub tree: &'a str,
+    #[serde(rename = "content")]
+    pub content: &'a str,
+    #[serde(rename = "status")]
+    pub status: StatusDto,
+    #[serde(rename = "truncated")]
+    pub truncated: bool,
+    #[serde(rename = "files", skip_serializing_if = "Option::is_none")]
+    pub files: Option<Vec<FileDto<'a>>>,
+    #[serde(rename = "file_tree", skip_serializing_if = "Option::is_none")]
+    pub file_tree: Option<TreeDto<'a>>,
+    #[serde(rename = "language_breakdown")]
+    pub language_breakdown: Vec<LanguageStatDto<'a>>,
+    #[serde(rename = "license")]
+    pub license: Option<LicenseDto<'a>>,
+    /// Only present on a digest that was interrupted while being written.
+    #[serde(rename = "incomplete", skip_serializing_if = "Option::is_none")]
+    pub incomplete: Option<IncompleteDto>,
+}
+
+/// First line of a `jsonl` digest: everything but the files, which follow
+/// one [`FileLineDto`] per line.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename = "metadata")]
+pub struct MetadataLineDto<'a> {
+    #[serde(rename = "schema_version")]
+    pub schema_version: u32,
+    #[serde(rename = "id")]
+    pub id: Uuid,
+    #[serde(rename = "repo_url")]
+    pub repo_url: &'a str,
+    #[serde(rename = "short_repo_url")]
+    pub short_repo_url: &'a str,
+    #[serde(rename = "summary")]
+    pub summary: &'a str,
+    #[serde(rename = "tree")]
+    pub tree: &'a str,
+    #[serde(rename = "language_breakdown")]
+    pub language_breakdown: Vec<LanguageStatDto<'a>>,
+    #[serde(rename = "license")]
+    pub license: Option<LicenseDto<'a>>,
+}
+
+/// One file with content in a `jsonl` digest.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename = "file")]
+pub struct FileLineDto<'a> {
+    #[serde(rename = "path")]
+    pub path: &'a str,
+    #[serde(rename = "size")]
+    pub size: u64,
+    #[serde(rename = "language")]
+    pub language: Option<&'a str>,
+    #[serde(rename = "content")]
+    pub content: &'a str,
+    #[serde(rename = "truncated")]
+    pub truncated: bool,
+}
+
+/// Last line of a `jsonl` digest whose files stopped at `max_output_bytes`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename = "truncated")]
+pub struct TruncatedLineDto {
+    #[serde(rename = "max_output_bytes")]
+    pub max_output_bytes: u64,
+    #[serde(rename = "files_omitted")]
+    pub files_omitted: usize,
+}
+
+/// Last line of a `jsonl` digest that was interrupted while being written.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename = "incomplete")]
+pub struct IncompleteLineDto {
+    #[serde(rename = "files_written")]
+    pub files_written: usize,
+    #[serde(rename = "files_total")]
+    pub files_total: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IncompleteDto {
+    #[serde(rename = "files_written")]
+    pub files_written: usize,
+    #[serde(rename = "files_total")]
+    pub files_total: usize,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub enum StatusDto {
+    #[serde(rename = "pending")]
+    Pending,
+    #[serde(rename = "processing")]
+    Processing,
+    #[serde(rename = "completed")]
+    Completed,
+    #[serde(rename = "failed")]
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileDto<'a> {
+    #[serde(rename = "relative_path")]
+    pub relative_path: &'a str,
+    #[serde(rename = "size")]
+    pub size: u64,
+    #[serde(rename = "language")]
+    pub language: Option<&'a str>,
+    #[serde(rename = "content")]
+    pub content: Option<&'a str>,
+    #[serde(rename = "truncated")]
+    pub truncated: bool,
+    #[serde(rename = "skip_reason")]
+    pub skip_reason: Option<&'a str>,
+    #[serde(rename = "tokens")]
+    pub tokens: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LanguageStatDto<'a> {
+    #[serde(rename = "language")]
+    pub language: &'a str,
+    #[serde(rename = "files")]
+    pub files: usize,
+    #[serde(rename = "total_size_bytes")]
+    pub total_size_bytes: u64,
+    #[serde(rename = "tokens")]
+    pub tokens: usize,
+    #[serde(rename = "token_share")]
+    pub token_share: f64,
+    #[serde(rename = "size_share")]
+    pub size_share: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LicenseDto<'a> {
+    #[serde(rename = "spdx_id")]
+    pub spdx_id: &'a str,
+    #[serde(rename = "path")]
+    pub path: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TreeDto<'a> {
+    #[serde(rename = "name")]
+    pub name: &'a str,
+    #[serde(rename = "path")]
+    pub path: &'a str,
+    #[serde(rename = "type")]
+    pub node_type: NodeTypeDto,
+    #[serde(rename = "size")]
+    pub size: u64,
+    #[serde(rename = "has_content")]
+    pub has_content: bool,
+    #[serde(rename = "link_target", skip_serializing_if = "Option::is_none")]
+    pub link_target: Option<String>,
+    #[serde(rename = "last_modified", skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<i64>,
+    #[serde(rename = "children", skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<TreeDto<'a>>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub enum NodeTypeDto {
+    #[serde(rename = "directory")]
+    Directory,
+    #[serde(rename = "file")]
+    File,
+    #[serde(rename = "symlink")]
+    Symlink,
+    /// `link_target` is the submodule's URL.
+    #[serde(rename = "submodule")]
+    Submodule,
+}
+
+impl<'a> From<&'a IngestResponse> for ResponseDto<'a> {
+    fn from(response: &'a IngestResponse) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            id: response.id,
+            repo_url: &response.repo_url,
+            short_repo_url: &response.short_repo_url,
+            summary: &response.summary,
+            digest_url: response.digest_url.as_deref(),
+            tree: &response.tree,
+            content: &response.content,
+            status: response.status.clone().into(),
+            truncated: response.truncated,
+            files: response.files.as_ref().map(|files| files.iter().map(FileDto::from).collect()),
+            file_tree: response.file_tree.as_ref().map(TreeDto::from),
+            language_breakdown: response.language_breakdown.iter().map(LanguageStatDto::from).collect(),
+            license: response.license.as_ref().map(LicenseDto::from),
+            incomplete: None,
+        }
+    }
+}
+
+impl<'a> From<&'a IngestResponse> for MetadataLineDto<'a> {
+    fn from(response: &'a IngestResponse) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            id: response.id,
+            repo_url: &response.repo_url,
+            short_repo_url: &response.short_repo_url,
+            summary: &response.summary,
+            tree: &response.tree,
+            language_breakdown: response.language_breakdown.iter().map(LanguageStatDto::from).collect(),
+            license: response.license.as_ref().map(LicenseDto::from),
+        }
+    }
+}
+
+impl From<IngestStatus> for StatusDto {
+    fn from(status: IngestStatus) -> Self {
+        match status {
+            IngestStatus::Pending => StatusDto::Pending,
+            IngestStatus::Processing => StatusDto::Processing,
+            IngestStatus::Completed => StatusDto::Completed,
+            IngestStatus::Failed => StatusDto::Failed,
+        }
+    }
+}
+
+impl<'a> From<&'a FileEntry> for FileDto<'a> {
+    fn from(entry: &'a FileEntry) -> Self {
+        Self {
+            relative_path: &entry.relative_path,
+            size: entry.size,
+            language: entry.language.as_deref(),
+            content: entry.content.as_deref(),
+            truncated: entry.truncated,
+            skip_reason: entry.skip_reason.as_deref(),
+            tokens: entry.tokens,
+        }
+    }
+}
+
+impl<'a> From<&'a LanguageStat> for LanguageStatDto<'a> {
+    fn from(stat: &'a LanguageStat) -> Self {
+        Self {
+            language: &stat.language,
+            files: stat.files,
+            total_size_bytes: stat.total_size_bytes,
+            tokens: stat.tokens,
+            token_share: stat.token_share,
+            size_share: stat.size_share,
+        }
+    }
+}
+
+impl From<IncompleteOutput> for IncompleteDto {
+    fn from(incomplete: IncompleteOutput) -> Self {
+        Self {
+            files_written: incomplete.files_written,
+            files_total: incomplete.files_total,
+        }
+    }
+}
+
+impl From<IncompleteOutput> for IncompleteLineDto {
+    fn from(incomplete: IncompleteOutput) -> Self {
+        Self {
+            files_written: incomplete.files_written,
+            files_total: incomplete.files_total,
+        }
+    }
+}
+
+impl<'a> From<&'a LicenseInfo> for LicenseDto<'a> {
+    fn from(license: &'a LicenseInfo) -> Self {
+        Self {
+            spdx_id: &license.spdx_id,
+            path: &license.path,
+        }
+    }
+}
+
+impl<'a> From<&'a TreeEntry> for TreeDto<'a> {
+    fn from(entry: &'a TreeEntry) -> Self {
+        Self {
+            name: &entry.name,
+            path: &entry.path,
+            node_type: entry.node_type.into(),
+            size: entry.size,
+            has_content: entry.has_content,
+            link_target: entry.link_target.as_ref().map(|target| target.to_string_lossy().into_owned()),
+            last_modified: entry.last_modified,
+            children: entry.children.iter().map(TreeDto::from).collect(),
+        }
+    }
+}
+
+impl From<FileNodeType> for NodeTypeDto {
+    fn from(node_type: FileNodeType) -> Self {
+        match node_type {
+            FileNodeType::Directory => NodeTypeDto::Directory,
+            FileNodeType::File => NodeTypeDto::File,
+            FileNodeType::Symlink => NodeTypeDto::Symlink,
+            FileNodeType::Submodule => NodeTypeDto::Submodule,
+        }
+    }
+}
@@ -1,49 +1,936 @@
-use crate::config::AppConfig;
+use crate::config::{AppConfig, BranchConflictPolicy};
 use crate::error::{GitingestError, Result};
-use crate::models::{CloneConfig, IngestRequest, IngestResponse, IngestStatus, PatternMatcher, ProcessingResult, ProcessingStats};
-use crate::utils::{FileService, GitService, PatternService, UrlParser, format_file_size};
-use std::time::Instant;
+use crate::formats;
+use crate::models::{
+    ChangedFile, CloneConfig, CloneMode, CloneOutcome, ContentFormat, ContentWriteOutcome, DiffStats, DirectoryPart, DownloadFormat, FileNode, IncompleteOutput, IngestEvent, IngestFailure, IngestPhase, IngestRequest, LanguageStat, LicenseInfo,
+    FileSystemStats, GitRef, IngestResponse, IngestStatus, IngestSummary, ListedFile, MissingFilePolicy, PatternMatcher, PhaseTimings, ProcessingStats,
+    Repository, RetryPolicy, ScanConfig, SortOrder, SplitManifest, SshAuth, TargetKind, TokenBudgetOutcome,
+};
+#[cfg(feature = "git")]
+use crate::utils::{CacheLimits, CacheLock, CloneCache, GitService};
+use crate::utils::{
+    FileService, HeuristicTokenCounter, INGEST_IGNORE_FILE, PatternService, TokenCounter, UrlParser, apply_token_budget,
+    count_tree_tokens, default_token_counter, detect_license, format_file_size, validate_token,
+};
+use futures::stream::{self, StreamExt};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use tempfile::TempDir;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
 pub struct IngestService;
 
+/// Upper bound on file reads in flight while writing content.
+const CONTENT_READ_AHEAD: usize = 64;
+
+/// Files listed under "Recently changed" in the summary.
+const RECENTLY_CHANGED_FILES: usize = 5;
+
+/// Counts the bytes passed through to the inner writer.
+struct CountingWriter<'a> {
+    inner: &'a mut dyn Write,
+    written: u64,
+}
+
+impl Write for CountingWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A cloned and scanned repository, kept alive until its outputs are written.
+struct ScannedRepository {
+    repository: Repository,
+    _workspace: Workspace,
+    local_path: PathBuf,
+    file_tree: FileNode,
+    clone_outcome: CloneOutcome,
+    /// Listed files that were not found, when reported as warnings.
+    missing_files: Vec<String>,
+    scan_stats: FileSystemStats,
+    /// Set when the request had a `max_tokens` budget.
+    token_budget: Option<TokenBudgetOutcome>,
+    language_breakdown: Vec<LanguageStat>,
+    /// Set for [`IngestRequest::dry_run`]; no file content is written.
+    dry_run: bool,
+    /// Set for a [`CloneMode::Blobless`] clone, whose file sizes (and so
+    /// token estimates) were never fetched.
+    sizes_unknown: bool,
+    /// The root license file; not looked for in a dry run.
+    license: Option<LicenseInfo>,
+    /// Order of the content section; `None` keeps tree (or listed) order.
+    content_order: Option<SortOrder>,
+    content_format: ContentFormat,
+    /// Bytes of each file's content written before it is truncated.
+    max_content_size: u64,
+    /// Bytes of file contents written before the remaining files are left out.
+    max_output_bytes: u64,
+    /// The local archive ingested in place of a repository.
+    archive: Option<PathBuf>,
+    /// Set when a local directory was scanned where it is, without a clone.
+    in_place: bool,
+    /// The directory of a tree/ URL the scan was narrowed to.
+    subpath: Option<String>,
+    /// Set when only the changes against [`IngestRequest::base_ref`] were
+    /// scanned.
+    diff: Option<DiffStats>,
+    clone_duration: Duration,
+    scan_duration: Duration,
+}
+
+/// Where a repository is checked out while it is ingested.
+enum Workspace {
+    /// A local directory scanned in place; never removed.
+    InPlace,
+    /// A fresh clone, removed when dropped.
+    Temp { _dir: TempDir },
+    /// An entry of the clone cache, locked against concurrent ingestions
+    /// until dropped.
+    #[cfg(feature = "git")]
+    Cached { _lock: CacheLock },
+}
+
+/// How far an ingestion got, so a failure can report where it stopped.
+struct IngestProgress {
+    start: Instant,
+    phase: IngestPhase,
+    repo_url: Option<String>,
+    timings: PhaseTimings,
+    warnings: Vec<String>,
+    incomplete_output: Option<IncompleteOutput>,
+    events: Option<mpsc::Sender<IngestEvent>>,
+}
+
+impl IngestProgress {
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            phase: IngestPhase::Parse,
+            repo_url: None,
+            timings: PhaseTimings::default(),
+            warnings: Vec::new(),
+            incomplete_output: None,
+            events: None,
+        }
+    }
+
+    /// Moves on to `phase`, telling the event listener if there is one.
+    async fn enter(&mut self, phase: IngestPhase) {
+        self.phase = phase;
+        if let Some(events) = &self.events {
+            let _ = events.send(IngestEvent::Phase { phase }).await;
+        }
+    }
+
+    fn into_failure(self, id: Uuid, input_text: String, err: GitingestError) -> IngestFailure {
+        IngestFailure {
+            id,
+            repo_url: self.repo_url.unwrap_or(input_text),
+            status: IngestStatus::Failed,
+            phase: self.phase,
+            error_code: err.code().to_string(),
+            message: err.to_string(),
+            timings: PhaseTimings {
+                total_ms: self.start.elapsed().as_millis() as u64,
+                ..self.timings
+            },
+            warnings: self.warnings,
+            incomplete_output: self.incomplete_output,
+        }
+    }
+}
+
 impl IngestService {
+    /// Convenience wrapper around [`IngestService::process_repository`] for
+    /// callers that don't track their own request ids.
+    pub async fn ingest(request: IngestRequest, config: &AppConfig) -> Result<IngestResponse> {
+        Self::process_repository(request, config, Uuid::new_v4()).await
+    }
+
+    /// Blocking [`IngestService::ingest`] for synchronous callers. The run
+    /// gets a runtime of its own, on a separate thread when the caller is
+    /// already inside one, so it can't stall or panic an enclosing runtime.
+    pub fn ingest_blocking(request: IngestRequest, config: &AppConfig) -> Result<IngestResponse> {
+        let run = move || -> Result<IngestResponse> {
+            let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+            runtime.block_on(Self::ingest(request, config))
+        };
+        if tokio::runtime::Handle::try_current().is_err() {
+            return run();
+        }
+        std::thread::scope(|scope| scope.spawn(run).join()).unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    }
+
+    /// Streams the digest into a new file at `path` through
+    /// [`IngestService::process_repository_to_writer`], so memory stays
+    /// bounded by the largest file rather than the whole digest. The file is
+    /// removed again if the run fails.
+    pub async fn ingest_to_file(request: IngestRequest, config: &AppConfig, path: &Path) -> Result<IngestSummary> {
+        let mut writer = BufWriter::new(std::fs::File::create(path)?);
+        let result =
+            Self::process_repository_to_writer(request, config, Uuid::new_v4(), &mut writer, CancellationToken::new())
+                .await;
+        let result = result.and_then(|summary| {
+            writer.flush()?;
+            Ok(summary)
+        });
+        if result.is_err() {
+            drop(writer);
+            let _ = std::fs::remove_file(path);
+        }
+        result
+    }
+
+    /// Ingests `requests` with up to `parallelism` of them running at once.
+    /// Results come back in request order, and a failed request doesn't
+    /// stop the others. Each run removes its clone as soon as its digest is
+    /// built, so no more than `parallelism` clones are on disk at a time.
+    pub async fn process_repositories(
+        requests: Vec<IngestRequest>,
+        config: &AppConfig,
+        parallelism: usize,
+    ) -> Vec<Result<IngestResponse>> {
+        stream::iter(requests)
+            .map(|request| Self::ingest(request, config))
+            .buffered(parallelism.max(1))
+            .collect()
+            .await
+    }
+
+    /// Like [`IngestService::process_repositories`], but streams each digest
+    /// to its paired path through [`IngestService::ingest_to_file`], so
+    /// only the file being written is held in memory.
+    pub async fn ingest_many_to_files(
+        jobs: Vec<(IngestRequest, PathBuf)>,
+        config: &AppConfig,
+        parallelism: usize,
+    ) -> Vec<Result<IngestSummary>> {
+        stream::iter(jobs)
+            .map(|(request, path)| async move { Self::ingest_to_file(request, config, &path).await })
+            .buffered(parallelism.max(1))
+            .collect()
+            .await
+    }
+
+    /// Clones and scans like a dry run, but returns only the rendered tree
+    /// and the scan's statistics; no file contents are read. With `sizes`,
+    /// files are annotated with their size instead of a token estimate.
+    pub async fn scan_only(request: IngestRequest, config: &AppConfig, sizes: bool) -> Result<(String, ProcessingStats)> {
+        let start_time = Instant::now();
+        let request = IngestRequest { dry_run: Some(true), ..request };
+        let scanned = Self::clone_and_scan(request, config, &CancellationToken::new(), &mut IngestProgress::new()).await?;
+        let file_tree = &scanned.file_tree;
+        let tree = if sizes {
+            FileService::generate_sized_tree_string(file_tree, "", true)
+        } else {
+            FileService::generate_tree_string(file_tree, "", true)
+        };
+        let stats = ProcessingStats {
+            files_analyzed: Self::count_files(file_tree),
+            total_size_bytes: Self::calculate_total_size(file_tree),
+            estimated_tokens: (!scanned.sizes_unknown)
+                .then(|| scanned.language_breakdown.iter().map(|stat| stat.tokens).sum()),
+            processing_time_ms: start_time.elapsed().as_millis() as u64,
+            skipped: scanned.scan_stats.skipped,
+            language_breakdown: scanned.language_breakdown.clone(),
+            diff: scanned.diff.clone(),
+        };
+        Ok((tree, stats))
+    }
+
     pub async fn process_repository(
         request: IngestRequest,
         config: &AppConfig,
+        id: Uuid,
+    ) -> Result<IngestResponse> {
+        Self::process_repository_cancellable(request, config, id, CancellationToken::new()).await
+    }
+
+    /// Like [`IngestService::process_repository`], but reports progress to
+    /// `events` as it goes: each phase as it starts, and the scan's file
+    /// counts batch by batch. A dropped receiver doesn't stop the run.
+    pub async fn process_repository_with_progress(
+        request: IngestRequest,
+        config: &AppConfig,
+        id: Uuid,
+        events: mpsc::Sender<IngestEvent>,
+    ) -> Result<IngestResponse> {
+        let mut progress = IngestProgress { events: Some(events), ..IngestProgress::new() };
+        progress.enter(IngestPhase::Parse).await;
+        Self::run_in_memory(request, config, id, CancellationToken::new(), &mut progress).await
+    }
+
+    /// Like [`IngestService::process_repository`], but stops with
+    /// [`GitingestError::Cancelled`] once `cancel` fires. The git child is
+    /// killed and the temporary clone removed before returning.
+    pub async fn process_repository_cancellable(
+        request: IngestRequest,
+        config: &AppConfig,
+        id: Uuid,
+        cancel: CancellationToken,
+    ) -> Result<IngestResponse> {
+        Self::run_in_memory(request, config, id, cancel, &mut IngestProgress::new()).await
+    }
+
+    /// Like [`IngestService::process_repository_cancellable`], but reports
+    /// errors as an [`IngestFailure`] carrying the phase that failed, the
+    /// timings of the phases before it, and any warnings raised so far.
+    pub async fn try_process_repository(
+        request: IngestRequest,
+        config: &AppConfig,
+        id: Uuid,
+        cancel: CancellationToken,
+    ) -> std::result::Result<IngestResponse, IngestFailure> {
+        let input_text = request.input_text.clone();
+        let mut progress = IngestProgress::new();
+        let result = Self::run_in_memory(request, config, id, cancel, &mut progress).await;
+        result.map_err(|err| progress.into_failure(id, input_text, err))
+    }
+
+    async fn run_in_memory(
+        request: IngestRequest,
+        config: &AppConfig,
+        id: Uuid,
+        cancel: CancellationToken,
+        progress: &mut IngestProgress,
     ) -> Result<IngestResponse> {
         let start_time = Instant::now();
-        let id = Uuid::new_v4();
+        let format = request.download_format.clone();
         
-        // Parse the repository URL
-        let repository = UrlParser::parse_git_url(&request.input_text)?;
+        let scanned = Self::clone_and_scan(request, config, &cancel, progress).await?;
+        progress.enter(IngestPhase::Content).await;
+        if cancel.is_cancelled() {
+            return Err(GitingestError::Cancelled("content".to_string()));
+        }
+        let content_start = Instant::now();
+        let response = Self::build_response(&scanned, format.as_ref(), config, id, start_time).await?;
+        progress.timings.content_ms = Some(content_start.elapsed().as_millis() as u64);
+        Ok(response)
+    }
+
+    /// Everything in a response except its content and per-file entries.
+    fn response_skeleton(scanned: &ScannedRepository, format: Option<&DownloadFormat>, id: Uuid) -> IngestResponse {
+        // A dry run reports per-file sizes through the tree instead of content
+        let include_tree = scanned.dry_run || matches!(format, Some(DownloadFormat::Json | DownloadFormat::JsonStructured));
+        let file_tree = &scanned.file_tree;
+        let files_analyzed = Self::count_files(file_tree);
+        let total_size_bytes = Self::calculate_total_size(file_tree);
+
+        IngestResponse {
+            id,
+            repo_url: scanned.repository.url.clone(),
+            short_repo_url: Self::create_short_url(scanned),
+            summary: Self::generate_summary(scanned, files_analyzed, total_size_bytes),
+            digest_url: None, // Would be implemented for actual digest storage
+            tree: FileService::generate_tree_string(file_tree, "", true),
+            content: String::new(),
+            status: IngestStatus::Completed,
+            truncated: false,
+            files: None,
+            file_tree: include_tree.then(|| FileService::build_tree_entry(file_tree, &scanned.local_path)),
+            language_breakdown: scanned.language_breakdown.clone(),
+            license: scanned.license.clone(),
+        }
+    }
+
+    /// Renders a scanned repository into an in-memory response. Per-file
+    /// entries and the structured tree are only built for the formats that
+    /// use them.
+    async fn build_response(
+        scanned: &ScannedRepository,
+        format: Option<&DownloadFormat>,
+        config: &AppConfig,
+        id: Uuid,
+        start_time: Instant,
+    ) -> Result<IngestResponse> {
+        let collect_files = !scanned.dry_run && matches!(format, Some(DownloadFormat::JsonStructured | DownloadFormat::Xml));
+
+        log::info!("Starting tree generation...");
+        let generation_start = Instant::now();
+        let mut response = Self::response_skeleton(scanned, format, id);
+        let generation_duration = generation_start.elapsed();
+        log::info!("Tree generation completed in {:.2}s", generation_duration.as_secs_f64());
         
-        // Create temporary directory for cloning
-        let temp_dir = TempDir::new()
-            .map_err(|e| GitingestError::FileSystemError(format!("Failed to create temp dir: {}", e)))?;
+        // Write content straight into the response buffer
+        log::info!("Starting content write...");
+        let content_start = Instant::now();
+        let mut buffer = Vec::new();
+        let mut entries = Vec::new();
+        if !scanned.dry_run {
+            let outcome = scanned.file_tree.write_content_async(
+                &mut buffer,
+                scanned.max_content_size,
+                scanned.max_output_bytes,
+                Self::read_ahead(config),
+                scanned.content_order,
+                &scanned.content_format,
+                None,
+                collect_files.then_some(&mut entries),
+            ).await?;
+            response.truncated = outcome.truncated;
+        }
+        response.files = collect_files.then_some(entries);
+        response.content = String::from_utf8(buffer)
+            .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned());
+        let content_duration = content_start.elapsed();
+        log::info!("Content write completed in {:.2}s", content_duration.as_secs_f64());
         
-        let local_path = temp_dir.path().join(&repository.name);
+        let total_processing_time = start_time.elapsed();
+        log::info!(
+            "Repository ingestion completed successfully - Total time: {:.2}s (Clone: {:.2}s, Scan: {:.2}s, Tree: {:.2}s, Content: {:.2}s)", 
+            total_processing_time.as_secs_f64(),
+            scanned.clone_duration.as_secs_f64(),
+            scanned.scan_duration.as_secs_f64(),
+            generation_duration.as_secs_f64(),
+            content_duration.as_secs_f64()
+        );
+        
+        Ok(response)
+    }
+
+    /// Streams the digest for `format` into `writer` instead of building it
+    /// in memory, returning only an [`IngestSummary`]. Text, markdown and
+    /// JSON are written file by file, flushing after each; XML needs the
+    /// whole response and is rendered in memory first. A run cancelled
+    /// while writing contents closes the digest off as incomplete (see
+    /// [`IncompleteOutput`]) before returning [`GitingestError::Cancelled`].
+    pub async fn process_repository_to_writer(
+        request: IngestRequest,
+        config: &AppConfig,
+        id: Uuid,
+        writer: &mut dyn Write,
+        cancel: CancellationToken,
+    ) -> Result<IngestSummary> {
+        Self::run_to_writer(request, config, id, writer, cancel, &mut IngestProgress::new()).await
+    }
+
+    /// [`IngestService::process_repository_to_writer`] reporting errors as
+    /// an [`IngestFailure`]; see [`IngestService::try_process_repository`].
+    pub async fn try_process_repository_to_writer(
+        request: IngestRequest,
+        config: &AppConfig,
+        id: Uuid,
+        writer: &mut dyn Write,
+        cancel: CancellationToken,
+    ) -> std::result::Result<IngestSummary, IngestFailure> {
+        let input_text = request.input_text.clone();
+        let mut progress = IngestProgress::new();
+        let result = Self::run_to_writer(request, config, id, writer, cancel, &mut progress).await;
+        result.map_err(|err| progress.into_failure(id, input_text, err))
+    }
+
+    async fn run_to_writer(
+        request: IngestRequest,
+        config: &AppConfig,
+        id: Uuid,
+        writer: &mut dyn Write,
+        cancel: CancellationToken,
+        progress: &mut IngestProgress,
+    ) -> Result<IngestSummary> {
+        let start_time = Instant::now();
+        let format = request.download_format.clone().unwrap_or(DownloadFormat::Text);
+
+        let scanned = Self::clone_and_scan(request, config, &cancel, progress).await?;
+        progress.enter(IngestPhase::Content).await;
+        if cancel.is_cancelled() {
+            return Err(GitingestError::Cancelled("content".to_string()));
+        }
+        let content_start = Instant::now();
+        let repository = &scanned.repository;
+        let file_tree = &scanned.file_tree;
+
+        let files_analyzed = Self::count_files(file_tree);
+        let total_size_bytes = Self::calculate_total_size(file_tree);
+        let short_repo_url = Self::create_short_url(&scanned);
+
+        let mut outcome = ContentWriteOutcome { files_written: files_analyzed, ..Default::default() };
+        let (summary, content_len) = match format {
+            DownloadFormat::Text | DownloadFormat::Markdown => {
+                let summary = Self::generate_summary(&scanned, files_analyzed, total_size_bytes);
+                let tree = FileService::generate_tree_string(file_tree, "", true);
+
+                // The header goes out before any file is read
+                formats::write_prelude(writer, &format, &short_repo_url, &summary, &tree)?;
+                writer.flush()?;
+                let mut content_writer = CountingWriter { inner: writer, written: 0 };
+                if !scanned.dry_run {
+                    outcome = file_tree
+                        .write_content_async(
+                            &mut content_writer,
+                            scanned.max_content_size,
+                            scanned.max_output_bytes,
+                            Self::read_ahead(config),
+                            scanned.content_order,
+                            &scanned.content_format,
+                            Some(&cancel),
+                            None,
+                        )
+                        .await?;
+                }
+                let content_len = content_writer.written as usize;
+                if let Some(incomplete) = Self::incomplete_output(&outcome, files_analyzed) {
+                    formats::write_incomplete_trailer(writer, &format, &incomplete)?;
+                }
+                (summary, content_len)
+            }
+            DownloadFormat::JsonLines => {
+                let response = Self::response_skeleton(&scanned, Some(&format), id);
+                formats::write_json_lines_metadata(writer, &response)?;
+                writer.flush()?;
+                let mut content_writer = CountingWriter { inner: writer, written: 0 };
+                if !scanned.dry_run {
+                    outcome = file_tree
+                        .write_content_async(
+                            &mut content_writer,
+                            scanned.max_content_size,
+                            scanned.max_output_bytes,
+                            Self::read_ahead(config),
+                            scanned.content_order,
+                            &scanned.content_format,
+                            Some(&cancel),
+                            None,
+                        )
+                        .await?;
+                }
+                let content_len = content_writer.written as usize;
+                if let Some(incomplete) = Self::incomplete_output(&outcome, files_analyzed) {
+                    formats::write_incomplete_trailer(writer, &format, &incomplete)?;
+                }
+                (response.summary, content_len)
+            }
+            DownloadFormat::Json | DownloadFormat::JsonStructured => {
+                // Everything but the content string is known up front, so
+                // the document is written around it as the files are read
+                let mut response = Self::response_skeleton(&scanned, Some(&format), id);
+                let (head, _) = formats::render_json_around_content(&response, None)?;
+                writer.write_all(head.as_bytes())?;
+                writer.flush()?;
+
+                let collect_files = !scanned.dry_run && matches!(format, DownloadFormat::JsonStructured);
+                let mut entries = Vec::new();
+                let mut content_writer = CountingWriter { inner: writer, written: 0 };
+                if !scanned.dry_run {
+                    let mut escaped = formats::JsonStringWriter::new(&mut content_writer);
+                    outcome = file_tree
+                        .write_content_async(
+                            &mut escaped,
+                            scanned.max_content_size,
+                            scanned.max_output_bytes,
+                            Self::read_ahead(config),
+                            scanned.content_order,
+                            &scanned.content_format,
+                            Some(&cancel),
+                            collect_files.then_some(&mut entries),
+                        )
+                        .await?;
+                }
+                let content_len = content_writer.written as usize;
+
+                response.files = collect_files.then_some(entries);
+                response.truncated = outcome.truncated;
+                let incomplete = Self::incomplete_output(&outcome, files_analyzed);
+                let (_, tail) = formats::render_json_around_content(&response, incomplete)?;
+                writer.write_all(tail.as_bytes())?;
+                (response.summary, content_len)
+            }
+            DownloadFormat::Xml => {
+                // XML needs the whole response before anything is written
+                let response = Self::build_response(&scanned, Some(&format), config, id, start_time).await?;
+                writer.write_all(response.render(&format)?.as_bytes())?;
+                outcome.truncated = response.truncated;
+                (response.summary, response.content.len())
+            }
+        };
+        writer.flush()?;
+        if let Some(incomplete) = Self::incomplete_output(&outcome, files_analyzed) {
+            // What was written stays usable; the caller decides whether to keep it
+            progress.incomplete_output = Some(incomplete);
+            return Err(GitingestError::Cancelled("content".to_string()));
+        }
+        progress.timings.content_ms = Some(content_start.elapsed().as_millis() as u64);
+
+        log::info!(
+            "Streaming ingestion completed in {:.2}s",
+            start_time.elapsed().as_secs_f64()
+        );
+
+        Ok(IngestSummary {
+            id,
+            repo_url: repository.url.clone(),
+            short_repo_url,
+            summary,
+            stats: ProcessingStats {
+                files_analyzed,
+                total_size_bytes,
+                estimated_tokens: if scanned.sizes_unknown {
+                    None
+                } else if scanned.dry_run {
+                    Some(scanned.language_breakdown.iter().map(|stat| stat.tokens).sum())
+                } else {
+                    Self::estimate_tokens(content_len)
+                },
+                processing_time_ms: start_time.elapsed().as_millis() as u64,
+                skipped: scanned.scan_stats.skipped,
+                language_breakdown: scanned.language_breakdown.clone(),
+                diff: scanned.diff.clone(),
+            },
+            timings: PhaseTimings {
+                total_ms: progress.start.elapsed().as_millis() as u64,
+                ..progress.timings.clone()
+            },
+            truncated: outcome.truncated,
+        })
+    }
+
+    /// How far a cancelled content write got; `None` when every file was
+    /// written or the rest were left out by `max_output_bytes`.
+    fn incomplete_output(outcome: &ContentWriteOutcome, files_total: usize) -> Option<IncompleteOutput> {
+        (!outcome.truncated && outcome.files_written < files_total)
+            .then_some(IncompleteOutput { files_written: outcome.files_written, files_total })
+    }
+
+    /// Ingests a repository into one output file per directory at `depth`,
+    /// plus `_root.txt` for files that live above that depth. Each part is
+    /// streamed straight to disk; the returned manifest maps directories to
+    /// their output files.
+    pub async fn process_repository_split(
+        request: IngestRequest,
+        config: &AppConfig,
+        id: Uuid,
+        depth: usize,
+        output_dir: &Path,
+        cancel: CancellationToken,
+    ) -> Result<SplitManifest> {
+        Self::run_split(request, config, id, depth, output_dir, cancel, &mut IngestProgress::new()).await
+    }
+
+    /// [`IngestService::process_repository_split`] reporting errors as an
+    /// [`IngestFailure`]; see [`IngestService::try_process_repository`].
+    pub async fn try_process_repository_split(
+        request: IngestRequest,
+        config: &AppConfig,
+        id: Uuid,
+        depth: usize,
+        output_dir: &Path,
+        cancel: CancellationToken,
+    ) -> std::result::Result<SplitManifest, IngestFailure> {
+        let input_text = request.input_text.clone();
+        let mut progress = IngestProgress::new();
+        let result = Self::run_split(request, config, id, depth, output_dir, cancel, &mut progress).await;
+        result.map_err(|err| progress.into_failure(id, input_text, err))
+    }
+
+    async fn run_split(
+        request: IngestRequest,
+        config: &AppConfig,
+        id: Uuid,
+        depth: usize,
+        output_dir: &Path,
+        cancel: CancellationToken,
+        progress: &mut IngestProgress,
+    ) -> Result<SplitManifest> {
+        let start_time = Instant::now();
+        if request.dry_run.unwrap_or(false) || request.clone_mode == Some(CloneMode::Blobless) {
+            return Err(GitingestError::ValidationError(
+                "dry_run and blobless clones are not supported for split output".to_string(),
+            ));
+        }
+
+        let scanned = Self::clone_and_scan(request, config, &cancel, progress).await?;
+        progress.enter(IngestPhase::Content).await;
+        let content_start = Instant::now();
+        let repository = &scanned.repository;
+        let file_tree = &scanned.file_tree;
+
+        std::fs::create_dir_all(output_dir)?;
+
+        let mut parts = Vec::new();
+        for (directory, node) in FileService::partition_by_directory(file_tree, depth) {
+            if cancel.is_cancelled() {
+                return Err(GitingestError::Cancelled("content".to_string()));
+            }
+            let key = if directory.is_empty() { "_root".to_string() } else { directory.clone() };
+            let output = PathBuf::from(format!("{}.txt", key));
+            let output_path = output_dir.join(&output);
+            if let Some(parent) = output_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+
+            let files = Self::count_files(&node);
+            let total_size_bytes = Self::calculate_total_size(&node);
+            Self::write_part(
+                &output_path,
+                &key,
+                &node,
+                files,
+                total_size_bytes,
+                &scanned,
+                config,
+            ).await?;
+            // Counted like the digest's languages, so the parts add up to its total
+            let estimated_tokens = FileService::language_breakdown(&node).iter().map(|stat| stat.tokens).sum();
+
+            parts.push(DirectoryPart {
+                directory: key,
+                output,
+                files,
+                total_size_bytes,
+                estimated_tokens,
+            });
+        }
+
+        let files_analyzed = Self::count_files(file_tree);
+        let total_size_bytes = Self::calculate_total_size(file_tree);
+        progress.timings.content_ms = Some(content_start.elapsed().as_millis() as u64);
+        log::info!(
+            "Split ingestion completed in {:.2}s - {} parts written to {:?}",
+            start_time.elapsed().as_secs_f64(),
+            parts.len(),
+            output_dir
+        );
+
+        Ok(SplitManifest {
+            id,
+            repo_url: repository.url.clone(),
+            short_repo_url: Self::create_short_url(&scanned),
+            summary: Self::generate_summary(&scanned, files_analyzed, total_size_bytes),
+            depth,
+            parts,
+        })
+    }
+
+    async fn write_part(
+        output_path: &Path,
+        directory: &str,
+        node: &FileNode,
+        files: usize,
+        total_size: u64,
+        scanned: &ScannedRepository,
+        config: &AppConfig,
+    ) -> Result<()> {
+        let mut writer = BufWriter::new(std::fs::File::create(output_path)?);
+        write!(
+            writer,
+            "Directory: {}\nFiles: {}\nTotal size: {}\n\nDirectory Structure:\n{}\nFile Contents:\n",
+            directory,
+            files,
+            format_file_size(total_size),
+            FileService::generate_tree_string(node, "", true)
+        )?;
+
+        node.write_content_async(
+            &mut writer,
+            scanned.max_content_size,
+            scanned.max_output_bytes,
+            Self::read_ahead(config),
+            scanned.content_order,
+            &scanned.content_format,
+            None,
+            None,
+        )
+        .await?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    async fn clone_and_scan(
+        mut request: IngestRequest,
+        config: &AppConfig,
+        cancel: &CancellationToken,
+        progress: &mut IngestProgress,
+    ) -> Result<ScannedRepository> {
+        // Archives are extracted in place of a clone and local directories
+        // scanned where they are; anything else is a URL
+        let archive = Self::archive_input(&request.input_text);
+        let local_dir = match archive {
+            Some(_) => None,
+            None => UrlParser::local_directory(&request.input_text),
+        };
+        if let Some(path) = archive.as_ref().or(local_dir.as_ref())
+            && !config.allow_local_inputs
+        {
+            return Err(GitingestError::ValidationError(format!(
+                "Local paths such as {} are not accepted unless ALLOW_LOCAL_INPUTS is set",
+                path.display()
+            )));
+        }
+        let mut repository = match (&archive, &local_dir) {
+            (Some(path), _) => Self::archive_repository(path, &request)?,
+            (None, Some(path)) => Self::local_repository(path, &request)?,
+            (None, None) => {
+                let repository = UrlParser::parse_git_url_with(&request.input_text, config)?;
+                // Local file:// clones have no host to restrict, only the opt-in
+                if !repository.url.starts_with("file://") {
+                    config.check_host_allowed(&repository.host)?;
+                } else if !config.allow_local_inputs {
+                    return Err(GitingestError::ValidationError(format!(
+                        "Local repositories such as {} are not accepted unless ALLOW_LOCAL_INPUTS is set",
+                        repository.url
+                    )));
+                }
+                repository
+            }
+        };
+        progress.repo_url = Some(repository.url.clone());
+        if config.https_only && !repository.url.starts_with("https://") {
+            // Local directories and archives have their path as URL
+            return Err(GitingestError::ValidationError(format!(
+                "Only https:// repository URLs are accepted, not {}",
+                repository.url
+            )));
+        }
+        let mut token = request.token.clone().or_else(|| config.token_for_host(&repository.host).map(str::to_string));
+        let ssh = SshAuth {
+            key_path: request.ssh_key_path.clone().or_else(|| config.ssh_key_path.clone()),
+            known_hosts_policy: request.ssh_known_hosts_policy.or(config.ssh_known_hosts_policy),
+        };
+        if archive.is_none() && local_dir.is_none() {
+            ssh.validate(&repository.url)?;
+            if token.is_none() {
+                token = Self::credential_helper_token(&repository.url, cancel).await;
+            } else if let Some(token) = &token
+                && let Err(err) = validate_token(&repository.host, token)
+            {
+                // Token formats change; let the host be the judge
+                log::warn!("{}", err);
+            }
+            // Self-hosted instances may want another username than their name suggests
+            token = token.map(|token| config.qualify_token(&repository.host, &token));
+            if !config.skip_existence_check && !repository.url.starts_with("file://") {
+                Self::check_repository_exists(&repository, token.as_deref(), &ssh, config, cancel).await?;
+            }
+            if let Some(branch) = &request.branch {
+                UrlParser::apply_branch(&mut repository, branch);
+            } else {
+                Self::resolve_url_ref(&mut repository, token.as_deref(), &ssh, cancel).await?;
+            }
+            repository.git_ref = Self::resolve_ref(
+                request.branch,
+                std::mem::take(&mut repository.git_ref),
+                config.branch_conflict_policy,
+                &mut progress.warnings,
+            )?;
+        }
+        // A blob URL names one file: fetch and scan just that, as if listed
+        let target_file = (repository.target_kind == Some(TargetKind::File)
+            && !repository.subpath.is_empty()
+            && request.files.is_none()
+            && request.base_ref.is_none()
+            && !request.api.unwrap_or(false))
+        .then(|| repository.subpath.clone());
+        if let Some(path) = &target_file {
+            request.files = Some(vec![path.clone()]);
+        }
+        
+        if let Some(files) = &request.files {
+            Self::validate_listed_files(files)?;
+        }
+        let dry_run = request.dry_run.unwrap_or(false);
+        // Listed files keep the caller's order unless a sort is asked for
+        let content_order = match (request.sort, &request.files) {
+            (None, Some(_)) => None,
+            (sort, _) => Some(sort.unwrap_or_default()),
+        };
+        let mut content_format = request.content_format.clone().unwrap_or_else(|| match request.download_format {
+            Some(DownloadFormat::Markdown) => ContentFormat::markdown(),
+            _ => ContentFormat::default(),
+        });
+        if let Some(line_numbers) = request.line_numbers {
+            content_format.line_numbers = line_numbers;
+        }
+        if let Some(max_line_width) = request.max_line_width {
+            content_format.max_line_width = Some(max_line_width);
+        }
+        // Only a JSON Lines digest can take JSON lines as its content
+        content_format.json_lines = matches!(request.download_format, Some(DownloadFormat::JsonLines));
+        
+        let use_api = request.api.unwrap_or(false);
+        if use_api && (request.files.is_some() || request.include_submodules.unwrap_or(false)) {
+            return Err(GitingestError::ValidationError(
+                "API fetches can't be combined with listed files or submodules".to_string(),
+            ));
+        }
+        if request.base_ref.is_some() && (use_api || request.files.is_some()) {
+            return Err(GitingestError::ValidationError(
+                "A base ref can't be combined with API fetches or listed files".to_string(),
+            ));
+        }
+        
+        // A blobless clone lists the tree without contents, like a dry run
+        let blobless = request.clone_mode == Some(CloneMode::Blobless);
+        if blobless
+            && (archive.is_some()
+                || local_dir.is_some()
+                || use_api
+                || request.files.is_some()
+                || request.base_ref.is_some()
+                || request.include_submodules.unwrap_or(false)
+                || request.with_timestamps.unwrap_or(false))
+        {
+            return Err(GitingestError::ValidationError(
+                "A blobless clone needs a repository URL and can't be combined with listed files or blob URLs, \
+                 a base ref, submodules, timestamps or API fetches"
+                    .to_string(),
+            ));
+        }
+        let dry_run = dry_run || blobless;
+
+        // Archives and API fetches have no history to date files by
+        let with_timestamps =
+            request.with_timestamps.unwrap_or(false) && archive.is_none() && local_dir.is_none() && !use_api;
+        let cacheable = request.files.is_none()
+            && !with_timestamps
+            && archive.is_none()
+            && !use_api
+            && !request.include_submodules.unwrap_or(false)
+            && !blobless
+            && !request.no_cache.unwrap_or(false);
+        let cache_dir = config.clone_cache_dir(request.use_cache).filter(|_| cacheable);
+        let (workspace, local_path) = match &local_dir {
+            Some(path) => (Workspace::InPlace, path.clone()),
+            None => Self::prepare_workspace(&repository, cache_dir.as_deref(), config).await?,
+        };
         
         // Create clone configuration
         let clone_config = CloneConfig {
             url: repository.url.clone(),
             local_path: local_path.clone(),
-            branch: request.branch.or(repository.branch.clone()),
-            commit: repository.commit.clone(),
+            git_ref: repository.git_ref.clone(),
             subpath: repository.subpath.clone(),
             include_submodules: request.include_submodules.unwrap_or(false),
-            token: request.token,
+            token,
+            sparse_paths: request.files.clone(),
+            clone_mode: match with_timestamps {
+                true => CloneMode::Full,
+                false => request.clone_mode.unwrap_or_default(),
+            },
+            // An archive link asks for just that snapshot
+            prefer_archive: !blobless
+                && (config.prefer_archive_download || UrlParser::is_archive_link(&request.input_text)),
+            ssh_key_path: ssh.key_path,
+            ssh_known_hosts_policy: ssh.known_hosts_policy,
+            retry: RetryPolicy {
+                max_retries: config.clone_max_retries,
+                base_delay_ms: config.clone_base_delay_ms,
+            },
+            cancel: cancel.clone(),
         };
         
-        // Clone the repository
-        let clone_start = Instant::now();
-        GitService::clone_repository(&clone_config).await?;
-        let clone_duration = clone_start.elapsed();
-        log::info!("Repository cloning phase completed in {:.2}s", clone_duration.as_secs_f64());
-        
         // Create pattern matcher
-        let mut matcher = PatternMatcher::default();
+        let mut matcher = if request.no_default_ignore.unwrap_or(false) {
+            PatternMatcher::without_default_excludes()
+        } else {
+            PatternMatcher::default()
+        };
         
         // Add user-specified patterns
         if let Some(pattern) = request.pattern {
@@ -66,124 +953,885 @@ impl IngestService {
         if let Some(patterns) = request.exclude_patterns {
             matcher.exclude_patterns.extend(patterns);
         }
+        // A typo would otherwise only show up as an empty digest after the clone
+        PatternService::validate_patterns(&matcher.include_patterns, &matcher.exclude_patterns)?;
         
-        // Add gitignore patterns
-        let gitignore_path = local_path.join(".gitignore");
-        PatternService::add_gitignore_patterns(&mut matcher, &gitignore_path)?;
+        let max_file_size = request.max_file_size.unwrap_or(config.max_file_size);
+        let max_content_size = request.max_content_size.unwrap_or(config.max_content_size);
+
+        // Clone the repository, or fetch just the matching files
+        progress.enter(IngestPhase::Clone).await;
+        let clone_start = Instant::now();
+        let clone_outcome = if let Some(path) = &archive {
+            Self::extract_archive(path, &local_path).await?
+        } else if local_dir.is_some() {
+            CloneOutcome::default()
+        } else if use_api {
+            Self::fetch_via_api(&clone_config, &matcher, max_file_size, config).await?
+        } else {
+            let needs_git_dir = request.base_ref.is_some() || with_timestamps;
+            Self::clone_repository(&clone_config, &repository, &workspace, needs_git_dir).await?
+        };
+        let changes = match &request.base_ref {
+            Some(base_ref) => Some(Self::changed_files(&clone_config, base_ref).await?),
+            None => None,
+        };
+        let listed_tree = match blobless {
+            true => Some(Self::list_blobless_files(&clone_config).await?),
+            false => None,
+        };
+        let clone_duration = clone_start.elapsed();
+        progress.timings.clone_ms = Some(clone_duration.as_millis() as u64);
+        log::info!("Repository cloning phase completed in {:.2}s", clone_duration.as_secs_f64());
+        if clone_outcome.skipped_submodules > 0 {
+            progress.warnings.push(format!("Submodules skipped: {}", clone_outcome.skipped_submodules));
+        }
+        progress.enter(IngestPhase::Scan).await;
+
+        // A src/ URL that doesn't say what it names is a file target when
+        // the checkout has a file there
+        if repository.target_kind.is_none()
+            && !repository.subpath.is_empty()
+            && request.files.is_none()
+            && request.base_ref.is_none()
+            && !blobless
+            && local_path.join(&repository.subpath).is_file()
+        {
+            request.files = Some(vec![repository.subpath.clone()]);
+        }
+
+        if let Some(path) = &target_file {
+            Self::check_file_target(&local_path, path, &repository)?;
+        }
+        // A tree/ URL narrows the scan to its directory, with paths relative
+        // to it; listed files (and blob targets) stay relative to the root
+        let subpath = match &request.files {
+            Some(_) => String::new(),
+            None => repository.subpath.trim_matches('/').to_string(),
+        };
+        let (scan_root, listed_tree) = match listed_tree {
+            Some(files) => (local_path.join(&subpath), Some(Self::listed_under(files, &subpath, &repository)?)),
+            None => (Self::scan_root(&local_path, &subpath, &repository)?, None),
+        };
+        let changes = changes.map(|changes| Self::changes_under(changes, &subpath));
+        
+        // Add gitignore patterns, then the ingestion-only ones. Scanned
+        // paths are relative to the subpath, so the root files' patterns
+        // are rebased onto it
+        PatternService::add_root_ignore_patterns(&mut matcher, &local_path.join(".gitignore"), &subpath)?;
+        if scan_root != local_path {
+            PatternService::add_gitignore_patterns(&mut matcher, &scan_root.join(".gitignore"))?;
+        }
+        PatternService::add_root_ignore_patterns(&mut matcher, &local_path.join(INGEST_IGNORE_FILE), &subpath)?;
         
         // Set limits from config and request
-        let max_file_size = request.max_file_size.unwrap_or(config.max_file_size);
+        let max_file_lines = request.max_file_lines.unwrap_or(config.max_file_lines);
         
         // Scan the repository with memory-efficient loading
         log::info!("Starting memory-efficient file scanning...");
         let scan_start = Instant::now();
-        let file_tree = FileService::scan_directory(
-            &local_path,
-            &matcher,
+        let scan_config = ScanConfig {
             max_file_size,
-            config.max_files,
-            config.max_directory_depth,
-            config.concurrent_file_limit,
-            config.batch_size,
-        ).await?;
+            max_file_lines,
+            max_files: request.max_files.unwrap_or(config.max_files),
+            max_depth: request.max_directory_depth.unwrap_or(config.max_directory_depth),
+            concurrent_limit: config.effective_concurrent_file_limit(),
+            batch_size: config.effective_batch_size(),
+            rescue_binary_text: config.rescue_binary_text,
+            cancel: cancel.clone(),
+            events: progress.events.clone(),
+        };
+        let (mut file_tree, missing_files, scan_stats) = match (&request.files, &changes) {
+            (Some(files), _) => {
+                let (file_tree, missing, stats) = FileService::scan_listed_files(&local_path, files, &scan_config).await?;
+                if let Some(path) = &target_file
+                    && !missing.is_empty()
+                {
+                    return Err(GitingestError::RepositoryNotFound(format!(
+                        "{} has no file '{}' on {}",
+                        repository.url,
+                        path,
+                        repository.git_ref
+                    )));
+                }
+                let missing = Self::apply_missing_policy(missing, request.missing_files.unwrap_or_default())?;
+                if !missing.is_empty() {
+                    progress.warnings.push(format!("Listed files not found: {}", missing.join(", ")));
+                }
+                (file_tree, missing, stats)
+            }
+            (None, Some(changes)) => {
+                let (file_tree, stats) =
+                    FileService::scan_changed_files(&scan_root, changes, &matcher, &scan_config).await?;
+                (file_tree, Vec::new(), stats)
+            }
+            (None, None) => {
+                let (mut file_tree, stats) = match &listed_tree {
+                    Some(files) => FileService::scan_listed_tree(&scan_root, files, &matcher, &scan_config).await?,
+                    None => FileService::scan_directory(&scan_root, &matcher, &scan_config).await?,
+                };
+                // Submodules that weren't cloned are listed rather than left out
+                let submodules = Self::submodules_under(&local_path, &subpath);
+                FileService::add_submodules(&mut file_tree, &submodules, &matcher, scan_config.max_depth)?;
+                (file_tree, Vec::new(), stats)
+            }
+        };
+        if with_timestamps {
+            Self::record_last_commit_times(&local_path, &subpath, &mut file_tree).await?;
+        }
+        let diff = request.base_ref.as_deref().zip(changes.as_deref()).map(|(base_ref, changes)| {
+            let head_ref = repository.git_ref.fetch_ref().unwrap_or_else(|| "HEAD".to_string());
+            DiffStats::new(base_ref, &head_ref, changes)
+        });
         let scan_duration = scan_start.elapsed();
         log::info!("File scanning completed in {:.2}s", scan_duration.as_secs_f64());
-        
-        // Generate tree string (lightweight)
-        log::info!("Starting tree generation...");
-        let generation_start = Instant::now();
-        let tree = FileService::generate_tree_string(&file_tree, "", true);
-        let generation_duration = generation_start.elapsed();
-        log::info!("Tree generation completed in {:.2}s", generation_duration.as_secs_f64());
-        
-        // Calculate statistics from file tree
-        let files_analyzed = Self::count_files(&file_tree);
-        let total_size_bytes = Self::calculate_total_size(&file_tree);
-        let processing_time = start_time.elapsed();
-        
-        // Write content to file directly (streaming approach)
-        log::info!("Starting streaming content write...");
-        let content_start = Instant::now();
-        let temp_content_path = local_path.join("temp_content.txt");
-        FileService::write_content_to_file(&file_tree, &temp_content_path)?;
-        
-        // Read back only for response (could be optimized further by not reading back)
-        let content = std::fs::read_to_string(&temp_content_path)
-            .unwrap_or_else(|_| "Error reading generated content".to_string());
-        let content_duration = content_start.elapsed();
-        log::info!("Streaming content write completed in {:.2}s", content_duration.as_secs_f64());
-        
-        // Clean up temp file
-        let _ = std::fs::remove_file(&temp_content_path);
-        
-        // Estimate tokens
-        let estimated_tokens = Self::estimate_tokens(&content);
-        
-        // Create processing result
-        let processing_result = ProcessingResult {
-            summary: Self::generate_summary(&repository, files_analyzed, total_size_bytes),
-            tree: tree.clone(),
-            content: content.clone(),
-            stats: ProcessingStats {
-                files_analyzed,
-                total_size_bytes,
-                estimated_tokens,
-                processing_time_ms: processing_time.as_millis() as u64,
-            },
+
+        // The repository's license covers a subpath too, but only a scan
+        // of the whole repository has it in its tree
+        let license = if dry_run { None } else { detect_license(&local_path).await? };
+        if request.include_license_text.unwrap_or(false)
+            && subpath.is_empty()
+            && let Some(license) = &license
+        {
+            FileService::pin_root_file(&mut file_tree, &license.path).await?;
+        }
+
+        // A dry run must not open file bodies, so it sticks to the size-based estimate
+        let counter: Box<dyn TokenCounter> = if dry_run {
+            Box::new(HeuristicTokenCounter)
+        } else {
+            default_token_counter()
         };
-        
-        // Create response
-        let response = IngestResponse {
-            id,
-            repo_url: repository.url.clone(),
-            short_repo_url: Self::create_short_url(&repository),
-            summary: processing_result.summary,
-            digest_url: None, // Would be implemented for actual digest storage
-            tree,
-            content,
-            status: IngestStatus::Completed,
+        // Unknown sizes would all read as empty files
+        if !blobless {
+            count_tree_tokens(&mut file_tree, counter.as_ref(), max_content_size).await?;
+        }
+        let token_budget = match request.max_tokens {
+            Some(max_tokens) => Some(
+                apply_token_budget(&mut file_tree, max_tokens, counter.as_ref(), max_content_size, &content_format)
+                    .await?,
+            ),
+            None => None,
         };
-        
-        let total_processing_time = start_time.elapsed();
-        log::info!(
-            "Repository ingestion completed successfully - Total time: {:.2}s (Clone: {:.2}s, Scan: {:.2}s, Tree: {:.2}s, Content: {:.2}s)", 
-            total_processing_time.as_secs_f64(),
-            clone_duration.as_secs_f64(),
-            scan_duration.as_secs_f64(),
-            generation_duration.as_secs_f64(),
-            content_duration.as_secs_f64()
-        );
-        
-        Ok(response)
+        if let Some(budget) = &token_budget
+            && budget.omitted_files > 0
+        {
+            progress.warnings.push(format!(
+                "Token budget of {} reached; omitted content of {} file(s)",
+                budget.max_tokens, budget.omitted_files
+            ));
+        }
+        let language_breakdown = FileService::language_breakdown(&file_tree);
+        progress.timings.scan_ms = Some(scan_start.elapsed().as_millis() as u64);
+
+        Ok(ScannedRepository {
+            repository,
+            _workspace: workspace,
+            local_path: scan_root,
+            file_tree,
+            clone_outcome,
+            missing_files,
+            scan_stats,
+            token_budget,
+            language_breakdown,
+            dry_run,
+            sizes_unknown: blobless,
+            license,
+            content_order,
+            content_format,
+            max_content_size,
+            max_output_bytes: request.max_output_bytes.unwrap_or(config.max_output_bytes),
+            archive,
+            in_place: local_dir.is_some(),
+            subpath: (!subpath.is_empty()).then_some(subpath),
+            diff,
+            clone_duration,
+            scan_duration,
+        })
     }
     
-    fn generate_summary(repository: &crate::models::Repository, files_count: usize, total_size: u64) -> String {
-        format!(
-            "Repository: {}/{}\nFiles processed: {}\nTotal size: {}\nHost: {}",
-            repository.owner,
-            repository.name,
+    /// Picks where to check the repository out: its entry in the clone
+    /// cache at `cache_dir` when there is one (sparse and submodule clones
+    /// have none), otherwise a fresh temporary directory. Expired and
+    /// excess entries are pruned first, so a stale entry for this
+    /// repository is cloned afresh.
+    async fn prepare_workspace(
+        repository: &Repository,
+        cache_dir: Option<&Path>,
+        config: &AppConfig,
+    ) -> Result<(Workspace, PathBuf)> {
+        #[cfg(feature = "git")]
+        if let Some(cache_dir) = cache_dir {
+            let cache = CloneCache::new(cache_dir);
+            let limits = CacheLimits {
+                ttl: config.cache_ttl_secs.map(Duration::from_secs),
+                max_bytes: config.cache_max_bytes,
+            };
+            if limits.ttl.is_some() || limits.max_bytes.is_some() {
+                cache.prune(&limits)?;
+            }
+            let local_path = cache.entry_path(repository);
+            let lock = cache.lock(&local_path).await?;
+            return Ok((Workspace::Cached { _lock: lock }, local_path));
+        }
+        #[cfg(not(feature = "git"))]
+        let _ = (cache_dir, config);
+
+        let temp_dir = TempDir::new()
+            .map_err(|e| GitingestError::FileSystemError(format!("Failed to create temp dir: {}", e)))?;
+        let local_path = temp_dir.path().join(&repository.name);
+        Ok((Workspace::Temp { _dir: temp_dir }, local_path))
+    }
+
+    /// Fetches the repository into the workspace. github.com repositories
+    /// without submodules are downloaded as a tarball first when
+    /// `prefer_archive_download` is set; cached entries always use git so
+    /// they can be refreshed.
+    #[cfg(feature = "git")]
+    async fn clone_repository(
+        clone_config: &CloneConfig,
+        repository: &Repository,
+        workspace: &Workspace,
+        needs_git_dir: bool,
+    ) -> Result<CloneOutcome> {
+        match workspace {
+            Workspace::Temp { .. } => {
+                // Snapshots have no `.git` to diff against
+                #[cfg(feature = "remote")]
+                if clone_config.prefer_archive
+                    && repository.host == "github.com"
+                    && clone_config.url.starts_with("https://")
+                    && !clone_config.include_submodules
+                    && !matches!(clone_config.git_ref, GitRef::PullRequest(_))
+                    && !needs_git_dir
+                {
+                    match GitService::download_archive(clone_config).await {
+                        Ok(outcome) => return Ok(outcome),
+                        Err(err @ GitingestError::Cancelled(_)) => return Err(err),
+                        Err(err) => {
+                            log::warn!("Archive download failed, falling back to git clone: {}", err);
+                            if clone_config.local_path.exists() {
+                                tokio::fs::remove_dir_all(&clone_config.local_path).await?;
+                            }
+                        }
+                    }
+                }
+                #[cfg(not(feature = "remote"))]
+                let _ = (repository, needs_git_dir);
+                GitService::clone_repository(clone_config).await
+            }
+            Workspace::Cached { .. } => GitService::clone_or_update(clone_config).await,
+            Workspace::InPlace => Ok(CloneOutcome::default()),
+        }
+    }
+
+    #[cfg(not(feature = "git"))]
+    async fn clone_repository(
+        _clone_config: &CloneConfig,
+        _repository: &Repository,
+        _workspace: &Workspace,
+        _needs_git_dir: bool,
+    ) -> Result<CloneOutcome> {
+        Err(GitingestError::ConfigError(
+            "cloning repositories requires the `git` feature".to_string(),
+        ))
+    }
+
+    /// Sets each file's [`FileNode::last_modified`] from the clone's history.
+    #[cfg(feature = "git")]
+    async fn record_last_commit_times(local_path: &Path, subpath: &str, file_tree: &mut FileNode) -> Result<()> {
+        let mut paths = Vec::new();
+        Self::collect_file_paths(file_tree, &mut paths);
+        // The history is keyed by repository paths, the tree by scan-root ones
+        let prefix = match subpath {
+            "" => String::new(),
+            subpath => format!("{}/", subpath),
+        };
+        let paths = paths.into_iter().map(|path| format!("{}{}", prefix, path)).collect();
+        let times = GitService::last_commit_times(local_path, paths)
+            .await?
+            .into_iter()
+            .filter_map(|(path, time)| Some((path.strip_prefix(&prefix)?.to_string(), time)))
+            .collect();
+        Self::apply_last_commit_times(file_tree, &times);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "git"))]
+    async fn record_last_commit_times(_local_path: &Path, _subpath: &str, _file_tree: &mut FileNode) -> Result<()> {
+        Ok(())
+    }
+
+    /// The directory under `local_path` a scan of `subpath` starts from. It
+    /// must be a directory inside the checkout, so `..` or a symlink can't
+    /// lead the scan out of it.
+    fn scan_root(local_path: &Path, subpath: &str, repository: &Repository) -> Result<PathBuf> {
+        if subpath.is_empty() {
+            return Ok(local_path.to_path_buf());
+        }
+        let root = local_path.canonicalize()?;
+        match local_path.join(subpath).canonicalize() {
+            Ok(path) if path.starts_with(&root) && path.is_dir() => Ok(path),
+            Ok(path) if path.starts_with(&root) && path.is_file() => Err(GitingestError::ValidationError(format!(
+                "Subpath '{}' is a file on {}, not a directory; use a blob/ URL to ingest one file",
+                subpath,
+                repository.git_ref
+            ))),
+            _ => Err(GitingestError::ValidationError(format!(
+                "Subpath '{}' is not a directory on {}",
+                subpath,
+                repository.git_ref
+            ))),
+        }
+    }
+
+    /// Fails when the file a blob/ URL names is a directory in the
+    /// checkout, rather than ingesting it as a missing file.
+    fn check_file_target(local_path: &Path, path: &str, repository: &Repository) -> Result<()> {
+        match local_path.join(path).is_dir() {
+            true => Err(GitingestError::ValidationError(format!(
+                "'{}' is a directory on {}, not a file; use a tree/ URL to ingest a directory",
+                path,
+                repository.git_ref
+            ))),
+            false => Ok(()),
+        }
+    }
+
+    /// The submodules declared in the `.gitmodules` of the checkout at
+    /// `local_path` that are under `subpath`, with paths relative to it.
+    fn submodules_under(local_path: &Path, subpath: &str) -> Vec<(String, String)> {
+        FileService::read_gitmodules(local_path)
+            .into_iter()
+            .filter_map(|(path, url)| match subpath {
+                "" => Some((path, url)),
+                subpath => path
+                    .strip_prefix(subpath)
+                    .and_then(|rest| rest.strip_prefix('/'))
+                    .map(|rest| (rest.to_string(), url)),
+            })
+            .collect()
+    }
+
+    /// The `files` of a blobless clone under `subpath`, with paths relative
+    /// to it. Like [`Self::scan_root`], `subpath` must name a directory.
+    fn listed_under(files: Vec<ListedFile>, subpath: &str, repository: &Repository) -> Result<Vec<ListedFile>> {
+        if subpath.is_empty() {
+            return Ok(files);
+        }
+        if files.iter().any(|file| file.path == subpath) {
+            return Err(GitingestError::ValidationError(format!(
+                "Subpath '{}' is a file on {}, not a directory; use a blob/ URL to ingest one file",
+                subpath,
+                repository.git_ref
+            )));
+        }
+        let prefix = format!("{}/", subpath);
+        let files: Vec<ListedFile> = files
+            .into_iter()
+            .filter_map(|file| {
+                let path = file.path.strip_prefix(&prefix)?.to_string();
+                Some(ListedFile { path, ..file })
+            })
+            .collect();
+        if files.is_empty() {
+            return Err(GitingestError::ValidationError(format!(
+                "Subpath '{}' is not a directory on {}",
+                subpath,
+                repository.git_ref
+            )));
+        }
+        Ok(files)
+    }
+
+    /// Lists the files of a blobless clone, checking out the ignore files
+    /// and `.gitmodules` the scan reads from disk.
+    #[cfg(feature = "git")]
+    async fn list_blobless_files(clone_config: &CloneConfig) -> Result<Vec<ListedFile>> {
+        let files = GitService::list_files(clone_config).await?;
+        let subpath = clone_config.subpath.trim_matches('/');
+        let wanted = [
+            ".gitignore".to_string(),
+            INGEST_IGNORE_FILE.to_string(),
+            ".gitmodules".to_string(),
+            format!("{}/.gitignore", subpath),
+        ];
+        let present: Vec<String> = wanted
+            .into_iter()
+            .filter(|path| files.iter().any(|file| !file.symlink && file.path == *path))
+            .collect();
+        GitService::checkout_paths(clone_config, &present).await?;
+        Ok(files)
+    }
+
+    #[cfg(not(feature = "git"))]
+    async fn list_blobless_files(_clone_config: &CloneConfig) -> Result<Vec<ListedFile>> {
+        Err(GitingestError::ConfigError(
+            "blobless clones require the `git` feature".to_string(),
+        ))
+    }
+
+    /// The `changes` under `subpath`, with paths relative to it.
+    fn changes_under(changes: Vec<ChangedFile>, subpath: &str) -> Vec<ChangedFile> {
+        if subpath.is_empty() {
+            return changes;
+        }
+        let prefix = format!("{}/", subpath);
+        changes
+            .into_iter()
+            .filter_map(|change| {
+                let path = change.path.strip_prefix(&prefix)?.to_string();
+                let old_path = change.old_path.and_then(|old| old.strip_prefix(&prefix).map(str::to_string));
+                Some(ChangedFile { path, old_path, ..change })
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "git")]
+    async fn changed_files(clone_config: &CloneConfig, base_ref: &str) -> Result<Vec<ChangedFile>> {
+        GitService::changed_files(clone_config, base_ref).await
+    }
+
+    #[cfg(not(feature = "git"))]
+    async fn changed_files(_clone_config: &CloneConfig, _base_ref: &str) -> Result<Vec<ChangedFile>> {
+        Err(GitingestError::ConfigError(
+            "diffing against a base ref requires the `git` feature".to_string(),
+        ))
+    }
+
+    #[cfg(feature = "git")]
+    async fn credential_helper_token(url: &str, cancel: &CancellationToken) -> Option<String> {
+        GitService::credential_helper_token(url, cancel).await
+    }
+
+    #[cfg(not(feature = "git"))]
+    async fn credential_helper_token(_url: &str, _cancel: &CancellationToken) -> Option<String> {
+        None
+    }
+
+    #[cfg(feature = "git")]
+    async fn check_repository_exists(
+        repository: &Repository,
+        token: Option<&str>,
+        ssh: &SshAuth,
+        config: &AppConfig,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        GitService::check_repository_exists(repository, token, ssh, config, cancel).await
+    }
+
+    #[cfg(not(feature = "git"))]
+    async fn check_repository_exists(
+        _repository: &Repository,
+        _token: Option<&str>,
+        _ssh: &SshAuth,
+        _config: &AppConfig,
+        _cancel: &CancellationToken,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// The URL parser takes the first segment after `tree/` or `blob/` as
+    /// the branch, which splits `feature/x` or a `release/v2.1` tag apart.
+    /// When a subpath follows, re-splits the ref against the longest of the
+    /// remote's branches and tags that leads the path, failing with
+    /// [`GitingestError::BranchNotFound`] when none does; if they can't be
+    /// listed, the parser's split stands.
+    #[cfg(feature = "git")]
+    async fn resolve_url_ref(
+        repository: &mut Repository,
+        token: Option<&str>,
+        ssh: &SshAuth,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        let Some(branch) = repository.git_ref.branch_name().filter(|_| !repository.subpath.is_empty()) else {
+            return Ok(());
+        };
+        let ref_path = format!("{}/{}", branch, repository.subpath);
+        match GitService::list_remote_refs(&repository.url, token, ssh, cancel).await {
+            Ok(refs) => {
+                let Some((branch, subpath)) = GitService::resolve_ref_and_subpath(&refs, &ref_path) else {
+                    return Err(GitingestError::BranchNotFound(format!(
+                        "no branch or tag of {} leads '{}'",
+                        repository.url, ref_path
+                    )));
+                };
+                repository.git_ref = match repository.git_ref {
+                    GitRef::Tag(_) => GitRef::Tag(branch),
+                    _ => GitRef::Branch(branch),
+                };
+                repository.subpath = subpath;
+            }
+            Err(err @ GitingestError::Cancelled(_)) => return Err(err),
+            Err(err) => log::debug!("Couldn't list remote refs, keeping branch '{}': {}", branch, err),
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "git"))]
+    async fn resolve_url_ref(
+        _repository: &mut Repository,
+        _token: Option<&str>,
+        _ssh: &SshAuth,
+        _cancel: &CancellationToken,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    #[cfg(all(feature = "git", feature = "remote"))]
+    async fn fetch_via_api(
+        clone_config: &CloneConfig,
+        matcher: &PatternMatcher,
+        max_file_size: u64,
+        config: &AppConfig,
+    ) -> Result<CloneOutcome> {
+        GitService::fetch_via_api(clone_config, &config.github_api_url, matcher, max_file_size).await
+    }
+
+    #[cfg(not(all(feature = "git", feature = "remote")))]
+    async fn fetch_via_api(
+        _clone_config: &CloneConfig,
+        _matcher: &PatternMatcher,
+        _max_file_size: u64,
+        _config: &AppConfig,
+    ) -> Result<CloneOutcome> {
+        Err(GitingestError::ConfigError(
+            "API fetches require the `git` and `remote` features".to_string(),
+        ))
+    }
+
+    #[cfg(feature = "archive")]
+    fn archive_input(input: &str) -> Option<PathBuf> {
+        crate::utils::archive_input(input)
+    }
+
+    #[cfg(not(feature = "archive"))]
+    fn archive_input(_input: &str) -> Option<PathBuf> {
+        None
+    }
+
+    /// Stands in for the repository of an archive input: named after the
+    /// archive, with no owner, branch or subpath.
+    fn archive_repository(path: &Path, request: &IngestRequest) -> Result<Repository> {
+        if request.branch.is_some()
+            || request.base_ref.is_some()
+            || request.api.unwrap_or(false)
+            || request.include_submodules.unwrap_or(false)
+        {
+            return Err(GitingestError::ValidationError(
+                "Archive inputs can't be combined with a branch, a base ref, API fetches or submodules".to_string(),
+            ));
+        }
+        let path = std::path::absolute(path)?;
+        Ok(Repository {
+            url: path.display().to_string(),
+            host: "localhost".to_string(),
+            owner: String::new(),
+            name: Self::archive_stem(&path),
+            git_ref: GitRef::DefaultBranch,
+            subpath: String::new(),
+            target_kind: None,
+        })
+    }
+
+    /// Stands in for the repository of a local directory: named after the
+    /// directory and its parent, like a `file://` URL.
+    fn local_repository(path: &Path, request: &IngestRequest) -> Result<Repository> {
+        if request.branch.is_some()
+            || request.base_ref.is_some()
+            || request.api.unwrap_or(false)
+            || request.include_submodules.unwrap_or(false)
+        {
+            return Err(GitingestError::ValidationError(
+                "Local directories can't be combined with a branch, a base ref, API fetches or submodules".to_string(),
+            ));
+        }
+        let name = |path: &Path| path.file_name().map(|name| name.to_string_lossy().into_owned());
+        Ok(Repository {
+            url: path.display().to_string(),
+            host: "localhost".to_string(),
+            owner: path.parent().and_then(name).unwrap_or_else(|| "local".to_string()),
+            name: name(path).unwrap_or_else(|| "local".to_string()),
+            git_ref: GitRef::DefaultBranch,
+            subpath: String::new(),
+            target_kind: None,
+        })
+    }
+
+    #[cfg(feature = "archive")]
+    fn archive_stem(path: &Path) -> String {
+        crate::utils::archive_stem(path)
+    }
+
+    #[cfg(not(feature = "archive"))]
+    fn archive_stem(_path: &Path) -> String {
+        unreachable!("archive inputs require the `archive` feature")
+    }
+
+    #[cfg(feature = "archive")]
+    async fn extract_archive(path: &Path, local_path: &Path) -> Result<CloneOutcome> {
+        crate::utils::extract_archive(path, local_path).await?;
+        Ok(CloneOutcome::default())
+    }
+
+    #[cfg(not(feature = "archive"))]
+    async fn extract_archive(_path: &Path, _local_path: &Path) -> Result<CloneOutcome> {
+        unreachable!("archive inputs require the `archive` feature")
+    }
+
+    /// Listed paths must stay inside the repository.
+    fn validate_listed_files(files: &[String]) -> Result<()> {
+        for file in files {
+            let path = Path::new(file);
+            let escapes = path.is_absolute()
+                || path.components().any(|c| !matches!(c, std::path::Component::Normal(_) | std::path::Component::CurDir));
+            if file.trim().is_empty() || escapes {
+                return Err(GitingestError::ValidationError(format!(
+                    "Listed file '{}' must be a path inside the repository",
+                    file
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies `policy` to listed files that were not found, returning the
+    /// ones to report in the summary.
+    fn apply_missing_policy(missing: Vec<String>, policy: MissingFilePolicy) -> Result<Vec<String>> {
+        if missing.is_empty() {
+            return Ok(missing);
+        }
+        match policy {
+            MissingFilePolicy::Warn => {
+                log::warn!("Listed files not found: {}", missing.join(", "));
+                Ok(missing)
+            }
+            MissingFilePolicy::Error => Err(GitingestError::ValidationError(format!(
+                "Listed files not found: {}",
+                missing.join(", ")
+            ))),
+            MissingFilePolicy::Skip => Ok(Vec::new()),
+        }
+    }
+
+    /// Picks the ref to check out when both the request and the URL name
+    /// one, warning (or failing, per `policy`) when they disagree. A
+    /// requested version such as `1.75.0` is taken as a tag.
+    fn resolve_ref(
+        flag_branch: Option<String>,
+        url_ref: GitRef,
+        policy: BranchConflictPolicy,
+        warnings: &mut Vec<String>,
+    ) -> Result<GitRef> {
+        let flag = match flag_branch {
+            Some(flag) if url_ref != GitRef::DefaultBranch && url_ref.branch_name() != Some(flag.as_str()) => flag,
+            Some(flag) if url_ref == GitRef::DefaultBranch && UrlParser::is_version_tag(&flag) => return Ok(GitRef::Tag(flag)),
+            Some(flag) if url_ref == GitRef::DefaultBranch => return Ok(GitRef::Branch(flag)),
+            _ => return Ok(url_ref),
+        };
+        let url_kind = match &url_ref {
+            GitRef::Tag(_) => "tag",
+            GitRef::Commit(_) => "commit",
+            GitRef::PullRequest(_) => "ref",
+            GitRef::DefaultBranch | GitRef::Branch(_) => "branch",
+        };
+        match policy {
+            BranchConflictPolicy::PreferFlag => {
+                let warning = format!(
+                    "Requested branch '{}' differs from URL {} '{}'; using '{}'",
+                    flag, url_kind, url_ref, flag
+                );
+                log::warn!("{}", warning);
+                warnings.push(warning);
+                Ok(GitRef::Branch(flag))
+            }
+            BranchConflictPolicy::PreferUrl => {
+                let warning = format!(
+                    "Requested branch '{}' differs from URL {} '{}'; using '{}'",
+                    flag, url_kind, url_ref, url_ref
+                );
+                log::warn!("{}", warning);
+                warnings.push(warning);
+                Ok(url_ref)
+            }
+            BranchConflictPolicy::Error => Err(GitingestError::ValidationError(format!(
+                "Requested branch '{}' conflicts with URL {} '{}'",
+                flag, url_kind, url_ref
+            ))),
+        }
+    }
+
+    fn generate_summary(scanned: &ScannedRepository, files_count: usize, total_size: u64) -> String {
+        let repository = &scanned.repository;
+        let skipped_submodules = scanned.clone_outcome.skipped_submodules;
+        let missing_files = scanned.missing_files.len();
+        let mut summary = format!(
+            "Repository: {}\nFiles processed: {}\nTotal size: {}\n{}",
+            match &scanned.archive {
+                Some(archive) => archive.file_name().unwrap_or(archive.as_os_str()).to_string_lossy().into_owned(),
+                None => format!("{}/{}", repository.owner, repository.name),
+            },
             files_count,
-            format_file_size(total_size),
-            repository.host
-        )
+            match scanned.sizes_unknown {
+                true => "unknown".to_string(),
+                false => format_file_size(total_size),
+            },
+            match scanned.in_place {
+                true => format!("Local path: {}", scanned.local_path.display()),
+                false => format!("Host: {}", repository.display_host()),
+            }
+        );
+        match &repository.git_ref {
+            GitRef::DefaultBranch => {}
+            GitRef::Branch(branch) => summary.push_str(&format!("\nBranch: {}", branch)),
+            GitRef::Tag(tag) => summary.push_str(&format!("\nTag: {}", tag)),
+            // The commit checked out is listed in full below
+            GitRef::Commit(commit) if scanned.clone_outcome.commit.is_none() => {
+                summary.push_str(&format!("\nCommit: {}", commit))
+            }
+            GitRef::Commit(_) => {}
+            GitRef::PullRequest(number) => summary.push_str(&format!("\nPull request: #{}", number)),
+        }
+        if let Some(commit) = &scanned.clone_outcome.commit {
+            summary.push_str(&format!("\nCommit: {}", commit));
+        }
+        if scanned.clone_outcome.cached {
+            summary.push_str("\nClone: reused from cache");
+        }
+        if let Some(subpath) = &scanned.subpath {
+            summary.push_str(&format!("\nSubpath: {}", subpath));
+        }
+        if let Some(diff) = &scanned.diff {
+            summary.push_str(&format!(
+                "\nDiff: {}..{} ({} added, {} modified, {} deleted, {} renamed)",
+                diff.base_ref, diff.head_ref, diff.added, diff.modified, diff.deleted, diff.renamed
+            ));
+        }
+        let recently_changed = Self::recently_changed(&scanned.file_tree, RECENTLY_CHANGED_FILES);
+        if !recently_changed.is_empty() {
+            let files: Vec<String> = recently_changed
+                .iter()
+                .map(|(path, time)| match chrono::DateTime::from_timestamp(*time, 0) {
+                    Some(date) => format!("{} ({})", path, date.format("%Y-%m-%d")),
+                    None => path.to_string(),
+                })
+                .collect();
+            summary.push_str(&format!("\nRecently changed: {}", files.join(", ")));
+        }
+        if let Some(license) = &scanned.license {
+            summary.push_str(&format!("\nLicense: {} ({})", license.spdx_id, license.path));
+        }
+        if scanned.dry_run {
+            summary.push_str("\nDry run: file contents were not read");
+        }
+        if skipped_submodules > 0 {
+            summary.push_str(&format!("\nSubmodules skipped: {}", skipped_submodules));
+        }
+        if missing_files > 0 {
+            summary.push_str(&format!("\nMissing files: {}", missing_files));
+        }
+        if let Some(budget) = &scanned.token_budget {
+            summary.push_str(&format!(
+                "\nToken budget: used {} of {} tokens, {} files omitted",
+                budget.used_tokens, budget.max_tokens, budget.omitted_files
+            ));
+        }
+        if scanned.scan_stats.skipped.total() > 0 {
+            summary.push_str(&format!("\nSkipped: {}", scanned.scan_stats.skipped));
+        }
+        if !scanned.language_breakdown.is_empty() {
+            let languages: Vec<String> = scanned
+                .language_breakdown
+                .iter()
+                .map(|stat| {
+                    let files = format!("{} {}", stat.files, if stat.files == 1 { "file" } else { "files" });
+                    if scanned.sizes_unknown {
+                        return format!("{} ({})", stat.language, files);
+                    }
+                    format!(
+                        "{} {:.0}% ({}, {}, {:.0}% of tokens)",
+                        stat.language,
+                        stat.size_share * 100.0,
+                        files,
+                        format_file_size(stat.total_size_bytes),
+                        stat.token_share * 100.0
+                    )
+                })
+                .collect();
+            summary.push_str(&format!("\nLanguages: {}", languages.join(", ")));
+        }
+        summary
     }
     
-    fn create_short_url(repository: &crate::models::Repository) -> String {
-        format!("{}/{}", repository.owner, repository.name)
+    /// `owner/name`, or just the stem of an archive input.
+    fn create_short_url(scanned: &ScannedRepository) -> String {
+        let repository = &scanned.repository;
+        match scanned.archive {
+            Some(_) => repository.name.clone(),
+            None => format!("{}/{}", repository.owner, repository.name),
+        }
     }
     
-    fn estimate_tokens(content: &str) -> Option<usize> {
-        // Rough estimation: ~4 characters per token for English text
-        Some(content.len() / 4)
+    /// How many file bodies to read ahead of the writer. Bounded separately
+    /// from the scan concurrency since each read holds a whole file.
+    fn read_ahead(config: &AppConfig) -> usize {
+        config.effective_concurrent_file_limit().min(CONTENT_READ_AHEAD)
+    }
+
+    /// ~4 characters per token, as in [`crate::utils::HeuristicTokenCounter`].
+    fn estimate_tokens(content_len: usize) -> Option<usize> {
+        Some(content_len / 4)
     }
     
+    /// Repo-relative paths of the files under `node`, `/`-separated as git
+    /// reports them.
+    #[cfg(feature = "git")]
+    fn collect_file_paths(node: &FileNode, paths: &mut Vec<String>) {
+        match node.node_type {
+            crate::models::FileNodeType::File => paths.push(node.relative_path.replace('\\', "/")),
+            crate::models::FileNodeType::Directory => {
+                for child in &node.children {
+                    Self::collect_file_paths(child, paths);
+                }
+            }
+            crate::models::FileNodeType::Symlink | crate::models::FileNodeType::Submodule => {}
+        }
+    }
+
+    #[cfg(feature = "git")]
+    fn apply_last_commit_times(node: &mut FileNode, times: &std::collections::HashMap<String, i64>) {
+        if node.node_type == crate::models::FileNodeType::File {
+            node.last_modified = times.get(&node.relative_path.replace('\\', "/")).copied();
+        }
+        for child in &mut node.children {
+            Self::apply_last_commit_times(child, times);
+        }
+    }
+
+    /// The `limit` most recently committed files under `node`, newest first.
+    fn recently_changed(node: &FileNode, limit: usize) -> Vec<(&str, i64)> {
+        fn collect<'a>(node: &'a FileNode, files: &mut Vec<(&'a str, i64)>) {
+            if let Some(time) = node.last_modified {
+                files.push((&node.relative_path, time));
+            }
+            for child in &node.children {
+                collect(child, files);
+            }
+        }
+        let mut files = Vec::new();
+        collect(node, &mut files);
+        files.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        files.truncate(limit);
+        files
+    }
+
     fn count_files(node: &crate::models::FileNode) -> usize {
         match node.node_type {
-            crate::models::FileNodeType::File => 1,
+            crate::models::FileNodeType::File => usize::from(!node.deleted),
             crate::models::FileNodeType::Directory => {
-                node.children.iter().map(|child| Self::count_files(child)).sum()
+                node.children.iter().map(Self::count_files).sum()
             }
-            crate::models::FileNodeType::Symlink => 0,
+            crate::models::FileNodeType::Symlink | crate::models::FileNodeType::Submodule => 0,
         }
     }
 
@@ -191,9 +1839,354 @@ impl IngestService {
         match node.node_type {
             crate::models::FileNodeType::File => node.size,
             crate::models::FileNodeType::Directory => {
-                node.children.iter().map(|child| Self::calculate_total_size(child)).sum()
+                node.children.iter().map(Self::calculate_total_size).sum()
             }
-            crate::models::FileNodeType::Symlink => 0,
+            crate::models::FileNodeType::Symlink | crate::models::FileNodeType::Submodule => 0,
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "git")]
+    fn local_config() -> AppConfig {
+        AppConfig { allow_local_inputs: true, ..AppConfig::default() }
+    }
+
+    #[cfg(feature = "git")]
+    fn create_local_repo(dir: &Path) {
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("README.md"), "# Test").unwrap();
+        std::fs::write(dir.join("src/main.rs"), "fn main() {}").unwrap();
+        git(dir, &["init", "-q"]);
+        commit_all(dir);
+    }
+
+    #[cfg(feature = "git")]
+    fn commit_all(dir: &Path) {
+        git(dir, &["add", "."]);
+        git(dir, &["commit", "-qm", "update"]);
+    }
+
+    #[cfg(feature = "git")]
+    fn git(dir: &Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn test_branch_conflict_prefer_flag() {
+        let mut warnings = Vec::new();
+        let git_ref = IngestService::resolve_ref(
+            Some("main".to_string()),
+            GitRef::Branch("develop".to_string()),
+            BranchConflictPolicy::PreferFlag,
+            &mut warnings,
+        ).unwrap();
+        assert_eq!(git_ref, GitRef::Branch("main".to_string()));
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_branch_conflict_prefer_url() {
+        let git_ref = IngestService::resolve_ref(
+            Some("main".to_string()),
+            GitRef::Tag("v1.0".to_string()),
+            BranchConflictPolicy::PreferUrl,
+            &mut Vec::new(),
+        ).unwrap();
+        assert_eq!(git_ref, GitRef::Tag("v1.0".to_string()));
+    }
+
+    #[test]
+    fn test_branch_conflict_error() {
+        let result = IngestService::resolve_ref(
+            Some("main".to_string()),
+            GitRef::Branch("develop".to_string()),
+            BranchConflictPolicy::Error,
+            &mut Vec::new(),
+        );
+        assert!(matches!(result, Err(GitingestError::ValidationError(msg)) if msg.contains("'main'") && msg.contains("'develop'")));
+    }
+
+    #[test]
+    fn test_matching_or_single_branch_is_not_a_conflict() {
+        for policy in [BranchConflictPolicy::PreferFlag, BranchConflictPolicy::PreferUrl, BranchConflictPolicy::Error] {
+            let same = IngestService::resolve_ref(Some("v2".to_string()), GitRef::Tag("v2".to_string()), policy, &mut Vec::new());
+            assert_eq!(same.unwrap(), GitRef::Tag("v2".to_string()));
+            let flag_only = IngestService::resolve_ref(Some("main".to_string()), GitRef::DefaultBranch, policy, &mut Vec::new());
+            assert_eq!(flag_only.unwrap(), GitRef::Branch("main".to_string()));
+            let version = IngestService::resolve_ref(Some("1.75.0".to_string()), GitRef::DefaultBranch, policy, &mut Vec::new());
+            assert_eq!(version.unwrap(), GitRef::Tag("1.75.0".to_string()));
+            let url_only = IngestService::resolve_ref(None, GitRef::Commit("abc1234".to_string()), policy, &mut Vec::new());
+            assert_eq!(url_only.unwrap(), GitRef::Commit("abc1234".to_string()));
+        }
+    }
+
+    #[test]
+    fn test_branch_conflict_policy_parses() {
+        assert_eq!("prefer_url".parse::<BranchConflictPolicy>().unwrap(), BranchConflictPolicy::PreferUrl);
+        assert!("sometimes".parse::<BranchConflictPolicy>().is_err());
+    }
+
+    #[cfg(feature = "git")]
+    #[tokio::test]
+    async fn test_url_ref_is_resolved_against_remote_refs() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("source");
+        create_local_repo(&source);
+        git(&source, &["branch", "-M", "main"]);
+        git(&source, &["branch", "releases/2024"]);
+        git(&source, &["tag", "v1/rc"]);
+        let repository = |ref_path: &str| {
+            let (branch, subpath) = ref_path.split_once('/').unwrap();
+            Repository {
+                url: format!("file://{}", source.display()),
+                host: "localhost".to_string(),
+                owner: "owner".to_string(),
+                name: "source".to_string(),
+                git_ref: GitRef::Branch(branch.to_string()),
+                subpath: subpath.to_string(),
+                target_kind: None,
+            }
+        };
+        let cancel = CancellationToken::new();
+
+        for (ref_path, branch, subpath) in [
+            ("releases/2024/src", "releases/2024", "src"),
+            ("v1/rc/src/main.rs", "v1/rc", "src/main.rs"),
+            ("main/src", "main", "src"),
+        ] {
+            let mut resolved = repository(ref_path);
+            IngestService::resolve_url_ref(&mut resolved, None, &SshAuth::default(), &cancel).await.unwrap();
+            assert_eq!((resolved.git_ref.branch_name(), resolved.subpath.as_str()), (Some(branch), subpath));
+        }
+
+        let mut unknown = repository("releases/2023/src");
+        let err = IngestService::resolve_url_ref(&mut unknown, None, &SshAuth::default(), &cancel).await.unwrap_err();
+        assert!(matches!(err, GitingestError::BranchNotFound(_)), "{}", err);
+
+        // An unreachable remote keeps the parser's split
+        let mut unreachable = Repository {
+            url: format!("file://{}", temp_dir.path().join("missing").display()),
+            ..repository("releases/2023/src")
+        };
+        IngestService::resolve_url_ref(&mut unreachable, None, &SshAuth::default(), &cancel).await.unwrap();
+        assert_eq!((unreachable.git_ref.branch_name(), unreachable.subpath.as_str()), (Some("releases"), "2023/src"));
+    }
+
+    #[test]
+    fn test_scan_root_stays_inside_checkout() {
+        let temp_dir = TempDir::new().unwrap();
+        let checkout = temp_dir.path().join("checkout");
+        std::fs::create_dir_all(checkout.join("src/nested")).unwrap();
+        std::fs::write(checkout.join("src/main.rs"), "fn main() {}").unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("outside")).unwrap();
+        let repository = Repository {
+            url: "https://github.com/owner/repo.git".to_string(),
+            host: "github.com".to_string(),
+            owner: "owner".to_string(),
+            name: "repo".to_string(),
+            git_ref: GitRef::Branch("main".to_string()),
+            subpath: String::new(),
+            target_kind: None,
+        };
+
+        assert_eq!(IngestService::scan_root(&checkout, "", &repository).unwrap(), checkout);
+        assert_eq!(
+            IngestService::scan_root(&checkout, "src/nested", &repository).unwrap(),
+            checkout.join("src/nested").canonicalize().unwrap()
+        );
+        for subpath in ["docs", "src/main.rs", "../outside"] {
+            let err = IngestService::scan_root(&checkout, subpath, &repository).unwrap_err();
+            assert!(matches!(err, GitingestError::ValidationError(_)), "{}", err);
+            assert!(err.to_string().contains("on main"), "{}", err);
+        }
+        let err = IngestService::scan_root(&checkout, "src/main.rs", &repository).unwrap_err();
+        assert!(err.to_string().contains("is a file on main, not a directory"), "{}", err);
+
+        // Blob targets have to be files
+        IngestService::check_file_target(&checkout, "src/main.rs", &repository).unwrap();
+        IngestService::check_file_target(&checkout, "docs/missing.md", &repository).unwrap();
+        let err = IngestService::check_file_target(&checkout, "src/nested", &repository).unwrap_err();
+        assert!(matches!(err, GitingestError::ValidationError(_)), "{}", err);
+        assert!(err.to_string().contains("'src/nested' is a directory on main, not a file"), "{}", err);
+    }
+
+    #[test]
+    fn test_changes_under_subpath_are_rebased() {
+        use crate::models::ChangeKind;
+
+        let change = |path: &str, kind, old_path: Option<&str>| ChangedFile {
+            path: path.to_string(),
+            kind,
+            old_path: old_path.map(str::to_string),
+        };
+        let changes = vec![
+            change("src/lib.rs", ChangeKind::Modified, None),
+            change("src/new.rs", ChangeKind::Renamed, Some("old.rs")),
+            change("README.md", ChangeKind::Added, None),
+            change("srcs/other.rs", ChangeKind::Deleted, None),
+        ];
+
+        assert_eq!(IngestService::changes_under(changes.clone(), ""), changes);
+        assert_eq!(
+            IngestService::changes_under(changes, "src"),
+            vec![change("lib.rs", ChangeKind::Modified, None), change("new.rs", ChangeKind::Renamed, None)]
+        );
+    }
+
+    #[cfg(not(feature = "git"))]
+    #[tokio::test]
+    async fn test_cloning_requires_git_feature() {
+        let request = IngestRequest {
+            input_text: "https://github.com/owner/repo".to_string(),
+            ..Default::default()
+        };
+
+        let failure = IngestService::try_process_repository(request, &AppConfig::default(), Uuid::new_v4(), CancellationToken::new())
+            .await
+            .unwrap_err();
+
+        assert_eq!(failure.phase, IngestPhase::Clone);
+        assert_eq!(failure.error_code, "config_error");
+        assert!(failure.message.contains("`git` feature"));
+    }
+
+    #[cfg(feature = "git")]
+    #[tokio::test]
+    async fn test_response_id_matches_request_id() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo_path = temp_dir.path().join("owner").join("repo");
+        create_local_repo(&repo_path);
+
+        let request = IngestRequest {
+            input_text: format!("file://{}", repo_path.display()),
+            ..Default::default()
+        };
+        let id = Uuid::new_v4();
+        let response = IngestService::process_repository(request, &local_config(), id)
+            .await
+            .unwrap();
+
+        assert_eq!(response.id, id);
+        assert_eq!(response.short_repo_url, "owner/repo");
+        assert!(response.content.contains("fn main() {}"));
+    }
+
+    #[cfg(feature = "git")]
+    #[tokio::test]
+    async fn test_cancelled_ingestion_stops_at_clone() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo_path = temp_dir.path().join("owner").join("repo");
+        create_local_repo(&repo_path);
+
+        let request = IngestRequest {
+            input_text: format!("file://{}", repo_path.display()),
+            ..Default::default()
+        };
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+
+        let result = IngestService::process_repository_cancellable(
+            request,
+            &local_config(),
+            Uuid::new_v4(),
+            cancel,
+        ).await;
+
+        assert!(matches!(result, Err(GitingestError::Cancelled(phase)) if phase == "clone"));
+    }
+
+    #[cfg(feature = "git")]
+    #[tokio::test]
+    async fn test_structured_json_includes_files_and_tree() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo_path = temp_dir.path().join("owner").join("repo");
+        create_local_repo(&repo_path);
+
+        let request = IngestRequest {
+            input_text: format!("file://{}", repo_path.display()),
+            download_format: Some(DownloadFormat::JsonStructured),
+            ..Default::default()
+        };
+        let response = IngestService::process_repository(request, &local_config(), Uuid::new_v4())
+            .await
+            .unwrap();
+
+        let files = response.files.unwrap();
+        let main = files.iter().find(|f| f.relative_path == "src/main.rs").unwrap();
+        assert_eq!(main.content.as_deref(), Some("fn main() {}"));
+        assert_eq!(main.language.as_deref(), Some("rust"));
+
+        let tree = serde_json::to_value(response.file_tree.unwrap()).unwrap();
+        let src = tree["children"].as_array().unwrap().iter().find(|c| c["path"] == "src").unwrap();
+        assert_eq!(src["type"], "directory");
+        assert_eq!(src["children"][0]["path"], "src/main.rs");
+        assert_eq!(src["size"], 12);
+    }
+
+    #[cfg(feature = "git")]
+    #[tokio::test]
+    async fn test_json_includes_nested_tree_without_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo_path = temp_dir.path().join("owner").join("repo");
+        create_local_repo(&repo_path);
+        std::fs::create_dir_all(repo_path.join("src/nested")).unwrap();
+        std::fs::write(repo_path.join("src/nested/mod.rs"), "pub mod a;").unwrap();
+        commit_all(&repo_path);
+
+        let request = IngestRequest {
+            input_text: format!("file://{}", repo_path.display()),
+            download_format: Some(DownloadFormat::Json),
+            ..Default::default()
+        };
+        let response = IngestService::process_repository(request, &local_config(), Uuid::new_v4())
+            .await
+            .unwrap();
+
+        let json = serde_json::to_value(&response).unwrap();
+        assert!(json.get("files").is_none());
+
+        let tree = &json["file_tree"];
+        assert_eq!(tree["type"], "directory");
+        assert_eq!(tree["size"], 6 + 12 + 10);
+        let src = &tree["children"][0];
+        assert_eq!(src["path"], "src");
+        assert_eq!(src["size"], 12 + 10);
+        let nested = &src["children"][0];
+        assert_eq!(nested["path"], "src/nested");
+        assert_eq!(nested["children"][0]["path"], "src/nested/mod.rs");
+        assert_eq!(nested["children"][0]["type"], "file");
+        assert_eq!(nested["children"][0]["size"], 10);
+        assert_eq!(nested["children"][0]["has_content"], true);
+        assert!(nested["children"][0].get("children").is_none());
+    }
+
+    #[cfg(feature = "git")]
+    #[tokio::test]
+    async fn test_text_omits_structured_fields() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo_path = temp_dir.path().join("owner").join("repo");
+        create_local_repo(&repo_path);
+
+        let request = IngestRequest {
+            input_text: format!("file://{}", repo_path.display()),
+            download_format: Some(DownloadFormat::Text),
+            ..Default::default()
+        };
+        let response = IngestService::process_repository(request, &local_config(), Uuid::new_v4())
+            .await
+            .unwrap();
+
+        assert!(response.files.is_none());
+        assert!(response.file_tree.is_none());
+    }
+}
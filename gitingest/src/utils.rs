@@ -1,9 +1,33 @@
+#[cfg(all(feature = "git", feature = "remote"))]
+pub mod archive;
+#[cfg(feature = "git")]
+pub mod cache;
+#[cfg(feature = "archive")]
+pub mod extract;
+#[cfg(all(feature = "git", feature = "remote"))]
+pub mod github_api;
+#[cfg(feature = "git")]
 pub mod git;
+pub mod credentials;
 pub mod patterns;
 pub mod files;
 pub mod url_parser;
+pub mod tokens;
+pub mod humanize;
+pub mod license;
+pub mod ordering;
 
+#[cfg(feature = "git")]
+pub use cache::*;
+#[cfg(feature = "archive")]
+pub use extract::*;
+#[cfg(feature = "git")]
 pub use git::*;
+pub use credentials::*;
 pub use patterns::*;
 pub use files::*;
-pub use url_parser::*;
\ No newline at end of file
+pub use url_parser::*;
+pub use tokens::*;
+pub use humanize::*;
+pub use license::*;
+pub use ordering::*;
\ No newline at end of file
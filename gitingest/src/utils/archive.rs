@@ -0,0 +1,187 @@
+use crate::error::{GitingestError, Result};
+use crate::models::{CloneConfig, CloneOutcome};
+use crate::utils::{GitCredentials, GitService, Platform, UrlParser};
+use crate::utils::extract::{is_metadata, unpack_entry};
+use flate2::read::GzDecoder;
+use futures::TryStreamExt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use tokio_util::io::{StreamReader, SyncIoBridge};
+
+impl GitService {
+    /// Downloads the GitHub tarball of the configured commit or branch and
+    /// unpacks it into `config.local_path` like a checkout, without needing
+    /// a `git` binary. Private repositories are fetched through the API
+    /// `tarball` endpoint with `config.token`.
+    pub async fn download_archive(config: &CloneConfig) -> Result<CloneOutcome> {
+        Self::validate_clone_config(config)?;
+        let start_time = Instant::now();
+        let repository = UrlParser::parse_git_url(&config.url)?;
+        if repository.host != "github.com" {
+            return Err(GitingestError::ValidationError(format!(
+                "Archive downloads are only supported for github.com, not {}",
+                repository.host
+            )));
+        }
+
+        let reference = config.git_ref.fetch_ref();
+        let url = archive_url(&repository.owner, &repository.name, reference.as_deref(), config.token.is_some());
+        log::info!("Downloading archive {} to {:?}", url, config.local_path);
+
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("gitingest/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        let mut request = client.get(&url);
+        if let Some(token) = &config.token {
+            // A `username:token` pair authenticates by its token
+            request = request.bearer_auth(GitCredentials::for_platform(Platform::GitHub, token).password);
+        }
+        let response = tokio::select! {
+            _ = config.cancel.cancelled() => {
+                return Err(GitingestError::Cancelled("clone".to_string()));
+            }
+            response = request.send() => response?,
+        };
+        match response.status() {
+            reqwest::StatusCode::NOT_FOUND => return Err(GitingestError::RepositoryNotFound(config.url.clone())),
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => {
+                return Err(GitingestError::AuthenticationRequired(config.url.clone()));
+            }
+            _ => {}
+        }
+        let response = response.error_for_status()?;
+
+        // Unpack while downloading; tar and flate2 only read synchronously
+        let stream = response.bytes_stream().map_err(std::io::Error::other);
+        let reader = SyncIoBridge::new(StreamReader::new(stream));
+        let local_path = config.local_path.clone();
+        let unpack = tokio::task::spawn_blocking(move || unpack_archive(reader, &local_path));
+        tokio::select! {
+            _ = config.cancel.cancelled() => {
+                return Err(GitingestError::Cancelled("clone".to_string()));
+            }
+            result = unpack => result.map_err(|e| {
+                GitingestError::InternalError(format!("Archive unpack task failed: {}", e))
+            })??,
+        }
+
+        log::info!("Archive download completed in {:.2}s", start_time.elapsed().as_secs_f64());
+        Ok(CloneOutcome::default())
+    }
+}
+
+fn archive_url(owner: &str, name: &str, reference: Option<&str>, authenticated: bool) -> String {
+    if authenticated {
+        // Redirects to a short-lived codeload URL; omitting the ref picks
+        // the default branch
+        match reference {
+            Some(reference) => format!("https://api.github.com/repos/{}/{}/tarball/{}", owner, name, reference),
+            None => format!("https://api.github.com/repos/{}/{}/tarball", owner, name),
+        }
+    } else {
+        format!("https://codeload.github.com/{}/{}/tar.gz/{}", owner, name, reference.unwrap_or("HEAD"))
+    }
+}
+
+/// Unpacks a gzipped tarball into `dest`, dropping the `repo-<ref>/`
+/// directory that every entry sits under. Links are left out as in
+/// [`unpack_entry`].
+fn unpack_archive<R: Read>(reader: R, dest: &Path) -> Result<()> {
+    std::fs::create_dir_all(dest)?;
+    let mut archive = tar::Archive::new(GzDecoder::new(reader));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if is_metadata(entry.header().entry_type()) {
+            continue;
+        }
+
+        let path = entry.path()?.into_owned();
+        let relative: PathBuf = path.components().skip(1).collect();
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+        unpack_entry(&mut entry, dest, &relative, &path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use tar::EntryType;
+    use tempfile::TempDir;
+
+    fn tarball(build: impl FnOnce(&mut tar::Builder<GzEncoder<Vec<u8>>>)) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::fast()));
+        build(&mut builder);
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn append_file(builder: &mut tar::Builder<GzEncoder<Vec<u8>>>, path: &str, content: &[u8]) {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_entry_type(EntryType::Regular);
+        builder.append_data(&mut header, path, content).unwrap();
+    }
+
+    #[test]
+    fn test_archive_url() {
+        assert_eq!(archive_url("o", "r", Some("main"), false), "https://codeload.github.com/o/r/tar.gz/main");
+        assert_eq!(archive_url("o", "r", None, false), "https://codeload.github.com/o/r/tar.gz/HEAD");
+        assert_eq!(archive_url("o", "r", Some("v1"), true), "https://api.github.com/repos/o/r/tarball/v1");
+        assert_eq!(archive_url("o", "r", None, true), "https://api.github.com/repos/o/r/tarball");
+    }
+
+    #[test]
+    fn test_unpack_strips_top_level_directory() {
+        let archive = tarball(|builder| {
+            let mut header = tar::Header::new_ustar();
+            header.set_size(0);
+            header.set_entry_type(EntryType::XGlobalHeader);
+            builder.append_data(&mut header, "pax_global_header", &[][..]).unwrap();
+            append_file(builder, "repo-main/README.md", b"# Repo\n");
+            append_file(builder, "repo-main/src/lib.rs", b"pub fn f() {}\n");
+        });
+        let temp_dir = TempDir::new().unwrap();
+        let dest = temp_dir.path().join("repo");
+
+        unpack_archive(&archive[..], &dest).unwrap();
+
+        assert_eq!(std::fs::read_to_string(dest.join("README.md")).unwrap(), "# Repo\n");
+        assert_eq!(std::fs::read_to_string(dest.join("src/lib.rs")).unwrap(), "pub fn f() {}\n");
+        assert!(!dest.join("repo-main").exists());
+        assert!(!dest.join("pax_global_header").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unpack_leaves_out_links() {
+        let outside = TempDir::new().unwrap();
+        std::fs::write(outside.path().join("secret"), "host file").unwrap();
+        let archive = tarball(|builder| {
+            let links = [
+                (EntryType::Symlink, "repo-main/escape", outside.path().to_path_buf()),
+                (EntryType::Link, "repo-main/secret", outside.path().join("secret")),
+            ];
+            for (entry_type, path, target) in links {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(0);
+                header.set_entry_type(entry_type);
+                builder.append_link(&mut header, path, target).unwrap();
+            }
+            append_file(builder, "repo-main/escape/pwned", b"x");
+        });
+        let temp_dir = TempDir::new().unwrap();
+        let dest = temp_dir.path().join("repo");
+
+        unpack_archive(&archive[..], &dest).unwrap();
+
+        assert!(!outside.path().join("pwned").exists());
+        assert!(dest.join("escape").is_dir());
+        assert!(dest.join("secret").symlink_metadata().is_err());
+    }
+}
@@ -0,0 +1,290 @@
+use crate::error::{GitingestError, Result};
+use crate::models::{GitRef, Repository};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use walkdir::WalkDir;
+
+/// Checkout used when a repository is cloned without a branch.
+const DEFAULT_BRANCH_KEY: &str = "_default";
+
+/// Directory of reusable clones. Each is checked out at
+/// `host/owner/name/<ref>-<hash>/name`, where `<hash>` covers the full URL
+/// and ref so distinct repositories never share an entry, and the trailing
+/// `name` keeps the checkout's directory name.
+pub struct CloneCache {
+    root: PathBuf,
+}
+
+/// A cached clone, as listed by [`CloneCache::entries`].
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    /// `host/owner/name/<ref>-<hash>`, relative to the cache root.
+    pub key: String,
+    pub path: PathBuf,
+    pub size_bytes: u64,
+    /// When an ingestion last locked the entry.
+    pub last_used: Option<SystemTime>,
+}
+
+/// Limits applied by [`CloneCache::prune`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheLimits {
+    /// Entries unused for longer are removed.
+    pub ttl: Option<Duration>,
+    /// Least recently used entries are removed until the cache fits.
+    pub max_bytes: Option<u64>,
+}
+
+/// Exclusive hold on a cache entry; released when dropped.
+pub struct CacheLock {
+    _file: File,
+}
+
+impl CloneCache {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Where `repository` is checked out inside the cache.
+    pub fn entry_path(&self, repository: &Repository) -> PathBuf {
+        let reference = match &repository.git_ref {
+            GitRef::DefaultBranch => DEFAULT_BRANCH_KEY.to_string(),
+            GitRef::Branch(name) | GitRef::Tag(name) | GitRef::Commit(name) => name.clone(),
+            GitRef::PullRequest(number) => format!("pull-{}", number),
+        };
+        let hash = Sha256::digest(format!("{}\0{}", repository.url, reference));
+        let hash = format!("{:x}", hash);
+        let name = sanitize_component(&repository.name);
+        self.root
+            .join(sanitize_component(&repository.host))
+            .join(sanitize_component(&repository.owner))
+            .join(&name)
+            .join(format!("{}-{}", sanitize_component(&reference), &hash[..12]))
+            .join(&name)
+    }
+
+    /// Locks the entry at `entry_path` so concurrent ingestions of the same
+    /// repository (in this or another process) take turns.
+    pub async fn lock(&self, entry_path: &Path) -> Result<CacheLock> {
+        let lock_path = lock_path(entry_path);
+        tokio::task::spawn_blocking(move || {
+            if let Some(parent) = lock_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let file = File::create(&lock_path)?;
+            file.lock()?;
+            // The lock file's mtime records when the entry was last used
+            file.set_modified(SystemTime::now())?;
+            Ok(CacheLock { _file: file })
+        })
+        .await
+        .map_err(|e| GitingestError::InternalError(format!("Cache lock task failed: {}", e)))?
+    }
+
+    /// Lists cached clones, sorted by key.
+    pub fn entries(&self) -> Result<Vec<CacheEntry>> {
+        if !self.root.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut entries = Vec::new();
+        for entry in WalkDir::new(&self.root).min_depth(5).max_depth(5) {
+            let entry = entry.map_err(|e| GitingestError::FileSystemError(e.to_string()))?;
+            if !entry.file_type().is_dir() || !entry.path().join(".git").exists() {
+                continue;
+            }
+            let key_path = entry.path().parent().unwrap_or(entry.path());
+            let key = key_path
+                .strip_prefix(&self.root)
+                .unwrap_or(key_path)
+                .to_string_lossy()
+                .replace('\\', "/");
+            entries.push(CacheEntry {
+                key,
+                path: entry.path().to_path_buf(),
+                size_bytes: directory_size(entry.path()),
+                last_used: std::fs::metadata(lock_path(entry.path()))
+                    .and_then(|m| m.modified())
+                    .ok(),
+            });
+        }
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(entries)
+    }
+
+    /// Removes entries past `limits.ttl`, then the least recently used ones
+    /// until the cache fits in `limits.max_bytes`. Entries locked by a
+    /// running ingestion are left alone. Returns how many were removed.
+    pub fn prune(&self, limits: &CacheLimits) -> Result<usize> {
+        let mut entries = self.entries()?;
+        entries.sort_by_key(|entry| entry.last_used);
+
+        let now = SystemTime::now();
+        let mut total: u64 = entries.iter().map(|entry| entry.size_bytes).sum();
+        let mut removed = 0;
+        for entry in entries {
+            let expired = limits.ttl.is_some_and(|ttl| {
+                entry
+                    .last_used
+                    .and_then(|used| now.duration_since(used).ok())
+                    .is_none_or(|age| age > ttl)
+            });
+            let over_size = limits.max_bytes.is_some_and(|max| total > max);
+            if !expired && !over_size {
+                continue;
+            }
+
+            // Skip entries another ingestion is using
+            let lock_file = File::create(lock_path(&entry.path))?;
+            if lock_file.try_lock().is_err() {
+                continue;
+            }
+            log::info!("Evicting cached clone {}", entry.key);
+            std::fs::remove_dir_all(&entry.path)?;
+            total = total.saturating_sub(entry.size_bytes);
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    /// Removes every cached clone, returning how many there were. Entries
+    /// locked by a running ingestion are waited for.
+    pub async fn clear(&self) -> Result<usize> {
+        let entries = self.entries()?;
+        for entry in &entries {
+            let _lock = self.lock(&entry.path).await?;
+            tokio::fs::remove_dir_all(&entry.path).await?;
+        }
+        if self.root.exists() {
+            tokio::fs::remove_dir_all(&self.root).await?;
+        }
+        Ok(entries.len())
+    }
+}
+
+fn lock_path(entry_path: &Path) -> PathBuf {
+    let mut name = entry_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".lock");
+    entry_path.with_file_name(name)
+}
+
+/// Keeps a URL or branch component to a single, portable path segment.
+fn sanitize_component(component: &str) -> String {
+    let sanitized: String = component
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' })
+        .collect();
+    match sanitized.as_str() {
+        "" | "." | ".." => format!("_{}", sanitized),
+        _ => sanitized,
+    }
+}
+
+fn directory_size(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn repository(branch: Option<&str>) -> Repository {
+        Repository {
+            url: "https://github.com/owner/repo".to_string(),
+            host: "github.com".to_string(),
+            owner: "owner".to_string(),
+            name: "repo".to_string(),
+            git_ref: branch.map_or(GitRef::DefaultBranch, |branch| GitRef::Branch(branch.to_string())),
+            subpath: String::new(),
+            target_kind: None,
+        }
+    }
+
+    #[test]
+    fn test_entry_path_is_keyed_by_branch() {
+        let cache = CloneCache::new("/cache");
+
+        let key = |branch| {
+            let path = cache.entry_path(&repository(branch));
+            assert_eq!(path.file_name().unwrap(), "repo");
+            path.parent().unwrap().strip_prefix("/cache").unwrap().to_string_lossy().into_owned()
+        };
+
+        assert!(key(None).starts_with("github.com/owner/repo/_default-"));
+        assert!(key(Some("feature/x")).starts_with("github.com/owner/repo/feature_x-"));
+        // Branches that sanitize to the same name still get their own entry
+        assert_ne!(key(Some("feature/x")), key(Some("feature_x")));
+        assert_eq!(key(Some("main")), key(Some("main")));
+    }
+
+    #[tokio::test]
+    async fn test_entries_and_clear() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = CloneCache::new(temp_dir.path().join("cache"));
+        let entry = cache.entry_path(&repository(Some("main")));
+        std::fs::create_dir_all(entry.join(".git")).unwrap();
+        std::fs::write(entry.join("README.md"), "12345").unwrap();
+        drop(cache.lock(&entry).await.unwrap());
+
+        let entries = cache.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].key.starts_with("github.com/owner/repo/main-"));
+        assert_eq!(entries[0].size_bytes, 5);
+        assert!(entries[0].last_used.is_some());
+
+        assert_eq!(cache.clear().await.unwrap(), 1);
+        assert!(cache.entries().unwrap().is_empty());
+        assert!(!cache.root().exists());
+    }
+
+    fn cached_entry(cache: &CloneCache, branch: &str, size: usize, last_used: SystemTime) -> PathBuf {
+        let entry = cache.entry_path(&repository(Some(branch)));
+        std::fs::create_dir_all(entry.join(".git")).unwrap();
+        std::fs::write(entry.join("data"), vec![b'x'; size]).unwrap();
+        File::create(lock_path(&entry)).unwrap().set_modified(last_used).unwrap();
+        entry
+    }
+
+    #[test]
+    fn test_prune_removes_expired_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = CloneCache::new(temp_dir.path());
+        let now = SystemTime::now();
+        let stale = cached_entry(&cache, "stale", 1, now - Duration::from_secs(3600));
+        let fresh = cached_entry(&cache, "fresh", 1, now);
+
+        let limits = CacheLimits { ttl: Some(Duration::from_secs(60)), max_bytes: None };
+        assert_eq!(cache.prune(&limits).unwrap(), 1);
+        assert!(!stale.exists());
+        assert!(fresh.exists());
+    }
+
+    #[test]
+    fn test_prune_evicts_least_recently_used_over_size_cap() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = CloneCache::new(temp_dir.path());
+        let now = SystemTime::now();
+        let oldest = cached_entry(&cache, "oldest", 100, now - Duration::from_secs(30));
+        let older = cached_entry(&cache, "older", 100, now - Duration::from_secs(20));
+        let newest = cached_entry(&cache, "newest", 100, now - Duration::from_secs(10));
+
+        let limits = CacheLimits { ttl: None, max_bytes: Some(150) };
+        assert_eq!(cache.prune(&limits).unwrap(), 2);
+        assert!(!oldest.exists());
+        assert!(!older.exists());
+        assert!(newest.exists());
+    }
+}
@@ -0,0 +1,309 @@
+use crate::error::{GitingestError, Result};
+use flate2::read::GzDecoder;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use tar::EntryType;
+
+/// File extensions of the archives accepted as inputs.
+pub const ARCHIVE_EXTENSIONS: &[&str] = &[".zip", ".tar.gz", ".tgz"];
+
+/// The archive `input` names, if it is the path of an existing `.zip`,
+/// `.tar.gz` or `.tgz` file.
+pub fn archive_input(input: &str) -> Option<PathBuf> {
+    let lower = input.to_lowercase();
+    if !ARCHIVE_EXTENSIONS.iter().any(|extension| lower.ends_with(extension)) {
+        return None;
+    }
+    let path = PathBuf::from(input);
+    path.is_file().then_some(path)
+}
+
+/// The archive's file name without its archive extension, as in
+/// `snapshot` for `snapshot.tar.gz`.
+pub fn archive_stem(path: &Path) -> String {
+    let name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+    let lower = name.to_lowercase();
+    ARCHIVE_EXTENSIONS
+        .iter()
+        .find(|extension| lower.ends_with(*extension))
+        .map_or(name.to_string(), |extension| name[..name.len() - extension.len()].to_string())
+}
+
+/// Extracts the archive at `archive` into `dest`. When every entry sits
+/// under one top-level directory, as in `snapshot/src/lib.rs`, that
+/// directory is dropped. Entries that would land outside `dest` fail the
+/// extraction with [`GitingestError::ValidationError`].
+pub async fn extract_archive(archive: &Path, dest: &Path) -> Result<()> {
+    let (archive, dest) = (archive.to_path_buf(), dest.to_path_buf());
+    tokio::task::spawn_blocking(move || {
+        std::fs::create_dir_all(&dest)?;
+        if archive.to_string_lossy().to_lowercase().ends_with(".zip") {
+            extract_zip(&archive, &dest)
+        } else {
+            extract_tar_gz(&archive, &dest)
+        }
+    })
+    .await
+    .map_err(|e| GitingestError::InternalError(format!("Archive extraction task failed: {}", e)))?
+}
+
+fn extract_zip(archive: &Path, dest: &Path) -> Result<()> {
+    let invalid = |e: zip::result::ZipError| {
+        GitingestError::ValidationError(format!("Invalid archive {}: {}", archive.display(), e))
+    };
+    let mut zip = zip::ZipArchive::new(File::open(archive)?).map_err(invalid)?;
+
+    let mut entries = Vec::new();
+    for i in 0..zip.len() {
+        let file = zip.by_index(i).map_err(invalid)?;
+        entries.push((PathBuf::from(file.name()), file.is_dir()));
+    }
+    let root = single_root(&entries);
+
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i).map_err(invalid)?;
+        let path = PathBuf::from(file.name());
+        let Some(relative) = strip_root(&path, root.as_ref()) else {
+            continue;
+        };
+        // Symlinks are left out rather than written as files holding their target
+        if file.is_symlink() {
+            continue;
+        }
+        let target = entry_destination(dest, &relative, &path)?;
+        if file.is_dir() {
+            std::fs::create_dir_all(&target)?;
+        } else {
+            std::io::copy(&mut file, &mut File::create(&target)?)?;
+        }
+    }
+    Ok(())
+}
+
+fn extract_tar_gz(archive: &Path, dest: &Path) -> Result<()> {
+    let open = || -> Result<tar::Archive<GzDecoder<File>>> { Ok(tar::Archive::new(GzDecoder::new(File::open(archive)?))) };
+
+    let mut entries = Vec::new();
+    for entry in open()?.entries()? {
+        let entry = entry?;
+        let entry_type = entry.header().entry_type();
+        if !is_metadata(entry_type) {
+            entries.push((entry.path()?.into_owned(), entry_type.is_dir()));
+        }
+    }
+    let root = single_root(&entries);
+
+    for entry in open()?.entries()? {
+        let mut entry = entry?;
+        if is_metadata(entry.header().entry_type()) {
+            continue;
+        }
+        let path = entry.path()?.into_owned();
+        let Some(relative) = strip_root(&path, root.as_ref()) else {
+            continue;
+        };
+        unpack_entry(&mut entry, dest, &relative, &path)?;
+    }
+    Ok(())
+}
+
+/// Unpacks the tar `entry` named `name` to `relative` below `dest`. Hard
+/// links and symlinks are left out, as in zip archives: their targets come
+/// verbatim from the archive and could pull in files from anywhere on the
+/// host.
+pub(crate) fn unpack_entry<R: Read>(entry: &mut tar::Entry<R>, dest: &Path, relative: &Path, name: &Path) -> Result<()> {
+    if matches!(entry.header().entry_type(), EntryType::Link | EntryType::Symlink) {
+        return Ok(());
+    }
+    entry.unpack(entry_destination(dest, relative, name)?)?;
+    Ok(())
+}
+
+/// Pax headers carry metadata for other entries rather than files.
+pub(crate) fn is_metadata(entry_type: EntryType) -> bool {
+    matches!(entry_type, EntryType::XGlobalHeader | EntryType::XHeader)
+}
+
+/// The one top-level directory every entry sits under, if there is one.
+fn single_root(entries: &[(PathBuf, bool)]) -> Option<OsString> {
+    let mut root = None;
+    for (path, is_dir) in entries {
+        let mut components = path.components().filter(|c| *c != Component::CurDir);
+        let Some(Component::Normal(first)) = components.next() else {
+            return None;
+        };
+        // A file at the top level means there is no wrapping directory
+        if components.next().is_none() && !is_dir {
+            return None;
+        }
+        match &root {
+            None => root = Some(first.to_os_string()),
+            Some(root) if root != first => return None,
+            Some(_) => {}
+        }
+    }
+    root
+}
+
+/// `path` below `root`, or `None` for `root` itself.
+fn strip_root(path: &Path, root: Option<&OsString>) -> Option<PathBuf> {
+    let components = path.components().filter(|c| *c != Component::CurDir);
+    let relative: PathBuf = components.skip(usize::from(root.is_some())).collect();
+    (!relative.as_os_str().is_empty()).then_some(relative)
+}
+
+/// Where the entry `name`, at `relative` below the archive's root, is
+/// written under `dest`, with its parent directories created. Fails for
+/// absolute paths, `..` components and paths through a symlink unpacked
+/// earlier, any of which could write outside `dest`.
+pub(crate) fn entry_destination(dest: &Path, relative: &Path, name: &Path) -> Result<PathBuf> {
+    if relative.components().any(|c| !matches!(c, Component::Normal(_))) {
+        return Err(GitingestError::ValidationError(format!(
+            "Archive entry '{}' escapes the repository",
+            name.display()
+        )));
+    }
+
+    let mut parent = dest.to_path_buf();
+    for component in relative.parent().into_iter().flat_map(Path::components) {
+        parent.push(component);
+        if parent.symlink_metadata().is_ok_and(|m| m.file_type().is_symlink()) {
+            return Err(GitingestError::ValidationError(format!(
+                "Archive entry '{}' is inside a symlink",
+                name.display()
+            )));
+        }
+    }
+    std::fs::create_dir_all(&parent)?;
+    Ok(dest.join(relative))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    fn write_zip(path: &Path, entries: &[(&str, &str)]) {
+        let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+        for (name, content) in entries {
+            if let Some(dir) = name.strip_suffix('/') {
+                zip.add_directory(dir, zip::write::SimpleFileOptions::default()).unwrap();
+            } else {
+                zip.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+                zip.write_all(content.as_bytes()).unwrap();
+            }
+        }
+        zip.finish().unwrap();
+    }
+
+    /// Writes entry names verbatim, including ones `tar::Builder` refuses.
+    fn write_tar_gz(path: &Path, entries: &[(&str, &str)]) {
+        let mut builder = tar::Builder::new(GzEncoder::new(File::create(path).unwrap(), Compression::fast()));
+        for (name, content) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_entry_type(EntryType::Regular);
+            header.set_cksum();
+            builder.append(&header, content.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[test]
+    fn test_archive_input_and_stem() {
+        let temp_dir = TempDir::new().unwrap();
+        let zip_path = temp_dir.path().join("Snapshot.ZIP");
+        write_zip(&zip_path, &[("a.txt", "a")]);
+
+        assert_eq!(archive_input(zip_path.to_str().unwrap()), Some(zip_path.clone()));
+        assert_eq!(archive_input(temp_dir.path().join("missing.zip").to_str().unwrap()), None);
+        assert_eq!(archive_input("https://github.com/owner/repo"), None);
+        assert_eq!(archive_stem(&zip_path), "Snapshot");
+        assert_eq!(archive_stem(Path::new("/tmp/src-1.2.tar.gz")), "src-1.2");
+        assert_eq!(archive_stem(Path::new("src.tgz")), "src");
+    }
+
+    #[tokio::test]
+    async fn test_extract_strips_a_single_top_level_directory() {
+        let temp_dir = TempDir::new().unwrap();
+        let zip_path = temp_dir.path().join("snapshot.zip");
+        write_zip(
+            &zip_path,
+            &[("snapshot/", ""), ("snapshot/README.md", "# Snapshot"), ("snapshot/src/lib/util.rs", "pub fn f() {}")],
+        );
+        let tar_path = temp_dir.path().join("snapshot.tgz");
+        write_tar_gz(&tar_path, &[("./snapshot/README.md", "# Snapshot"), ("snapshot/src/lib/util.rs", "pub fn f() {}")]);
+
+        for archive in [zip_path, tar_path] {
+            let dest = temp_dir.path().join(archive_stem(&archive) + "-" + &archive.extension().unwrap().to_string_lossy());
+            extract_archive(&archive, &dest).await.unwrap();
+            assert_eq!(std::fs::read_to_string(dest.join("README.md")).unwrap(), "# Snapshot");
+            assert_eq!(std::fs::read_to_string(dest.join("src/lib/util.rs")).unwrap(), "pub fn f() {}");
+            assert!(!dest.join("snapshot").exists());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_extract_keeps_multiple_top_level_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = temp_dir.path().join("flat.tar.gz");
+        write_tar_gz(&archive, &[("src/main.rs", "fn main() {}"), ("README.md", "# Flat")]);
+
+        let dest = temp_dir.path().join("flat");
+        extract_archive(&archive, &dest).await.unwrap();
+
+        assert!(dest.join("src/main.rs").is_file());
+        assert!(dest.join("README.md").is_file());
+    }
+
+    #[tokio::test]
+    async fn test_extract_rejects_entries_escaping_the_destination() {
+        let temp_dir = TempDir::new().unwrap();
+        let zip_path = temp_dir.path().join("evil.zip");
+        write_zip(&zip_path, &[("repo/README.md", "ok"), ("repo/../../evil.txt", "pwned")]);
+        let tar_path = temp_dir.path().join("evil.tar.gz");
+        write_tar_gz(&tar_path, &[("README.md", "ok"), ("../evil.txt", "pwned")]);
+
+        for archive in [zip_path, tar_path] {
+            let dest = temp_dir.path().join("out").join("repo");
+            let err = extract_archive(&archive, &dest).await.unwrap_err();
+            assert!(matches!(err, GitingestError::ValidationError(_)), "{}", err);
+            assert!(!temp_dir.path().join("evil.txt").exists());
+            assert!(!temp_dir.path().join("out/evil.txt").exists());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_extract_skips_hard_links_and_symlinks() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = temp_dir.path().join("links.tar.gz");
+        let mut builder = tar::Builder::new(GzEncoder::new(File::create(&archive).unwrap(), Compression::fast()));
+        for (entry_type, name) in [(EntryType::Link, "passwd"), (EntryType::Symlink, "hosts")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(0);
+            header.set_mode(0o644);
+            header.set_entry_type(entry_type);
+            builder.append_link(&mut header, name, format!("/etc/{}", name)).unwrap();
+        }
+        let mut header = tar::Header::new_gnu();
+        header.set_size(2);
+        header.set_mode(0o644);
+        header.set_entry_type(EntryType::Regular);
+        builder.append_data(&mut header, "README.md", &b"ok"[..]).unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let dest = temp_dir.path().join("links");
+        extract_archive(&archive, &dest).await.unwrap();
+
+        assert!(dest.join("README.md").is_file());
+        assert!(dest.join("passwd").symlink_metadata().is_err());
+        assert!(dest.join("hosts").symlink_metadata().is_err());
+    }
+}
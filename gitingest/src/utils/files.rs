@@ -1,10 +1,10 @@
 use crate::error::{GitingestError, Result};
-use crate::models::{FileNode, FileNodeType, ContentWriter};
-use crate::utils::patterns::{is_binary_file, PatternService};
+use crate::models::{ChangeKind, ChangedFile, FileEntry, FileNode, FileNodeType, FileSystemStats, ContentWriter, IngestEvent, LanguageStat, ListedFile, ScanConfig, TreeEntry};
+use crate::utils::format_tokens;
+use crate::utils::patterns::{is_binary_file, language_name, PatternService};
 use crate::models::PatternMatcher;
-use encoding_rs::UTF_8;
 use futures::future::join_all;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::{fs as std_fs};
@@ -12,128 +12,105 @@ use tokio::fs;
 use tokio::sync::Semaphore;
 use walkdir::WalkDir;
 
+/// Largest file with a binary extension that is still sniffed for text.
+pub const BINARY_RESCUE_MAX_SIZE: u64 = 64 * 1024;
+
 pub struct FileService;
 
 impl FileService {
     pub fn read_file_content<P: AsRef<Path>>(path: P) -> Result<String> {
         let path = path.as_ref();
         let bytes = std_fs::read(path)?;
-        
-        // Detect encoding
-        let (cow, _encoding_used, _had_errors) = UTF_8.decode(&bytes);
-        
-        // If UTF-8 decoding had errors, try to detect the actual encoding
-        if _had_errors {
-            // Try common encodings
-            for encoding in &[encoding_rs::WINDOWS_1252, encoding_rs::ISO_8859_2] {
-                let (cow, _encoding_used, had_errors) = encoding.decode(&bytes);
-                if !had_errors {
-                    return Ok(cow.into_owned());
-                }
-            }
-            
-            // If all else fails, replace invalid sequences
-            return Ok(String::from_utf8_lossy(&bytes).into_owned());
-        }
-
-        Ok(cow.into_owned())
+        Ok(decode_bytes(&bytes))
     }
 
     pub async fn read_file_content_async<P: AsRef<Path>>(path: P) -> Result<String> {
         let path = path.as_ref();
         let bytes = fs::read(path).await?;
-        
-        // Detect encoding
-        let (cow, _encoding_used, _had_errors) = UTF_8.decode(&bytes);
-        
-        // If UTF-8 decoding had errors, try to detect the actual encoding
-        if _had_errors {
-            // Try common encodings
-            for encoding in &[encoding_rs::WINDOWS_1252, encoding_rs::ISO_8859_2] {
-                let (cow, _encoding_used, had_errors) = encoding.decode(&bytes);
-                if !had_errors {
-                    return Ok(cow.into_owned());
-                }
-            }
-            
-            // If all else fails, replace invalid sequences
-            return Ok(String::from_utf8_lossy(&bytes).into_owned());
-        }
-
-        Ok(cow.into_owned())
+        Ok(decode_bytes(&bytes))
     }
 
 
+    /// Scans `path` into a tree, along with counts of the files whose
+    /// content was skipped and why.
     pub async fn scan_directory<P: AsRef<Path>>(
         path: P,
         matcher: &PatternMatcher,
-        max_file_size: u64,
-        max_files: usize,
-        max_depth: u32,
-        concurrent_limit: usize,
-        batch_size: usize,
-    ) -> Result<FileNode> {
+        config: &ScanConfig,
+    ) -> Result<(FileNode, FileSystemStats)> {
         let path = path.as_ref();
+        let mut stats = FileSystemStats::default();
         
         let discovery_start = std::time::Instant::now();
-        let all_paths: Vec<PathBuf> = WalkDir::new(path)
-            .max_depth(max_depth as usize)
-            .into_iter()
-            .filter_map(|entry| {
-                let entry = entry.ok()?;
-                let entry_path = entry.path();
-                
-                // For directories, check if we should include them for traversal
-                if entry_path.is_dir() {
-                    if PatternService::should_include_directory(matcher, entry_path).unwrap_or(true) {
-                        Some(entry_path.to_path_buf())
-                    } else {
-                        None
-                    }
+        let mut all_paths: Vec<(PathBuf, std_fs::FileType)> = Vec::new();
+        let mut walker = WalkDir::new(path).max_depth(config.max_depth as usize).into_iter();
+        while let Some(entry) = walker.next() {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    log::warn!("Skipping unreadable path: {}", err);
+                    stats.skip(|skipped| skipped.read_error += 1);
+                    continue;
+                }
+            };
+            let entry_path = entry.path();
+            let relative_path = entry_path.strip_prefix(path).unwrap_or(entry_path);
+            
+            // Excluded directories are pruned without being read, so the
+            // files below them aren't counted as skipped one by one.
+            // Symlinks are never followed, so they are filtered like files
+            let is_dir = entry.file_type().is_dir();
+            let included = if is_dir {
+                PatternService::should_include_directory(matcher, relative_path).unwrap_or(true)
+            } else {
+                PatternService::should_include_file(matcher, relative_path).unwrap_or(false)
+            };
+            if !included {
+                if is_dir {
+                    walker.skip_current_dir();
                 } else {
-                    // For files, check if they match include patterns
-                    if PatternService::should_include_file(matcher, entry_path).unwrap_or(false) {
-                        Some(entry_path.to_path_buf())
-                    } else {
-                        None
-                    }
+                    stats.skip(|skipped| skipped.excluded += 1);
                 }
-            })
-            .take(max_files)
-            .collect();
+                continue;
+            }
+
+            if all_paths.len() < config.max_files {
+                all_paths.push((entry_path.to_path_buf(), entry.file_type()));
+            } else if !is_dir {
+                stats.skip(|skipped| skipped.over_file_limit += 1);
+            }
+        }
+        if config.cancel.is_cancelled() {
+            return Err(GitingestError::Cancelled("scan".to_string()));
+        }
         let discovery_duration = discovery_start.elapsed();
         log::info!("Path discovery completed in {:.3}s - found {} paths", 
                   discovery_duration.as_secs_f64(), all_paths.len());
 
-        let mut file_map: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
-        let mut all_files = Vec::new();
-        
-        for path_buf in all_paths {
-            if path_buf.is_file() {
-                all_files.push(path_buf.clone());
-                // Add all ancestor directories to file_map for complete path structure
-                let mut current_parent = path_buf.parent();
-                while let Some(parent) = current_parent {
-                    file_map.entry(parent.to_path_buf())
-                        .or_insert_with(Vec::new);
-                    current_parent = parent.parent();
-                }
-                // Add the file to its immediate parent directory
-                if let Some(parent) = path_buf.parent() {
-                    file_map.entry(parent.to_path_buf())
-                        .and_modify(|files| files.push(path_buf.clone()));
-                }
-            }
-        }
+        // The walk's file types are those of the entries themselves, so
+        // symlinks are kept as files and never followed
+        let all_files: Vec<PathBuf> = all_paths
+            .into_iter()
+            .filter(|(_, file_type)| file_type.is_file() || file_type.is_symlink())
+            .map(|(path_buf, _)| path_buf)
+            .collect();
+        let index = DirectoryIndex::new(path, &all_files);
 
         // Only process metadata, no content loading
         log::info!("Starting metadata processing of {} files", all_files.len());
+        let total = all_files.len();
+        config.emit(IngestEvent::FilesDiscovered { total }).await;
         let processing_start = std::time::Instant::now();
         
         let mut file_nodes: HashMap<PathBuf, FileNode> = HashMap::new();
-        let semaphore = Arc::new(Semaphore::new(concurrent_limit));
+        let semaphore = Arc::new(Semaphore::new(config.concurrent_limit));
+        let mut scanned = 0;
         
-        for chunk in all_files.chunks(batch_size) {
+        for chunk in all_files.chunks(config.batch_size) {
+            if config.cancel.is_cancelled() {
+                return Err(GitingestError::Cancelled("scan".to_string()));
+            }
+
             let futures: Vec<_> = chunk
                 .iter()
                 .map(|file_path| {
@@ -146,7 +123,7 @@ impl FileService {
                             &file_path,
                             &path_buf,
                             matcher,
-                            max_file_size,
+                            config,
                         ).await;
                         (file_path, result)
                     }
@@ -155,32 +132,277 @@ impl FileService {
 
             let batch_results = join_all(futures).await;
             for (file_path, result) in batch_results {
-                if let Ok(node) = result {
-                    file_nodes.insert(file_path, node);
+                match result {
+                    Ok(node) => {
+                        stats.record(&node, config.max_file_size);
+                        file_nodes.insert(file_path, node);
+                    }
+                    Err(err) => {
+                        log::warn!("Skipping unreadable file {}: {}", file_path.display(), err);
+                        stats.skip(|skipped| skipped.read_error += 1);
+                    }
                 }
             }
+            scanned += chunk.len();
+            config.emit(IngestEvent::FilesScanned { scanned, total }).await;
         }
         
         let processing_duration = processing_start.elapsed();
         log::info!("Metadata processing completed in {:.3}s", 
                   processing_duration.as_secs_f64());
 
-        Self::build_directory_tree(path, &file_nodes, &file_map)
+        Ok((Self::build_directory_tree(path, path, &mut file_nodes, &index), stats))
+    }
+
+    /// Builds the tree of a [`crate::models::CloneMode::Blobless`] clone
+    /// from its `files`, with paths relative to `root`, filtered and limited
+    /// like [`FileService::scan_directory`]: directories, the root included,
+    /// count against `max_files` as its walk counts them. There is nothing
+    /// on disk to read, so binary files are told by extension only, no file
+    /// is checked against the size or line limits and sizes and symlink
+    /// targets are unknown unless listed.
+    pub async fn scan_listed_tree<P: AsRef<Path>>(
+        root: P,
+        files: &[ListedFile],
+        matcher: &PatternMatcher,
+        config: &ScanConfig,
+    ) -> Result<(FileNode, FileSystemStats)> {
+        let root = root.as_ref();
+        let mut stats = FileSystemStats::default();
+        if config.cancel.is_cancelled() {
+            return Err(GitingestError::Cancelled("scan".to_string()));
+        }
+
+        let mut included_dirs: HashMap<PathBuf, bool> = HashMap::new();
+        let mut file_nodes: HashMap<PathBuf, FileNode> = HashMap::new();
+        let mut all_files = Vec::new();
+        // The root is the walk's first entry
+        let mut entries = 1;
+        for file in files {
+            let relative_path = Path::new(&file.path);
+            // Files below an excluded directory are pruned with it, uncounted
+            let mut dirs: Vec<&Path> = relative_path.ancestors().skip(1).filter(|dir| !dir.as_os_str().is_empty()).collect();
+            dirs.reverse();
+            let mut in_included_dir = true;
+            for dir in dirs {
+                let included = match included_dirs.get(dir) {
+                    Some(included) => *included,
+                    None => {
+                        let included = PatternService::should_include_directory(matcher, dir).unwrap_or(true);
+                        included_dirs.insert(dir.to_path_buf(), included);
+                        if included && dir.components().count() as u32 <= config.max_depth && entries < config.max_files {
+                            entries += 1;
+                        }
+                        included
+                    }
+                };
+                if !included {
+                    in_included_dir = false;
+                    break;
+                }
+            }
+            if !in_included_dir || path_depth(&file.path) > config.max_depth {
+                continue;
+            }
+            if !PatternService::should_include_file(matcher, relative_path).unwrap_or(false) {
+                stats.skip(|skipped| skipped.excluded += 1);
+                continue;
+            }
+            if entries >= config.max_files {
+                stats.skip(|skipped| skipped.over_file_limit += 1);
+                continue;
+            }
+            entries += 1;
+
+            let path = root.join(relative_path);
+            let node = FileNode {
+                name: relative_path
+                    .file_name()
+                    .unwrap_or(relative_path.as_os_str())
+                    .to_string_lossy()
+                    .into_owned(),
+                path: path.clone(),
+                relative_path: file.path.clone(),
+                node_type: if file.symlink { FileNodeType::Symlink } else { FileNodeType::File },
+                size: if file.symlink { 0 } else { file.size.unwrap_or(0) },
+                has_content: !file.symlink
+                    && file.size.is_none_or(|size| size <= config.max_file_size)
+                    && !is_binary_file(&path),
+                children: Vec::new(),
+                depth: path_depth(&file.path),
+                link_target: None,
+                budget_omitted: false,
+                text_rescued: false,
+                tokens: None,
+                pinned: false,
+                over_line_limit: None,
+                deleted: false,
+                renamed_from: None,
+                last_modified: None,
+            };
+            stats.record(&node, config.max_file_size);
+            file_nodes.insert(path.clone(), node);
+            all_files.push(path);
+        }
+
+        let total = all_files.len();
+        config.emit(IngestEvent::FilesDiscovered { total }).await;
+        config.emit(IngestEvent::FilesScanned { scanned: total, total }).await;
+        let index = DirectoryIndex::new(root, &all_files);
+        Ok((Self::build_directory_tree(root, root, &mut file_nodes, &index), stats))
+    }
+
+    /// Builds a flat tree of exactly the listed repo-relative `paths`, in
+    /// the given order, without applying any patterns. Paths that don't
+    /// name a file or symlink under `root` are returned as missing, and
+    /// aren't counted in the stats.
+    pub async fn scan_listed_files<P: AsRef<Path>>(
+        root: P,
+        paths: &[String],
+        config: &ScanConfig,
+    ) -> Result<(FileNode, Vec<String>, FileSystemStats)> {
+        let root = root.as_ref();
+        let matcher = PatternMatcher {
+            include_patterns: Vec::new(),
+            exclude_patterns: Vec::new(),
+            gitignore_patterns: Vec::new(),
+        };
+
+        let mut children = Vec::new();
+        let mut missing = Vec::new();
+        let mut stats = FileSystemStats::default();
+        for relative_path in paths {
+            if config.cancel.is_cancelled() {
+                return Err(GitingestError::Cancelled("scan".to_string()));
+            }
+
+            let file_path = root.join(relative_path);
+            let is_listed_file = std_fs::symlink_metadata(&file_path)
+                .map(|metadata| !metadata.is_dir())
+                .unwrap_or(false);
+            if !is_listed_file || Self::is_under_symlink(root, Path::new(relative_path)) {
+                missing.push(relative_path.clone());
+                continue;
+            }
+
+            let mut node = Self::process_file(&file_path, &root.to_path_buf(), &matcher, config).await?;
+            // Listed files are shown flat, so name them by their full path
+            node.name = node.relative_path.clone();
+            stats.record(&node, config.max_file_size);
+            children.push(node);
+        }
+
+        let root_node = FileNode {
+            name: root
+                .file_name()
+                .unwrap_or(root.as_os_str())
+                .to_string_lossy()
+                .into_owned(),
+            path: root.to_path_buf(),
+            relative_path: String::new(),
+            node_type: FileNodeType::Directory,
+            size: 0,
+            has_content: false,
+            children,
+            depth: 0,
+            link_target: None,
+            budget_omitted: false,
+            text_rescued: false,
+            tokens: None,
+            pinned: false,
+            over_line_limit: None,
+            deleted: false,
+            renamed_from: None,
+            last_modified: None,
+        };
+
+        Ok((root_node, missing, stats))
+    }
+
+    /// Builds a flat tree of the `changes` between two refs that pass
+    /// `matcher`, in the given order. Files still present are scanned like
+    /// listed files, renamed ones noting their old path; deleted files are
+    /// listed with a `[deleted]` marker and no content.
+    pub async fn scan_changed_files<P: AsRef<Path>>(
+        root: P,
+        changes: &[ChangedFile],
+        matcher: &PatternMatcher,
+        config: &ScanConfig,
+    ) -> Result<(FileNode, FileSystemStats)> {
+        let root = root.as_ref();
+        let mut changes_included = Vec::new();
+        for change in changes {
+            if PatternService::should_include_file(matcher, &change.path)? {
+                changes_included.push(change);
+            }
+        }
+        let present: Vec<String> = changes_included
+            .iter()
+            .filter(|change| change.kind != ChangeKind::Deleted)
+            .map(|change| change.path.clone())
+            .collect();
+        let (mut root_node, _, stats) = Self::scan_listed_files(root, &present, config).await?;
+
+        let mut scanned: HashMap<String, FileNode> =
+            root_node.children.drain(..).map(|node| (node.relative_path.clone(), node)).collect();
+        for change in changes_included {
+            if change.kind == ChangeKind::Deleted {
+                root_node.children.push(FileNode {
+                    name: change.path.clone(),
+                    path: root.join(&change.path),
+                    relative_path: change.path.clone(),
+                    node_type: FileNodeType::File,
+                    size: 0,
+                    has_content: false,
+                    children: Vec::new(),
+                    depth: path_depth(&change.path),
+                    link_target: None,
+                    budget_omitted: false,
+                    text_rescued: false,
+                    tokens: None,
+                    pinned: false,
+                    over_line_limit: None,
+                    deleted: true,
+                    renamed_from: None,
+                    last_modified: None,
+                });
+                continue;
+            }
+            // Submodule pointers and the like have no file to scan
+            if let Some(mut node) = scanned.remove(&change.path) {
+                node.renamed_from = change.old_path.clone();
+                root_node.children.push(node);
+            }
+        }
+
+        Ok((root_node, stats))
+    }
+
+    /// Whether any directory between `root` and `relative_path` is a
+    /// symlink, which would let a listed path reach outside the repository.
+    fn is_under_symlink(root: &Path, relative_path: &Path) -> bool {
+        relative_path
+            .ancestors()
+            .skip(1)
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .any(|dir| root.join(dir).is_symlink())
     }
 
     async fn process_file<P: AsRef<Path>>(
         file_path: P,
         root_path: P,
         matcher: &PatternMatcher,
-        max_file_size: u64,
+        config: &ScanConfig,
     ) -> Result<FileNode> {
         let file_path = file_path.as_ref();
         let root_path = root_path.as_ref();
         
-        let metadata = fs::metadata(file_path).await?;
+        // Inspect the link itself rather than its target so that links
+        // escaping the root, dangling links and cycles are never followed
+        let metadata = fs::symlink_metadata(file_path).await?;
         let name = file_path
             .file_name()
-            .unwrap_or_else(|| file_path.as_os_str())
+            .unwrap_or(file_path.as_os_str())
             .to_string_lossy()
             .into_owned();
 
@@ -190,101 +412,533 @@ impl FileService {
             .to_string_lossy()
             .into_owned();
 
-        let has_content = metadata.len() <= max_file_size 
-            && PatternService::should_include_file(matcher, file_path)?
-            && !is_binary_file(file_path);
+        if metadata.file_type().is_symlink() {
+            return Ok(FileNode {
+                name,
+                path: file_path.to_path_buf(),
+                depth: path_depth(&relative_path),
+                relative_path,
+                node_type: FileNodeType::Symlink,
+                size: 0,
+                has_content: false,
+                children: Vec::new(),
+                link_target: Some(fs::read_link(file_path).await?),
+                budget_omitted: false,
+                text_rescued: false,
+                tokens: None,
+                pinned: false,
+                over_line_limit: None,
+                deleted: false,
+                renamed_from: None,
+                last_modified: None,
+            });
+        }
+
+        let mut has_content = metadata.len() <= config.max_file_size
+            && PatternService::should_include_file(matcher, &relative_path)?;
+        let mut text_rescued = false;
+        if has_content && is_binary_file(file_path) {
+            text_rescued = config.rescue_binary_text
+                && metadata.len() <= BINARY_RESCUE_MAX_SIZE
+                && looks_like_text(&fs::read(file_path).await?);
+            has_content = text_rescued;
+        }
+        // A line is at least one byte, so small files can't be over the limit
+        let mut over_line_limit = None;
+        if has_content
+            && metadata.len() > config.max_file_lines
+            && exceeds_line_limit(file_path, config.max_file_lines).await?
+        {
+            has_content = false;
+            over_line_limit = Some(config.max_file_lines);
+        }
 
         Ok(FileNode {
             name,
             path: file_path.to_path_buf(),
+            depth: path_depth(&relative_path),
             relative_path,
             node_type: FileNodeType::File,
             size: metadata.len(),
             has_content,
             children: Vec::new(),
-            depth: 0,
+            link_target: None,
+            budget_omitted: false,
+            text_rescued,
+            tokens: None,
+            pinned: false,
+            over_line_limit,
+            deleted: false,
+            renamed_from: None,
+            last_modified: None,
         })
     }
 
 
-    fn build_directory_tree<P: AsRef<Path>>(
-        current_path: P,
-        file_nodes: &HashMap<PathBuf, FileNode>,
-        file_map: &HashMap<PathBuf, Vec<PathBuf>>,
-    ) -> Result<FileNode> {
-        let current_path = current_path.as_ref();
+    /// Builds the node of the directory at `current_path` from the scanned
+    /// `file_nodes`, with its path and depth relative to `root_path`. The
+    /// nodes are moved into the tree; the filesystem isn't touched.
+    fn build_directory_tree(
+        root_path: &Path,
+        current_path: &Path,
+        file_nodes: &mut HashMap<PathBuf, FileNode>,
+        index: &DirectoryIndex,
+    ) -> FileNode {
+        let relative_path = current_path
+            .strip_prefix(root_path)
+            .unwrap_or(Path::new(""))
+            .to_string_lossy()
+            .into_owned();
         let name = current_path
             .file_name()
-            .unwrap_or_else(|| current_path.as_os_str())
+            .unwrap_or(current_path.as_os_str())
             .to_string_lossy()
             .into_owned();
 
-        let mut children = Vec::new();
-        let mut subdirectories = std::collections::HashSet::new();
-        
-        if let Some(child_paths) = file_map.get(current_path) {
-            for child_path in child_paths {
-                if child_path.is_file() {
-                    if let Some(child_node) = file_nodes.get(child_path) {
-                        children.push(child_node.clone());
-                    }
-                } else if child_path.is_dir() {
-                    subdirectories.insert(child_path.clone());
+        // Files that failed to scan have no node and are left out
+        let mut children: Vec<FileNode> = index
+            .files
+            .get(current_path)
+            .into_iter()
+            .flatten()
+            .filter_map(|file_path| file_nodes.remove(file_path))
+            .collect();
+        for subdir_path in index.subdirectories.get(current_path).into_iter().flatten() {
+            children.push(Self::build_directory_tree(root_path, subdir_path, file_nodes, index));
+        }
+
+        sort_children(&mut children);
+
+        FileNode {
+            name,
+            path: current_path.to_path_buf(),
+            depth: path_depth(&relative_path),
+            relative_path,
+            node_type: FileNodeType::Directory,
+            size: 0,
+            has_content: false,
+            children,
+            link_target: None,
+            budget_omitted: false,
+            text_rescued: false,
+            tokens: None,
+            pinned: false,
+            over_line_limit: None,
+            deleted: false,
+            renamed_from: None,
+            last_modified: None,
+        }
+    }
+
+    pub fn write_content_to_file<P: AsRef<Path>>(
+        node: &FileNode,
+        output_path: P,
+        max_content_size: u64,
+    ) -> Result<()> {
+        let mut file = std::fs::File::create(output_path)?;
+        node.write_content(&mut file, max_content_size).map_err(|e| GitingestError::FileSystemError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Like [`FileService::write_content_to_file`], also returning a
+    /// [`FileEntry`] per file gathered during the same pass.
+    pub fn write_structured_content_to_file<P: AsRef<Path>>(
+        node: &FileNode,
+        output_path: P,
+        max_content_size: u64,
+    ) -> Result<Vec<FileEntry>> {
+        let mut file = std::fs::File::create(output_path)?;
+        let mut entries = Vec::new();
+        node.write_content_with_entries(&mut file, max_content_size, Some(&mut entries))
+            .map_err(|e| GitingestError::FileSystemError(e.to_string()))?;
+        Ok(entries)
+    }
+
+    /// Converts a scanned tree into its serializable form, with paths
+    /// relative to `root` and directory sizes summed from their files.
+    pub fn build_tree_entry(node: &FileNode, root: &Path) -> TreeEntry {
+        let children: Vec<TreeEntry> = node
+            .children
+            .iter()
+            .map(|child| Self::build_tree_entry(child, root))
+            .collect();
+        let size = match node.node_type {
+            FileNodeType::Directory => children.iter().map(|child| child.size).sum(),
+            _ => node.size,
+        };
+
+        TreeEntry {
+            name: node.name.clone(),
+            path: node
+                .path
+                .strip_prefix(root)
+                .unwrap_or(&node.path)
+                .to_string_lossy()
+                .into_owned(),
+            node_type: node.node_type,
+            size,
+            has_content: node.has_content,
+            link_target: node.link_target.clone(),
+            last_modified: node.last_modified,
+            children,
+        }
+    }
+
+    /// Puts the root-level file `name` into the digest in full, adding it
+    /// to the tree when the scan left it out (excluded by patterns, over
+    /// `max_file_size` or past `max_files`).
+    pub async fn pin_root_file(tree: &mut FileNode, name: &str) -> Result<()> {
+        if let Some(node) = tree
+            .children
+            .iter_mut()
+            .find(|child| child.node_type == FileNodeType::File && child.relative_path == name)
+        {
+            node.has_content = true;
+            node.pinned = true;
+            node.over_line_limit = None;
+            return Ok(());
+        }
+
+        let path = tree.path.join(name);
+        let metadata = fs::symlink_metadata(&path).await?;
+        let node = FileNode {
+            name: name.to_string(),
+            path,
+            relative_path: name.to_string(),
+            node_type: FileNodeType::File,
+            size: metadata.len(),
+            has_content: true,
+            children: Vec::new(),
+            depth: 1,
+            link_target: None,
+            budget_omitted: false,
+            text_rescued: false,
+            tokens: None,
+            pinned: true,
+            over_line_limit: None,
+            deleted: false,
+            renamed_from: None,
+            last_modified: None,
+        };
+        // Keep the directories-first, by-name order of the scan
+        let position = tree.children.partition_point(|child| {
+            matches!(child.node_type, FileNodeType::Directory | FileNodeType::Submodule) || child.name < node.name
+        });
+        tree.children.insert(position, node);
+        Ok(())
+    }
+
+    /// Reads the `(path, url)` of each submodule declared in the
+    /// `.gitmodules` file at `root`, in file order. A missing or unreadable
+    /// file declares none.
+    pub fn read_gitmodules(root: &Path) -> Vec<(String, String)> {
+        let Ok(content) = std_fs::read_to_string(root.join(".gitmodules")) else {
+            return Vec::new();
+        };
+
+        let mut submodules = Vec::new();
+        let mut current: Option<(Option<String>, Option<String>)> = None;
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if line.starts_with('[') {
+                if let Some((Some(path), Some(url))) = current.take() {
+                    submodules.push((path, url));
                 }
+                current = line.starts_with("[submodule").then_some((None, None));
+                continue;
+            }
+            let (Some((path, url)), Some((key, value))) = (current.as_mut(), line.split_once('=')) else {
+                continue;
+            };
+            let value = value.trim().trim_matches('"').to_string();
+            match key.trim() {
+                "path" => *path = Some(value.trim_matches('/').to_string()),
+                "url" => *url = Some(value),
+                _ => {}
             }
         }
+        if let Some((Some(path), Some(url))) = current {
+            submodules.push((path, url));
+        }
+        submodules
+    }
 
-        for (dir_path, _) in file_map {
-            if let Some(parent) = dir_path.parent() {
-                if parent == current_path && dir_path.is_dir() {
-                    subdirectories.insert(dir_path.clone());
-                }
+    /// Lists the `submodules` that weren't cloned (their directory is
+    /// missing or empty) in a scanned tree, as a single
+    /// [`FileNodeType::Submodule`] entry each. Paths are relative to the
+    /// tree's root; those excluded by `matcher` or deeper than `max_depth`
+    /// are left out, as the scan would have left out their directory.
+    pub fn add_submodules(
+        tree: &mut FileNode,
+        submodules: &[(String, String)],
+        matcher: &PatternMatcher,
+        max_depth: u32,
+    ) -> Result<()> {
+        let root = tree.path.clone();
+        for (path, url) in submodules {
+            let relative_path = Path::new(path);
+            let depth = path_depth(path);
+            if depth == 0 || depth > max_depth {
+                continue;
+            }
+            let mut included = true;
+            for ancestor in relative_path.ancestors().filter(|ancestor| !ancestor.as_os_str().is_empty()) {
+                included &= PatternService::should_include_directory(matcher, ancestor)?;
             }
+            let checked_out = std_fs::read_dir(root.join(relative_path))
+                .map(|mut entries| entries.next().is_some())
+                .unwrap_or(false);
+            if !included || checked_out {
+                continue;
+            }
+
+            let names: Vec<String> = relative_path
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            Self::insert_submodule(tree, &root, &names, url);
         }
+        Ok(())
+    }
+
+    /// Puts a submodule node at `names` below `dir`, creating the
+    /// directories leading to it.
+    fn insert_submodule(dir: &mut FileNode, root: &Path, names: &[String], url: &str) {
+        let Some((name, rest)) = names.split_first() else {
+            return;
+        };
+        let path = dir.path.join(name);
+        let relative_path = path.strip_prefix(root).unwrap_or(&path).to_string_lossy().into_owned();
+        let existing = dir.children.iter().position(|child| child.name == *name);
 
-        for subdir_path in subdirectories {
-            let subdir_node = Self::build_directory_tree(
-                &subdir_path,
-                file_nodes,
-                file_map,
-            )?;
-            children.push(subdir_node);
+        if rest.is_empty() {
+            match existing {
+                // An empty checkout directory is what the submodule replaces
+                Some(index) if dir.children[index].node_type == FileNodeType::Directory
+                    && dir.children[index].children.is_empty() =>
+                {
+                    dir.children.remove(index);
+                }
+                Some(_) => return,
+                None => {}
+            }
+            dir.children.push(FileNode {
+                name: name.clone(),
+                depth: path_depth(&relative_path),
+                path,
+                relative_path,
+                node_type: FileNodeType::Submodule,
+                size: 0,
+                has_content: false,
+                children: Vec::new(),
+                link_target: Some(PathBuf::from(url)),
+                budget_omitted: false,
+                text_rescued: false,
+                tokens: None,
+                pinned: false,
+                over_line_limit: None,
+                deleted: false,
+                renamed_from: None,
+                last_modified: None,
+            });
+            sort_children(&mut dir.children);
+            return;
         }
 
-        children.sort_by(|a, b| {
-            match (a.node_type, b.node_type) {
-                (FileNodeType::Directory, FileNodeType::File) => std::cmp::Ordering::Less,
-                (FileNodeType::File, FileNodeType::Directory) => std::cmp::Ordering::Greater,
-                _ => a.name.cmp(&b.name),
+        let index = match existing {
+            Some(index) if dir.children[index].node_type == FileNodeType::Directory => index,
+            Some(_) => return,
+            None => {
+                dir.children.push(FileNode {
+                    name: name.clone(),
+                    depth: path_depth(&relative_path),
+                    path,
+                    relative_path,
+                    node_type: FileNodeType::Directory,
+                    size: 0,
+                    has_content: false,
+                    children: Vec::new(),
+                    link_target: None,
+                    budget_omitted: false,
+                    text_rescued: false,
+                    tokens: None,
+                    pinned: false,
+                    over_line_limit: None,
+                    deleted: false,
+                    renamed_from: None,
+                    last_modified: None,
+                });
+                sort_children(&mut dir.children);
+                dir.children.iter().position(|child| child.name == *name).unwrap_or(0)
+            }
+        };
+        Self::insert_submodule(&mut dir.children[index], root, rest, url);
+    }
+
+    /// Totals the files of a scanned tree by [`language_name`], largest
+    /// first. Files dropped by a token budget count towards size but not
+    /// tokens.
+    pub fn language_breakdown(node: &FileNode) -> Vec<LanguageStat> {
+        let mut by_language: HashMap<&'static str, LanguageStat> = HashMap::new();
+        Self::collect_language_stats(node, &mut by_language);
+
+        let total_tokens: usize = by_language.values().map(|stat| stat.tokens).sum();
+        let total_size: u64 = by_language.values().map(|stat| stat.total_size_bytes).sum();
+        let mut stats: Vec<LanguageStat> = by_language.into_values().collect();
+        for stat in &mut stats {
+            if total_tokens > 0 {
+                stat.token_share = stat.tokens as f64 / total_tokens as f64;
+            }
+            if total_size > 0 {
+                stat.size_share = stat.total_size_bytes as f64 / total_size as f64;
             }
+        }
+        stats.sort_by(|a, b| {
+            b.total_size_bytes
+                .cmp(&a.total_size_bytes)
+                .then_with(|| a.language.cmp(&b.language))
         });
+        stats
+    }
 
-        Ok(FileNode {
-            name,
-            path: current_path.to_path_buf(),
-            relative_path: String::new(),
-            node_type: FileNodeType::Directory,
-            size: 0,
-            has_content: false,
-            children,
-            depth: 0,
-        })
+    fn collect_language_stats(node: &FileNode, by_language: &mut HashMap<&'static str, LanguageStat>) {
+        match node.node_type {
+            FileNodeType::Directory => {
+                for child in &node.children {
+                    Self::collect_language_stats(child, by_language);
+                }
+            }
+            // Binary files and files whose content was skipped say nothing
+            // about what the repository is written in
+            FileNodeType::File if node.deleted || !node.has_content || node.over_line_limit.is_some() => {}
+            FileNodeType::File => {
+                let language = language_name(&node.name);
+                let stat = by_language.entry(language).or_insert_with(|| LanguageStat {
+                    language: language.to_string(),
+                    files: 0,
+                    total_size_bytes: 0,
+                    tokens: 0,
+                    token_share: 0.0,
+                    size_share: 0.0,
+                });
+                stat.files += 1;
+                stat.total_size_bytes += node.size;
+                if !node.budget_omitted {
+                    stat.tokens += node.tokens.unwrap_or(0);
+                }
+            }
+            FileNodeType::Symlink | FileNodeType::Submodule => {}
+        }
     }
 
-    pub fn write_content_to_file<P: AsRef<Path>>(node: &FileNode, output_path: P) -> Result<()> {
-        let mut file = std::fs::File::create(output_path)?;
-        node.write_content(&mut file).map_err(|e| GitingestError::FileSystemError(e.to_string()))?;
-        Ok(())
+    /// Splits a scanned tree into parts keyed by directory path. Directories
+    /// at `depth` become one part each; files that live above that depth are
+    /// grouped by their parent directory, with the root keyed as `""`.
+    /// Every file in the tree ends up in exactly one part.
+    pub fn partition_by_directory(node: &FileNode, depth: usize) -> Vec<(String, FileNode)> {
+        let mut parts = Vec::new();
+        Self::collect_partitions(node, &node.path, 0, depth.max(1), &mut parts);
+        parts
+    }
+
+    fn collect_partitions(
+        node: &FileNode,
+        root_path: &Path,
+        current_depth: usize,
+        depth: usize,
+        parts: &mut Vec<(String, FileNode)>,
+    ) {
+        let key = node
+            .path
+            .strip_prefix(root_path)
+            .unwrap_or(&node.path)
+            .to_string_lossy()
+            .replace('\\', "/");
+
+        if current_depth == depth {
+            parts.push((key, node.clone()));
+            return;
+        }
+
+        let loose_files: Vec<FileNode> = node
+            .children
+            .iter()
+            .filter(|child| child.node_type != FileNodeType::Directory)
+            .cloned()
+            .collect();
+        if !loose_files.is_empty() {
+            let loose_node = FileNode {
+                name: node.name.clone(),
+                path: node.path.clone(),
+                relative_path: node.relative_path.clone(),
+                node_type: node.node_type,
+                size: node.size,
+                has_content: node.has_content,
+                children: loose_files,
+                depth: node.depth,
+                link_target: None,
+                budget_omitted: false,
+                text_rescued: false,
+                tokens: None,
+                pinned: false,
+                over_line_limit: None,
+                deleted: false,
+                renamed_from: None,
+                last_modified: None,
+            };
+            parts.push((key, loose_node));
+        }
+
+        for child in &node.children {
+            if child.node_type == FileNodeType::Directory {
+                Self::collect_partitions(child, root_path, current_depth + 1, depth, parts);
+            }
+        }
     }
 
     pub fn generate_tree_string(node: &FileNode, prefix: &str, is_last: bool) -> String {
+        Self::render_tree(node, prefix, is_last, &|node| {
+            if node.deleted {
+                return format!("{} [deleted]", node.name);
+            }
+            let name = match &node.renamed_from {
+                Some(old_path) => format!("{} (renamed from {})", node.name, old_path),
+                None => node.name.clone(),
+            };
+            if node.budget_omitted {
+                return format!("{} [omitted: token budget]", name);
+            }
+            match node.tokens {
+                Some(tokens) => format!("{} ({} tokens)", name, format_tokens(tokens)),
+                None => name,
+            }
+        })
+    }
+
+    /// [`FileService::generate_tree_string`] with each file annotated by
+    /// its size rather than its token estimate.
+    pub fn generate_sized_tree_string(node: &FileNode, prefix: &str, is_last: bool) -> String {
+        Self::render_tree(node, prefix, is_last, &|node| format!("{} ({})", node.name, format_file_size(node.size)))
+    }
+
+    /// Draws the tree under `node`, labelling files with `file_label`.
+    fn render_tree(node: &FileNode, prefix: &str, is_last: bool, file_label: &dyn Fn(&FileNode) -> String) -> String {
         let mut result = String::new();
         
         let connector = if is_last { "└── " } else { "├── " };
         let name_display = match node.node_type {
             FileNodeType::Directory => format!("{}/", node.name),
-            FileNodeType::Symlink => format!("{} -> ?", node.name),
-            FileNodeType::File => node.name.clone(),
+            FileNodeType::Symlink => match &node.link_target {
+                Some(target) => format!("{} -> {}", node.name, target.display()),
+                None => format!("{} -> ?", node.name),
+            },
+            FileNodeType::Submodule => match &node.link_target {
+                Some(url) => format!("{}/ [submodule: {}]", node.name, url.display()),
+                None => format!("{}/ [submodule]", node.name),
+            },
+            FileNodeType::File => file_label(node),
         };
         
         result.push_str(&format!("{}{}{}\n", prefix, connector, name_display));
@@ -294,7 +948,7 @@ impl FileService {
             
             for (i, child) in node.children.iter().enumerate() {
                 let is_child_last = i == node.children.len() - 1;
-                result.push_str(&Self::generate_tree_string(child, &new_prefix, is_child_last));
+                result.push_str(&Self::render_tree(child, &new_prefix, is_child_last, file_label));
             }
         }
         
@@ -302,6 +956,115 @@ impl FileService {
     }
 }
 
+/// Decodes file contents as UTF-8, falling back to common single-byte
+/// encodings before replacing invalid sequences.
+#[cfg(feature = "encoding")]
+fn decode_bytes(bytes: &[u8]) -> String {
+    let (cow, _encoding_used, had_errors) = encoding_rs::UTF_8.decode(bytes);
+    if !had_errors {
+        return cow.into_owned();
+    }
+
+    for encoding in &[encoding_rs::WINDOWS_1252, encoding_rs::ISO_8859_2] {
+        let (cow, _encoding_used, had_errors) = encoding.decode(bytes);
+        if !had_errors {
+            return cow.into_owned();
+        }
+    }
+
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+/// Decodes file contents as UTF-8, replacing invalid sequences.
+#[cfg(not(feature = "encoding"))]
+fn decode_bytes(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+/// The scanned files and subdirectories of each directory that holds any
+/// scanned file, gathered once from the walk.
+#[derive(Default)]
+struct DirectoryIndex {
+    files: HashMap<PathBuf, Vec<PathBuf>>,
+    subdirectories: HashMap<PathBuf, Vec<PathBuf>>,
+}
+
+impl DirectoryIndex {
+    /// Indexes `files`, all below `root`, under their parent directories,
+    /// and each of those directories under its own parent up to `root`.
+    fn new(root: &Path, files: &[PathBuf]) -> Self {
+        let mut index = Self::default();
+        let mut indexed_dirs = HashSet::new();
+        for file in files {
+            let Some(parent) = file.parent() else {
+                continue;
+            };
+            index.files.entry(parent.to_path_buf()).or_default().push(file.clone());
+
+            // Stop at the first directory indexed before; its ancestors are too
+            let mut dir = parent;
+            while dir != root && indexed_dirs.insert(dir.to_path_buf()) {
+                let Some(grandparent) = dir.parent() else {
+                    break;
+                };
+                index.subdirectories.entry(grandparent.to_path_buf()).or_default().push(dir.to_path_buf());
+                dir = grandparent;
+            }
+        }
+        index
+    }
+}
+
+/// Orders a directory's entries: directories (submodules among them)
+/// first, then files, each by name.
+fn sort_children(children: &mut [FileNode]) {
+    let is_dir = |node: &FileNode| matches!(node.node_type, FileNodeType::Directory | FileNodeType::Submodule);
+    children.sort_by(|a, b| is_dir(b).cmp(&is_dir(a)).then_with(|| a.name.cmp(&b.name)));
+}
+
+/// Depth of the node at the root-relative `relative_path`: 0 for the
+/// root, 1 for its entries and so on.
+fn path_depth(relative_path: &str) -> u32 {
+    Path::new(relative_path).components().count() as u32
+}
+
+/// Whether `bytes` are plainly text: valid UTF-8 with no control
+/// characters other than whitespace and escapes.
+fn looks_like_text(bytes: &[u8]) -> bool {
+    std::str::from_utf8(bytes).is_ok_and(|text| {
+        !text
+            .chars()
+            .any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t' | '\x0c' | '\x1b'))
+    })
+}
+
+/// Whether the file at `path` has more than `max_lines` lines, counting a
+/// final line without a newline. Reads in chunks and stops as soon as the
+/// limit is passed, so a huge file is never held in memory.
+async fn exceeds_line_limit(path: &Path, max_lines: u64) -> std::io::Result<bool> {
+    use tokio::io::AsyncReadExt;
+
+    let mut file = fs::File::open(path).await?;
+    let mut buffer = vec![0; 64 * 1024];
+    let mut lines = 0u64;
+    let mut last_byte = b'\n';
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        lines += buffer[..read].iter().filter(|&&byte| byte == b'\n').count() as u64;
+        if lines > max_lines {
+            return Ok(true);
+        }
+        last_byte = buffer[read - 1];
+    }
+    if last_byte != b'\n' {
+        lines += 1;
+    }
+    Ok(lines > max_lines)
+}
+
 pub fn format_file_size(bytes: u64) -> String {
     const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
     let mut size = bytes as f64;
@@ -322,6 +1085,7 @@ pub fn format_file_size(bytes: u64) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::models::{ContentFormat, SkippedFiles};
     use tempfile::TempDir;
     use std::fs::File;
     use std::io::Write;
@@ -347,4 +1111,540 @@ mod tests {
         
         Ok(())
     }
+
+    #[test]
+    fn test_non_utf8_decoding() {
+        // "café" in Windows-1252
+        let content = decode_bytes(b"caf\xe9");
+        if cfg!(feature = "encoding") {
+            assert_eq!(content, "café");
+        } else {
+            assert_eq!(content, "caf\u{FFFD}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_symlinks_are_reported_but_not_followed() -> Result<()> {
+        use std::os::unix::fs::symlink;
+
+        let outside_dir = TempDir::new().unwrap();
+        std::fs::write(outside_dir.path().join("secret.txt"), "outside content").unwrap();
+
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::write(root.join("real.txt"), "real content").unwrap();
+        symlink("real.txt", root.join("link.txt")).unwrap();
+        symlink("missing.txt", root.join("dangling.txt")).unwrap();
+        symlink(outside_dir.path(), root.join("outside")).unwrap();
+        symlink(".", root.join("loop")).unwrap();
+
+        let (tree, _) = FileService::scan_directory(root, &PatternMatcher::default(), &ScanConfig::default()).await?;
+
+        let find = |name: &str| tree.children.iter().find(|child| child.name == name).unwrap();
+        assert_eq!(find("real.txt").node_type, FileNodeType::File);
+        for name in ["link.txt", "dangling.txt", "outside", "loop"] {
+            let node = find(name);
+            assert_eq!(node.node_type, FileNodeType::Symlink);
+            assert!(!node.has_content);
+        }
+
+        let tree_string = FileService::generate_tree_string(&tree, "", true);
+        assert!(tree_string.contains("link.txt -> real.txt"));
+        assert!(tree_string.contains("dangling.txt -> missing.txt"));
+        assert!(tree_string.contains(&format!("outside -> {}", outside_dir.path().display())));
+
+        let mut content = Vec::new();
+        tree.write_content(&mut content, u64::MAX).unwrap();
+        let content = String::from_utf8(content).unwrap();
+        assert_eq!(content.matches("real content").count(), 1);
+        assert!(!content.contains("outside content"));
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_listed_files_are_not_read_through_symlinked_directories() -> Result<()> {
+        use std::os::unix::fs::symlink;
+
+        let outside_dir = TempDir::new().unwrap();
+        std::fs::write(outside_dir.path().join("secret.txt"), "outside content").unwrap();
+
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir(root.join("src")).unwrap();
+        std::fs::write(root.join("src/lib.rs"), "pub fn lib() {}\n").unwrap();
+        symlink(outside_dir.path(), root.join("outside")).unwrap();
+
+        let listed = ["src/lib.rs".to_string(), "outside/secret.txt".to_string(), "outside".to_string()];
+        let (tree, missing, _) = FileService::scan_listed_files(root, &listed, &ScanConfig::default()).await?;
+
+        assert_eq!(missing, vec!["outside/secret.txt".to_string()]);
+        let names: Vec<&str> = tree.children.iter().map(|child| child.name.as_str()).collect();
+        assert_eq!(names, vec!["src/lib.rs", "outside"]);
+        assert_eq!(tree.children[1].node_type, FileNodeType::Symlink);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_text_with_binary_extension_is_rescued() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::write(root.join("icon.svg"), "<svg xmlns=\"http://www.w3.org/2000/svg\"/>").unwrap();
+        std::fs::write(root.join("fixture.dat"), "id,name\n1,alpha\n").unwrap();
+        std::fs::write(root.join("blob.dat"), [0x7f, b'E', b'L', b'F', 0, 1, 2, 0xff]).unwrap();
+        std::fs::write(root.join("large.dat"), "x".repeat(BINARY_RESCUE_MAX_SIZE as usize + 1)).unwrap();
+
+        let (tree, _) = FileService::scan_directory(root, &PatternMatcher::default(), &ScanConfig::default()).await?;
+        let find = |name: &str| tree.children.iter().find(|child| child.name == name).unwrap();
+        assert!(find("icon.svg").has_content);
+        assert!(!find("icon.svg").text_rescued);
+        assert!(find("fixture.dat").text_rescued);
+        assert!(!find("blob.dat").has_content);
+        assert!(!find("large.dat").has_content);
+
+        let mut content = Vec::new();
+        tree.write_content(&mut content, u64::MAX).unwrap();
+        let content = String::from_utf8(content).unwrap();
+        assert!(content.contains("icon.svg:\n"));
+        assert!(content.contains("fixture.dat: [binary extension, detected as text]\n"));
+        assert!(content.contains("1,alpha"));
+        assert!(!content.contains("blob.dat"));
+
+        let config = ScanConfig { rescue_binary_text: false, ..Default::default() };
+        let (tree, _) = FileService::scan_directory(root, &PatternMatcher::default(), &config).await?;
+        assert!(!tree.children.iter().find(|child| child.name == "fixture.dat").unwrap().has_content);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_scan_stats_count_skips_by_reason() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir_all(root.join("node_modules/dep")).unwrap();
+        std::fs::write(root.join("node_modules/dep/index.js"), "module.exports = 1;").unwrap();
+        std::fs::write(root.join("a.rs"), "fn a() {}").unwrap();
+        std::fs::write(root.join("b.rs"), "x".repeat(100)).unwrap();
+        std::fs::write(root.join("c.bin"), [0, 1, 2]).unwrap();
+        std::fs::write(root.join("d.log"), "ignored").unwrap();
+        std::fs::write(root.join("e.rs"), "fn e() {}").unwrap();
+
+        let config = ScanConfig { max_file_size: 50, ..Default::default() };
+        let (_, stats) = FileService::scan_directory(root, &PatternMatcher::default(), &config).await?;
+
+        assert_eq!(stats.total_files, 4);
+        assert_eq!(stats.processed_files, 2);
+        assert_eq!(
+            stats.skipped,
+            SkippedFiles { binary: 1, excluded: 1, too_large: 1, too_many_lines: 0, read_error: 0, over_file_limit: 0 }
+        );
+        assert_eq!(stats.skipped_files, 3);
+        assert_eq!(stats.skipped.to_string(), "1 binary, 1 excluded, 1 too large");
+
+        // The walk yields the root first, so three entries leave two files over
+        let config = ScanConfig { max_files: 3, ..Default::default() };
+        let (_, stats) = FileService::scan_directory(root, &PatternMatcher::default(), &config).await?;
+        assert_eq!(stats.total_files, 2);
+        assert_eq!(stats.skipped.over_file_limit, 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_listed_tree_counts_entries_like_the_walk() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root = temp_dir.path();
+        std::fs::create_dir(root.join("src"))?;
+        for path in ["a.rs", "src/b.rs", "src/c.rs"] {
+            std::fs::write(root.join(path), "fn f() {}")?;
+        }
+        let listed: Vec<ListedFile> = ["a.rs", "src/b.rs", "src/c.rs"]
+            .into_iter()
+            .map(|path| ListedFile { path: path.to_string(), size: None, symlink: false })
+            .collect();
+
+        let config = ScanConfig { max_files: 3, ..Default::default() };
+        let (_, walked) = FileService::scan_directory(root, &PatternMatcher::default(), &config).await?;
+        let (tree, listed) = FileService::scan_listed_tree(root, &listed, &PatternMatcher::default(), &config).await?;
+        assert_eq!((listed.total_files, listed.skipped.over_file_limit), (walked.total_files, walked.skipped.over_file_limit));
+        assert_eq!((listed.total_files, listed.skipped.over_file_limit), (1, 2));
+        assert_eq!(tree.size, 0);
+
+        Ok(())
+    }
+
+    /// Nests directories under `dir` until their path is longer than
+    /// `PATH_MAX`, so reading the innermost ones fails for anyone, root
+    /// included. Two halves short enough to create are joined by a rename.
+    #[cfg(target_os = "linux")]
+    fn create_unreadably_deep_dirs(dir: &Path) {
+        let nest = |dir: &Path| {
+            let leaf = (0..10).fold(dir.to_path_buf(), |path, _| path.join("d".repeat(250)));
+            std::fs::create_dir_all(&leaf).unwrap();
+            leaf
+        };
+        let staging = TempDir::new().unwrap();
+        let lower = staging.path().join("lower");
+        std::fs::write(nest(&lower).join("leaf.js"), "").unwrap();
+        std::fs::rename(&lower, nest(dir).join("lower")).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_excluded_directories_are_not_walked() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::write(root.join("main.rs"), "fn main() {}").unwrap();
+        create_unreadably_deep_dirs(&root.join("node_modules/pkg"));
+        create_unreadably_deep_dirs(&root.join("vendored"));
+
+        // Walking into the deep directories is an error the stats record
+        let (_, stats) = FileService::scan_directory(root, &PatternMatcher::without_default_excludes(), &ScanConfig::default()).await?;
+        assert!(stats.skipped.read_error >= 2, "{:?}", stats.skipped);
+
+        // An excluded directory is pruned before it is read
+        let mut matcher = PatternMatcher::default();
+        matcher.exclude_patterns.push("vendored/".to_string());
+        let (tree, stats) = FileService::scan_directory(root, &matcher, &ScanConfig::default()).await?;
+        assert_eq!(stats.skipped.read_error, 0);
+        let names: Vec<&str> = tree.children.iter().map(|node| node.name.as_str()).collect();
+        assert_eq!(names, vec!["main.rs"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tree_nodes_carry_their_relative_path_and_depth() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir_all(root.join("src/lib/nested")).unwrap();
+        std::fs::write(root.join("README.md"), "").unwrap();
+        std::fs::write(root.join("src/main.rs"), "").unwrap();
+        std::fs::write(root.join("src/lib/nested/deep.rs"), "").unwrap();
+
+        let (tree, _) = FileService::scan_directory(root, &PatternMatcher::default(), &ScanConfig::default()).await?;
+        fn collect(node: &FileNode, nodes: &mut Vec<(String, FileNodeType, u32)>) {
+            nodes.push((node.relative_path.replace('\\', "/"), node.node_type, node.depth));
+            for child in &node.children {
+                collect(child, nodes);
+            }
+        }
+        let mut nodes = Vec::new();
+        collect(&tree, &mut nodes);
+
+        assert_eq!(
+            nodes,
+            vec![
+                (String::new(), FileNodeType::Directory, 0),
+                ("src".to_string(), FileNodeType::Directory, 1),
+                ("src/lib".to_string(), FileNodeType::Directory, 2),
+                ("src/lib/nested".to_string(), FileNodeType::Directory, 3),
+                ("src/lib/nested/deep.rs".to_string(), FileNodeType::File, 4),
+                ("src/main.rs".to_string(), FileNodeType::File, 2),
+                ("README.md".to_string(), FileNodeType::File, 1),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_max_depth_limits_the_walk() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir_all(root.join("a/b")).unwrap();
+        std::fs::write(root.join("top.rs"), "").unwrap();
+        std::fs::write(root.join("a/mid.rs"), "").unwrap();
+        std::fs::write(root.join("a/b/deep.rs"), "").unwrap();
+
+        let config = ScanConfig { max_depth: 2, ..Default::default() };
+        let (tree, stats) = FileService::scan_directory(root, &PatternMatcher::default(), &config).await?;
+        assert_eq!(stats.total_files, 2);
+        let mut paths = Vec::new();
+        collect_files(&tree, &mut paths);
+        assert_eq!(paths, vec!["a/mid.rs", "top.rs"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_uncloned_submodules_are_listed_from_gitmodules() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::write(
+            root.join(".gitmodules"),
+            "[submodule \"lib\"]\n\tpath = vendor/lib\n\turl = https://example.com/lib.git\n\
+             [submodule \"tools\"]\n\tpath = tools\n\turl = https://example.com/tools.git\n\
+             [submodule \"cloned\"]\n\tpath = cloned\n\turl = https://example.com/cloned.git\n\
+             [submodule \"hidden\"]\n\tpath = node_modules/hidden\n\turl = https://example.com/hidden.git\n",
+        )
+        .unwrap();
+        std::fs::create_dir_all(root.join("tools")).unwrap();
+        std::fs::create_dir_all(root.join("cloned")).unwrap();
+        std::fs::write(root.join("cloned/lib.rs"), "").unwrap();
+        std::fs::write(root.join("main.rs"), "").unwrap();
+
+        let submodules = FileService::read_gitmodules(root);
+        assert_eq!(submodules.len(), 4);
+        assert_eq!(submodules[0], ("vendor/lib".to_string(), "https://example.com/lib.git".to_string()));
+
+        let matcher = PatternMatcher::default();
+        let (mut tree, _) = FileService::scan_directory(root, &matcher, &ScanConfig::default()).await?;
+        FileService::add_submodules(&mut tree, &submodules, &matcher, 20)?;
+        let rendered = FileService::generate_tree_string(&tree, "", true);
+        assert!(rendered.contains("── cloned/\n"), "{}", rendered);
+        assert!(rendered.contains("── lib.rs\n"), "{}", rendered);
+        assert!(rendered.contains("── tools/ [submodule: https://example.com/tools.git]\n"), "{}", rendered);
+        assert!(rendered.contains("── vendor/\n"), "{}", rendered);
+        assert!(rendered.contains("── lib/ [submodule: https://example.com/lib.git]\n"), "{}", rendered);
+        assert!(!rendered.contains("hidden"), "{}", rendered);
+
+        // Submodules past the depth limit are left out like directories
+        let (mut tree, _) = FileService::scan_directory(root, &matcher, &ScanConfig::default()).await?;
+        FileService::add_submodules(&mut tree, &submodules, &matcher, 1)?;
+        let rendered = FileService::generate_tree_string(&tree, "", true);
+        assert!(rendered.contains("tools/ [submodule"), "{}", rendered);
+        assert!(!rendered.contains("vendor"), "{}", rendered);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_files_over_line_limit_are_listed_without_content() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::write(root.join("at_limit.rs"), "a\nb\nc\n").unwrap();
+        std::fs::write(root.join("no_trailing_newline.rs"), "a\nb\nc").unwrap();
+        std::fs::write(root.join("over_limit.rs"), "a\nb\nc\nd").unwrap();
+        std::fs::write(root.join("bundle.min.js"), "x".repeat(10_000)).unwrap();
+
+        let config = ScanConfig { max_file_lines: 3, ..Default::default() };
+        let (tree, stats) = FileService::scan_directory(root, &PatternMatcher::default(), &config).await?;
+
+        let find = |name: &str| tree.children.iter().find(|child| child.name == name).unwrap();
+        assert!(find("at_limit.rs").has_content);
+        assert!(find("no_trailing_newline.rs").has_content);
+        assert!(find("bundle.min.js").has_content);
+        let over = find("over_limit.rs");
+        assert!(!over.has_content);
+        assert_eq!(over.over_line_limit, Some(3));
+        assert_eq!(stats.skipped.too_many_lines, 1);
+        assert_eq!(stats.skipped.to_string(), "1 too many lines");
+
+        let mut content = Vec::new();
+        let mut entries = Vec::new();
+        tree.write_content_with_entries(&mut content, u64::MAX, Some(&mut entries)).unwrap();
+        let content = String::from_utf8(content).unwrap();
+        assert!(content.contains("over_limit.rs:\n================================================\n[File excluded: exceeds 3 lines]\n\n"));
+        assert!(!content.contains("c\nd"));
+        let entry = entries.iter().find(|entry| entry.relative_path == "over_limit.rs").unwrap();
+        assert_eq!(entry.skip_reason.as_deref(), Some("too_many_lines"));
+
+        // Lines, not bytes, decide: one long line passes a one-line limit
+        let config = ScanConfig { max_file_lines: 1, ..Default::default() };
+        let (tree, _) = FileService::scan_directory(root, &PatternMatcher::default(), &config).await?;
+        let find = |name: &str| tree.children.iter().find(|child| child.name == name).unwrap();
+        assert!(find("bundle.min.js").has_content);
+        assert_eq!(find("at_limit.rs").over_line_limit, Some(1));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_scan() {
+        let temp_dir = TempDir::new().unwrap();
+        for i in 0..100 {
+            std::fs::write(temp_dir.path().join(format!("file{}.txt", i)), "content").unwrap();
+        }
+
+        let config = ScanConfig { batch_size: 10, ..Default::default() };
+        config.cancel.cancel();
+
+        let result = FileService::scan_directory(temp_dir.path(), &PatternMatcher::default(), &config).await;
+        assert!(matches!(result, Err(GitingestError::Cancelled(phase)) if phase == "scan"));
+    }
+
+    #[tokio::test]
+    async fn test_async_content_matches_sync_for_many_files() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        for dir in 0..10 {
+            let dir_path = temp_dir.path().join(format!("dir{:02}", dir));
+            std_fs::create_dir(&dir_path)?;
+            for file in 0..30 {
+                std_fs::write(
+                    dir_path.join(format!("file{:02}.txt", file)),
+                    format!("dir {} file {}\n{}", dir, file, "x".repeat(file * 7)),
+                )?;
+            }
+        }
+        // One file over the content limit exercises truncation
+        std_fs::write(temp_dir.path().join("large.txt"), "y".repeat(500))?;
+
+        let (tree, _) = FileService::scan_directory(temp_dir.path(), &PatternMatcher::default(), &ScanConfig::default()).await?;
+
+        let mut sync_output = Vec::new();
+        let mut sync_entries = Vec::new();
+        tree.write_content_with_entries(&mut sync_output, 300, Some(&mut sync_entries))?;
+
+        let start = std::time::Instant::now();
+        let mut async_output = Vec::new();
+        let mut async_entries = Vec::new();
+        tree.write_content_async(&mut async_output, 300, u64::MAX, 16, None, &ContentFormat::default(), None, Some(&mut async_entries)).await?;
+        log::info!("Async content write of 301 files took {:?}", start.elapsed());
+
+        assert_eq!(String::from_utf8(async_output).unwrap(), String::from_utf8(sync_output).unwrap());
+        assert_eq!(async_entries.len(), 301);
+        let sync_paths: Vec<_> = sync_entries.iter().map(|e| &e.relative_path).collect();
+        let async_paths: Vec<_> = async_entries.iter().map(|e| &e.relative_path).collect();
+        assert_eq!(async_paths, sync_paths);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tree_is_assembled_without_touching_the_filesystem() -> Result<()> {
+        let mut relative_paths: Vec<String> = (0..300).map(|i| format!("wide/file{:03}.rs", i)).collect();
+        let deep: Vec<String> = (0..12).map(|i| format!("d{}", i)).collect();
+        relative_paths.push(format!("{}/leaf.rs", deep.join("/")));
+        relative_paths.push("deep_sibling/a.rs".to_string());
+        relative_paths.push("README.md".to_string());
+
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        for relative_path in &relative_paths {
+            std::fs::create_dir_all(root.join(relative_path).parent().unwrap()).unwrap();
+            std::fs::write(root.join(relative_path), "").unwrap();
+        }
+        let (scanned, _) = FileService::scan_directory(root, &PatternMatcher::default(), &ScanConfig::default()).await?;
+
+        // None of these paths exist, so any stat would change the tree
+        let missing_root = Path::new("/nonexistent-gitingest-root").join(root.file_name().unwrap());
+        let files: Vec<PathBuf> = relative_paths.iter().map(|relative_path| missing_root.join(relative_path)).collect();
+        let mut file_nodes: HashMap<PathBuf, FileNode> = files
+            .iter()
+            .map(|file| {
+                let relative_path = file.strip_prefix(&missing_root).unwrap().to_string_lossy().into_owned();
+                let node = FileNode {
+                    name: file.file_name().unwrap().to_string_lossy().into_owned(),
+                    path: file.clone(),
+                    depth: path_depth(&relative_path),
+                    relative_path,
+                    node_type: FileNodeType::File,
+                    size: 0,
+                    has_content: true,
+                    children: Vec::new(),
+                    link_target: None,
+                    budget_omitted: false,
+                    text_rescued: false,
+                    tokens: None,
+                    pinned: false,
+                    over_line_limit: None,
+                    deleted: false,
+                    renamed_from: None,
+                    last_modified: None,
+                };
+                (file.clone(), node)
+            })
+            .collect();
+        let index = DirectoryIndex::new(&missing_root, &files);
+        let built = FileService::build_directory_tree(&missing_root, &missing_root, &mut file_nodes, &index);
+
+        assert!(file_nodes.is_empty());
+        assert_eq!(
+            FileService::generate_tree_string(&built, "", true),
+            FileService::generate_tree_string(&scanned, "", true)
+        );
+        let mut paths = Vec::new();
+        collect_files(&built, &mut paths);
+        assert_eq!(paths.len(), relative_paths.len());
+        assert_eq!(built.children[0].name, "d0");
+        assert_eq!(built.children.iter().find(|child| child.name == "wide").unwrap().children.len(), 300);
+
+        Ok(())
+    }
+
+    fn collect_files(node: &FileNode, files: &mut Vec<String>) {
+        match node.node_type {
+            FileNodeType::File => files.push(node.relative_path.clone()),
+            _ => node.children.iter().for_each(|child| collect_files(child, files)),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_language_breakdown() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(root.join("src/main.rs"), "a".repeat(400)).unwrap();
+        std::fs::write(root.join("src/lib.rs"), "b".repeat(200)).unwrap();
+        std::fs::write(root.join("app.py"), "c".repeat(300)).unwrap();
+        std::fs::write(root.join("README.md"), "d".repeat(100)).unwrap();
+        std::fs::write(root.join("notes.xyz"), "e".repeat(100)).unwrap();
+        // Binary content is listed in the tree but not counted as a language
+        std::fs::write(root.join("firmware.dat"), [0u8, 159, 146, 150].repeat(500)).unwrap();
+
+        let (mut tree, _) = FileService::scan_directory(root, &PatternMatcher::default(), &ScanConfig::default()).await?;
+        crate::utils::count_tree_tokens(&mut tree, &crate::utils::HeuristicTokenCounter, u64::MAX).await?;
+        let breakdown = FileService::language_breakdown(&tree);
+
+        // Largest first; equal sizes fall back to the language name
+        let rows: Vec<(&str, usize, u64, usize)> = breakdown
+            .iter()
+            .map(|stat| (stat.language.as_str(), stat.files, stat.total_size_bytes, stat.tokens))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                ("Rust", 2, 600, 150),
+                ("Python", 1, 300, 75),
+                ("Markdown", 1, 100, 25),
+                ("Other", 1, 100, 25),
+            ]
+        );
+        assert!((breakdown[0].token_share - 150.0 / 275.0).abs() < 1e-9);
+        assert!((breakdown.iter().map(|stat| stat.token_share).sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!((breakdown[0].size_share - 600.0 / 1100.0).abs() < 1e-9);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_partition_by_directory() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        for file in ["README.md", "src/main.rs", "src/utils/helper.rs", "docs/guide.md"] {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, file).unwrap();
+        }
+
+        let (tree, _) = FileService::scan_directory(root, &PatternMatcher::default(), &ScanConfig::default()).await?;
+
+        let parts = FileService::partition_by_directory(&tree, 1);
+        let keys: Vec<&str> = parts.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, vec!["", "docs", "src"]);
+
+        let mut root_files = Vec::new();
+        collect_files(&parts[0].1, &mut root_files);
+        assert_eq!(root_files, vec!["README.md"]);
+
+        let parts = FileService::partition_by_directory(&tree, 2);
+        let keys: Vec<&str> = parts.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, vec!["", "docs", "src", "src/utils"]);
+
+        // Every scanned file lands in exactly one part
+        let mut all_files = Vec::new();
+        collect_files(&tree, &mut all_files);
+        let mut partitioned_files = Vec::new();
+        for (_, node) in &parts {
+            collect_files(node, &mut partitioned_files);
+        }
+        all_files.sort();
+        partitioned_files.sort();
+        assert_eq!(all_files, partitioned_files);
+
+        Ok(())
+    }
 }
\ No newline at end of file
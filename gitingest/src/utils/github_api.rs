@@ -0,0 +1,473 @@
+use crate::error::{GitingestError, Result};
+use crate::models::{CloneConfig, CloneOutcome, GitRef, PatternMatcher};
+use crate::utils::{is_binary_file, GitCredentials, GitService, PatternService, Platform, UrlParser};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use reqwest::{Response, StatusCode};
+use serde::Deserialize;
+use std::path::Path;
+use std::time::Instant;
+use url::Url;
+
+/// Blob downloads kept in flight at once.
+const API_DOWNLOADS_IN_FLIGHT: usize = 8;
+
+const JSON: &str = "application/vnd.github+json";
+const RAW: &str = "application/vnd.github.raw";
+
+#[derive(Deserialize)]
+struct RepositoryInfo {
+    default_branch: String,
+}
+
+#[derive(Deserialize)]
+struct PullRequestInfo {
+    head: PullRequestHead,
+}
+
+#[derive(Deserialize)]
+struct PullRequestHead {
+    sha: String,
+}
+
+#[derive(Deserialize)]
+struct TreeListing {
+    tree: Vec<TreeItem>,
+    truncated: bool,
+}
+
+#[derive(Deserialize)]
+struct TreeItem {
+    path: String,
+    mode: String,
+    #[serde(rename = "type")]
+    item_type: String,
+    size: Option<u64>,
+}
+
+/// The files of a [`GitService::fetch_via_api`] listing to write out.
+struct ApiFile {
+    path: String,
+    size: u64,
+    /// Whether the content is downloaded; other files are written as
+    /// placeholders of the right size, which the scan lists without
+    /// content anyway.
+    download: bool,
+}
+
+impl GitService {
+    /// Fetches only the files under `config.subpath` that `matcher` lets
+    /// through, using the GitHub trees and contents API instead of cloning,
+    /// and writes them into `config.local_path` for the usual scan. Files
+    /// that would be listed without content (binary extensions, larger
+    /// than `max_file_size`) are not downloaded but written as empty
+    /// placeholders of their size. Symlinks and submodules are skipped.
+    pub async fn fetch_via_api(
+        config: &CloneConfig,
+        api_url: &str,
+        matcher: &PatternMatcher,
+        max_file_size: u64,
+    ) -> Result<CloneOutcome> {
+        Self::validate_clone_config(config)?;
+        let start_time = Instant::now();
+        let repository = UrlParser::parse_git_url(&config.url)?;
+        if repository.host != "github.com" {
+            return Err(GitingestError::ValidationError(format!(
+                "API fetches are only supported for github.com, not {}",
+                repository.host
+            )));
+        }
+
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("gitingest/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        let api = ApiClient {
+            client,
+            base: api_url.trim_end_matches('/'),
+            owner: &repository.owner,
+            name: &repository.name,
+            config,
+        };
+
+        let reference = match &config.git_ref {
+            GitRef::DefaultBranch => api.get(&[], &[], JSON).await?.json::<RepositoryInfo>().await?.default_branch,
+            GitRef::Branch(name) | GitRef::Tag(name) | GitRef::Commit(name) => name.clone(),
+            GitRef::PullRequest(number) => match api.get(&["pulls", &number.to_string()], &[], JSON).await {
+                Err(GitingestError::RepositoryNotFound(_)) => {
+                    api.get(&[], &[], JSON).await?;
+                    return Err(GitingestError::BranchNotFound(format!("pull request #{} of {}", number, config.url)));
+                }
+                response => response?.json::<PullRequestInfo>().await?.head.sha,
+            },
+        };
+        let listing: TreeListing = match api.get(&["git", "trees", &reference], &[("recursive", "1")], JSON).await {
+            // An explicit ref that isn't there, as long as the repository is
+            Err(GitingestError::RepositoryNotFound(_)) if config.git_ref != GitRef::DefaultBranch => {
+                api.get(&[], &[], JSON).await?;
+                return Err(GitingestError::BranchNotFound(reference));
+            }
+            response => response?.json().await?,
+        };
+        if listing.truncated {
+            return Err(GitingestError::GitOperationFailed(format!(
+                "GitHub truncated the file listing of {}; clone it instead",
+                config.url
+            )));
+        }
+
+        let files = select_api_files(listing.tree, &config.subpath, matcher, max_file_size)?;
+        log::info!("Fetching {} files of {} through the GitHub API", files.len(), config.url);
+        tokio::fs::create_dir_all(&config.local_path).await?;
+        let writes = stream::iter(files.iter().map(|file| api.write_file(&reference, file)))
+            .buffer_unordered(API_DOWNLOADS_IN_FLIGHT)
+            .try_collect::<Vec<()>>();
+        tokio::select! {
+            _ = config.cancel.cancelled() => {
+                return Err(GitingestError::Cancelled("clone".to_string()));
+            }
+            result = writes => result?,
+        };
+
+        log::info!("API fetch completed in {:.2}s", start_time.elapsed().as_secs_f64());
+        Ok(CloneOutcome::default())
+    }
+}
+
+/// Picks the regular files under `subpath` that `matcher` includes, in an
+/// order that doesn't matter since each is written to its own path.
+fn select_api_files(
+    tree: Vec<TreeItem>,
+    subpath: &str,
+    matcher: &PatternMatcher,
+    max_file_size: u64,
+) -> Result<Vec<ApiFile>> {
+    let subpath = subpath.trim_matches('/');
+    let mut files = Vec::new();
+    for item in tree {
+        // Symlinks (120000) and submodules (commits) aren't fetched
+        if item.item_type != "blob" || item.mode == "120000" {
+            continue;
+        }
+        let under_subpath = subpath.is_empty()
+            || item.path == subpath
+            || item.path.strip_prefix(subpath).is_some_and(|rest| rest.starts_with('/'));
+        if !under_subpath || !is_safe_relative_path(&item.path) {
+            continue;
+        }
+
+        let path = Path::new(&item.path);
+        let mut dirs_included = true;
+        for dir in path.ancestors().skip(1).filter(|dir| !dir.as_os_str().is_empty()) {
+            if !PatternService::should_include_directory(matcher, dir)? {
+                dirs_included = false;
+                break;
+            }
+        }
+        if !dirs_included || !PatternService::should_include_file(matcher, path)? {
+            continue;
+        }
+
+        let size = item.size.unwrap_or(0);
+        files.push(ApiFile {
+            download: size <= max_file_size && !is_binary_file(path),
+            path: item.path,
+            size,
+        });
+    }
+    Ok(files)
+}
+
+/// Whether a path from the API only has plain components, so it can't
+/// be written outside the destination.
+fn is_safe_relative_path(path: &str) -> bool {
+    !path.is_empty()
+        && Path::new(path)
+            .components()
+            .all(|component| matches!(component, std::path::Component::Normal(_)))
+}
+
+struct ApiClient<'a> {
+    client: reqwest::Client,
+    base: &'a str,
+    owner: &'a str,
+    name: &'a str,
+    config: &'a CloneConfig,
+}
+
+impl ApiClient<'_> {
+    /// Sends a GET for `/repos/{owner}/{name}` followed by `segments`, each
+    /// percent-encoded as a single segment, mapping error statuses to
+    /// errors.
+    async fn get(&self, segments: &[&str], query: &[(&str, &str)], accept: &str) -> Result<Response> {
+        let mut url = Url::parse(self.base)?;
+        url.path_segments_mut()
+            .map_err(|_| GitingestError::ConfigError(format!("Invalid GitHub API URL: {}", self.base)))?
+            .extend(["repos", self.owner, self.name])
+            .extend(segments);
+        if !query.is_empty() {
+            url.query_pairs_mut().extend_pairs(query);
+        }
+
+        let mut request = self.client.get(url).header("Accept", accept);
+        if let Some(token) = &self.config.token {
+            // A `username:token` pair authenticates by its token
+            request = request.bearer_auth(GitCredentials::for_platform(Platform::GitHub, token).password);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        let rate_limited = matches!(status, StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS)
+            && response
+                .headers()
+                .get("x-ratelimit-remaining")
+                .is_some_and(|remaining| remaining == "0");
+        if rate_limited {
+            let reset = response
+                .headers()
+                .get("x-ratelimit-reset")
+                .and_then(|reset| reset.to_str().ok())
+                .and_then(|reset| reset.parse::<i64>().ok())
+                .and_then(|reset| chrono::DateTime::from_timestamp(reset, 0))
+                .map(|reset| format!(", resets at {}", reset.format("%H:%M:%S UTC")))
+                .unwrap_or_default();
+            return Err(GitingestError::RateLimited(format!("GitHub API{}", reset)));
+        }
+        match status {
+            StatusCode::NOT_FOUND => Err(GitingestError::RepositoryNotFound(self.config.url.clone())),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                Err(GitingestError::AuthenticationRequired(self.config.url.clone()))
+            }
+            _ => Ok(response.error_for_status()?),
+        }
+    }
+
+    /// Writes one listed file under the destination, downloading its raw
+    /// content at `reference` when it is wanted.
+    async fn write_file(&self, reference: &str, file: &ApiFile) -> Result<()> {
+        let dest = self.config.local_path.join(&file.path);
+        if let Some(parent) = dest.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        if !file.download {
+            tokio::fs::File::create(&dest).await?.set_len(file.size).await?;
+            return Ok(());
+        }
+
+        let mut segments = vec!["contents"];
+        segments.extend(file.path.split('/'));
+        let response = self.get(&segments, &[("ref", reference)], RAW).await?;
+        tokio::fs::write(&dest, response.bytes().await?).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CloneMode, RetryPolicy};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use tempfile::TempDir;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio_util::sync::CancellationToken;
+
+    /// Canned response: status, extra headers and body.
+    type Route = (u16, Vec<(&'static str, String)>, String);
+
+    /// Request target and `Authorization` header of each request served.
+    type Requests = Arc<Mutex<Vec<(String, Option<String>)>>>;
+
+    /// Serves canned responses by request path and query, recording each
+    /// request line and its `Authorization` header.
+    struct MockApi {
+        url: String,
+        requests: Requests,
+    }
+
+    impl MockApi {
+        async fn start(routes: HashMap<String, Route>) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let requests = Requests::default();
+            let routes = Arc::new(routes);
+            let recorded = requests.clone();
+            tokio::spawn(async move {
+                loop {
+                    let (mut socket, _) = listener.accept().await.unwrap();
+                    let routes = routes.clone();
+                    let recorded = recorded.clone();
+                    tokio::spawn(async move {
+                        let mut head = Vec::new();
+                        let mut byte = [0u8; 1];
+                        while !head.ends_with(b"\r\n\r\n") && socket.read(&mut byte).await.unwrap_or(0) == 1 {
+                            head.push(byte[0]);
+                        }
+                        let head = String::from_utf8_lossy(&head).into_owned();
+                        let target = head.split_whitespace().nth(1).unwrap_or_default().to_string();
+                        let authorization = head
+                            .lines()
+                            .find_map(|line| line.strip_prefix("authorization: ").map(str::to_string));
+                        recorded.lock().unwrap().push((target.clone(), authorization));
+
+                        let (status, headers, body) = routes
+                            .get(&target)
+                            .cloned()
+                            .unwrap_or((404, Vec::new(), "{\"message\":\"Not Found\"}".to_string()));
+                        let mut response = format!("HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n", status, body.len());
+                        for (name, value) in headers {
+                            response.push_str(&format!("{}: {}\r\n", name, value));
+                        }
+                        response.push_str("\r\n");
+                        response.push_str(&body);
+                        let _ = socket.write_all(response.as_bytes()).await;
+                    });
+                }
+            });
+            Self { url, requests }
+        }
+
+        fn requested(&self) -> Vec<String> {
+            self.requests.lock().unwrap().iter().map(|(target, _)| target.clone()).collect()
+        }
+    }
+
+    fn ok(body: &str) -> Route {
+        (200, Vec::new(), body.to_string())
+    }
+
+    fn clone_config(temp_dir: &TempDir, subpath: &str) -> CloneConfig {
+        CloneConfig {
+            url: "https://github.com/owner/repo".to_string(),
+            local_path: temp_dir.path().join("repo"),
+            git_ref: GitRef::DefaultBranch,
+            subpath: subpath.to_string(),
+            include_submodules: false,
+            token: None,
+            sparse_paths: None,
+            clone_mode: CloneMode::Shallow,
+            prefer_archive: false,
+            ssh_key_path: None,
+            ssh_known_hosts_policy: None,
+            retry: RetryPolicy::default(),
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    const TREE: &str = r#"{"sha": "abc", "truncated": false, "tree": [
+        {"path": "README.md", "mode": "100644", "type": "blob", "size": 7},
+        {"path": "src", "mode": "040000", "type": "tree"},
+        {"path": "src/lib.rs", "mode": "100644", "type": "blob", "size": 13},
+        {"path": "src/logo.png", "mode": "100644", "type": "blob", "size": 2048},
+        {"path": "src/link.rs", "mode": "120000", "type": "blob", "size": 6},
+        {"path": "src/target/out.rs", "mode": "100644", "type": "blob", "size": 4},
+        {"path": "src/vendor", "mode": "160000", "type": "commit"}
+    ]}"#;
+
+    fn repo_routes() -> HashMap<String, Route> {
+        HashMap::from([
+            ("/repos/owner/repo".to_string(), ok(r#"{"default_branch": "main"}"#)),
+            ("/repos/owner/repo/git/trees/main?recursive=1".to_string(), ok(TREE)),
+            ("/repos/owner/repo/contents/README.md?ref=main".to_string(), ok("# Repo\n")),
+            ("/repos/owner/repo/contents/src/lib.rs?ref=main".to_string(), ok("pub fn f() {}")),
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_fetch_via_api_downloads_matching_files_under_subpath() {
+        let api = MockApi::start(repo_routes()).await;
+        let temp_dir = TempDir::new().unwrap();
+        let config = CloneConfig { token: Some("ghp_test".to_string()), ..clone_config(&temp_dir, "src") };
+        let matcher = PatternMatcher { exclude_patterns: vec!["target/".to_string()], ..PatternMatcher::default() };
+
+        GitService::fetch_via_api(&config, &api.url, &matcher, u64::MAX).await.unwrap();
+
+        let repo = temp_dir.path().join("repo");
+        assert_eq!(std::fs::read_to_string(repo.join("src/lib.rs")).unwrap(), "pub fn f() {}");
+        // Binary files keep their size but are never downloaded
+        assert_eq!(std::fs::metadata(repo.join("src/logo.png")).unwrap().len(), 2048);
+        for skipped in ["README.md", "src/link.rs", "src/target/out.rs", "src/vendor"] {
+            assert!(!repo.join(skipped).exists(), "{}", skipped);
+        }
+
+        let mut requested = api.requested();
+        requested.sort();
+        assert_eq!(
+            requested,
+            vec![
+                "/repos/owner/repo",
+                "/repos/owner/repo/contents/src/lib.rs?ref=main",
+                "/repos/owner/repo/git/trees/main?recursive=1",
+            ]
+        );
+        assert!(
+            api.requests.lock().unwrap().iter().all(|(_, auth)| auth.as_deref() == Some("Bearer ghp_test"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_via_api_reports_rate_limit() {
+        let routes = HashMap::from([(
+            "/repos/owner/repo/git/trees/main?recursive=1".to_string(),
+            (
+                403,
+                vec![("X-RateLimit-Remaining", "0".to_string()), ("X-RateLimit-Reset", "1700000000".to_string())],
+                r#"{"message": "API rate limit exceeded"}"#.to_string(),
+            ),
+        )]);
+        let api = MockApi::start(routes).await;
+        let temp_dir = TempDir::new().unwrap();
+        let config = CloneConfig { git_ref: GitRef::Branch("main".to_string()), ..clone_config(&temp_dir, "") };
+
+        let err = GitService::fetch_via_api(&config, &api.url, &PatternMatcher::default(), u64::MAX)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), "rate_limited");
+        assert_eq!(err.to_string(), "Rate limit exceeded: GitHub API, resets at 22:13:20 UTC");
+    }
+
+    #[tokio::test]
+    async fn test_fetch_via_api_maps_missing_repository_and_branch() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CloneConfig { git_ref: GitRef::Branch("nope".to_string()), ..clone_config(&temp_dir, "") };
+
+        let api = MockApi::start(HashMap::new()).await;
+        let err = GitService::fetch_via_api(&config, &api.url, &PatternMatcher::default(), u64::MAX)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, GitingestError::RepositoryNotFound(_)));
+
+        let api = MockApi::start(repo_routes()).await;
+        let err = GitService::fetch_via_api(&config, &api.url, &PatternMatcher::default(), u64::MAX)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Branch not found: nope");
+    }
+
+    #[tokio::test]
+    async fn test_fetch_via_api_resolves_pull_request_head() {
+        let mut routes = repo_routes();
+        routes.insert("/repos/owner/repo/pulls/7".to_string(), ok(r#"{"head": {"sha": "abc123"}}"#));
+        routes.insert("/repos/owner/repo/git/trees/abc123?recursive=1".to_string(), ok(TREE));
+        routes.insert("/repos/owner/repo/contents/src/lib.rs?ref=abc123".to_string(), ok("pub fn g() {}"));
+        let api = MockApi::start(routes).await;
+        let temp_dir = TempDir::new().unwrap();
+        let config = CloneConfig { git_ref: GitRef::PullRequest(7), ..clone_config(&temp_dir, "src") };
+        let matcher = PatternMatcher { exclude_patterns: vec!["target/".to_string()], ..PatternMatcher::default() };
+
+        GitService::fetch_via_api(&config, &api.url, &matcher, u64::MAX).await.unwrap();
+        assert_eq!(std::fs::read_to_string(temp_dir.path().join("repo/src/lib.rs")).unwrap(), "pub fn g() {}");
+
+        let config = CloneConfig { git_ref: GitRef::PullRequest(8), ..clone_config(&temp_dir, "") };
+        let err = GitService::fetch_via_api(&config, &api.url, &PatternMatcher::default(), u64::MAX)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Branch not found: pull request #8 of https://github.com/owner/repo");
+    }
+
+    #[test]
+    fn test_api_paths_must_stay_inside_destination() {
+        assert!(is_safe_relative_path("src/lib.rs"));
+        assert!(!is_safe_relative_path("../escape.rs"));
+        assert!(!is_safe_relative_path("/etc/passwd"));
+        assert!(!is_safe_relative_path(""));
+    }
+}
@@ -0,0 +1,144 @@
+use crate::models::{PhaseTimings, ProcessingStats};
+use crate::utils::format_file_size;
+
+/// Formats a duration as `850ms` below a second, `14.2s` below a minute and
+/// `2m 05s` above.
+pub fn format_duration_ms(ms: u64) -> String {
+    if ms < 1_000 {
+        format!("{}ms", ms)
+    } else if ms < 59_950 {
+        format!("{:.1}s", ms as f64 / 1_000.0)
+    } else {
+        let secs = (ms + 500) / 1_000;
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    }
+}
+
+/// Formats a count with thousands separators, e.g. `1,204`.
+pub fn format_count(count: usize) -> String {
+    let digits = count.to_string();
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+/// Formats a token count with `k`/`M` suffixes, e.g. `812k` or `1.4M`.
+/// One decimal is kept below ten of a unit.
+pub fn format_tokens(tokens: usize) -> String {
+    let tokens = tokens as f64;
+    let scaled = |divisor: f64, suffix: &str| {
+        let value = tokens / divisor;
+        if value < 9.95 {
+            format!("{:.1}{}", value, suffix)
+        } else {
+            format!("{:.0}{}", value, suffix)
+        }
+    };
+    if tokens < 1_000.0 {
+        format!("{}", tokens)
+    } else if tokens < 999_500.0 {
+        scaled(1_000.0, "k")
+    } else {
+        scaled(1_000_000.0, "M")
+    }
+}
+
+/// One-line summary of a finished ingestion, e.g.
+/// `✅ repo.txt — 1,204 files, 3.4 MB content, ~812k tokens, 14.2s (clone 6.1s, scan 3.0s, write 5.1s)`.
+pub fn completion_line(label: &str, stats: &ProcessingStats, timings: &PhaseTimings) -> String {
+    let mut line = format!(
+        "✅ {} — {} files, {} content",
+        label,
+        format_count(stats.files_analyzed),
+        format_file_size(stats.total_size_bytes)
+    );
+    if let Some(tokens) = stats.estimated_tokens {
+        line.push_str(&format!(", ~{} tokens", format_tokens(tokens)));
+    }
+    line.push_str(&format!(", {}", format_duration_ms(timings.total_ms)));
+
+    let phases: Vec<String> = [("clone", timings.clone_ms), ("scan", timings.scan_ms), ("write", timings.content_ms)]
+        .into_iter()
+        .filter_map(|(name, ms)| ms.map(|ms| format!("{} {}", name, format_duration_ms(ms))))
+        .collect();
+    if !phases.is_empty() {
+        line.push_str(&format!(" ({})", phases.join(", ")));
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_duration_ms() {
+        assert_eq!(format_duration_ms(0), "0ms");
+        assert_eq!(format_duration_ms(999), "999ms");
+        assert_eq!(format_duration_ms(1_000), "1.0s");
+        assert_eq!(format_duration_ms(14_249), "14.2s");
+        assert_eq!(format_duration_ms(59_900), "59.9s");
+        assert_eq!(format_duration_ms(59_960), "1m 00s");
+        assert_eq!(format_duration_ms(125_400), "2m 05s");
+        assert_eq!(format_duration_ms(3_723_000), "62m 03s");
+    }
+
+    #[test]
+    fn test_format_count() {
+        assert_eq!(format_count(0), "0");
+        assert_eq!(format_count(999), "999");
+        assert_eq!(format_count(1_204), "1,204");
+        assert_eq!(format_count(123_456), "123,456");
+        assert_eq!(format_count(1_234_567), "1,234,567");
+    }
+
+    #[test]
+    fn test_format_tokens() {
+        assert_eq!(format_tokens(0), "0");
+        assert_eq!(format_tokens(999), "999");
+        assert_eq!(format_tokens(1_000), "1.0k");
+        assert_eq!(format_tokens(8_140), "8.1k");
+        assert_eq!(format_tokens(9_960), "10k");
+        assert_eq!(format_tokens(812_345), "812k");
+        assert_eq!(format_tokens(999_499), "999k");
+        assert_eq!(format_tokens(999_500), "1.0M");
+        assert_eq!(format_tokens(1_430_000), "1.4M");
+        assert_eq!(format_tokens(25_000_000), "25M");
+    }
+
+    #[test]
+    fn test_completion_line() {
+        let stats = ProcessingStats {
+            files_analyzed: 1_204,
+            total_size_bytes: 3_565_158,
+            estimated_tokens: Some(812_000),
+            processing_time_ms: 14_200,
+            skipped: Default::default(),
+            language_breakdown: Vec::new(),
+            diff: None,
+        };
+        let timings = PhaseTimings {
+            clone_ms: Some(6_100),
+            scan_ms: Some(3_000),
+            content_ms: Some(5_100),
+            total_ms: 14_200,
+        };
+
+        assert_eq!(
+            completion_line("repo.txt", &stats, &timings),
+            "✅ repo.txt — 1,204 files, 3.4 MB content, ~812k tokens, 14.2s (clone 6.1s, scan 3.0s, write 5.1s)"
+        );
+
+        let stats = ProcessingStats { estimated_tokens: None, ..stats };
+        let timings = PhaseTimings { total_ms: 450, ..Default::default() };
+        assert_eq!(
+            completion_line("stdout", &stats, &timings),
+            "✅ stdout — 1,204 files, 3.4 MB content, 450ms"
+        );
+    }
+}
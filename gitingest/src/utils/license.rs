@@ -0,0 +1,277 @@
+use crate::error::Result;
+use crate::models::LicenseInfo;
+use std::collections::HashSet;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::Path;
+use tokio::fs;
+
+/// SPDX identifier reported for a license file that matches no fingerprint.
+pub const UNRECOGNIZED_LICENSE: &str = "Unrecognized";
+
+/// License files larger than this are not read for identification.
+const LICENSE_MAX_SIZE: u64 = 256 * 1024;
+
+/// Words per hashed shingle. Long enough that common legal phrasing
+/// doesn't match across licenses, short enough to survive rewrapping and
+/// edited copyright lines.
+const SHINGLE_WORDS: usize = 4;
+
+/// A license is recognized when its text has every passage of one of its
+/// fingerprints. Checked in order, so more specific licenses come before
+/// those whose passages they share (LGPL before GPL, BSD-3 before BSD-2).
+/// Identifiers follow what GitHub reports, which can't tell `-only` from
+/// `-or-later` by the license text alone.
+struct Fingerprint {
+    spdx_id: &'static str,
+    passages: &'static [&'static str],
+}
+
+const FINGERPRINTS: &[Fingerprint] = &[
+    Fingerprint {
+        spdx_id: "AGPL-3.0",
+        passages: &["GNU AFFERO GENERAL PUBLIC LICENSE Version 3, 19 November 2007"],
+    },
+    Fingerprint {
+        spdx_id: "LGPL-3.0",
+        passages: &["GNU LESSER GENERAL PUBLIC LICENSE Version 3, 29 June 2007"],
+    },
+    Fingerprint {
+        spdx_id: "LGPL-2.1",
+        passages: &["GNU LESSER GENERAL PUBLIC LICENSE Version 2.1, February 1999"],
+    },
+    Fingerprint {
+        spdx_id: "GPL-3.0",
+        passages: &["GNU GENERAL PUBLIC LICENSE Version 3, 29 June 2007"],
+    },
+    Fingerprint {
+        spdx_id: "GPL-2.0",
+        passages: &["GNU GENERAL PUBLIC LICENSE Version 2, June 1991"],
+    },
+    Fingerprint {
+        spdx_id: "Apache-2.0",
+        passages: &[
+            "Apache License Version 2.0, January 2004",
+            "TERMS AND CONDITIONS FOR USE, REPRODUCTION, AND DISTRIBUTION",
+        ],
+    },
+    // The short notice many projects ship instead of the full text
+    Fingerprint {
+        spdx_id: "Apache-2.0",
+        passages: &[
+            "Licensed under the Apache License, Version 2.0 (the \"License\"); you may not use this file except in compliance with the License.",
+        ],
+    },
+    Fingerprint {
+        spdx_id: "MIT",
+        passages: &[
+            "Permission is hereby granted, free of charge, to any person obtaining a copy of this software and associated documentation files (the \"Software\"), to deal in the Software without restriction",
+            "The above copyright notice and this permission notice shall be included in all copies or substantial portions of the Software.",
+        ],
+    },
+    Fingerprint {
+        spdx_id: "BSD-3-Clause",
+        passages: &[
+            "Redistribution and use in source and binary forms, with or without modification, are permitted provided that the following conditions are met",
+            "may be used to endorse or promote products derived from this software without specific prior written permission.",
+        ],
+    },
+    Fingerprint {
+        spdx_id: "BSD-2-Clause",
+        passages: &[
+            "Redistribution and use in source and binary forms, with or without modification, are permitted provided that the following conditions are met",
+            "Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the following disclaimer in the documentation and/or other materials provided with the distribution.",
+        ],
+    },
+];
+
+/// Finds the license file at the root of `root` (`LICENSE`, `LICENCE` or
+/// `COPYING`, with or without an extension or suffix) and identifies it.
+/// Symlinks are ignored. When several match, the shortest name wins, so
+/// `LICENSE` is preferred over `LICENSE-MIT`.
+pub async fn detect_license(root: &Path) -> Result<Option<LicenseInfo>> {
+    let mut candidates = Vec::new();
+    let mut entries = fs::read_dir(root).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let upper = name.to_uppercase();
+        if !["LICENSE", "LICENCE", "COPYING"].iter().any(|prefix| upper.starts_with(prefix)) {
+            continue;
+        }
+        let metadata = fs::symlink_metadata(entry.path()).await?;
+        if metadata.is_file() && metadata.len() <= LICENSE_MAX_SIZE {
+            candidates.push(name);
+        }
+    }
+    candidates.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+
+    let Some(name) = candidates.into_iter().next() else {
+        return Ok(None);
+    };
+    let bytes = fs::read(root.join(&name)).await?;
+    Ok(Some(LicenseInfo {
+        spdx_id: identify_license(&String::from_utf8_lossy(&bytes))
+            .unwrap_or(UNRECOGNIZED_LICENSE)
+            .to_string(),
+        path: name,
+    }))
+}
+
+/// The SPDX identifier of the first fingerprint all of whose passages
+/// appear in `text`. Texts are compared as hashed shingles of lowercased
+/// words, ignoring punctuation and line wrapping; a passage matches when
+/// nine in ten of its shingles are present.
+pub fn identify_license(text: &str) -> Option<&'static str> {
+    let shingles = shingle_hashes(text);
+    FINGERPRINTS
+        .iter()
+        .find(|fingerprint| {
+            fingerprint.passages.iter().all(|passage| {
+                let passage = shingle_hashes(passage);
+                let found = passage.iter().filter(|hash| shingles.contains(hash)).count();
+                found * 10 >= passage.len() * 9
+            })
+        })
+        .map(|fingerprint| fingerprint.spdx_id)
+}
+
+fn shingle_hashes(text: &str) -> HashSet<u64> {
+    let lowered = text.to_lowercase();
+    let words: Vec<&str> = lowered
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+    words
+        .windows(SHINGLE_WORDS.min(words.len().max(1)))
+        .map(|shingle| {
+            let mut hasher = DefaultHasher::new();
+            shingle.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const MIT: &str = "MIT License
+
+Copyright (c) 2024 Example Corp
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the \"Software\"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED \"AS IS\", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY.
+";
+
+    const APACHE: &str = "
+                                 Apache License
+                           Version 2.0, January 2004
+                        http://www.apache.org/licenses/
+
+   TERMS AND CONDITIONS FOR USE, REPRODUCTION, AND DISTRIBUTION
+
+   1. Definitions.
+
+      \"License\" shall mean the terms and conditions for use, reproduction,
+      and distribution as defined by Sections 1 through 9 of this document.
+";
+
+    const APACHE_NOTICE: &str = "Copyright 2024 Example Corp
+
+Licensed under the Apache License, Version 2.0 (the \"License\");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+";
+
+    const BSD_CONDITIONS: &str = "Copyright (c) 2024, Example Corp
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice, this
+   list of conditions and the following disclaimer.
+
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+";
+
+    const BSD_3_CLAUSE: &str = "
+3. Neither the name of the copyright holder nor the names of its
+   contributors may be used to endorse or promote products derived from
+   this software without specific prior written permission.
+";
+
+    const BSD_DISCLAIMER: &str = "
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS \"AS IS\"
+AND ANY EXPRESS OR IMPLIED WARRANTIES ARE DISCLAIMED.
+";
+
+    fn gnu(title: &str, version: &str) -> String {
+        format!(
+            "                    {}\n                       {}\n\n Copyright (C) Free Software Foundation, Inc. <https://fsf.org/>\n Everyone is permitted to copy and distribute verbatim copies\n of this license document, but changing it is not allowed.\n",
+            title, version
+        )
+    }
+
+    #[test]
+    fn test_identify_supported_licenses() {
+        let bsd_2 = format!("{}{}", BSD_CONDITIONS, BSD_DISCLAIMER);
+        let bsd_3 = format!("{}{}{}", BSD_CONDITIONS, BSD_3_CLAUSE, BSD_DISCLAIMER);
+        let cases = [
+            (MIT.to_string(), "MIT"),
+            (APACHE.to_string(), "Apache-2.0"),
+            (APACHE_NOTICE.to_string(), "Apache-2.0"),
+            (bsd_2, "BSD-2-Clause"),
+            (bsd_3, "BSD-3-Clause"),
+            (gnu("GNU GENERAL PUBLIC LICENSE", "Version 2, June 1991"), "GPL-2.0"),
+            (gnu("GNU GENERAL PUBLIC LICENSE", "Version 3, 29 June 2007"), "GPL-3.0"),
+            (gnu("GNU LESSER GENERAL PUBLIC LICENSE", "Version 2.1, February 1999"), "LGPL-2.1"),
+            (gnu("GNU LESSER GENERAL PUBLIC LICENSE", "Version 3, 29 June 2007"), "LGPL-3.0"),
+            (gnu("GNU AFFERO GENERAL PUBLIC LICENSE", "Version 3, 19 November 2007"), "AGPL-3.0"),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(identify_license(&text), Some(expected), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_identification_ignores_wrapping_and_case() {
+        let rewrapped = MIT.split_whitespace().collect::<Vec<_>>().join(" ").to_uppercase();
+        assert_eq!(identify_license(&rewrapped), Some("MIT"));
+    }
+
+    #[tokio::test]
+    async fn test_detect_license_file() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        assert_eq!(detect_license(root).await?, None);
+
+        std::fs::write(root.join("LICENSE-MIT"), MIT).unwrap();
+        std::fs::write(root.join("LICENSE"), "All rights reserved. Do not copy.\n").unwrap();
+        std::fs::create_dir(root.join("licenses")).unwrap();
+
+        let license = detect_license(root).await?.unwrap();
+        assert_eq!(license.path, "LICENSE");
+        assert_eq!(license.spdx_id, UNRECOGNIZED_LICENSE);
+
+        std::fs::remove_file(root.join("LICENSE")).unwrap();
+        let license = detect_license(root).await?.unwrap();
+        assert_eq!(license, LicenseInfo { spdx_id: "MIT".to_string(), path: "LICENSE-MIT".to_string() });
+
+        Ok(())
+    }
+}
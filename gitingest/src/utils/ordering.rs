@@ -0,0 +1,182 @@
+use crate::models::{FileNode, SortOrder};
+use std::path::Path;
+
+/// Build and package manifests that describe a project, in the order they
+/// are ranked when several sit at the root.
+const MANIFESTS: &[&str] = &[
+    "Cargo.toml",
+    "package.json",
+    "pyproject.toml",
+    "setup.py",
+    "setup.cfg",
+    "go.mod",
+    "pom.xml",
+    "build.gradle",
+    "build.gradle.kts",
+    "Gemfile",
+    "composer.json",
+    "CMakeLists.txt",
+    "Makefile",
+    "Dockerfile",
+];
+
+/// Lock files and other machine-written files that rarely help a reader.
+const GENERATED_FILES: &[&str] = &[
+    "Cargo.lock",
+    "package-lock.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "poetry.lock",
+    "Pipfile.lock",
+    "Gemfile.lock",
+    "composer.lock",
+    "go.sum",
+];
+
+const GENERATED_SUFFIXES: &[&str] = &[".min.js", ".min.css", ".map", "_pb2.py", ".pb.go", ".g.dart"];
+
+const GENERATED_DIRS: &[&str] = &["vendor", "third_party", "node_modules", "generated", "dist", "build"];
+
+const TEST_DIRS: &[&str] = &["test", "tests", "__tests__", "spec", "specs", "testdata", "fixtures"];
+
+const DOC_EXTENSIONS: &[&str] = &["md", "markdown", "rst", "txt", "adoc"];
+
+/// Sorts files into the order their contents are written for `order`.
+/// Ties keep their relative (tree) order.
+pub fn sort_for_content(files: &mut [&FileNode], order: SortOrder) {
+    match order {
+        SortOrder::Importance => files.sort_by_cached_key(|node| {
+            let path = Path::new(&node.relative_path);
+            (importance_tier(path), path.components().count(), node.size)
+        }),
+        SortOrder::Path => files.sort_by(|a, b| a.relative_path.cmp(&b.relative_path)),
+        SortOrder::SizeAsc => files.sort_by_key(|node| node.size),
+    }
+}
+
+/// Rank of a repo-relative path for [`SortOrder::Importance`]: root
+/// READMEs, root docs, root manifests, other files, tests, then generated
+/// and vendored files.
+fn importance_tier(path: &Path) -> u8 {
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    let at_root = path.components().count() == 1;
+    let dirs: Vec<String> = path
+        .parent()
+        .into_iter()
+        .flat_map(Path::components)
+        .map(|dir| dir.as_os_str().to_string_lossy().to_lowercase())
+        .collect();
+
+    if dirs.iter().any(|dir| GENERATED_DIRS.contains(&dir.as_str()))
+        || GENERATED_FILES.contains(&name.as_ref())
+        || GENERATED_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+    {
+        return 5;
+    }
+    if dirs.iter().any(|dir| TEST_DIRS.contains(&dir.as_str())) || is_test_file(&name) {
+        return 4;
+    }
+    if !at_root {
+        return 3;
+    }
+    if name.to_uppercase().starts_with("README") {
+        0
+    } else if MANIFESTS.contains(&name.as_ref()) {
+        2
+    } else if path
+        .extension()
+        .is_some_and(|ext| DOC_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str()))
+    {
+        1
+    } else {
+        3
+    }
+}
+
+/// Test files by naming convention: `test_x.py`, `x_test.go`,
+/// `x.test.ts`, `x.spec.js`.
+fn is_test_file(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or(name);
+    stem.starts_with("test_")
+        || stem.ends_with("_test")
+        || stem.ends_with("_tests")
+        || name.contains(".test.")
+        || name.contains(".spec.")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::FileNodeType;
+
+    fn node(relative_path: &str, size: u64) -> FileNode {
+        FileNode {
+            name: relative_path.rsplit('/').next().unwrap().to_string(),
+            path: relative_path.into(),
+            relative_path: relative_path.to_string(),
+            node_type: FileNodeType::File,
+            size,
+            has_content: true,
+            children: Vec::new(),
+            depth: 0,
+            link_target: None,
+            budget_omitted: false,
+            text_rescued: false,
+            tokens: None,
+            pinned: false,
+            over_line_limit: None,
+            deleted: false,
+            renamed_from: None,
+            last_modified: None,
+        }
+    }
+
+    fn sorted(nodes: &[FileNode], order: SortOrder) -> Vec<&str> {
+        let mut files: Vec<&FileNode> = nodes.iter().collect();
+        sort_for_content(&mut files, order);
+        files.iter().map(|node| node.relative_path.as_str()).collect()
+    }
+
+    #[test]
+    fn test_importance_order() {
+        let nodes = [
+            node("Cargo.lock", 10),
+            node("src/utils/deep.rs", 10),
+            node("tests/integration.rs", 10),
+            node("vendor/lib/big.rs", 10),
+            node("src/main_test.go", 10),
+            node("src/lib.rs", 300),
+            node("src/main.rs", 100),
+            node("Cargo.toml", 50),
+            node("CONTRIBUTING.md", 50),
+            node("README.md", 500),
+            node("build.rs", 20),
+        ];
+
+        assert_eq!(
+            sorted(&nodes, SortOrder::Importance),
+            vec![
+                "README.md",
+                "CONTRIBUTING.md",
+                "Cargo.toml",
+                "build.rs",
+                "src/main.rs",
+                "src/lib.rs",
+                "src/utils/deep.rs",
+                "tests/integration.rs",
+                "src/main_test.go",
+                "Cargo.lock",
+                "vendor/lib/big.rs",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_path_and_size_orders() {
+        let nodes = [node("b.rs", 3), node("a/z.rs", 1), node("a.rs", 2), node("c.rs", 1)];
+
+        assert_eq!(sorted(&nodes, SortOrder::Path), vec!["a.rs", "a/z.rs", "b.rs", "c.rs"]);
+        // Equal sizes keep their tree order
+        assert_eq!(sorted(&nodes, SortOrder::SizeAsc), vec!["a/z.rs", "c.rs", "a.rs", "b.rs"]);
+    }
+}
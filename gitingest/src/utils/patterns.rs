@@ -1,10 +1,13 @@
 use crate::error::{GitingestError, Result};
 use crate::models::PatternMatcher;
-use globset::{Glob, GlobSet, GlobSetBuilder};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
 use std::path::Path;
 
 pub struct PatternService;
 
+/// Ingestion-only ignore file at the repository root, in `.gitignore` format.
+pub const INGEST_IGNORE_FILE: &str = ".gitingestignore";
+
 impl PatternService {
     pub fn new_matcher(
         include_patterns: Vec<String>,
@@ -17,16 +20,18 @@ impl PatternService {
         })
     }
 
+    /// Checks a file against the matcher. `file_path` is relative to the
+    /// repository root, which is what anchored patterns are matched against.
     pub fn should_include_file<P: AsRef<Path>>(
         matcher: &PatternMatcher,
         file_path: P,
     ) -> Result<bool> {
-        let path_ref = file_path.as_ref();
+        let path = to_match_path(file_path.as_ref());
 
         // If we have include patterns, the file must match at least one
         if !matcher.include_patterns.is_empty() {
             let include_set = Self::build_glob_set(&matcher.include_patterns)?;
-            if !include_set.is_match(path_ref) {
+            if !include_set.is_match(&path) {
                 return Ok(false);
             }
         }
@@ -34,7 +39,7 @@ impl PatternService {
         // Check exclude patterns
         if !matcher.exclude_patterns.is_empty() {
             let exclude_set = Self::build_glob_set(&matcher.exclude_patterns)?;
-            if exclude_set.is_match(path_ref) {
+            if exclude_set.is_match(&path) {
                 return Ok(false);
             }
         }
@@ -42,7 +47,7 @@ impl PatternService {
         // Check gitignore patterns
         if !matcher.gitignore_patterns.is_empty() {
             let gitignore_set = Self::build_glob_set(&matcher.gitignore_patterns)?;
-            if gitignore_set.is_match(path_ref) {
+            if gitignore_set.is_match(&path) {
                 return Ok(false);
             }
         }
@@ -50,17 +55,18 @@ impl PatternService {
         Ok(true)
     }
 
+    /// Checks a directory, relative to the repository root, against the
+    /// exclude patterns.
     pub fn should_include_directory<P: AsRef<Path>>(
         matcher: &PatternMatcher,
         dir_path: P,
     ) -> Result<bool> {
-        let path_ref = dir_path.as_ref();
-        let path_str = path_ref.to_string_lossy();
+        let path = to_match_path(dir_path.as_ref());
 
         // Always include directories for traversal, unless explicitly excluded
         if !matcher.exclude_patterns.is_empty() {
             let exclude_set = Self::build_glob_set(&matcher.exclude_patterns)?;
-            if exclude_set.is_match(path_ref) || exclude_set.is_match(&format!("{}/", path_str)) {
+            if exclude_set.is_match(&path) || exclude_set.is_match(format!("{}/", path)) {
                 return Ok(false);
             }
         }
@@ -68,13 +74,56 @@ impl PatternService {
         Ok(true)
     }
 
+    /// Compiles every include and exclude pattern on its own, failing with
+    /// the first one that isn't a valid glob. Lets callers reject a typo
+    /// before cloning rather than end up with an empty digest.
+    pub fn validate_patterns(include: &[String], exclude: &[String]) -> Result<()> {
+        let patterns = include
+            .iter()
+            .map(|pattern| ("include", pattern))
+            .chain(exclude.iter().map(|pattern| ("exclude", pattern)));
+        for (kind, pattern) in patterns {
+            for expanded in expand_pattern(pattern) {
+                if let Err(e) = GlobBuilder::new(&expanded).literal_separator(true).build() {
+                    return Err(GitingestError::PatternError(format!(
+                        "Invalid {} pattern '{}': {}",
+                        kind,
+                        pattern,
+                        e.kind()
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether each of the repo-relative `paths` would be ingested under
+    /// `matcher`, without touching the filesystem. Paths ending in `/` are
+    /// checked as directories.
+    pub fn dry_match<'a>(matcher: &PatternMatcher, paths: &'a [String]) -> Result<Vec<(&'a str, bool)>> {
+        paths
+            .iter()
+            .map(|path| {
+                let included = match path.strip_suffix('/') {
+                    Some(dir) => Self::should_include_directory(matcher, dir)?,
+                    None => Self::should_include_file(matcher, path)?,
+                };
+                Ok((path.as_str(), included))
+            })
+            .collect()
+    }
+
     fn build_glob_set(patterns: &[String]) -> Result<GlobSet> {
         let mut builder = GlobSetBuilder::new();
         
         for pattern in patterns {
-            let glob = Glob::new(pattern)
-                .map_err(|e| GitingestError::PatternError(format!("Invalid glob pattern '{}': {}", pattern, e)))?;
-            builder.add(glob);
+            for expanded in expand_pattern(pattern) {
+                let glob = GlobBuilder::new(&expanded)
+                    .literal_separator(true)
+                    .build()
+                    .map_err(|e| GitingestError::PatternError(format!("Invalid glob pattern '{}': {}", pattern, e)))?;
+                builder.add(glob);
+            }
         }
 
         builder.build()
@@ -112,12 +161,100 @@ impl PatternService {
         matcher: &mut PatternMatcher,
         gitignore_path: &Path,
     ) -> Result<()> {
-        if gitignore_path.exists() {
+        // Only regular files: an ignore file symlinked to somewhere like
+        // `/dev/zero` must not be read
+        let is_file = std::fs::symlink_metadata(gitignore_path).is_ok_and(|metadata| metadata.is_file());
+        if is_file {
             let patterns = Self::parse_gitignore(gitignore_path)?;
             matcher.gitignore_patterns.extend(patterns);
         }
         Ok(())
     }
+
+    /// Like [`PatternService::add_gitignore_patterns`] for an ignore file at
+    /// the repository root when only `subpath` is scanned, with paths
+    /// relative to it: patterns anchored at the root are rebased onto
+    /// `subpath`, and those for paths outside it are dropped.
+    pub fn add_root_ignore_patterns(matcher: &mut PatternMatcher, ignore_path: &Path, subpath: &str) -> Result<()> {
+        let start = matcher.gitignore_patterns.len();
+        Self::add_gitignore_patterns(matcher, ignore_path)?;
+        let added = matcher.gitignore_patterns.split_off(start);
+        matcher
+            .gitignore_patterns
+            .extend(added.iter().filter_map(|pattern| rebase_pattern(pattern, subpath)));
+        Ok(())
+    }
+}
+
+/// `pattern`, written for the repository root, as seen from `subpath`, or
+/// `None` when it can only match outside it. Unanchored patterns match at
+/// any depth and are kept as they are.
+fn rebase_pattern(pattern: &str, subpath: &str) -> Option<String> {
+    let normalized = pattern.replace('\\', "/");
+    let trimmed = normalized.trim_end_matches('/');
+    if subpath.is_empty() || !trimmed.contains('/') {
+        return Some(pattern.to_string());
+    }
+    let suffix = if normalized.ends_with('/') { "/" } else { "" };
+    let mut components = trimmed.trim_start_matches('/').split('/');
+    for dir in subpath.split('/') {
+        match components.next() {
+            // Matches at any depth from here on
+            Some("**") => {
+                let parts: Vec<&str> = std::iter::once("**").chain(components).collect();
+                return Some(format!("{}{}", parts.join("/"), suffix));
+            }
+            Some(component) => {
+                let glob = GlobBuilder::new(component).literal_separator(true).build();
+                if !glob.is_ok_and(|glob| glob.compile_matcher().is_match(dir)) {
+                    return None;
+                }
+            }
+            // The pattern names a directory the scan is inside of
+            None => return Some("**".to_string()),
+        }
+    }
+    let rest: Vec<&str> = components.collect();
+    match rest.first() {
+        None => Some("**".to_string()),
+        Some(&"**") => Some(format!("{}{}", rest.join("/"), suffix)),
+        Some(_) => Some(format!("/{}{}", rest.join("/"), suffix)),
+    }
+}
+
+/// Renders a relative path with `/` separators on every platform.
+fn to_match_path(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    if cfg!(windows) {
+        path.replace('\\', "/")
+    } else {
+        path.into_owned()
+    }
+}
+
+/// Expands a gitignore-style pattern into globs over repo-relative paths.
+/// Patterns containing a `/` (other than a trailing one) are anchored at the
+/// repository root; bare patterns like `*.rs` or `node_modules/` match at
+/// any depth. Every pattern also matches everything beneath a matching
+/// directory, and a trailing `/` restricts it to directories.
+fn expand_pattern(pattern: &str) -> Vec<String> {
+    let pattern = pattern.replace('\\', "/");
+    let dir_only = pattern.ends_with('/');
+    let trimmed = pattern.trim_end_matches('/');
+    let anchored = trimmed.contains('/');
+    let trimmed = trimmed.trim_start_matches('/');
+
+    let base = if anchored || trimmed.starts_with("**/") {
+        trimmed.to_string()
+    } else {
+        format!("**/{}", trimmed)
+    };
+
+    if dir_only || base.ends_with("/**") {
+        vec![format!("{}/**", base)]
+    } else {
+        vec![base.clone(), format!("{}/**", base)]
+    }
 }
 
 pub fn normalize_pattern(pattern: &str) -> String {
@@ -142,16 +279,95 @@ pub fn is_binary_file<P: AsRef<Path>>(path: P) -> bool {
         let ext = extension.to_string_lossy().to_lowercase();
         matches!(ext.as_str(), 
             "exe" | "dll" | "so" | "dylib" | "a" | "lib" | "o" | "obj" |
-            "png" | "jpg" | "jpeg" | "gif" | "bmp" | "ico" | "svg" |
+            "png" | "jpg" | "jpeg" | "gif" | "bmp" | "ico" |
             "pdf" | "doc" | "docx" | "xls" | "xlsx" | "ppt" | "pptx" |
             "zip" | "tar" | "gz" | "bz2" | "7z" | "rar" |
-            "mp3" | "mp4" | "avi" | "mov" | "wmv" | "flv"
+            "mp3" | "mp4" | "avi" | "mov" | "wmv" | "flv" |
+            "bin" | "dat"
         )
     } else {
         false
     }
 }
 
+/// Guesses a file's language from its extension or well-known file name.
+pub fn guess_language<P: AsRef<Path>>(path: P) -> Option<&'static str> {
+    let path = path.as_ref();
+    let file_name = path.file_name()?.to_string_lossy();
+    match file_name.as_ref() {
+        "Dockerfile" => return Some("dockerfile"),
+        "Makefile" | "makefile" => return Some("makefile"),
+        _ => {}
+    }
+
+    let ext = path.extension()?.to_string_lossy().to_lowercase();
+    let language = match ext.as_str() {
+        "rs" => "rust",
+        "py" | "pyi" => "python",
+        "js" | "mjs" | "cjs" => "javascript",
+        "jsx" => "jsx",
+        "ts" => "typescript",
+        "tsx" => "tsx",
+        "go" => "go",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "swift" => "swift",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hpp" | "hh" => "cpp",
+        "cs" => "csharp",
+        "rb" => "ruby",
+        "php" => "php",
+        "scala" => "scala",
+        "sh" | "bash" | "zsh" => "shell",
+        "sql" => "sql",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "scss" | "sass" => "scss",
+        "json" => "json",
+        "yaml" | "yml" => "yaml",
+        "toml" => "toml",
+        "xml" | "svg" => "xml",
+        "md" | "markdown" => "markdown",
+        "txt" => "text",
+        _ => return None,
+    };
+    Some(language)
+}
+
+/// Human-readable name of a file's language for statistics, grouping the
+/// dialects [`guess_language`] tells apart; `Other` when it is unknown.
+pub fn language_name<P: AsRef<Path>>(path: P) -> &'static str {
+    match guess_language(path) {
+        Some("rust") => "Rust",
+        Some("python") => "Python",
+        Some("javascript" | "jsx") => "JavaScript",
+        Some("typescript" | "tsx") => "TypeScript",
+        Some("go") => "Go",
+        Some("java") => "Java",
+        Some("kotlin") => "Kotlin",
+        Some("swift") => "Swift",
+        Some("c") => "C",
+        Some("cpp") => "C++",
+        Some("csharp") => "C#",
+        Some("ruby") => "Ruby",
+        Some("php") => "PHP",
+        Some("scala") => "Scala",
+        Some("shell") => "Shell",
+        Some("sql") => "SQL",
+        Some("html") => "HTML",
+        Some("css" | "scss") => "CSS",
+        Some("json") => "JSON",
+        Some("yaml") => "YAML",
+        Some("toml") => "TOML",
+        Some("xml") => "XML",
+        Some("markdown") => "Markdown",
+        Some("text") => "Text",
+        Some("dockerfile") => "Dockerfile",
+        Some("makefile") => "Makefile",
+        _ => "Other",
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -169,11 +385,138 @@ mod tests {
         assert!(!PatternService::should_include_file(&matcher, "README.md").unwrap());
     }
 
+    fn matcher(include: &[&str], exclude: &[&str]) -> PatternMatcher {
+        PatternMatcher {
+            include_patterns: include.iter().map(|p| p.to_string()).collect(),
+            exclude_patterns: exclude.iter().map(|p| p.to_string()).collect(),
+            gitignore_patterns: vec![],
+        }
+    }
+
+    #[test]
+    fn test_anchored_include_matches_relative_paths() {
+        let matcher = matcher(&["src/**"], &[]);
+
+        assert!(PatternService::should_include_file(&matcher, "src/main.rs").unwrap());
+        assert!(PatternService::should_include_file(&matcher, "src/nested/lib.rs").unwrap());
+        assert!(!PatternService::should_include_file(&matcher, "tests/src/main.rs").unwrap());
+        assert!(!PatternService::should_include_file(&matcher, "README.md").unwrap());
+    }
+
+    #[test]
+    fn test_root_anchored_exclude_skips_nested_dir_of_same_name() {
+        let matcher = matcher(&[], &["/docs/"]);
+
+        assert!(!PatternService::should_include_file(&matcher, "docs/guide.md").unwrap());
+        assert!(PatternService::should_include_file(&matcher, "src/docs/guide.md").unwrap());
+        assert!(!PatternService::should_include_directory(&matcher, "docs").unwrap());
+        assert!(PatternService::should_include_directory(&matcher, "src/docs").unwrap());
+    }
+
+    #[test]
+    fn test_bare_patterns_match_at_any_depth() {
+        let matcher = matcher(&["*.rs"], &[".git", "node_modules/"]);
+
+        assert!(PatternService::should_include_file(&matcher, "main.rs").unwrap());
+        assert!(PatternService::should_include_file(&matcher, "src/deep/lib.rs").unwrap());
+        assert!(!PatternService::should_include_file(&matcher, ".git/hooks/update.rs").unwrap());
+        assert!(!PatternService::should_include_file(&matcher, "web/node_modules/pkg/index.rs").unwrap());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_windows_separators_are_normalized() {
+        let matcher = matcher(&["src/**"], &[]);
+        assert!(PatternService::should_include_file(&matcher, Path::new("src\\main.rs")).unwrap());
+    }
+
+    #[test]
+    fn test_validate_patterns() {
+        let include = vec!["src/**".to_string(), "*.rs".to_string(), "**/*.{toml,md}".to_string()];
+        let exclude = vec!["target/".to_string(), "/docs/".to_string()];
+        assert!(PatternService::validate_patterns(&include, &exclude).is_ok());
+        assert!(PatternService::validate_patterns(&[], &[]).is_ok());
+
+        let exclude = vec!["target/".to_string(), "src/[a-z".to_string()];
+        let err = PatternService::validate_patterns(&include, &exclude).unwrap_err();
+        assert!(matches!(&err, GitingestError::PatternError(msg) if msg.starts_with("Invalid exclude pattern 'src/[a-z': ")), "{}", err);
+    }
+
+    #[test]
+    fn test_dry_match_previews_sample_paths() {
+        let matcher = matcher(&["src/**", "*.md"], &["src/generated/"]);
+        let paths: Vec<String> = ["src/main.rs", "src/generated/api.rs", "README.md", "build.rs", "src/generated/"]
+            .iter()
+            .map(|path| path.to_string())
+            .collect();
+
+        assert_eq!(
+            PatternService::dry_match(&matcher, &paths).unwrap(),
+            vec![
+                ("src/main.rs", true),
+                ("src/generated/api.rs", false),
+                ("README.md", true),
+                ("build.rs", false),
+                ("src/generated/", false),
+            ]
+        );
+    }
+
     #[test]
     fn test_binary_file_detection() {
         assert!(is_binary_file("test.exe"));
         assert!(is_binary_file("image.png"));
         assert!(!is_binary_file("source.rs"));
         assert!(!is_binary_file("README.md"));
+        assert!(!is_binary_file("icon.svg"));
+        assert!(is_binary_file("fixture.dat"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_root_patterns_are_rebased_onto_the_subpath() {
+        let cases = [
+            ("*.log", Some("*.log")),
+            ("build/", Some("build/")),
+            ("/docs/", None),
+            ("/app/gen/", Some("/gen/")),
+            ("app/dist", Some("/dist")),
+            ("*/tmp", Some("/tmp")),
+            ("app/**/cache", Some("**/cache")),
+            ("**/vendor", Some("**/vendor")),
+            ("/app", Some("**")),
+            ("other/gen", None),
+        ];
+        for (pattern, rebased) in cases {
+            assert_eq!(rebase_pattern(pattern, "app").as_deref(), rebased, "{}", pattern);
+        }
+        assert_eq!(rebase_pattern("/docs/", ""), Some("/docs/".to_string()));
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join(".gitignore"), "/gen/\n/app/gen/\n*.log\n").unwrap();
+        let mut matcher = PatternMatcher::default();
+        PatternService::add_root_ignore_patterns(&mut matcher, &temp_dir.path().join(".gitignore"), "app").unwrap();
+        assert!(PatternService::should_include_file(&matcher, "main.rs").unwrap());
+        assert!(!PatternService::should_include_file(&matcher, "gen/api.rs").unwrap());
+        assert!(!PatternService::should_include_file(&matcher, "debug.log").unwrap());
+        assert!(PatternService::should_include_file(&matcher, "src/gen/api.rs").unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_ignore_file_is_not_read() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::write(root.join("patterns"), "*.log\n").unwrap();
+        std::os::unix::fs::symlink(root.join("patterns"), root.join(".gitignore")).unwrap();
+        std::os::unix::fs::symlink("/dev/zero", root.join(INGEST_IGNORE_FILE)).unwrap();
+
+        let mut matcher = PatternMatcher::default();
+        PatternService::add_gitignore_patterns(&mut matcher, &root.join(".gitignore")).unwrap();
+        PatternService::add_gitignore_patterns(&mut matcher, &root.join(INGEST_IGNORE_FILE)).unwrap();
+        assert!(matcher.gitignore_patterns.is_empty());
+
+        std::fs::rename(root.join("patterns"), root.join(".gitignore")).unwrap();
+        PatternService::add_gitignore_patterns(&mut matcher, &root.join(".gitignore")).unwrap();
+        assert_eq!(matcher.gitignore_patterns, vec!["*.log".to_string()]);
+    }
+}
@@ -0,0 +1,356 @@
+use crate::error::Result;
+use crate::models::{ContentFormat, FileNode, FileNodeType, TokenBudgetOutcome};
+use crate::utils::guess_language;
+use futures::future::join_all;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+/// Counts tokens for batches of text. Implementations are called with many
+/// texts at once so that expensive counters (such as a BPE tokenizer) are
+/// invoked once per batch rather than once per file.
+pub trait TokenCounter: Send + Sync {
+    /// Name used when logging a failed count.
+    fn name(&self) -> &str;
+
+    /// Returns one count per input text, in order.
+    fn count_batch(&self, texts: &[&str]) -> Result<Vec<usize>>;
+
+    /// Count for a text of `len` bytes, for counters that don't need to see
+    /// the text itself. Lets files be counted without being read.
+    fn count_len(&self, _len: usize) -> Option<usize> {
+        None
+    }
+}
+
+/// Built-in estimate of ~4 characters per token for English text. Counts
+/// round up, so the counts of several texts never sum to less than the
+/// count of the texts joined together.
+pub struct HeuristicTokenCounter;
+
+impl TokenCounter for HeuristicTokenCounter {
+    fn name(&self) -> &str {
+        "heuristic"
+    }
+
+    fn count_batch(&self, texts: &[&str]) -> Result<Vec<usize>> {
+        Ok(texts.iter().map(|text| text.len().div_ceil(4)).collect())
+    }
+
+    fn count_len(&self, len: usize) -> Option<usize> {
+        Some(len.div_ceil(4))
+    }
+}
+
+/// Counts with OpenAI's `cl100k_base` encoding.
+#[cfg(feature = "tiktoken")]
+pub struct TiktokenCounter {
+    bpe: tiktoken_rs::CoreBPE,
+}
+
+#[cfg(feature = "tiktoken")]
+impl TiktokenCounter {
+    pub fn new() -> Result<Self> {
+        let bpe = tiktoken_rs::cl100k_base()
+            .map_err(|e| crate::GitingestError::ConfigError(format!("Failed to load cl100k_base: {}", e)))?;
+        Ok(Self { bpe })
+    }
+}
+
+#[cfg(feature = "tiktoken")]
+impl TokenCounter for TiktokenCounter {
+    fn name(&self) -> &str {
+        "cl100k_base"
+    }
+
+    fn count_batch(&self, texts: &[&str]) -> Result<Vec<usize>> {
+        Ok(texts.iter().map(|text| self.bpe.encode_ordinary(text).len()).collect())
+    }
+}
+
+/// The counter ingestion uses: [`TiktokenCounter`] with the `tiktoken`
+/// feature, otherwise [`HeuristicTokenCounter`].
+pub fn default_token_counter() -> Box<dyn TokenCounter> {
+    #[cfg(feature = "tiktoken")]
+    match TiktokenCounter::new() {
+        Ok(counter) => return Box::new(counter),
+        Err(err) => log::warn!("{}; estimating tokens instead", err),
+    }
+    Box::new(HeuristicTokenCounter)
+}
+
+/// Files rendered and counted together while filling a token budget.
+const BUDGET_BATCH_SIZE: usize = 64;
+
+/// Sets [`FileNode::tokens`] for every file whose content is in the digest,
+/// counting the content as written (truncated to `max_content_size`).
+/// Returns the total.
+pub async fn count_tree_tokens(
+    tree: &mut FileNode,
+    counter: &dyn TokenCounter,
+    max_content_size: u64,
+) -> Result<usize> {
+    let mut files = Vec::new();
+    collect_content_files(tree, &mut files);
+
+    let mut counts = HashMap::new();
+    for batch in files.chunks(BUDGET_BATCH_SIZE) {
+        let lens: Option<Vec<usize>> = batch
+            .iter()
+            .map(|node| counter.count_len(node.size.min(node.content_limit(max_content_size)) as usize))
+            .collect();
+        let batch_counts = match lens {
+            Some(lens) => lens,
+            None => {
+                let contents = join_all(batch.iter().map(|node| node.read_content_async(max_content_size)))
+                    .await
+                    .into_iter()
+                    .map(|content| content.unwrap_or_default())
+                    .collect::<Vec<_>>();
+                let texts: Vec<&str> = contents.iter().map(String::as_str).collect();
+                match counter.count_batch(&texts) {
+                    Ok(counts) => counts,
+                    Err(err) => {
+                        log::warn!("Token counter '{}' failed, using heuristic: {}", counter.name(), err);
+                        HeuristicTokenCounter.count_batch(&texts)?
+                    }
+                }
+            }
+        };
+        for (node, tokens) in batch.iter().zip(batch_counts) {
+            counts.insert(node.path.clone(), tokens);
+        }
+    }
+
+    let total = counts.values().sum();
+    set_tokens(tree, &counts);
+    Ok(total)
+}
+
+/// Marks files whose content doesn't fit in `max_tokens`. Source files are
+/// considered before other text, smaller files before larger ones, and each
+/// file's whole digest section (header included, laid out as `format`) is
+/// counted. Once one file doesn't fit, it and every later file are omitted.
+/// Pinned files are counted first and always kept, even past the budget.
+pub async fn apply_token_budget(
+    tree: &mut FileNode,
+    max_tokens: usize,
+    counter: &dyn TokenCounter,
+    max_content_size: u64,
+    format: &ContentFormat,
+) -> Result<TokenBudgetOutcome> {
+    let mut candidates = Vec::new();
+    collect_content_files(tree, &mut candidates);
+    candidates.sort_by_key(|node| (!node.pinned, !is_source_file(node), node.size));
+
+    let mut used_tokens = 0;
+    let mut omitted = HashSet::new();
+    for batch in candidates.chunks(BUDGET_BATCH_SIZE) {
+        if !omitted.is_empty() {
+            omitted.extend(batch.iter().map(|node| node.path.clone()));
+            continue;
+        }
+
+        let sections = join_all(batch.iter().map(|node| async move {
+            let mut section = Vec::new();
+            node.write_content_async(&mut section, max_content_size, u64::MAX, 1, None, format, None, None).await?;
+            Ok::<_, std::io::Error>(String::from_utf8_lossy(&section).into_owned())
+        }))
+        .await
+        .into_iter()
+        .collect::<std::io::Result<Vec<_>>>()?;
+        let texts: Vec<&str> = sections.iter().map(String::as_str).collect();
+        let counts = match counter.count_batch(&texts) {
+            Ok(counts) => counts,
+            Err(err) => {
+                log::warn!("Token counter '{}' failed, using heuristic: {}", counter.name(), err);
+                HeuristicTokenCounter.count_batch(&texts)?
+            }
+        };
+
+        for (node, tokens) in batch.iter().zip(counts) {
+            if node.pinned || (omitted.is_empty() && used_tokens + tokens <= max_tokens) {
+                used_tokens += tokens;
+            } else {
+                omitted.insert(node.path.clone());
+            }
+        }
+    }
+
+    mark_omitted(tree, &omitted);
+    if !omitted.is_empty() {
+        log::warn!(
+            "Token budget of {} reached; omitted content of {} file(s)",
+            max_tokens,
+            omitted.len()
+        );
+    }
+
+    Ok(TokenBudgetOutcome {
+        max_tokens,
+        used_tokens,
+        omitted_files: omitted.len(),
+    })
+}
+
+fn collect_content_files<'a>(node: &'a FileNode, files: &mut Vec<&'a FileNode>) {
+    match node.node_type {
+        FileNodeType::Directory => {
+            for child in &node.children {
+                collect_content_files(child, files);
+            }
+        }
+        FileNodeType::File if node.has_content => files.push(node),
+        FileNodeType::File | FileNodeType::Symlink | FileNodeType::Submodule => {}
+    }
+}
+
+fn is_source_file(node: &FileNode) -> bool {
+    !matches!(
+        guess_language(&node.path),
+        None | Some("markdown" | "text" | "json" | "yaml" | "toml" | "xml")
+    )
+}
+
+fn set_tokens(node: &mut FileNode, counts: &HashMap<PathBuf, usize>) {
+    if let Some(tokens) = counts.get(&node.path) {
+        node.tokens = Some(*tokens);
+    }
+    for child in &mut node.children {
+        set_tokens(child, counts);
+    }
+}
+
+fn mark_omitted(node: &mut FileNode, omitted: &HashSet<PathBuf>) {
+    if omitted.contains(&node.path) {
+        node.budget_omitted = true;
+    }
+    for child in &mut node.children {
+        mark_omitted(child, omitted);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counts words, so it has to see the text.
+    struct WordCounter;
+
+    impl TokenCounter for WordCounter {
+        fn name(&self) -> &str {
+            "words"
+        }
+
+        fn count_batch(&self, texts: &[&str]) -> Result<Vec<usize>> {
+            Ok(texts.iter().map(|text| text.split_whitespace().count()).collect())
+        }
+    }
+
+    #[test]
+    fn test_heuristic_counter() {
+        assert_eq!(HeuristicTokenCounter.count_batch(&["abcdefgh", "abcd", ""]).unwrap(), vec![2, 1, 0]);
+        assert_eq!(HeuristicTokenCounter.count_len(5), Some(2));
+    }
+
+    fn file(dir: &std::path::Path, name: &str, content: &str) -> FileNode {
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        FileNode {
+            name: name.to_string(),
+            path,
+            relative_path: name.to_string(),
+            node_type: FileNodeType::File,
+            size: content.len() as u64,
+            has_content: true,
+            children: Vec::new(),
+            depth: 0,
+            link_target: None,
+            budget_omitted: false,
+            text_rescued: false,
+            tokens: None,
+            pinned: false,
+            over_line_limit: None,
+            deleted: false,
+            renamed_from: None,
+            last_modified: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_token_budget_keeps_content_under_budget() {
+        use crate::models::ContentWriter;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut tree = FileNode {
+            name: "repo".to_string(),
+            path: temp_dir.path().to_path_buf(),
+            relative_path: String::new(),
+            node_type: FileNodeType::Directory,
+            size: 0,
+            has_content: false,
+            children: vec![
+                file(temp_dir.path(), "README.md", &"docs ".repeat(20)),
+                file(temp_dir.path(), "big.rs", &"fn big() {}\n".repeat(40)),
+                file(temp_dir.path(), "small.rs", "fn small() {}\n"),
+                file(temp_dir.path(), "mid.rs", &"fn mid() {}\n".repeat(5)),
+            ],
+            depth: 0,
+            link_target: None,
+            budget_omitted: false,
+            text_rescued: false,
+            tokens: None,
+            pinned: false,
+            over_line_limit: None,
+            deleted: false,
+            renamed_from: None,
+            last_modified: None,
+        };
+
+        let outcome = apply_token_budget(&mut tree, 60, &HeuristicTokenCounter, 1_000, &ContentFormat::default()).await.unwrap();
+
+        let mut content = Vec::new();
+        tree.write_content(&mut content, 1_000).unwrap();
+        let content = String::from_utf8(content).unwrap();
+        let tokens = HeuristicTokenCounter.count_batch(&[&content]).unwrap()[0];
+
+        assert!(tokens <= 60);
+        assert_eq!(tokens, outcome.used_tokens);
+        // Source files come first, smallest first; the README doesn't fit
+        assert!(content.contains("small.rs:") && content.contains("mid.rs:"));
+        assert!(!content.contains("big.rs:") && !content.contains("README.md:"));
+        assert_eq!(outcome.omitted_files, 2);
+        assert!(tree.children.iter().filter(|c| c.budget_omitted).count() == 2);
+    }
+
+    #[tokio::test]
+    async fn test_count_tree_tokens_per_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut tree = file(temp_dir.path(), "a.rs", "");
+        tree.node_type = FileNodeType::Directory;
+        tree.has_content = false;
+        tree.children = vec![
+            file(temp_dir.path(), "one.rs", "one two three four"),
+            file(temp_dir.path(), "two.md", &"word ".repeat(100)),
+        ];
+
+        // Estimated from the size, truncated like the digest
+        let total = count_tree_tokens(&mut tree, &HeuristicTokenCounter, 100).await.unwrap();
+        assert_eq!(tree.children[0].tokens, Some(5));
+        assert_eq!(tree.children[1].tokens, Some(25));
+        assert_eq!(total, 30);
+
+        // Counters that need the text read the files
+        let total = count_tree_tokens(&mut tree, &WordCounter, 10).await.unwrap();
+        assert_eq!(tree.children[0].tokens, Some(3));
+        assert_eq!(tree.children[1].tokens, Some(2));
+        assert_eq!(total, 5);
+    }
+
+    #[cfg(feature = "tiktoken")]
+    #[test]
+    fn test_tiktoken_counter() {
+        let counter = TiktokenCounter::new().unwrap();
+        assert_eq!(counter.count_batch(&["hello world", ""]).unwrap(), vec![2, 0]);
+        assert_eq!(counter.count_len(11), None);
+    }
+}
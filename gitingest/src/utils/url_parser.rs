@@ -1,42 +1,86 @@
+use crate::config::AppConfig;
 use crate::error::{GitingestError, Result};
-use crate::models::Repository;
+use crate::models::{GitRef, Repository, TargetKind};
+use crate::utils::Platform;
 use regex::Regex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use url::Url;
 
+/// What the snapshot archive links of GitHub and Gitea end in.
+const ARCHIVE_LINK_SUFFIXES: &[&str] = &[".tar.gz", ".zip"];
+
 pub struct UrlParser;
 
 impl UrlParser {
     pub fn parse_git_url(input: &str) -> Result<Repository> {
+        Self::parse_git_url_with(input, &AppConfig::default())
+    }
+    
+    /// [`UrlParser::parse_git_url`], reading URL layouts per the platform
+    /// [`AppConfig::host_overrides`] assigns to self-hosted instances and
+    /// keeping the case of owner and name when
+    /// [`AppConfig::preserve_repo_case`] is set. Hosts of no known platform
+    /// are read as `owner/repo` with GitHub-style `tree/<ref>` paths.
+    ///
+    /// The result is canonical, whatever was pasted: a lowercase ASCII host
+    /// (see [`normalize_host`]) without `www.`, no empty path segments,
+    /// query or fragment, and on platforms that ignore case (GitHub,
+    /// Bitbucket, Gitea) a lowercase owner and name, so equal repositories
+    /// get equal URLs.
+    pub fn parse_git_url_with(input: &str, config: &AppConfig) -> Result<Repository> {
         let trimmed_input = input.trim();
+        let host_overrides = &config.host_overrides;
+        let fold_case = !config.preserve_repo_case;
         
         // Try to parse as a direct URL first
         if let Ok(url) = Url::parse(trimmed_input) {
-            return Self::parse_url(url);
+            return Self::parse_url(url, host_overrides, fold_case);
         }
         
-        // Handle GitHub shorthand (owner/repo)
-        if let Some(caps) = Regex::new(r"^([a-zA-Z0-9_.-]+)/([a-zA-Z0-9_.-]+)$")
+        // Handle scp-style SSH remotes (git@host:owner/repo.git) as the
+        // equivalent ssh:// URL
+        if let Some(caps) = Regex::new(r"^([a-zA-Z0-9_.-]+)@([\w.-]+):/*([^/].*)$")
             .unwrap()
             .captures(trimmed_input)
+            && let Ok(url) = Url::parse(&format!("ssh://{}@{}/{}", &caps[1], &caps[2], &caps[3]))
         {
-            let owner = caps[1].to_string();
-            let repo = caps[2].to_string();
-            return Ok(Repository {
-                url: format!("https://github.com/{}/{}", owner, repo),
-                host: "github.com".to_string(),
-                owner,
-                name: repo,
-                branch: None,
-                commit: None,
-                subpath: String::new(),
-            });
+            return Self::parse_url(url, host_overrides, fold_case);
         }
         
-        // Try to construct a GitHub URL if it looks like a repository reference
+        // A URL pasted without its scheme (github.com/owner/repo) starts
+        // with a host; GitHub owners can't contain dots
+        if !trimmed_input.contains("://")
+            && trimmed_input.split('/').next().is_some_and(|host| host.contains('.'))
+            && let Ok(url) = Url::parse(&format!("https://{}", trimmed_input))
+        {
+            return Self::parse_url(url, host_overrides, fold_case);
+        }
+        
+        // owner/repo@ref checks out a branch, tag or commit; names may
+        // contain dots and hyphens but not `@`
+        if let Some(caps) = Regex::new(r"^([A-Za-z0-9_.-]+/[A-Za-z0-9_.-]+)@(.*)$")
+            .unwrap()
+            .captures(trimmed_input)
+        {
+            let reference = &caps[2];
+            if reference.is_empty() || reference.contains('@') {
+                return Err(GitingestError::InvalidRepositoryUrl(format!(
+                    "Expected owner/repo@ref with a single ref, got: {}",
+                    trimmed_input
+                )));
+            }
+            let mut repository = Self::parse_git_url_with(&caps[1], config)?;
+            repository.git_ref = url_ref(reference.to_string());
+            return Ok(repository);
+        }
+        
+        // Anything else that looks like a repository reference, such as the
+        // owner/repo shorthand, is on GitHub
         if !trimmed_input.contains("://") {
             let github_url = format!("https://github.com/{}", trimmed_input);
             if let Ok(url) = Url::parse(&github_url) {
-                return Self::parse_url(url);
+                return Self::parse_url(url, host_overrides, fold_case);
             }
         }
         
@@ -45,62 +89,245 @@ impl UrlParser {
         ))
     }
     
-    fn parse_url(url: Url) -> Result<Repository> {
-        let host = url.host_str()
-            .ok_or_else(|| GitingestError::InvalidRepositoryUrl("No host found".to_string()))?
-            .to_string();
+    fn parse_url(url: Url, host_overrides: &HashMap<String, Platform>, fold_case: bool) -> Result<Repository> {
+        if url.scheme() == "file" {
+            return Self::parse_file_url(url);
+        }
+
+        let host = normalize_host(url.host_str()
+            .ok_or_else(|| GitingestError::InvalidRepositoryUrl("No host found".to_string()))?);
+        let host = host.strip_prefix("www.").unwrap_or(&host).to_string();
         
         let path_segments: Vec<&str> = url.path_segments()
             .ok_or_else(|| GitingestError::InvalidRepositoryUrl("Invalid path".to_string()))?
+            .filter(|segment| !segment.is_empty())
             .collect();
         
-        if path_segments.len() < 2 {
+        let platform = Platform::for_host_in(&host, host_overrides);
+        let (project, rest) = Self::split_project_path(platform, &path_segments);
+        let Some((name, owner)) = project.split_last().filter(|(_, owner)| !owner.is_empty()) else {
             return Err(GitingestError::InvalidRepositoryUrl(
                 "URL must contain owner and repository name".to_string()
             ));
+        };
+        
+        let mut owner = owner.join("/");
+        let mut repo_name = trim_git_suffix(name).to_string();
+        if fold_case && platform.ignores_case() {
+            owner = owner.to_lowercase();
+            repo_name = repo_name.to_lowercase();
         }
         
-        let owner = path_segments[0].to_string();
-        let repo_name = path_segments[1].trim_end_matches(".git").to_string();
+        let (git_ref, subpath, mut target_kind) = Self::parse_ref_path(platform, rest);
+        if target_kind.is_none() && !subpath.is_empty() && url.path().ends_with('/') {
+            target_kind = Some(TargetKind::Directory);
+        }
         
-        // Handle GitHub-style URLs with tree/blob/etc.
-        let (branch, subpath) = if path_segments.len() > 3 && 
-            (path_segments[2] == "tree" || path_segments[2] == "blob") 
-        {
-            let branch = if path_segments.len() > 3 {
-                Some(path_segments[3].to_string())
-            } else {
-                None
-            };
-            let subpath = if path_segments.len() > 4 {
-                path_segments[4..].join("/")
-            } else {
-                String::new()
-            };
-            (branch, subpath)
-        } else {
-            (None, String::new())
+        // Construct clean repository URL without tree/blob paths, keeping
+        // a non-default port. SSH and git:// remotes keep their scheme (and
+        // SSH its user) so the clone goes over that protocol, not HTTPS.
+        let port = url.port().map(|port| format!(":{}", port)).unwrap_or_default();
+        let clean_url = match url.scheme() {
+            "ssh" => {
+                let user = match url.username() {
+                    "" => String::new(),
+                    user => format!("{}@", user),
+                };
+                format!("ssh://{}{}{}/{}/{}.git", user, host, port, owner, repo_name)
+            }
+            "git" => format!("git://{}{}/{}/{}.git", host, port, owner, repo_name),
+            _ => format!("https://{}{}/{}/{}", host, port, owner, repo_name),
         };
         
-        // Construct clean repository URL without tree/blob paths
-        let clean_url = format!("https://{}/{}/{}", host, owner, repo_name);
-        
         Ok(Repository {
             url: clean_url,
             host,
             owner,
             name: repo_name,
-            branch,
-            commit: None,
+            git_ref,
             subpath,
+            target_kind,
         })
     }
     
-    pub fn is_valid_github_url(url: &str) -> bool {
-        if let Ok(parsed_url) = Url::parse(url) {
-            if let Some(host) = parsed_url.host_str() {
-                return host == "github.com" || host == "www.github.com";
+    /// Whether `reference` reads as a release version (`1.75.0`, `v2.0`,
+    /// `v1.2.3-rc.1`), which repositories name tags, not branches.
+    pub fn is_version_tag(reference: &str) -> bool {
+        Regex::new(r"^v?\d+(\.\d+)+([-+][0-9A-Za-z.-]+)?$").unwrap().is_match(reference)
+    }
+
+    /// Whether git reaches `url` over SSH: an `ssh://` URL or the scp-like
+    /// `user@host:path` form.
+    pub fn is_ssh_url(url: &str) -> bool {
+        match url.split_once("://") {
+            Some((scheme, _)) => matches!(scheme, "ssh" | "git+ssh" | "ssh+git"),
+            None => url.split_once(':').is_some_and(|(host, _)| !host.is_empty() && !host.contains('/')),
+        }
+    }
+
+    /// Whether `input` is a link to a snapshot archive of a ref, such as
+    /// `https://github.com/owner/repo/archive/refs/tags/v1.2.3.tar.gz`.
+    pub fn is_archive_link(input: &str) -> bool {
+        Url::parse(input.trim()).is_ok_and(|url| {
+            let path = url.path().to_ascii_lowercase();
+            path.contains("/archive/") && ARCHIVE_LINK_SUFFIXES.iter().any(|suffix| path.ends_with(suffix))
+        })
+    }
+    
+    /// Splits the URL path into the project path and what follows it. GitLab
+    /// projects sit in nested groups (`group/subgroup/project`), so everything
+    /// before the `-` segment is the project; Azure DevOps repositories are
+    /// at `organization/project/_git/repo`; elsewhere it is `owner/repo`.
+    fn split_project_path<'a>(platform: Platform, segments: &'a [&'a str]) -> (&'a [&'a str], &'a [&'a str]) {
+        let end = match platform {
+            Platform::GitLab => segments.iter().position(|segment| *segment == "-").unwrap_or(segments.len()),
+            Platform::AzureDevOps => segments
+                .iter()
+                .position(|segment| *segment == "_git")
+                .map_or(segments.len().min(2), |index| (index + 2).min(segments.len())),
+            _ => segments.len().min(2),
+        };
+        segments.split_at(end)
+    }
+    
+    /// Extracts the ref, subpath and what the subpath names from the
+    /// segments after the project. GitHub uses `tree|blob/<ref>/...`, GitLab
+    /// `-/tree|blob/<ref>/...`, Bitbucket `src/<ref>/...`, Gitea
+    /// `src/branch|tag|commit/<ref>/...` and sourcehut
+    /// `tree/<ref>/item/...`, whose `item` marks where a ref with slashes
+    /// ends; commit pages
+    /// (`commit/<sha>`, GitLab `-/commit/<sha>`, Bitbucket `commits/<sha>`)
+    /// name just a commit, GitHub-style `pull/<n>` pages (and their tabs) a
+    /// pull request, and release pages and archive links a tag. A URL
+    /// can't tell a branch with slashes from a shorter branch and a
+    /// subpath, so the ref is its first segment unless the slashes are
+    /// percent-encoded; see [`UrlParser::apply_branch`].
+    fn parse_ref_path(platform: Platform, segments: &[&str]) -> (GitRef, String, Option<TargetKind>) {
+        let no_path = |git_ref| (git_ref, String::new(), None);
+        let (kind, rest) = match (platform, segments) {
+            (Platform::GitLab, ["-", kind @ ("tree" | "blob"), rest @ ..]) => (*kind, rest),
+            (Platform::GitLab, ["-", "commit", sha, ..]) => return no_path(GitRef::Commit(decode_segment(sha))),
+            (Platform::GitLab, ["-", "releases", tag, ..]) => return no_path(GitRef::Tag(decode_segment(tag))),
+            (Platform::GitLab, ["-", "archive", reference, ..]) => return no_path(url_ref(decode_segment(reference))),
+            (Platform::Bitbucket, ["src", rest @ ..]) => ("src", rest),
+            (Platform::Bitbucket, ["commits", sha, ..]) => return no_path(GitRef::Commit(decode_segment(sha))),
+            (Platform::Gitea, ["src", kind @ ("branch" | "tag" | "commit"), reference, subpath @ ..]) => {
+                let reference = decode_segment(reference);
+                let git_ref = match *kind {
+                    "tag" => GitRef::Tag(reference),
+                    "commit" => GitRef::Commit(reference),
+                    _ => GitRef::Branch(reference),
+                };
+                return (git_ref, decode_segments(subpath), None);
+            }
+            (Platform::SourceHut, ["tree", rest @ ..]) => {
+                let (reference, subpath) = match rest.iter().position(|segment| *segment == "item") {
+                    Some(item) => (&rest[..item], &rest[item + 1..]),
+                    None => (rest, &[][..]),
+                };
+                if reference.is_empty() {
+                    return no_path(GitRef::DefaultBranch);
+                }
+                return (url_ref(decode_segments(reference)), decode_segments(subpath), Some(TargetKind::Directory));
+            }
+            (Platform::GitHub | Platform::Other, ["pull", number, ..]) => {
+                return no_path(number.parse().map_or(GitRef::DefaultBranch, GitRef::PullRequest));
+            }
+            (_, ["releases", "tag", tag @ ..]) if !tag.is_empty() => return no_path(GitRef::Tag(decode_segments(tag))),
+            (_, ["archive", link @ ..]) => return no_path(archive_link_ref(link).unwrap_or_default()),
+            (_, [kind @ ("tree" | "blob"), rest @ ..]) => (*kind, rest),
+            (_, ["commit", sha, ..]) => return no_path(GitRef::Commit(decode_segment(sha))),
+            _ => return no_path(GitRef::DefaultBranch),
+        };
+        // `src/` pages show files and directories alike; only a trailing
+        // slash, checked by the caller, marks a directory
+        let kind = match kind {
+            "blob" => Some(TargetKind::File),
+            "src" => None,
+            _ => Some(TargetKind::Directory),
+        };
+        
+        match rest {
+            [reference, subpath @ ..] => (url_ref(decode_segment(reference)), decode_segments(subpath), kind),
+            [] => no_path(GitRef::DefaultBranch),
+        }
+    }
+    
+    /// Re-splits the URL's `<ref>/<subpath>` so that `branch`, when the path
+    /// starts with it, is the ref and the rest the subpath. This is how a
+    /// requested branch with slashes (`feature/x`) is told apart from URL
+    /// branch `feature` and subpath `x/...`.
+    pub fn apply_branch(repository: &mut Repository, branch: &str) {
+        let Some(url_branch) = repository.git_ref.branch_name() else {
+            return;
+        };
+        let ref_path = if repository.subpath.is_empty() {
+            url_branch.to_string()
+        } else {
+            format!("{}/{}", url_branch, repository.subpath)
+        };
+        if ref_path == branch {
+            repository.subpath.clear();
+        } else if let Some(subpath) = ref_path.strip_prefix(branch).and_then(|rest| rest.strip_prefix('/')) {
+            repository.subpath = subpath.to_string();
+        } else {
+            return;
+        }
+        repository.git_ref = GitRef::Branch(branch.to_string());
+    }
+    
+    /// Local repositories are cloned straight from their `file://` URL, less
+    /// any query or fragment, with the parent directory standing in for the
+    /// owner.
+    fn parse_file_url(mut url: Url) -> Result<Repository> {
+        url.set_query(None);
+        url.set_fragment(None);
+        let path_segments: Vec<&str> = url.path_segments()
+            .ok_or_else(|| GitingestError::InvalidRepositoryUrl("Invalid path".to_string()))?
+            .filter(|segment| !segment.is_empty())
+            .collect();
+
+        let (owner, repo_name) = match path_segments.as_slice() {
+            [.., owner, name] => (owner.to_string(), trim_git_suffix(name).to_string()),
+            [name] => ("local".to_string(), trim_git_suffix(name).to_string()),
+            [] => {
+                return Err(GitingestError::InvalidRepositoryUrl(
+                    "URL must contain a repository path".to_string()
+                ));
             }
+        };
+
+        Ok(Repository {
+            url: url.to_string(),
+            host: "localhost".to_string(),
+            owner,
+            name: repo_name,
+            git_ref: GitRef::DefaultBranch,
+            subpath: String::new(),
+            target_kind: None,
+        })
+    }
+    
+    /// The existing local directory `input` names, absolute or relative
+    /// to the working directory, with a leading `~` standing for `$HOME`.
+    /// Such inputs are scanned in place instead of being parsed as URLs.
+    /// A relative path shaped like the `owner/repo` shorthand is left to
+    /// the shorthand; `./owner/repo` names the directory.
+    pub fn local_directory(input: &str) -> Option<PathBuf> {
+        let input = input.trim();
+        if Regex::new(r"^[A-Za-z0-9_-][A-Za-z0-9_.-]*/[A-Za-z0-9_.-]+$").unwrap().is_match(input) {
+            return None;
+        }
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        let path = expand_home(input, home.as_deref())?;
+        std::fs::canonicalize(path).ok().filter(|path| path.is_dir())
+    }
+    
+    pub fn is_valid_github_url(url: &str) -> bool {
+        if let Ok(parsed_url) = Url::parse(url)
+            && let Some(host) = parsed_url.host_str()
+        {
+            return host == "github.com" || host == "www.github.com";
         }
         false
     }
@@ -114,10 +341,145 @@ impl UrlParser {
     }
 }
 
+/// `input` with a leading `~` or `~/` replaced by `home`; `None` when it
+/// needs a home directory and there is none.
+fn expand_home(input: &str, home: Option<&Path>) -> Option<PathBuf> {
+    match input.strip_prefix('~') {
+        Some("") => home.map(Path::to_path_buf),
+        Some(rest) if rest.starts_with('/') => home.map(|home| home.join(rest.trim_start_matches('/'))),
+        _ => Some(PathBuf::from(input)),
+    }
+}
+
+/// `name` without one trailing `.git`, so a repository named `x.git` in a
+/// `x.git.git` URL keeps its name.
+fn trim_git_suffix(name: &str) -> &str {
+    name.strip_suffix(".git").filter(|name| !name.is_empty()).unwrap_or(name)
+}
+
+/// Whether a URL's ref looks like a full or abbreviated commit id (7 to 40
+/// hex digits) rather than a branch or tag name.
+fn is_commit_sha(reference: &str) -> bool {
+    (7..=40).contains(&reference.len()) && reference.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// The form hosts are compared, cloned from and cached by: lowercase
+/// ASCII without a trailing dot, internationalized names in punycode
+/// (`git.büro.example` is `git.xn--bro-hoa.example`). `ssh://` and
+/// `git://` URLs carry such names percent-encoded, so they are decoded
+/// first.
+pub fn normalize_host(host: &str) -> String {
+    let host = decode_segment(host.trim().trim_end_matches('.'));
+    idna::domain_to_ascii(&host).unwrap_or_else(|_| host.to_lowercase())
+}
+
+/// `host` as people write it, with punycode labels in unicode again.
+pub fn display_host(host: &str) -> String {
+    idna::domain_to_unicode(host).0
+}
+
+fn decode_segment(segment: &str) -> String {
+    percent_encoding::percent_decode_str(segment).decode_utf8_lossy().into_owned()
+}
+
+fn decode_segments(segments: &[&str]) -> String {
+    segments.iter().map(|segment| decode_segment(segment)).collect::<Vec<_>>().join("/")
+}
+
+/// The ref of an `archive/` link: `refs/tags/<tag>.tar.gz`,
+/// `refs/heads/<branch>.zip` or the bare `<ref>.tar.gz`.
+fn archive_link_ref(segments: &[&str]) -> Option<GitRef> {
+    let link = decode_segments(segments);
+    let reference = ARCHIVE_LINK_SUFFIXES.iter().find_map(|suffix| link.strip_suffix(suffix))?;
+    if reference.is_empty() {
+        return None;
+    }
+    Some(if let Some(tag) = reference.strip_prefix("refs/tags/") {
+        GitRef::Tag(tag.to_string())
+    } else if let Some(branch) = reference.strip_prefix("refs/heads/") {
+        GitRef::Branch(branch.to_string())
+    } else {
+        url_ref(reference.to_string())
+    })
+}
+
+/// A ref from a URL path that doesn't say what it is: a commit when it
+/// looks like a commit id, a tag when it looks like a version (`1.75.0`,
+/// `v2.0-rc.1`), else a branch. `git clone --branch` takes either name
+/// just the same; the kind only decides what the summary calls it.
+fn url_ref(reference: String) -> GitRef {
+    if is_commit_sha(&reference) {
+        GitRef::Commit(reference)
+    } else if UrlParser::is_version_tag(&reference) {
+        GitRef::Tag(reference)
+    } else {
+        GitRef::Branch(reference)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_idn_and_uppercase_hosts_are_normalized() {
+        let config = AppConfig {
+            host_overrides: HashMap::from([("git.büro.example".to_string(), Platform::GitLab)]),
+            ..AppConfig::default()
+        };
+        for (input, url) in [
+            ("https://git.büro.example/team/app", "https://git.xn--bro-hoa.example/team/app"),
+            ("https://GIT.BÜRO.EXAMPLE/team/app", "https://git.xn--bro-hoa.example/team/app"),
+            ("https://git.xn--bro-hoa.example/team/app", "https://git.xn--bro-hoa.example/team/app"),
+            ("ssh://git@git.büro.example/team/app.git", "ssh://git@git.xn--bro-hoa.example/team/app.git"),
+            ("git@git.büro.example:team/app.git", "ssh://git@git.xn--bro-hoa.example/team/app.git"),
+        ] {
+            let result = UrlParser::parse_git_url_with(input, &config).unwrap();
+            assert_eq!((result.host.as_str(), result.url.as_str()), ("git.xn--bro-hoa.example", url), "{}", input);
+            assert_eq!(result.display_host(), "git.büro.example", "{}", input);
+        }
+        // The override, written in unicode, still picks GitLab's layout
+        let result = UrlParser::parse_git_url_with("https://git.büro.example/team/app/-/tree/main/docs", &config).unwrap();
+        assert_eq!((result.git_ref.branch_name(), result.subpath.as_str()), (Some("main"), "docs"));
+
+        let result = UrlParser::parse_git_url("https://GITLAB.COM/Group/Project").unwrap();
+        assert_eq!((result.host.as_str(), result.display_host().as_str()), ("gitlab.com", "gitlab.com"));
+        assert_eq!(normalize_host("Git.Example.COM."), "git.example.com");
+    }
+
+    #[test]
+    fn test_pasted_urls_are_canonicalized() {
+        let github = "https://github.com/owner/repo";
+        for (input, url, owner, name) in [
+            ("https://www.github.com/Owner/Repo/", github, "owner", "repo"),
+            ("https://GitHub.com/owner/repo", github, "owner", "repo"),
+            ("HTTPS://WWW.GITHUB.COM/OWNER/REPO", github, "owner", "repo"),
+            ("http://github.com/owner/repo", github, "owner", "repo"),
+            ("  https://github.com/owner/repo.git  ", github, "owner", "repo"),
+            ("https://github.com/owner/repo.git/", github, "owner", "repo"),
+            ("https://github.com//owner//repo//", github, "owner", "repo"),
+            ("https://github.com/owner/repo?tab=readme-ov-file#usage", github, "owner", "repo"),
+            ("github.com/owner/repo", github, "owner", "repo"),
+            ("www.github.com/Owner/Repo/", github, "owner", "repo"),
+            ("Owner/Repo", github, "owner", "repo"),
+            ("git@GitHub.com:Owner/Repo.git", "ssh://git@github.com/owner/repo.git", "owner", "repo"),
+            ("https://www.bitbucket.org/Team/App/", "https://bitbucket.org/team/app", "team", "app"),
+            // GitLab paths are case-sensitive
+            ("https://www.gitlab.com/Group/Sub/Project/", "https://gitlab.com/Group/Sub/Project", "Group/Sub", "Project"),
+            ("gitlab.com/Group/Project.git", "https://gitlab.com/Group/Project", "Group", "Project"),
+        ] {
+            let result = UrlParser::parse_git_url(input).unwrap();
+            assert_eq!(result.url, url, "{}", input);
+            assert_eq!((result.owner.as_str(), result.name.as_str()), (owner, name), "{}", input);
+            assert_eq!(result.host, url.split('/').nth(2).unwrap().trim_start_matches("git@"), "{}", input);
+        }
+
+        let config = AppConfig { preserve_repo_case: true, ..AppConfig::default() };
+        let result = UrlParser::parse_git_url_with("https://www.github.com/Owner/Repo/", &config).unwrap();
+        assert_eq!((result.owner.as_str(), result.name.as_str()), ("Owner", "Repo"));
+        assert_eq!(result.url, "https://github.com/Owner/Repo");
+    }
+
     #[test]
     fn test_github_shorthand() {
         let result = UrlParser::parse_git_url("owner/repo").unwrap();
@@ -127,6 +489,90 @@ mod tests {
         assert_eq!(result.url, "https://github.com/owner/repo");
     }
 
+    #[test]
+    fn test_shorthand_with_ref() {
+        let sha = "3f2a9bc1d4e5f60718293a4b5c6d7e8f90a1b2c3";
+        for (input, name, git_ref) in [
+            ("rust-lang/cargo@1.75.0", "cargo", GitRef::Tag("1.75.0".to_string())),
+            ("owner/repo@v1.2.3-rc.1", "repo", GitRef::Tag("v1.2.3-rc.1".to_string())),
+            ("tokio-rs/tokio@master", "tokio", GitRef::Branch("master".to_string())),
+            ("owner/my.repo-name@feature/x", "my.repo-name", GitRef::Branch("feature/x".to_string())),
+            ("owner/repo.git@v2", "repo", GitRef::Branch("v2".to_string())),
+            (&format!("owner/repo@{}", sha), "repo", GitRef::Commit(sha.to_string())),
+        ] {
+            let result = UrlParser::parse_git_url(input).unwrap();
+            assert_eq!((result.name.as_str(), &result.git_ref), (name, &git_ref), "{}", input);
+            assert_eq!(result.host, "github.com", "{}", input);
+            assert_eq!(result.subpath, "", "{}", input);
+        }
+
+        for input in ["owner/repo@", "owner/repo@v1@v2"] {
+            assert!(matches!(UrlParser::parse_git_url(input), Err(GitingestError::InvalidRepositoryUrl(_))), "{}", input);
+        }
+        // SSH remotes aren't shorthand
+        assert_eq!(UrlParser::parse_git_url("git@github.com:owner/repo.git").unwrap().git_ref, GitRef::DefaultBranch);
+    }
+
+    #[test]
+    fn test_git_suffix_trailing_slash_query_and_fragment_are_dropped() {
+        for input in [
+            "https://github.com/owner/repo.git",
+            "https://github.com/owner/repo.git/",
+            "https://github.com/owner/repo/",
+            "https://github.com/owner/repo?tab=readme-ov-file",
+            "https://github.com/owner/repo#readme",
+            "https://github.com/owner/repo.git/?tab=readme#install",
+            "owner/repo.git",
+            "owner/repo/",
+        ] {
+            let result = UrlParser::parse_git_url(input).unwrap();
+            assert_eq!((result.owner.as_str(), result.name.as_str()), ("owner", "repo"), "{}", input);
+            assert_eq!(result.url, "https://github.com/owner/repo", "{}", input);
+            assert_eq!((result.git_ref, result.subpath), (GitRef::DefaultBranch, String::new()), "{}", input);
+        }
+
+        let result = UrlParser::parse_git_url("https://github.com/owner/repo/tree/main/src?plain=1#L10").unwrap();
+        assert_eq!(result.git_ref.branch_name(), Some("main"));
+        assert_eq!(result.subpath, "src");
+        assert_eq!(result.url, "https://github.com/owner/repo");
+
+        // Only one .git is a suffix; the rest is the name
+        assert_eq!(UrlParser::parse_git_url("https://github.com/owner/repo.git.git").unwrap().name, "repo.git");
+    }
+
+    #[test]
+    fn test_ssh_and_git_remotes_keep_their_scheme() {
+        let cases = [
+            ("git@github.com:myorg/myrepo.git", "github.com", "myorg", "ssh://git@github.com/myorg/myrepo.git"),
+            ("git@github.com:myorg/myrepo", "github.com", "myorg", "ssh://git@github.com/myorg/myrepo.git"),
+            (
+                "git@gitlab.com:group/subgroup/myrepo.git",
+                "gitlab.com",
+                "group/subgroup",
+                "ssh://git@gitlab.com/group/subgroup/myrepo.git",
+            ),
+            ("git@bitbucket.org:team/myrepo.git", "bitbucket.org", "team", "ssh://git@bitbucket.org/team/myrepo.git"),
+            ("ssh://git@github.com/myorg/myrepo", "github.com", "myorg", "ssh://git@github.com/myorg/myrepo.git"),
+            (
+                "ssh://git@gitlab.com:2222/group/subgroup/myrepo.git",
+                "gitlab.com",
+                "group/subgroup",
+                "ssh://git@gitlab.com:2222/group/subgroup/myrepo.git",
+            ),
+            ("ssh://git@bitbucket.org/team/myrepo.git/", "bitbucket.org", "team", "ssh://git@bitbucket.org/team/myrepo.git"),
+            ("git://git.kernel.org/pub/myrepo.git", "git.kernel.org", "pub", "git://git.kernel.org/pub/myrepo.git"),
+            ("git://git.example.com:9419/team/myrepo", "git.example.com", "team", "git://git.example.com:9419/team/myrepo.git"),
+        ];
+        for (input, host, owner, url) in cases {
+            let repo = UrlParser::parse_git_url(input).unwrap();
+            assert_eq!(repo.host, host, "{}", input);
+            assert_eq!(repo.owner, owner, "{}", input);
+            assert_eq!(repo.name, "myrepo", "{}", input);
+            assert_eq!(repo.url, url, "{}", input);
+            assert_eq!(repo.git_ref, GitRef::DefaultBranch, "{}", input);
+        }
+    }
+
     #[test]
     fn test_full_github_url() {
         let result = UrlParser::parse_git_url("https://github.com/owner/repo").unwrap();
@@ -140,6 +586,341 @@ mod tests {
         let result = UrlParser::parse_git_url("https://github.com/owner/repo/tree/main").unwrap();
         assert_eq!(result.owner, "owner");
         assert_eq!(result.name, "repo");
-        assert_eq!(result.branch, Some("main".to_string()));
+        assert_eq!(result.git_ref, GitRef::Branch("main".to_string()));
+    }
+
+    #[test]
+    fn test_github_blob_url_with_subpath() {
+        let result = UrlParser::parse_git_url("https://github.com/owner/repo/blob/main/src/lib.rs").unwrap();
+        assert_eq!(result.git_ref, GitRef::Branch("main".to_string()));
+        assert_eq!(result.subpath, "src/lib.rs");
+        assert_eq!(result.url, "https://github.com/owner/repo");
+    }
+
+    #[test]
+    fn test_blob_urls_target_a_file() {
+        for url in [
+            "https://github.com/owner/repo/blob/main/src/lib.rs",
+            "https://gitlab.com/group/sub/repo/-/blob/main/src/lib.rs",
+        ] {
+            let result = UrlParser::parse_git_url(url).unwrap();
+            assert_eq!(result.target_kind, Some(TargetKind::File), "{}", url);
+            assert_eq!(result.subpath, "src/lib.rs", "{}", url);
+        }
+        for url in [
+            "https://github.com/owner/repo/tree/main/src",
+            "https://gitlab.com/group/repo/-/tree/main/src",
+            "https://bitbucket.org/owner/repo/src/main/src/",
+        ] {
+            assert_eq!(UrlParser::parse_git_url(url).unwrap().target_kind, Some(TargetKind::Directory), "{}", url);
+        }
+        assert_eq!(UrlParser::parse_git_url("https://github.com/owner/repo").unwrap().target_kind, None);
+    }
+
+    #[test]
+    fn test_gitlab_tree_url() {
+        let result = UrlParser::parse_git_url("https://gitlab.com/owner/repo/-/tree/main/src").unwrap();
+        assert_eq!(result.owner, "owner");
+        assert_eq!(result.name, "repo");
+        assert_eq!(result.host, "gitlab.com");
+        assert_eq!(result.git_ref, GitRef::Branch("main".to_string()));
+        assert_eq!(result.subpath, "src");
+        assert_eq!(result.url, "https://gitlab.com/owner/repo");
+    }
+
+    #[test]
+    fn test_gitlab_blob_url() {
+        let result = UrlParser::parse_git_url("https://gitlab.com/owner/repo/-/blob/dev/src/main.rs").unwrap();
+        assert_eq!(result.git_ref, GitRef::Branch("dev".to_string()));
+        assert_eq!(result.subpath, "src/main.rs");
+    }
+
+    #[test]
+    fn test_azure_devops_repository() {
+        let result = UrlParser::parse_git_url("https://dev.azure.com/org/project/_git/repo").unwrap();
+        assert_eq!(result.host, "dev.azure.com");
+        assert_eq!(result.owner, "org/project/_git");
+        assert_eq!(result.name, "repo");
+        assert_eq!(result.url, "https://dev.azure.com/org/project/_git/repo");
+    }
+
+    #[test]
+    fn test_gitlab_nested_groups() {
+        let result = UrlParser::parse_git_url("https://gitlab.com/group/subgroup/project").unwrap();
+        assert_eq!(result.owner, "group/subgroup");
+        assert_eq!(result.name, "project");
+        assert_eq!(result.git_ref, GitRef::DefaultBranch);
+        assert_eq!(result.url, "https://gitlab.com/group/subgroup/project");
+
+        let result = UrlParser::parse_git_url("https://gitlab.com/group/sub/team/project.git").unwrap();
+        assert_eq!(result.owner, "group/sub/team");
+        assert_eq!(result.name, "project");
+        assert_eq!(result.url, "https://gitlab.com/group/sub/team/project");
+
+        let overrides = HashMap::from([("gitlab.example.com".to_string(), Platform::GitLab)]);
+        let config = AppConfig { host_overrides: overrides, ..AppConfig::default() };
+        let url = "https://gitlab.example.com/group/sub/team/project/-/blob/main/src/lib.rs";
+        let result = UrlParser::parse_git_url_with(url, &config).unwrap();
+        assert_eq!(result.host, "gitlab.example.com");
+        assert_eq!(result.owner, "group/sub/team");
+        assert_eq!(result.name, "project");
+        assert_eq!(result.git_ref.branch_name(), Some("main"));
+        assert_eq!(result.subpath, "src/lib.rs");
+        assert_eq!(result.url, "https://gitlab.example.com/group/sub/team/project");
+
+        let result = UrlParser::parse_git_url("https://gitlab.com/a/b/c/project/-/tree/v1.0/").unwrap();
+        assert_eq!(result.owner, "a/b/c");
+        assert_eq!(result.name, "project");
+        assert_eq!(result.git_ref.branch_name(), Some("v1.0"));
+        assert_eq!(result.subpath, "");
+        assert_eq!(result.url, "https://gitlab.com/a/b/c/project");
+
+        // Other project pages don't carry a ref
+        let result = UrlParser::parse_git_url("https://gitlab.com/group/subgroup/project/-/merge_requests/7").unwrap();
+        assert_eq!(result.owner, "group/subgroup");
+        assert_eq!(result.git_ref, GitRef::DefaultBranch);
+
+        assert!(UrlParser::parse_git_url("https://gitlab.com/project/-/tree/main").is_err());
+    }
+
+    #[test]
+    fn test_pull_request_urls() {
+        for url in [
+            "https://github.com/owner/repo/pull/1234",
+            "https://github.com/owner/repo/pull/1234/files",
+            "https://github.com/owner/repo/pull/1234/commits/",
+        ] {
+            let result = UrlParser::parse_git_url(url).unwrap();
+            assert_eq!((result.git_ref, result.subpath.as_str()), (GitRef::PullRequest(1234), ""), "{}", url);
+            assert_eq!(result.url, "https://github.com/owner/repo", "{}", url);
+        }
+        assert_eq!(UrlParser::parse_git_url("https://github.com/owner/repo/pull/new").unwrap().git_ref, GitRef::DefaultBranch);
+        assert_eq!(UrlParser::parse_git_url("https://github.com/owner/repo").unwrap().git_ref, GitRef::DefaultBranch);
+    }
+
+    #[test]
+    fn test_release_and_archive_urls() {
+        for (url, tag) in [
+            ("https://github.com/owner/repo/releases/tag/v1.2.3", "v1.2.3"),
+            ("https://github.com/owner/repo/releases/tag/release/2024-01", "release/2024-01"),
+            ("https://github.com/owner/repo/archive/refs/tags/v1.2.3.tar.gz", "v1.2.3"),
+            ("https://github.com/owner/repo/archive/refs/tags/v1.2.3.zip", "v1.2.3"),
+            ("https://github.com/owner/repo/archive/refs/heads/main.tar.gz", "main"),
+            ("https://github.com/owner/repo/archive/v1.2.3.tar.gz", "v1.2.3"),
+            ("https://codeberg.org/owner/repo/archive/v1.2.3.tar.gz", "v1.2.3"),
+            ("https://gitlab.com/group/project/-/releases/v1.2.3", "v1.2.3"),
+            ("https://gitlab.com/group/project/-/archive/v1.2.3/project-v1.2.3.tar.gz", "v1.2.3"),
+        ] {
+            let result = UrlParser::parse_git_url(url).unwrap();
+            assert_eq!(result.git_ref.branch_name(), Some(tag), "{}", url);
+            assert!(!matches!(result.git_ref, GitRef::Commit(_)), "{}", url);
+            assert_eq!(result.subpath, "", "{}", url);
+            assert_eq!(result.name, if url.contains("gitlab") { "project" } else { "repo" }, "{}", url);
+        }
+
+        let result = UrlParser::parse_git_url("https://github.com/owner/repo/archive/3f2a9bc1.zip").unwrap();
+        assert_eq!(result.git_ref, GitRef::Commit("3f2a9bc1".to_string()));
+        // Release pages and refs/ paths say which kind of ref they name
+        let kind = |url: &str| UrlParser::parse_git_url(url).unwrap().git_ref;
+        assert_eq!(kind("https://github.com/owner/repo/releases/tag/v1.2.3"), GitRef::Tag("v1.2.3".to_string()));
+        assert_eq!(kind("https://github.com/owner/repo/archive/refs/heads/main.zip"), GitRef::Branch("main".to_string()));
+
+        // The releases list names no ref
+        assert_eq!(UrlParser::parse_git_url("https://github.com/owner/repo/releases").unwrap().git_ref, GitRef::DefaultBranch);
+
+        assert!(UrlParser::is_archive_link("https://github.com/owner/repo/archive/refs/tags/v1.2.3.tar.gz"));
+        assert!(UrlParser::is_archive_link("https://github.com/owner/repo/archive/main.zip"));
+        assert!(!UrlParser::is_archive_link("https://github.com/owner/repo/releases/tag/v1.2.3"));
+        assert!(!UrlParser::is_archive_link("owner/repo"));
+    }
+
+    #[test]
+    fn test_commit_urls() {
+        let sha = "3f2a9bc1d4e5f60718293a4b5c6d7e8f90a1b2c3";
+        let result = UrlParser::parse_git_url(&format!("https://github.com/owner/repo/tree/{}/src", sha)).unwrap();
+        assert_eq!(result.git_ref, GitRef::Commit(sha.to_string()));
+        assert_eq!(result.subpath, "src");
+
+        let result = UrlParser::parse_git_url("https://github.com/owner/repo/commit/3f2a9bc").unwrap();
+        assert_eq!(result.git_ref, GitRef::Commit("3f2a9bc".to_string()));
+        assert_eq!(result.url, "https://github.com/owner/repo");
+
+        let result = UrlParser::parse_git_url(&format!("https://gitlab.com/group/sub/project/-/commit/{}", sha)).unwrap();
+        assert_eq!(result.owner, "group/sub");
+        assert_eq!(result.git_ref, GitRef::Commit(sha.to_string()));
+
+        let result = UrlParser::parse_git_url("https://bitbucket.org/owner/repo/commits/3f2a9bc1").unwrap();
+        assert_eq!(result.git_ref, GitRef::Commit("3f2a9bc1".to_string()));
+
+        // Too short, too long or not hex: a branch
+        for branch in ["abc123", "feature", "deadbeef-feature", &format!("{}0", sha)] {
+            let result = UrlParser::parse_git_url(&format!("https://github.com/owner/repo/tree/{}", branch)).unwrap();
+            assert_eq!(result.git_ref.branch_name(), Some(branch));
+            assert!(!matches!(result.git_ref, GitRef::Commit(_)));
+        }
+    }
+
+    #[test]
+    fn test_gitea_and_sourcehut_urls() {
+        let result = UrlParser::parse_git_url("https://codeberg.org/forgejo/forgejo/src/branch/main/docs/guide").unwrap();
+        assert_eq!(result.owner, "forgejo");
+        assert_eq!(result.name, "forgejo");
+        assert_eq!(result.git_ref.branch_name(), Some("main"));
+        assert_eq!(result.subpath, "docs/guide");
+        assert_eq!(result.url, "https://codeberg.org/forgejo/forgejo");
+
+        let overrides = HashMap::from([("gitea.example.com".to_string(), Platform::Gitea)]);
+        let config = AppConfig { host_overrides: overrides, ..AppConfig::default() };
+        let result = UrlParser::parse_git_url_with("https://gitea.example.com/team/app/src/tag/v1.2", &config).unwrap();
+        assert_eq!(result.git_ref.branch_name(), Some("v1.2"));
+        assert_eq!(result.subpath, "");
+        let result = UrlParser::parse_git_url("https://codeberg.org/team/app/src/commit/3f2a9bc1/src").unwrap();
+        assert_eq!(result.git_ref, GitRef::Commit("3f2a9bc1".to_string()));
+        assert_eq!(result.subpath, "src");
+
+        let result = UrlParser::parse_git_url("https://git.sr.ht/~sircmpwn/scdoc/tree/release/1.0/item/src/main.c").unwrap();
+        assert_eq!(result.owner, "~sircmpwn");
+        assert_eq!(result.name, "scdoc");
+        assert_eq!(result.git_ref.branch_name(), Some("release/1.0"));
+        assert_eq!(result.subpath, "src/main.c");
+        assert_eq!(result.url, "https://git.sr.ht/~sircmpwn/scdoc");
+
+        let result = UrlParser::parse_git_url("https://git.sr.ht/~sircmpwn/scdoc/tree/master").unwrap();
+        assert_eq!(result.git_ref.branch_name(), Some("master"));
+        assert_eq!(result.subpath, "");
+        let result = UrlParser::parse_git_url("git@git.sr.ht:~sircmpwn/scdoc").unwrap();
+        assert_eq!(result.owner, "~sircmpwn");
+        assert_eq!(result.url, "ssh://git@git.sr.ht/~sircmpwn/scdoc.git");
+    }
+
+    #[test]
+    fn test_self_hosted_instances() {
+        // Unknown hosts read as owner/repo
+        let result = UrlParser::parse_git_url("https://git.mycompany.com/team/repo/tree/main/src").unwrap();
+        assert_eq!(result.host, "git.mycompany.com");
+        assert_eq!(result.owner, "team");
+        assert_eq!(result.name, "repo");
+        assert_eq!(result.git_ref.branch_name(), Some("main"));
+        assert_eq!(result.subpath, "src");
+        assert_eq!(result.url, "https://git.mycompany.com/team/repo");
+
+        let overrides = HashMap::from([("code.mycompany.com".to_string(), Platform::GitLab)]);
+        let url = "https://Code.MyCompany.com/group/sub/repo/-/tree/main/src";
+        let result = UrlParser::parse_git_url_with(url, &AppConfig { host_overrides: overrides, ..AppConfig::default() }).unwrap();
+        assert_eq!(result.owner, "group/sub");
+        assert_eq!(result.name, "repo");
+        assert_eq!(result.git_ref.branch_name(), Some("main"));
+        assert_eq!(result.subpath, "src");
+        assert_eq!(UrlParser::parse_git_url(url).unwrap().owner, "group");
+    }
+
+    #[test]
+    fn test_ports_are_kept_in_the_clone_url() {
+        let overrides = HashMap::from([("git.internal".to_string(), Platform::GitLab)]);
+        let result =
+            UrlParser::parse_git_url_with("https://git.internal:8443/group/sub/repo/-/tree/main/src", &AppConfig { host_overrides: overrides, ..AppConfig::default() }).unwrap();
+        assert_eq!(result.host, "git.internal");
+        assert_eq!(result.owner, "group/sub");
+        assert_eq!(result.git_ref.branch_name(), Some("main"));
+        assert_eq!(result.subpath, "src");
+        assert_eq!(result.url, "https://git.internal:8443/group/sub/repo");
+
+        let result = UrlParser::parse_git_url("https://github.com:443/owner/repo/tree/main").unwrap();
+        assert_eq!(result.url, "https://github.com/owner/repo");
+    }
+
+    #[test]
+    fn test_branches_with_slashes() {
+        // Encoded slashes are unambiguous
+        let result = UrlParser::parse_git_url("https://gitlab.com/group/subgroup/project/-/tree/feature%2Fnested%2Fx/docs").unwrap();
+        assert_eq!(result.owner, "group/subgroup");
+        assert_eq!(result.git_ref.branch_name(), Some("feature/nested/x"));
+        assert_eq!(result.subpath, "docs");
+
+        // Raw ones take the requested branch into account
+        let mut result = UrlParser::parse_git_url("https://github.com/owner/repo/tree/feature/x/src/lib").unwrap();
+        assert_eq!((result.git_ref.branch_name(), result.subpath.as_str()), (Some("feature"), "x/src/lib"));
+        UrlParser::apply_branch(&mut result, "feature/x");
+        assert_eq!((result.git_ref.branch_name(), result.subpath.as_str()), (Some("feature/x"), "src/lib"));
+
+        let mut result = UrlParser::parse_git_url("https://bitbucket.org/owner/repo/src/release/1.0").unwrap();
+        UrlParser::apply_branch(&mut result, "release/1.0");
+        assert_eq!((result.git_ref.branch_name(), result.subpath.as_str()), (Some("release/1.0"), ""));
+
+        // A branch the path doesn't start with is left to the conflict policy
+        let mut result = UrlParser::parse_git_url("https://github.com/owner/repo/tree/main/src").unwrap();
+        UrlParser::apply_branch(&mut result, "mai");
+        assert_eq!((result.git_ref.branch_name(), result.subpath.as_str()), (Some("main"), "src"));
+    }
+
+    #[test]
+    fn test_bitbucket_src_url() {
+        let result = UrlParser::parse_git_url("https://bitbucket.org/owner/repo/src/master/lib").unwrap();
+        assert_eq!(result.owner, "owner");
+        assert_eq!(result.name, "repo");
+        assert_eq!(result.host, "bitbucket.org");
+        assert_eq!(result.git_ref, GitRef::Branch("master".to_string()));
+        assert_eq!(result.subpath, "lib");
+        assert_eq!(result.url, "https://bitbucket.org/owner/repo");
+        assert_eq!(result.target_kind, None);
+
+        // A trailing slash marks a directory; otherwise the checkout decides
+        let result = UrlParser::parse_git_url("https://bitbucket.org/owner/repo/src/main/path/to/dir/").unwrap();
+        assert_eq!((result.git_ref.branch_name(), result.subpath.as_str()), (Some("main"), "path/to/dir"));
+        assert_eq!(result.target_kind, Some(TargetKind::Directory));
+
+        let result = UrlParser::parse_git_url("https://bitbucket.org/owner/repo/src/v1.2/src/lib.rs?at=v1.2").unwrap();
+        assert_eq!((result.git_ref.branch_name(), result.subpath.as_str()), (Some("v1.2"), "src/lib.rs"));
+        assert_eq!(result.target_kind, None);
+
+        // The ref page itself, with and without the trailing slash
+        for url in ["https://bitbucket.org/owner/repo/src/main", "https://bitbucket.org/owner/repo/src/main/"] {
+            let result = UrlParser::parse_git_url(url).unwrap();
+            assert_eq!((result.git_ref.branch_name(), result.subpath.as_str()), (Some("main"), ""), "{}", url);
+        }
+
+        // A self-hosted Bitbucket parses the same way once configured
+        let overrides = HashMap::from([("git.example.com".to_string(), Platform::Bitbucket)]);
+        let result = UrlParser::parse_git_url_with("https://git.example.com/team/repo/src/develop/docs/", &AppConfig { host_overrides: overrides, ..AppConfig::default() }).unwrap();
+        assert_eq!((result.owner.as_str(), result.name.as_str()), ("team", "repo"));
+        assert_eq!((result.git_ref.branch_name(), result.subpath.as_str()), (Some("develop"), "docs"));
+        assert_eq!(result.url, "https://git.example.com/team/repo");
+    }
+
+    #[test]
+    fn test_local_directories() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = temp_dir.path().join("project");
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(temp_dir.path().join("file.txt"), "").unwrap();
+
+        let canonical = std::fs::canonicalize(&dir).unwrap();
+        assert_eq!(UrlParser::local_directory(&dir.display().to_string()), Some(canonical));
+        assert_eq!(UrlParser::local_directory(&temp_dir.path().join("file.txt").display().to_string()), None);
+        assert_eq!(UrlParser::local_directory(&temp_dir.path().join("missing").display().to_string()), None);
+        assert_eq!(UrlParser::local_directory("https://github.com/owner/repo"), None);
+        // Tests run from the package root, where src/utils reads as owner/repo
+        assert_eq!(UrlParser::local_directory("src/utils"), None);
+        assert_eq!(UrlParser::local_directory("./src/utils"), Some(std::fs::canonicalize("src/utils").unwrap()));
+
+        let home = Path::new("/home/me");
+        assert_eq!(expand_home("~", Some(home)), Some(PathBuf::from("/home/me")));
+        assert_eq!(expand_home("~/src/app", Some(home)), Some(PathBuf::from("/home/me/src/app")));
+        assert_eq!(expand_home("~other/app", Some(home)), Some(PathBuf::from("~other/app")));
+        assert_eq!(expand_home("~/app", None), None);
+        assert_eq!(expand_home("./app", Some(home)), Some(PathBuf::from("./app")));
+    }
+
+    #[test]
+    fn test_file_url() {
+        let result = UrlParser::parse_git_url("file:///srv/git/project.git").unwrap();
+        assert_eq!(result.owner, "git");
+        assert_eq!(result.name, "project");
+        assert_eq!(result.host, "localhost");
+        assert_eq!(result.url, "file:///srv/git/project.git");
+
+        let result = UrlParser::parse_git_url("file:///srv/git/project.git?ref=main#top").unwrap();
+        assert_eq!(result.name, "project");
+        assert_eq!(result.url, "file:///srv/git/project.git");
     }
 }
\ No newline at end of file
@@ -0,0 +1,105 @@
+//! Snapshots of every digest format for a fixed response.
+
+mod support;
+
+use gitingest::{formats, DownloadFormat, FileEntry, IngestResponse, IngestStatus, LanguageStat, LicenseInfo};
+use std::io::Write;
+use std::path::Path;
+use support::assert_golden;
+use uuid::Uuid;
+
+fn fixture_response() -> IngestResponse {
+    IngestResponse {
+        id: Uuid::nil(),
+        repo_url: "https://github.com/owner/repo".to_string(),
+        short_repo_url: "owner/repo".to_string(),
+        summary: "Repository: owner/repo\nFiles processed: 2\nTotal size: 40 B\nHost: github.com".to_string(),
+        digest_url: None,
+        tree: "└── repo/\n    ├── src/\n    │   └── main.rs\n    └── README.md\n".to_string(),
+        content: "src/main.rs:\n================================================\nfn main() {}\n\n\nREADME.md:\n================================================\n# Repo\n\n\n".to_string(),
+        status: IngestStatus::Completed,
+        truncated: false,
+        files: Some(vec![
+            FileEntry {
+                relative_path: "src/main.rs".to_string(),
+                size: 12,
+                language: Some("rust".to_string()),
+                content: Some("fn main() {}\n".to_string()),
+                truncated: false,
+                skip_reason: None,
+                tokens: None,
+            },
+            FileEntry {
+                relative_path: "README.md".to_string(),
+                size: 7,
+                language: Some("markdown".to_string()),
+                content: Some("# Repo\n".to_string()),
+                truncated: false,
+                skip_reason: None,
+                tokens: None,
+            },
+        ]),
+        file_tree: None,
+        language_breakdown: vec![
+            LanguageStat {
+                language: "Rust".to_string(),
+                files: 1,
+                total_size_bytes: 12,
+                tokens: 3,
+                token_share: 0.75,
+                size_share: 12.0 / 19.0,
+            },
+            LanguageStat {
+                language: "Markdown".to_string(),
+                files: 1,
+                total_size_bytes: 7,
+                tokens: 1,
+                token_share: 0.25,
+                size_share: 7.0 / 19.0,
+            },
+        ],
+        license: Some(LicenseInfo {
+            spdx_id: "MIT".to_string(),
+            path: "LICENSE".to_string(),
+        }),
+    }
+}
+
+fn assert_rendered(format: DownloadFormat, golden: &str) {
+    let rendered = fixture_response().render(&format).unwrap();
+    assert_golden(golden, &rendered, Path::new("/nonexistent"));
+}
+
+#[test]
+fn test_text_rendering() {
+    assert_rendered(DownloadFormat::Text, "render_text.txt");
+}
+
+#[test]
+fn test_markdown_rendering() {
+    assert_rendered(DownloadFormat::Markdown, "render_markdown.md");
+}
+
+#[test]
+fn test_json_rendering() {
+    assert_rendered(DownloadFormat::Json, "render_json.json");
+}
+
+#[test]
+fn test_xml_rendering() {
+    assert_rendered(DownloadFormat::Xml, "render_xml.xml");
+}
+
+#[test]
+fn test_json_string_writer_escapes_split_writes() {
+    let text = "tab\there \"quoted\" ünïcödé\n";
+    let mut escaped = Vec::new();
+    let mut writer = formats::JsonStringWriter::new(&mut escaped);
+    // One byte at a time splits every multi-byte character
+    for byte in text.as_bytes() {
+        writer.write_all(std::slice::from_ref(byte)).unwrap();
+    }
+
+    let expected = serde_json::to_string(text).unwrap();
+    assert_eq!(String::from_utf8(escaped).unwrap(), expected[1..expected.len() - 1]);
+}
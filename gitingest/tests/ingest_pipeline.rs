@@ -0,0 +1,1359 @@
+//! End-to-end ingestion against local fixture repositories.
+
+// Every test here clones a fixture repository
+#![cfg(feature = "git")]
+
+mod support;
+
+use gitingest::{AppConfig, CloneMode, DiffStats, DownloadFormat, GitingestError, IngestEvent, IngestFailure, IngestPhase, IngestRequest, IngestService, MissingFilePolicy, SortOrder};
+use tokio_util::sync::CancellationToken;
+use support::repo_builder::{FixtureRepo, RepoBuilder, run_git};
+#[cfg(not(feature = "tiktoken"))]
+use support::{assert_golden, render_response};
+use uuid::Uuid;
+
+fn sample_repo() -> FixtureRepo {
+    RepoBuilder::new("fixtures", "sample")
+        .file("README.md", "# Sample\n\nA fixture repository.\n")
+        .file("src/main.rs", "fn main() {\n    println!(\"hello\");\n}\n")
+        .file("src/lib/util.rs", "pub fn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n")
+        .file("docs/héllo wörld.md", "Ünïcödé names survive.\n")
+        .file(".gitignore", "*.secret\n")
+        .file("notes.secret", "committed but gitignored\n")
+        .binary_file("assets/logo.png", &[0x89, b'P', b'N', b'G', 0x00, 0xff])
+        .binary_file("data.bin", &[0x00, 0xff, 0xfe, 0x80])
+        .submodule_stub("vendor/lib", "https://example.invalid/lib.git")
+        .commit("initial")
+        .tag("v1.0")
+        .branch("feature")
+        .file("src/feature.rs", "pub const FEATURE: bool = true;\n")
+        .commit("add feature")
+        .checkout("main")
+        .file("CHANGELOG.md", "## Unreleased\n")
+        .commit("changelog")
+        .build()
+}
+
+/// The fixtures are cloned from `file://` URLs, which need the opt-in.
+fn local_config() -> AppConfig {
+    AppConfig { allow_local_inputs: true, ..AppConfig::default() }
+}
+
+fn request(repo: &FixtureRepo) -> IngestRequest {
+    IngestRequest {
+        input_text: repo.url(),
+        ..Default::default()
+    }
+}
+
+async fn ingest(request: IngestRequest) -> gitingest::IngestResponse {
+    ingest_with_config(request, &local_config()).await
+}
+
+async fn ingest_with_config(request: IngestRequest, config: &AppConfig) -> gitingest::IngestResponse {
+    IngestService::process_repository(request, config, Uuid::new_v4())
+        .await
+        .unwrap()
+}
+
+// The goldens hold the heuristic counter's token counts, which the
+// `tiktoken` feature replaces
+#[cfg(not(feature = "tiktoken"))]
+#[tokio::test]
+async fn test_default_branch_matches_golden() {
+    let repo = sample_repo();
+    let response = ingest(request(&repo)).await;
+
+    assert_golden("sample_main.txt", &render_response(&response), repo.root());
+}
+
+#[tokio::test]
+async fn test_progress_events_follow_the_phases() {
+    let repo = sample_repo();
+    let config = AppConfig { batch_size: 2, ..local_config() };
+    let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+    let collect = async {
+        let mut events = Vec::new();
+        while let Some(event) = rx.recv().await {
+            events.push(event);
+        }
+        events
+    };
+    let (response, events) =
+        tokio::join!(IngestService::process_repository_with_progress(request(&repo), &config, Uuid::new_v4(), tx), collect);
+    response.unwrap();
+
+    let phases: Vec<IngestPhase> = events
+        .iter()
+        .filter_map(|event| match event {
+            IngestEvent::Phase { phase } => Some(*phase),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(phases, [IngestPhase::Parse, IngestPhase::Clone, IngestPhase::Scan, IngestPhase::Content]);
+
+    let Some(IngestEvent::FilesDiscovered { total }) =
+        events.iter().find(|event| matches!(event, IngestEvent::FilesDiscovered { .. })).cloned()
+    else {
+        panic!("no FilesDiscovered event in {:?}", events);
+    };
+    let scanned: Vec<usize> = events
+        .iter()
+        .filter_map(|event| match event {
+            IngestEvent::FilesScanned { scanned, total: of } if *of == total => Some(*scanned),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(scanned.len(), total.div_ceil(2));
+    assert!(scanned.is_sorted());
+    assert_eq!(scanned.last(), Some(&total));
+}
+
+#[test]
+fn test_ingest_blocking_outside_and_inside_a_runtime() {
+    let repo = sample_repo();
+    let expected = IngestService::ingest_blocking(request(&repo), &local_config()).unwrap();
+    assert!(expected.content.contains("fn main()"));
+
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    let nested = runtime.block_on(async { IngestService::ingest_blocking(request(&repo), &local_config()) }).unwrap();
+    assert_eq!(nested.content, expected.content);
+
+    let err = IngestService::ingest_blocking(
+        IngestRequest { branch: Some("no-such-branch".to_string()), ..request(&repo) },
+        &local_config(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, GitingestError::BranchNotFound(_)));
+}
+
+#[cfg(feature = "archive")]
+#[tokio::test]
+async fn test_archive_input_is_extracted_and_ingested() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let source = temp_dir.path().join("snapshot-1.0");
+    std::fs::create_dir_all(source.join("src/nested")).unwrap();
+    std::fs::write(source.join("README.md"), "# Snapshot\n").unwrap();
+    std::fs::write(source.join("src/nested/deep.rs"), "pub fn deep() {}\n").unwrap();
+    let archive = temp_dir.path().join("snapshot-1.0.tar.gz");
+    let status = std::process::Command::new("tar")
+        .arg("-czf")
+        .arg(&archive)
+        .arg("-C")
+        .arg(temp_dir.path())
+        .arg("snapshot-1.0")
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let response = ingest(IngestRequest { input_text: archive.display().to_string(), ..Default::default() }).await;
+
+    assert!(response.summary.starts_with("Repository: snapshot-1.0.tar.gz\n"));
+    assert_eq!(response.short_repo_url, "snapshot-1.0");
+    assert!(response.content.contains("src/nested/deep.rs:\n"));
+    assert!(!response.content.contains("snapshot-1.0/"));
+
+    let refused = IngestService::ingest(
+        IngestRequest { input_text: archive.display().to_string(), ..Default::default() },
+        &AppConfig::default(),
+    )
+    .await
+    .unwrap_err();
+    assert!(refused.to_string().contains("ALLOW_LOCAL_INPUTS"), "{}", refused);
+
+    let err = IngestService::ingest(
+        IngestRequest { input_text: archive.display().to_string(), branch: Some("main".to_string()), ..Default::default() },
+        &local_config(),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, GitingestError::ValidationError(_)));
+}
+
+#[tokio::test]
+async fn test_local_directory_is_scanned_in_place() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let source = temp_dir.path().join("work").join("checkout");
+    std::fs::create_dir_all(source.join("src")).unwrap();
+    std::fs::create_dir_all(source.join(".git")).unwrap();
+    std::fs::write(source.join(".git/config"), "[core]\n").unwrap();
+    std::fs::write(source.join(".gitignore"), "*.log\n").unwrap();
+    std::fs::write(source.join("debug.log"), "noise\n").unwrap();
+    std::fs::write(source.join("src/lib.rs"), "pub fn local() {}\n").unwrap();
+
+    let response = ingest(IngestRequest { input_text: source.display().to_string(), ..Default::default() }).await;
+
+    let canonical = std::fs::canonicalize(&source).unwrap();
+    assert!(response.summary.starts_with("Repository: work/checkout\n"), "{}", response.summary);
+    assert!(response.summary.contains(&format!("\nLocal path: {}", canonical.display())));
+    assert!(!response.summary.contains("Host:"));
+    assert!(response.content.contains("pub fn local() {}"));
+    assert!(!response.content.contains("debug.log"));
+    assert!(!response.content.contains("[core]"));
+    // Nothing is cleaned up afterwards
+    assert!(source.join("src/lib.rs").exists());
+
+    let err = IngestService::ingest(
+        IngestRequest { input_text: source.display().to_string(), branch: Some("main".to_string()), ..Default::default() },
+        &local_config(),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, GitingestError::ValidationError(_)));
+}
+
+#[tokio::test]
+async fn test_request_depth_overrides_config() {
+    let repo = sample_repo();
+    let config = AppConfig { max_directory_depth: 1, ..local_config() };
+
+    let shallow = ingest_with_config(request(&repo), &config).await;
+    assert!(shallow.content.contains("README.md:\n"));
+    assert!(!shallow.content.contains("src/main.rs"));
+
+    let deeper = ingest_with_config(IngestRequest { max_directory_depth: Some(2), ..request(&repo) }, &config).await;
+    assert!(deeper.content.contains("src/main.rs:\n"));
+    assert!(!deeper.content.contains("src/lib/util.rs"));
+}
+
+#[tokio::test]
+async fn test_request_max_files_caps_the_scan() {
+    let repo = sample_repo();
+    let config = local_config();
+    let (_, all) = IngestService::scan_only(request(&repo), &config, false).await.unwrap();
+
+    // Directories walked count against the cap too
+    let capped = IngestRequest { max_files: Some(3), ..request(&repo) };
+    let (_, stats) = IngestService::scan_only(capped.clone(), &config, false).await.unwrap();
+    assert!(stats.files_analyzed > 0 && stats.files_analyzed <= 3, "{}", stats.files_analyzed);
+    assert!(stats.files_analyzed < all.files_analyzed);
+    let response = ingest_with_config(capped, &config).await;
+    let files_processed = format!("Files processed: {}\n", stats.files_analyzed);
+    assert!(response.summary.contains(&files_processed), "{}", response.summary);
+
+    // The request's cap overrides the configured one
+    let config = AppConfig { max_files: 1, ..local_config() };
+    let (_, raised) = IngestService::scan_only(IngestRequest { max_files: None, ..request(&repo) }, &config, false)
+        .await
+        .unwrap();
+    assert_eq!(raised.files_analyzed, 0);
+    let (_, raised) = IngestService::scan_only(IngestRequest { max_files: Some(usize::MAX), ..request(&repo) }, &config, false)
+        .await
+        .unwrap();
+    assert_eq!(raised.files_analyzed, all.files_analyzed);
+}
+
+#[tokio::test]
+async fn test_request_content_size_overrides_config() {
+    let schema: String = (0..80_000).map(|i| format!("  \"field_{:05}\": \"string\",\n", i)).collect();
+    assert!(schema.len() > 2 * 1024 * 1024);
+    let repo = RepoBuilder::new("owner", "schemas")
+        .file("schema.json", &schema)
+        .commit("initial")
+        .build();
+
+    let truncated = ingest(request(&repo)).await;
+    assert!(!truncated.content.contains("field_79999"));
+    assert!(truncated.content.contains(&format!(
+        "[Large file content truncated at max_content_size of 100000 bytes - showing 100000 of {} bytes]",
+        schema.len()
+    )));
+
+    let request = IngestRequest { max_content_size: Some(4 * 1024 * 1024), ..request(&repo) };
+    let inlined = ingest(request).await;
+    assert!(inlined.content.contains(&schema));
+    assert!(!inlined.content.contains("truncated"));
+}
+
+#[tokio::test]
+async fn test_max_output_bytes_leaves_out_remaining_files() {
+    let mut builder = RepoBuilder::new("owner", "many");
+    for i in 0..200 {
+        builder = builder.file(&format!("src/file_{:03}.rs", i), &format!("pub const VALUE_{}: u32 = {};\n", i, i));
+    }
+    let repo = builder.commit("initial").build();
+    let capped = || IngestRequest { max_output_bytes: Some(2_000), ..request(&repo) };
+
+    let response = ingest(capped()).await;
+    assert!(response.truncated);
+    let (body, footer) = response.content.split_once("[digest truncated at 2000 bytes; ").unwrap();
+    assert!(body.len() <= 2_000, "{}", body.len());
+    let omitted: usize = footer.split_once(' ').unwrap().0.parse().unwrap();
+    assert_eq!(omitted, 200 - body.matches("pub const").count());
+    assert!(footer.ends_with(" files omitted]\n"), "{}", footer);
+    // The tree and stats still cover every file
+    assert!(response.tree.contains("file_199.rs"));
+    assert!(response.summary.contains("Files processed: 200"));
+
+    // A streamed digest stops the same way without being marked incomplete
+    let output = tempfile::TempDir::new().unwrap();
+    let path = output.path().join("digest.json");
+    let json_request = IngestRequest { download_format: Some(DownloadFormat::Json), ..capped() };
+    let summary = IngestService::ingest_to_file(json_request, &local_config(), &path).await.unwrap();
+    assert!(summary.truncated);
+    let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(json["truncated"], true);
+    assert_eq!(json["status"], "completed");
+    assert!(json.get("incomplete").is_none());
+
+    assert!(!ingest(request(&repo)).await.truncated);
+}
+
+// The JSON digests are a wire format: a change to these snapshots must come
+// with a SCHEMA_VERSION bump.
+#[cfg(not(feature = "tiktoken"))]
+#[tokio::test]
+async fn test_json_wire_format_matches_golden() {
+    let repo = sample_repo();
+    for (format, golden) in [
+        (DownloadFormat::Json, "sample_main.json"),
+        (DownloadFormat::JsonStructured, "sample_main_structured.json"),
+    ] {
+        let response = ingest(IngestRequest {
+            download_format: Some(format.clone()),
+            ..request(&repo)
+        })
+        .await;
+
+        let rendered = response.render(&format).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&rendered).unwrap();
+        assert_eq!(json["schema_version"], gitingest::SCHEMA_VERSION);
+        assert_golden(golden, &rendered, repo.root());
+    }
+}
+
+#[cfg(not(feature = "tiktoken"))]
+#[tokio::test]
+async fn test_feature_branch_matches_golden() {
+    let repo = sample_repo();
+    let response = ingest(IngestRequest {
+        branch: Some("feature".to_string()),
+        ..request(&repo)
+    })
+    .await;
+
+    assert_golden("sample_feature.txt", &render_response(&response), repo.root());
+}
+
+#[tokio::test]
+async fn test_summary_records_the_commit_checked_out() {
+    let repo = sample_repo();
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(&repo.path)
+        .args(["rev-parse", "feature"])
+        .output()
+        .unwrap();
+    let feature = String::from_utf8(output.stdout).unwrap().trim().to_string();
+
+    let response = ingest(IngestRequest {
+        branch: Some("feature".to_string()),
+        ..request(&repo)
+    })
+    .await;
+    assert!(response.summary.contains(&format!("\nCommit: {}", feature)), "{}", response.summary);
+}
+
+#[tokio::test]
+async fn test_tag_checkout_excludes_later_commits() {
+    let repo = sample_repo();
+    let response = ingest(IngestRequest {
+        branch: Some("v1.0".to_string()),
+        ..request(&repo)
+    })
+    .await;
+
+    assert!(response.content.contains("README.md"));
+    assert!(!response.content.contains("CHANGELOG.md"));
+    assert!(!response.content.contains("src/feature.rs"));
+    assert!(response.summary.contains("\nTag: v1.0\n"), "{}", response.summary);
+}
+
+#[tokio::test]
+async fn test_gitignored_and_binary_files_are_filtered() {
+    let repo = sample_repo();
+    let response = ingest(request(&repo)).await;
+
+    assert!(!response.tree.contains("notes.secret"));
+    assert!(!response.tree.contains("logo.png"));
+    assert!(response.tree.contains("héllo wörld.md"));
+}
+
+#[tokio::test]
+async fn test_uncloned_submodule_is_listed_with_its_url() {
+    let repo = sample_repo();
+    let response = ingest(request(&repo)).await;
+
+    assert!(response.tree.contains("vendor/"), "{}", response.tree);
+    assert!(response.tree.contains("lib/ [submodule: https://example.invalid/lib.git]"), "{}", response.tree);
+}
+
+#[tokio::test]
+async fn test_git_directory_is_excluded_by_default() {
+    let repo = sample_repo();
+    let response = ingest(request(&repo)).await;
+
+    assert!(!response.tree.contains("HEAD"));
+    assert!(!response.content.contains(".git/"));
+}
+
+#[tokio::test]
+async fn test_anchored_include_pattern_is_relative_to_repo_root() {
+    let repo = sample_repo();
+    let response = ingest(IngestRequest {
+        include_patterns: Some(vec!["src/**".to_string()]),
+        ..request(&repo)
+    })
+    .await;
+
+    assert!(response.content.contains("src/main.rs"));
+    assert!(response.content.contains("src/lib/util.rs"));
+    assert!(!response.content.contains("README.md"));
+}
+
+#[tokio::test]
+async fn test_include_pattern_limits_content() {
+    let repo = sample_repo();
+    let response = ingest(IngestRequest {
+        include_patterns: Some(vec!["**/*.rs".to_string()]),
+        ..request(&repo)
+    })
+    .await;
+
+    assert!(response.content.contains("src/main.rs"));
+    assert!(!response.content.contains("README.md"));
+}
+
+#[tokio::test]
+async fn test_missing_repository_fails() {
+    let repo = sample_repo();
+    let result = IngestService::process_repository(
+        IngestRequest {
+            input_text: format!("{}-missing", repo.url()),
+            ..Default::default()
+        },
+        &local_config(),
+        Uuid::new_v4(),
+    )
+    .await;
+
+    assert!(matches!(result, Err(GitingestError::RepositoryNotFound(_))));
+}
+
+#[tokio::test]
+async fn test_missing_branch_fails_with_branch_not_found() {
+    let repo = sample_repo();
+    let failure = fail(IngestRequest {
+        branch: Some("no-such-branch".to_string()),
+        ..request(&repo)
+    })
+    .await;
+
+    assert_eq!(failure.error_code, "branch_not_found");
+    assert_eq!(
+        failure.message,
+        "Branch not found: no-such-branch (fatal: Remote branch no-such-branch not found in upstream origin)"
+    );
+}
+
+fn listed(repo: &FixtureRepo, files: &[&str], missing: MissingFilePolicy) -> IngestRequest {
+    IngestRequest {
+        files: Some(files.iter().map(|f| f.to_string()).collect()),
+        missing_files: Some(missing),
+        ..request(repo)
+    }
+}
+
+#[tokio::test]
+async fn test_listed_files_keep_order_and_bypass_filters() {
+    let repo = sample_repo();
+    let response = ingest(listed(
+        &repo,
+        &["src/main.rs", "notes.secret", "README.md"],
+        MissingFilePolicy::Error,
+    ))
+    .await;
+
+    let main = response.content.find("src/main.rs:").unwrap();
+    let secret = response.content.find("notes.secret:").unwrap();
+    let readme = response.content.find("README.md:").unwrap();
+    assert!(main < secret && secret < readme);
+    assert!(response.content.contains("committed but gitignored"));
+    assert!(!response.content.contains("CHANGELOG.md"));
+    assert!(response.summary.contains("Files processed: 3"));
+}
+
+#[tokio::test]
+async fn test_missing_listed_files_warn() {
+    let repo = sample_repo();
+    let response = ingest(listed(&repo, &["README.md", "nope.rs", "src"], MissingFilePolicy::Warn)).await;
+
+    assert!(response.summary.contains("Missing files: 2"));
+    assert!(response.content.contains("README.md:"));
+}
+
+#[tokio::test]
+async fn test_missing_listed_files_skip() {
+    let repo = sample_repo();
+    let response = ingest(listed(&repo, &["README.md", "nope.rs"], MissingFilePolicy::Skip)).await;
+
+    assert!(!response.summary.contains("Missing files"));
+    assert!(response.summary.contains("Files processed: 1"));
+}
+
+#[tokio::test]
+async fn test_missing_listed_files_error() {
+    let repo = sample_repo();
+    let result = IngestService::process_repository(
+        listed(&repo, &["README.md", "nope.rs"], MissingFilePolicy::Error),
+        &local_config(),
+        Uuid::new_v4(),
+    )
+    .await;
+
+    assert!(matches!(result, Err(GitingestError::ValidationError(msg)) if msg.contains("nope.rs")));
+}
+
+#[tokio::test]
+async fn test_listed_files_must_stay_inside_repository() {
+    let repo = sample_repo();
+    let result = IngestService::process_repository(
+        listed(&repo, &["../../etc/passwd"], MissingFilePolicy::Warn),
+        &local_config(),
+        Uuid::new_v4(),
+    )
+    .await;
+
+    assert!(matches!(result, Err(GitingestError::ValidationError(_))));
+}
+
+async fn stream(request: IngestRequest) -> (gitingest::IngestSummary, String) {
+    let mut output = Vec::new();
+    let summary = IngestService::process_repository_to_writer(
+        request,
+        &local_config(),
+        Uuid::new_v4(),
+        &mut output,
+        CancellationToken::new(),
+    )
+    .await
+    .unwrap();
+    (summary, String::from_utf8(output).unwrap())
+}
+
+#[tokio::test]
+async fn test_streamed_digest_matches_in_memory_render() {
+    let repo = sample_repo();
+    for format in [DownloadFormat::Text, DownloadFormat::Markdown, DownloadFormat::Xml] {
+        let request = IngestRequest {
+            download_format: Some(format.clone()),
+            ..request(&repo)
+        };
+        let expected = ingest(request.clone()).await.render(&format).unwrap();
+        let (summary, streamed) = stream(request).await;
+
+        assert_eq!(streamed, expected);
+        assert!(summary.summary.contains("Files processed: 8"));
+        assert_eq!(summary.stats.files_analyzed, 8);
+        assert_eq!(summary.stats.total_size_bytes, 254);
+    }
+}
+
+#[tokio::test]
+async fn test_json_lines_streams_one_object_per_line() {
+    let repo = sample_repo();
+    let request = IngestRequest {
+        download_format: Some(DownloadFormat::JsonLines),
+        ..request(&repo)
+    };
+    let expected = ingest(request.clone()).await.render(&DownloadFormat::JsonLines).unwrap();
+    let (summary, streamed) = stream(request.clone()).await;
+    assert_eq!(summary.stats.files_analyzed, 8);
+
+    let parse = |digest: &str| -> Vec<serde_json::Value> {
+        let mut lines: Vec<serde_json::Value> =
+            digest.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        lines[0].as_object_mut().unwrap().remove("id");
+        lines
+    };
+    let lines = parse(&streamed);
+    assert_eq!(lines, parse(&expected));
+    assert_eq!(lines[0]["type"], "metadata");
+    assert!(lines[0]["tree"].as_str().unwrap().contains("main.rs"));
+    let files: Vec<_> = lines[1..].iter().filter(|line| line["type"] == "file").collect();
+    assert_eq!(files.len(), lines.len() - 1);
+    let main = files.iter().find(|line| line["path"] == "src/main.rs").unwrap();
+    assert!(main["content"].as_str().unwrap().contains("fn main()"));
+
+    let capped = IngestRequest {
+        max_output_bytes: Some(200),
+        ..request
+    };
+    let (summary, streamed) = stream(capped).await;
+    assert!(summary.truncated);
+    let last: serde_json::Value = serde_json::from_str(streamed.lines().last().unwrap()).unwrap();
+    assert_eq!(last["type"], "truncated");
+    assert_eq!(last["max_output_bytes"], 200);
+}
+
+#[tokio::test]
+async fn test_markdown_fences_each_file_and_numbers_lines_on_request() {
+    let repo = sample_repo();
+    let markdown = IngestRequest { download_format: Some(DownloadFormat::Markdown), ..request(&repo) };
+
+    let response = ingest(markdown.clone()).await;
+    assert!(response.content.contains("### src/main.rs\n```rust\nfn main() {\n"), "{}", response.content);
+
+    let numbered = ingest(IngestRequest { line_numbers: Some(true), max_line_width: Some(8), ..markdown }).await;
+    assert!(
+        numbered.content.contains("### src/main.rs\n```rust\n1 | fn main(… [3 more characters]\n2 |     prin… [14 more characters]\n3 | }\n```\n"),
+        "{}",
+        numbered.content
+    );
+
+    // Other formats keep their plain layout unless asked
+    let text = ingest(IngestRequest { line_numbers: Some(true), ..request(&repo) }).await;
+    assert!(text.content.contains(&format!("src/main.rs:\n{}\n1 | fn main() {{\n", "=".repeat(48))));
+}
+
+#[tokio::test]
+async fn test_split_parts_add_up_to_the_digest_tokens() {
+    let repo = sample_repo();
+    let output_dir = tempfile::TempDir::new().unwrap();
+    let manifest = IngestService::process_repository_split(
+        request(&repo),
+        &local_config(),
+        Uuid::new_v4(),
+        1,
+        output_dir.path(),
+        CancellationToken::new(),
+    )
+    .await
+    .unwrap();
+    let digest = output_dir.path().join("digest.txt");
+    let summary = IngestService::ingest_to_file(request(&repo), &local_config(), &digest).await.unwrap();
+
+    let digest_tokens: usize = summary.stats.language_breakdown.iter().map(|stat| stat.tokens).sum();
+    assert!(digest_tokens > 0);
+    assert_eq!(manifest.parts.iter().map(|part| part.estimated_tokens).sum::<usize>(), digest_tokens);
+}
+
+#[tokio::test]
+async fn test_ingest_to_file_writes_digest_and_returns_summary() {
+    let repo = sample_repo();
+    let output = tempfile::TempDir::new().unwrap();
+    let path = output.path().join("digest.txt");
+
+    let expected = ingest(request(&repo)).await.render(&DownloadFormat::Text).unwrap();
+    let summary = IngestService::ingest_to_file(request(&repo), &local_config(), &path).await.unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), expected);
+    assert_eq!(summary.stats.files_analyzed, 8);
+    assert_eq!(summary.stats.total_size_bytes, 254);
+
+    // A failed run leaves no file behind
+    let missing = IngestRequest { input_text: "/nonexistent/repo".to_string(), ..request(&repo) };
+    let missing_path = output.path().join("missing.txt");
+    assert!(IngestService::ingest_to_file(missing, &local_config(), &missing_path).await.is_err());
+    assert!(!missing_path.exists());
+}
+
+#[tokio::test]
+async fn test_process_repositories_keeps_order_and_isolates_failures() {
+    let repo = sample_repo();
+    let requests = vec![
+        request(&repo),
+        IngestRequest { input_text: format!("{}-missing", repo.url()), ..Default::default() },
+        IngestRequest { branch: Some("feature".to_string()), ..request(&repo) },
+    ];
+
+    let results = IngestService::process_repositories(requests, &local_config(), 2).await;
+    assert_eq!(results.len(), 3);
+    assert!(!results[0].as_ref().unwrap().content.contains("src/feature.rs"));
+    assert!(matches!(results[1], Err(GitingestError::RepositoryNotFound(_))));
+    assert!(results[2].as_ref().unwrap().content.contains("src/feature.rs"));
+}
+
+#[tokio::test]
+async fn test_streamed_json_is_complete_response() {
+    let repo = sample_repo();
+    let (summary, streamed) = stream(IngestRequest {
+        download_format: Some(DownloadFormat::Json),
+        ..request(&repo)
+    })
+    .await;
+
+    let json: serde_json::Value = serde_json::from_str(&streamed).unwrap();
+    assert_eq!(json["id"], summary.id.to_string());
+    assert!(json["content"].as_str().unwrap().contains("fn main()"));
+
+    for format in [DownloadFormat::Json, DownloadFormat::JsonStructured] {
+        let request = IngestRequest {
+            download_format: Some(format.clone()),
+            ..request(&repo)
+        };
+        let mut expected: serde_json::Value =
+            serde_json::from_str(&ingest(request.clone()).await.render(&format).unwrap()).unwrap();
+        let (summary, streamed) = stream(request).await;
+        expected["id"] = summary.id.to_string().into();
+
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&streamed).unwrap(), expected);
+    }
+}
+
+/// Cancels `cancel` once it has been flushed `cancel_on` times.
+struct CancellingWriter {
+    output: Vec<u8>,
+    flushes: usize,
+    cancel_on: usize,
+    cancel: CancellationToken,
+}
+
+impl std::io::Write for CancellingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.flushes += 1;
+        if self.flushes == self.cancel_on {
+            self.cancel.cancel();
+        }
+        Ok(())
+    }
+}
+
+/// Streams `format`, cancelling after the header and the first file.
+async fn stream_cancelled(repo: &FixtureRepo, format: DownloadFormat) -> (IngestFailure, String) {
+    let cancel = CancellationToken::new();
+    let mut writer = CancellingWriter { output: Vec::new(), flushes: 0, cancel_on: 2, cancel: cancel.clone() };
+    let failure = IngestService::try_process_repository_to_writer(
+        IngestRequest {
+            download_format: Some(format),
+            ..request(repo)
+        },
+        &local_config(),
+        Uuid::new_v4(),
+        &mut writer,
+        cancel,
+    )
+    .await
+    .unwrap_err();
+    (failure, String::from_utf8(writer.output).unwrap())
+}
+
+#[tokio::test]
+async fn test_cancelled_text_digest_ends_with_incomplete_trailer() {
+    let repo = sample_repo();
+    let (failure, output) = stream_cancelled(&repo, DownloadFormat::Text).await;
+
+    assert_eq!(failure.error_code, "cancelled");
+    assert_eq!(failure.phase, IngestPhase::Content);
+    let incomplete = failure.incomplete_output.unwrap();
+    assert_eq!((incomplete.files_written, incomplete.files_total), (1, 8));
+
+    assert!(output.starts_with("Repository: fixtures/sample\nSummary:\n"));
+    assert!(output.contains("└── sample/\n"));
+    assert!(output.contains("README.md:\n====="));
+    assert_eq!(output.matches(":\n=====").count(), 1);
+    assert!(output.ends_with("\n[INGESTION INCOMPLETE: stopped after 1/8 files]\n"));
+}
+
+#[tokio::test]
+async fn test_cancelled_json_digest_is_valid_and_marked_incomplete() {
+    let repo = sample_repo();
+    let (failure, output) = stream_cancelled(&repo, DownloadFormat::JsonStructured).await;
+    assert_eq!(failure.error_code, "cancelled");
+
+    let json: serde_json::Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(json["status"], "failed");
+    assert_eq!(json["incomplete"]["files_written"], 1);
+    assert_eq!(json["incomplete"]["files_total"], 8);
+    assert!(json["content"].as_str().unwrap().contains("README.md:\n====="));
+    assert_eq!(json["files"].as_array().unwrap().len(), 1);
+    assert!(json["tree"].as_str().unwrap().contains("src/"));
+}
+
+#[tokio::test]
+async fn test_token_budget_bounds_content_and_marks_omitted_files() {
+    let repo = sample_repo();
+    let budget = 60;
+    let response = ingest(IngestRequest {
+        max_tokens: Some(budget),
+        ..request(&repo)
+    })
+    .await;
+
+    // The heuristic counter's estimate; cl100k_base counts differently
+    #[cfg(not(feature = "tiktoken"))]
+    assert!(response.content.len().div_ceil(4) <= budget);
+    assert!(response.content.contains("src/main.rs:"));
+    assert!(!response.content.contains("README.md:"));
+    assert!(response.tree.contains("README.md [omitted: token budget]"));
+    assert!(response.summary.contains("Token budget: used "));
+    assert!(response.summary.contains(&format!("of {} tokens", budget)));
+}
+
+async fn fail(request: IngestRequest) -> IngestFailure {
+    IngestService::try_process_repository(request, &local_config(), Uuid::new_v4(), CancellationToken::new())
+        .await
+        .unwrap_err()
+}
+
+#[tokio::test]
+async fn test_failure_in_parse_phase() {
+    let failure = fail(IngestRequest {
+        input_text: "not a repository".to_string(),
+        ..Default::default()
+    })
+    .await;
+
+    assert_eq!(failure.phase, IngestPhase::Parse);
+    assert_eq!(failure.error_code, "invalid_repository_url");
+    assert_eq!(failure.repo_url, "not a repository");
+    assert!(failure.timings.clone_ms.is_none());
+}
+
+#[tokio::test]
+async fn test_host_outside_allowed_hosts_is_refused_before_cloning() {
+    let failure = fail(IngestRequest {
+        input_text: "https://169.254.169.254/owner/repo".to_string(),
+        ..Default::default()
+    })
+    .await;
+
+    assert_eq!(failure.phase, IngestPhase::Parse);
+    assert_eq!(failure.error_code, "host_not_allowed");
+    assert!(failure.message.contains("github.com"), "{}", failure.message);
+    assert!(failure.timings.clone_ms.is_none());
+}
+
+#[tokio::test]
+async fn test_local_repositories_need_the_opt_in() {
+    let repo = sample_repo();
+    for input_text in [repo.url(), repo.path.display().to_string()] {
+        let request = IngestRequest { input_text: input_text.clone(), ..Default::default() };
+        let failure = IngestService::try_process_repository(request, &AppConfig::default(), Uuid::new_v4(), CancellationToken::new())
+            .await
+            .unwrap_err();
+        assert_eq!(failure.error_code, "validation_error", "{}", input_text);
+        assert!(failure.message.contains("ALLOW_LOCAL_INPUTS"), "{}", failure.message);
+        assert!(failure.timings.clone_ms.is_none());
+    }
+}
+
+#[tokio::test]
+async fn test_https_only_refuses_other_sources() {
+    let repo = sample_repo();
+    let config = AppConfig { https_only: true, ..local_config() };
+    for input_text in [repo.url(), "ssh://git@github.com/owner/repo".to_string(), repo.path.display().to_string()] {
+        let request = IngestRequest { input_text: input_text.clone(), ..Default::default() };
+        let failure = IngestService::try_process_repository(request, &config, Uuid::new_v4(), CancellationToken::new())
+            .await
+            .unwrap_err();
+        assert_eq!(failure.error_code, "validation_error", "{}", input_text);
+        assert!(failure.message.contains("Only https://"), "{}", failure.message);
+        assert!(failure.timings.clone_ms.is_none());
+    }
+}
+
+#[tokio::test]
+async fn test_missing_ssh_key_only_matters_for_ssh_remotes() {
+    let repo = sample_repo();
+    let config = AppConfig { ssh_key_path: Some(repo.path.join("no-such-key")), ..local_config() };
+    let response = ingest_with_config(request(&repo), &config).await;
+    assert!(response.content.contains("src/main.rs"));
+
+    let request = IngestRequest { input_text: "ssh://git@github.com/owner/repo".to_string(), ..Default::default() };
+    let failure = IngestService::try_process_repository(request, &config, Uuid::new_v4(), CancellationToken::new())
+        .await
+        .unwrap_err();
+    assert_eq!(failure.error_code, "validation_error");
+    assert!(failure.message.contains("no-such-key"), "{}", failure.message);
+}
+
+#[tokio::test]
+async fn test_failure_in_clone_phase() {
+    let repo = sample_repo();
+    let failure = fail(IngestRequest {
+        input_text: format!("{}-missing", repo.url()),
+        ..Default::default()
+    })
+    .await;
+
+    assert_eq!(failure.phase, IngestPhase::Clone);
+    assert_eq!(failure.error_code, "repository_not_found");
+    assert!(failure.repo_url.ends_with("sample-missing"));
+    assert!(failure.timings.clone_ms.is_none());
+    assert!(failure.timings.scan_ms.is_none());
+}
+
+#[tokio::test]
+async fn test_failure_in_scan_phase_reports_clone_timing() {
+    let repo = sample_repo();
+    let failure = fail(IngestRequest {
+        files: Some(vec!["nope.rs".to_string()]),
+        missing_files: Some(MissingFilePolicy::Error),
+        ..request(&repo)
+    })
+    .await;
+
+    assert_eq!(failure.phase, IngestPhase::Scan);
+    assert_eq!(failure.error_code, "validation_error");
+    assert!(failure.message.contains("nope.rs"));
+    assert!(failure.timings.clone_ms.is_some());
+    assert!(failure.timings.scan_ms.is_none());
+}
+
+struct FailingWriter;
+
+impl std::io::Write for FailingWriter {
+    fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+        Err(std::io::Error::other("disk full"))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_failure_in_content_phase_keeps_warnings_and_timings() {
+    let repo = sample_repo();
+    let failure = IngestService::try_process_repository_to_writer(
+        IngestRequest {
+            files: Some(vec!["README.md".to_string(), "nope.rs".to_string()]),
+            ..request(&repo)
+        },
+        &local_config(),
+        Uuid::new_v4(),
+        &mut FailingWriter,
+        CancellationToken::new(),
+    )
+    .await
+    .unwrap_err();
+
+    assert_eq!(failure.phase, IngestPhase::Content);
+    assert_eq!(failure.error_code, "io_error");
+    assert!(failure.timings.clone_ms.is_some());
+    assert!(failure.timings.scan_ms.is_some());
+    assert!(failure.timings.content_ms.is_none());
+    assert_eq!(failure.warnings, vec!["Listed files not found: nope.rs".to_string()]);
+
+    let json = serde_json::to_value(&failure).unwrap();
+    assert_eq!(json["status"], "failed");
+    assert_eq!(json["phase"], "content");
+}
+
+fn ignore_repo() -> FixtureRepo {
+    RepoBuilder::new("fixtures", "ignores")
+        .file("src/lib.rs", "pub fn lib() {}\n")
+        .file("fixtures/input.txt", "fixture data\n")
+        .file("src/output.snap", "snapshot\n")
+        .file("build/generated.rs", "// generated\n")
+        .file("app.log", "log line\n")
+        .file(".gitignore", "*.log\n")
+        .file(".gitingestignore", "# ingestion only\nfixtures/\n*.snap\n")
+        .commit("initial")
+        .build()
+}
+
+#[tokio::test]
+async fn test_gitingestignore_composes_with_gitignore() {
+    let repo = ignore_repo();
+    let response = ingest(request(&repo)).await;
+
+    assert!(response.content.contains("src/lib.rs:"));
+    // .gitignore didn't exclude these; .gitingestignore does
+    assert!(!response.tree.contains("fixtures/"));
+    assert!(!response.tree.contains("output.snap"));
+    // .gitignore and the built-in excludes still apply
+    assert!(!response.tree.contains("app.log"));
+    assert!(!response.tree.contains("build/"));
+}
+
+#[tokio::test]
+async fn test_no_default_ignore_keeps_ignore_files() {
+    let repo = ignore_repo();
+    let response = ingest(IngestRequest {
+        no_default_ignore: Some(true),
+        ..request(&repo)
+    })
+    .await;
+
+    assert!(response.content.contains("build/generated.rs:"));
+    assert!(!response.tree.contains(".git/"));
+    assert!(!response.tree.contains("output.snap"));
+    assert!(!response.tree.contains("app.log"));
+}
+
+#[tokio::test]
+async fn test_clone_cache_is_reused_and_refreshed() {
+    let repo = RepoBuilder::new("fixtures", "cached")
+        .file("README.md", "# Cached\n")
+        .commit("initial")
+        .build();
+    let cache_dir = tempfile::TempDir::new().unwrap();
+    let config = AppConfig {
+        cache_dir: Some(cache_dir.path().display().to_string()),
+        ..local_config()
+    };
+    let ingest_cached = || ingest_with_config(request(&repo), &config);
+
+    let first = ingest_cached().await;
+    assert!(first.content.contains("# Cached"));
+    assert!(first.tree.starts_with("└── cached/"));
+    let entries = gitingest::CloneCache::new(cache_dir.path()).entries().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert!(entries[0].key.starts_with("localhost/fixtures/cached/_default-"));
+    // Survives a fetch and reset, but not a fresh clone
+    std::fs::write(entries[0].path.join(".git/reused"), "").unwrap();
+    std::fs::write(entries[0].path.join("stray.txt"), "left behind").unwrap();
+
+    std::fs::write(repo.path.join("NEW.md"), "added later\n").unwrap();
+    run_git(&repo.path, &["add", "-A"]);
+    run_git(&repo.path, &["commit", "-qm", "second"]);
+
+    let second = ingest_cached().await;
+    assert!(second.content.contains("added later"));
+    assert!(!second.content.contains("left behind"));
+    assert!(entries[0].path.join(".git/reused").exists());
+    assert!(!first.summary.contains("Clone: reused from cache"), "{}", first.summary);
+    assert!(second.summary.contains("Clone: reused from cache"), "{}", second.summary);
+}
+
+#[tokio::test]
+async fn test_use_cache_opts_in_under_temp_dir() {
+    let repo = RepoBuilder::new("fixtures", "opted")
+        .file("README.md", "# Opted in\n")
+        .commit("initial")
+        .build();
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let config = AppConfig {
+        temp_dir: temp_dir.path().display().to_string(),
+        ..local_config()
+    };
+
+    // Without a cache_dir, only requests that ask are cached
+    ingest_with_config(request(&repo), &config).await;
+    assert!(!temp_dir.path().join("cache").exists());
+    let opted = || ingest_with_config(IngestRequest { use_cache: Some(true), ..request(&repo) }, &config);
+    let first = opted().await;
+    let second = opted().await;
+    assert!(first.content.contains("# Opted in"));
+    assert!(second.summary.contains("Clone: reused from cache"), "{}", second.summary);
+    let entries = gitingest::CloneCache::new(temp_dir.path().join("cache")).entries().unwrap();
+    assert_eq!(entries.len(), 1);
+}
+
+#[tokio::test]
+async fn test_dry_run_lists_files_without_content() {
+    let repo = sample_repo();
+    let full = ingest(IngestRequest {
+        download_format: Some(DownloadFormat::Json),
+        ..request(&repo)
+    })
+    .await;
+    let dry = ingest(IngestRequest {
+        dry_run: Some(true),
+        ..request(&repo)
+    })
+    .await;
+
+    assert!(dry.content.is_empty());
+    assert!(dry.tree.contains("main.rs"));
+    assert!(dry.summary.contains("Dry run"));
+    let stat_lines = |summary: &str| -> Vec<String> {
+        summary
+            .lines()
+            .filter(|line| line.starts_with("Files processed:") || line.starts_with("Total size:"))
+            .map(str::to_string)
+            .collect()
+    };
+    assert_eq!(stat_lines(&dry.summary), stat_lines(&full.summary));
+    assert_eq!(stat_lines(&dry.summary).len(), 2);
+
+    // Per-file sizes come with the tree even for text output
+    let (dry_tree, full_tree) = (dry.file_tree.unwrap(), full.file_tree.unwrap());
+    assert_eq!(dry_tree.size, full_tree.size);
+    assert_eq!(dry_tree.children.len(), full_tree.children.len());
+}
+
+#[tokio::test]
+async fn test_blobless_clone_lists_the_tree_of_a_dry_run() {
+    let repo = sample_repo();
+    let dry = ingest(IngestRequest {
+        dry_run: Some(true),
+        branch: Some("feature".to_string()),
+        ..request(&repo)
+    })
+    .await;
+    let blobless = ingest(IngestRequest {
+        clone_mode: Some(CloneMode::Blobless),
+        branch: Some("feature".to_string()),
+        ..request(&repo)
+    })
+    .await;
+
+    assert!(blobless.content.is_empty());
+    let without_tokens = regex::Regex::new(r" \(\d+ tokens\)").unwrap();
+    assert_eq!(blobless.tree, without_tokens.replace_all(&dry.tree, ""));
+    let files_line = |summary: &str| summary.lines().find(|line| line.starts_with("Files processed:")).map(str::to_string);
+    assert_eq!(files_line(&blobless.summary), files_line(&dry.summary));
+    // No blob was fetched to size the files
+    assert!(blobless.summary.contains("\nTotal size: unknown\n"), "{}", blobless.summary);
+    assert!(blobless.summary.contains("Rust (3 files)"), "{}", blobless.summary);
+    // The ignore files are read from the clone as usual
+    assert!(!blobless.tree.contains("notes.secret"));
+    assert!(blobless.tree.contains("lib/ [submodule: https://example.invalid/lib.git]"));
+
+    let err = IngestService::process_repository(
+        IngestRequest {
+            clone_mode: Some(CloneMode::Blobless),
+            with_timestamps: Some(true),
+            ..request(&repo)
+        },
+        &local_config(),
+        Uuid::new_v4(),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, GitingestError::ValidationError(_)), "{:?}", err);
+}
+
+#[tokio::test]
+async fn test_scan_only_returns_tree_and_stats() {
+    let repo = sample_repo();
+    let full = ingest(request(&repo)).await;
+    let (tree, stats) = IngestService::scan_only(request(&repo), &local_config(), false).await.unwrap();
+
+    assert_eq!(tree.lines().count(), full.tree.lines().count());
+    // A scan only estimates tokens from sizes, as the heuristic counter does
+    #[cfg(not(feature = "tiktoken"))]
+    assert_eq!(tree, full.tree);
+    assert!(full.summary.contains(&format!("Files processed: {}\n", stats.files_analyzed)));
+    assert!(stats.estimated_tokens.unwrap() > 0);
+
+    let (sized, _) = IngestService::scan_only(request(&repo), &local_config(), true).await.unwrap();
+    assert!(sized.contains("README.md (32 B)\n"));
+    assert!(!sized.contains("tokens"));
+}
+
+#[tokio::test]
+async fn test_base_ref_limits_the_digest_to_changed_files() {
+    let moved = "pub fn moved() -> u32 {\n    42\n}\n";
+    let repo = RepoBuilder::new("fixtures", "diffed")
+        .file("README.md", "# Diffed\n")
+        .file("src/lib.rs", "pub fn one() {}\n")
+        .file("src/old_name.rs", moved)
+        .file("docs/gone.md", "Removed later.\n")
+        .commit("initial")
+        .tag("v1")
+        .file("src/lib.rs", "pub fn one() {}\npub fn two() {}\n")
+        .remove("src/old_name.rs")
+        .file("src/new_name.rs", moved)
+        .remove("docs/gone.md")
+        .file("src/added.rs", "pub fn added() {}\n")
+        .commit("changes")
+        .build();
+    let diff_request = || IngestRequest { base_ref: Some("v1".to_string()), ..request(&repo) };
+
+    let response = ingest(diff_request()).await;
+    assert!(response.tree.contains("docs/gone.md [deleted]\n"), "{}", response.tree);
+    assert!(response.tree.contains("src/new_name.rs (renamed from src/old_name.rs)"));
+    assert!(response.tree.contains("src/lib.rs"));
+    assert!(response.tree.contains("src/added.rs"));
+    assert!(!response.tree.contains("README.md"));
+    assert!(response.content.contains("pub fn two() {}"));
+    assert!(response.content.contains("src/new_name.rs: [renamed from src/old_name.rs]\n"));
+    assert!(!response.content.contains("Removed later."));
+    assert!(response.summary.contains("Files processed: 3\n"), "{}", response.summary);
+    assert!(response.summary.contains("\nDiff: v1..HEAD (1 added, 1 modified, 1 deleted, 1 renamed)"));
+
+    let (_, stats) = IngestService::scan_only(diff_request(), &local_config(), false).await.unwrap();
+    assert_eq!(
+        stats.diff,
+        Some(DiffStats {
+            base_ref: "v1".to_string(),
+            head_ref: "HEAD".to_string(),
+            added: 1,
+            modified: 1,
+            deleted: 1,
+            renamed: 1,
+        })
+    );
+
+    let failure = fail(IngestRequest { base_ref: Some("no-such-ref".to_string()), ..request(&repo) }).await;
+    assert_eq!(failure.error_code, "branch_not_found", "{}", failure.message);
+}
+
+#[tokio::test]
+async fn test_timestamps_record_each_files_last_commit() {
+    let repo = RepoBuilder::new("fixtures", "dated")
+        .file("README.md", "# Dated\n")
+        .file("src/old.rs", "pub fn old() {}\n")
+        .file("src/churn.rs", "pub fn v1() {}\n")
+        .commit_at("initial", "2024-01-01T00:00:00Z")
+        .file("src/churn.rs", "pub fn v2() {}\n")
+        .file("src/new.rs", "pub fn new() {}\n")
+        .commit_at("second", "2024-02-15T12:00:00Z")
+        .file("src/churn.rs", "pub fn v3() {}\n")
+        .commit_at("third", "2024-03-10T08:30:00Z")
+        .build();
+    let dated = IngestRequest { with_timestamps: Some(true), download_format: Some(DownloadFormat::JsonStructured), ..request(&repo) };
+
+    let response = ingest(dated).await;
+    let mut times = Vec::new();
+    collect_last_modified(response.file_tree.as_ref().unwrap(), &mut times);
+    times.sort();
+    assert_eq!(
+        times,
+        vec![
+            ("README.md".to_string(), Some(1_704_067_200)),
+            ("src/churn.rs".to_string(), Some(1_710_059_400)),
+            ("src/new.rs".to_string(), Some(1_707_998_400)),
+            ("src/old.rs".to_string(), Some(1_704_067_200)),
+        ]
+    );
+    assert!(
+        response.summary.contains(
+            "\nRecently changed: src/churn.rs (2024-03-10), src/new.rs (2024-02-15), README.md (2024-01-01), src/old.rs (2024-01-01)"
+        ),
+        "{}",
+        response.summary
+    );
+
+    // Without the flag the clone stays shallow and nothing is dated
+    let response = ingest(IngestRequest { download_format: Some(DownloadFormat::JsonStructured), ..request(&repo) }).await;
+    let mut times = Vec::new();
+    collect_last_modified(response.file_tree.as_ref().unwrap(), &mut times);
+    assert!(times.iter().all(|(_, time)| time.is_none()));
+    assert!(!response.summary.contains("Recently changed"));
+}
+
+fn collect_last_modified(entry: &gitingest::TreeEntry, times: &mut Vec<(String, Option<i64>)>) {
+    if entry.node_type == gitingest::FileNodeType::File {
+        times.push((entry.path.clone(), entry.last_modified));
+    }
+    for child in &entry.children {
+        collect_last_modified(child, times);
+    }
+}
+
+#[tokio::test]
+async fn test_content_is_ordered_by_importance_by_default() {
+    let repo = RepoBuilder::new("fixtures", "ordered")
+        .file("api/handlers/users.rs", "pub fn users() {}\n")
+        .file("src/main.rs", "fn main() {}\n")
+        .file("tests/smoke.rs", "#[test]\nfn smoke() {}\n")
+        .file("README.md", "# Ordered\n")
+        .commit("initial")
+        .build();
+
+    let response = ingest(request(&repo)).await;
+    assert!(response.content.starts_with("README.md:\n"));
+    let position = |path: &str| response.content.find(&format!("{}:\n", path)).unwrap();
+    assert!(position("src/main.rs") < position("api/handlers/users.rs"));
+    assert!(position("api/handlers/users.rs") < position("tests/smoke.rs"));
+    // Only the content is reordered
+    let tree_position = |name: &str| response.tree.find(name).unwrap();
+    assert!(tree_position("api/") < tree_position("README.md"));
+
+    let response = ingest(IngestRequest {
+        sort: Some(SortOrder::Path),
+        ..request(&repo)
+    })
+    .await;
+    assert!(response.content.starts_with("README.md:\n"));
+    let position = |path: &str| response.content.find(&format!("{}:\n", path)).unwrap();
+    assert!(position("api/handlers/users.rs") < position("src/main.rs"));
+}
+
+const MIT_LICENSE: &str = "MIT License
+
+Copyright (c) 2024 Fixture Authors
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the \"Software\"), to deal
+in the Software without restriction, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+";
+
+#[tokio::test]
+async fn test_license_is_identified_and_can_be_forced_into_content() {
+    let repo = RepoBuilder::new("fixtures", "licensed")
+        .file("LICENSE", MIT_LICENSE)
+        .file("src/main.rs", "fn main() {}\n")
+        .commit("initial")
+        .build();
+    let filtered = IngestRequest {
+        exclude_patterns: Some(vec!["LICENSE".to_string()]),
+        max_file_size: Some(100),
+        max_tokens: Some(10),
+        ..request(&repo)
+    };
+
+    let response = ingest(filtered.clone()).await;
+    assert!(response.summary.contains("\nLicense: MIT (LICENSE)"));
+    assert!(!response.content.contains("Permission is hereby granted"));
+
+    let response = ingest(IngestRequest {
+        include_license_text: Some(true),
+        ..filtered
+    })
+    .await;
+    assert!(response.content.contains(MIT_LICENSE));
+    assert!(response.tree.contains("LICENSE"));
+    assert_eq!(response.license.unwrap().spdx_id, "MIT");
+}
+
+#[tokio::test]
+async fn test_no_cache_bypasses_clone_cache() {
+    let repo = RepoBuilder::new("fixtures", "uncached")
+        .file("README.md", "# Uncached\n")
+        .commit("initial")
+        .build();
+    let cache_dir = tempfile::TempDir::new().unwrap();
+    let config = AppConfig {
+        cache_dir: Some(cache_dir.path().display().to_string()),
+        ..local_config()
+    };
+
+    let response = ingest_with_config(
+        IngestRequest {
+            no_cache: Some(true),
+            ..request(&repo)
+        },
+        &config,
+    )
+    .await;
+
+    assert!(response.content.contains("# Uncached"));
+    assert!(gitingest::CloneCache::new(cache_dir.path()).entries().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_expired_cache_entry_is_recloned() {
+    let repo = RepoBuilder::new("fixtures", "expiring")
+        .file("README.md", "# Expiring\n")
+        .commit("initial")
+        .build();
+    let cache_dir = tempfile::TempDir::new().unwrap();
+    let config = AppConfig {
+        cache_dir: Some(cache_dir.path().display().to_string()),
+        cache_ttl_secs: Some(0),
+        ..local_config()
+    };
+
+    ingest_with_config(request(&repo), &config).await;
+    let entries = gitingest::CloneCache::new(cache_dir.path()).entries().unwrap();
+    assert_eq!(entries.len(), 1);
+    // Only a fresh clone drops this
+    std::fs::write(entries[0].path.join(".git/reused"), "").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+
+    let response = ingest_with_config(request(&repo), &config).await;
+    assert!(response.content.contains("# Expiring"));
+    assert!(!entries[0].path.join(".git/reused").exists());
+}
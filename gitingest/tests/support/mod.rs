@@ -0,0 +1,41 @@
+#![allow(dead_code)]
+
+pub mod repo_builder;
+
+use gitingest::IngestResponse;
+use std::path::Path;
+
+/// Renders the parts of a response that golden files compare against.
+pub fn render_response(response: &IngestResponse) -> String {
+    format!(
+        "Summary:\n{}\n\nTree:\n{}\nContent:\n{}",
+        response.summary, response.tree, response.content
+    )
+}
+
+/// Compares `actual` against `tests/golden/<name>`, after normalizing the
+/// parts of a run that change every time. Set `UPDATE_GOLDEN=1` to rewrite
+/// the golden file instead.
+pub fn assert_golden(name: &str, actual: &str, fixture_root: &Path) {
+    let actual = normalize(actual, fixture_root);
+    let golden_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(name);
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&golden_path, &actual).unwrap();
+        return;
+    }
+
+    let expected = std::fs::read_to_string(&golden_path)
+        .unwrap_or_else(|_| panic!("missing golden file {:?}; run with UPDATE_GOLDEN=1", golden_path));
+    assert_eq!(actual, expected, "output differs from golden file {:?}", golden_path);
+}
+
+fn normalize(output: &str, fixture_root: &Path) -> String {
+    let uuid = regex::Regex::new(r"[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}").unwrap();
+    let commit = regex::Regex::new(r"Commit: [0-9a-f]{40}").unwrap();
+    let output = output.replace(&fixture_root.display().to_string(), "<fixture>");
+    let output = uuid.replace_all(&output, "<uuid>");
+    commit.replace_all(&output, "Commit: <commit>").into_owned()
+}
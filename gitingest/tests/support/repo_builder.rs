@@ -0,0 +1,134 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::TempDir;
+
+/// Builds throwaway git repositories for end-to-end tests. Commits use a
+/// fixed identity and timestamp so object ids are reproducible.
+pub struct RepoBuilder {
+    temp_dir: TempDir,
+    path: PathBuf,
+}
+
+impl RepoBuilder {
+    /// Creates an empty repository at `<tempdir>/<owner>/<name>` on `main`.
+    pub fn new(owner: &str, name: &str) -> Self {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(owner).join(name);
+        std::fs::create_dir_all(&path).unwrap();
+
+        let builder = Self { temp_dir, path };
+        builder.git(&["init", "-q", "-b", "main"]);
+        builder
+    }
+
+    pub fn file(self, relative_path: &str, content: &str) -> Self {
+        self.binary_file(relative_path, content.as_bytes())
+    }
+
+    pub fn binary_file(self, relative_path: &str, content: &[u8]) -> Self {
+        let path = self.path.join(relative_path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+        self
+    }
+
+    pub fn remove(self, relative_path: &str) -> Self {
+        std::fs::remove_file(self.path.join(relative_path)).unwrap();
+        self
+    }
+
+    /// Stages everything, including files a `.gitignore` would skip.
+    pub fn commit(self, message: &str) -> Self {
+        self.commit_at(message, FIXTURE_DATE)
+    }
+
+    /// Like [`RepoBuilder::commit`], authored and committed at `date`.
+    pub fn commit_at(self, message: &str, date: &str) -> Self {
+        self.git(&["add", "-A", "-f"]);
+        run_git_at(&self.path, &["commit", "-q", "--allow-empty", "-m", message], date);
+        self
+    }
+
+    pub fn branch(self, name: &str) -> Self {
+        self.git(&["checkout", "-q", "-b", name]);
+        self
+    }
+
+    pub fn checkout(self, name: &str) -> Self {
+        self.git(&["checkout", "-q", name]);
+        self
+    }
+
+    pub fn tag(self, name: &str) -> Self {
+        self.git(&["tag", name]);
+        self
+    }
+
+    /// Registers a submodule at `relative_path` without cloning it, so the
+    /// URL may point anywhere (including nowhere).
+    pub fn submodule_stub(self, relative_path: &str, url: &str) -> Self {
+        let gitmodules = self.path.join(".gitmodules");
+        let mut content = std::fs::read_to_string(&gitmodules).unwrap_or_default();
+        content.push_str(&format!(
+            "[submodule \"{0}\"]\n\tpath = {0}\n\turl = {1}\n",
+            relative_path, url
+        ));
+        std::fs::write(&gitmodules, content).unwrap();
+
+        let commit = "1111111111111111111111111111111111111111";
+        self.git(&["update-index", "--add", "--cacheinfo", &format!("160000,{},{}", commit, relative_path)]);
+        self
+    }
+
+    pub fn build(self) -> FixtureRepo {
+        FixtureRepo {
+            temp_dir: self.temp_dir,
+            path: self.path,
+        }
+    }
+
+    fn git(&self, args: &[&str]) {
+        run_git(&self.path, args);
+    }
+}
+
+pub struct FixtureRepo {
+    temp_dir: TempDir,
+    pub path: PathBuf,
+}
+
+impl FixtureRepo {
+    pub fn url(&self) -> String {
+        format!("file://{}", self.path.display())
+    }
+
+    /// Directory that contains the fixture, for normalizing output paths.
+    pub fn root(&self) -> &Path {
+        self.temp_dir.path()
+    }
+}
+
+const FIXTURE_DATE: &str = "2024-01-01T00:00:00Z";
+
+pub fn run_git(dir: &Path, args: &[&str]) {
+    run_git_at(dir, args, FIXTURE_DATE);
+}
+
+fn run_git_at(dir: &Path, args: &[&str], date: &str) {
+    let output = Command::new("git")
+        .args(["-c", "user.name=Fixture", "-c", "user.email=fixture@example.com"])
+        .args(["-c", "commit.gpgsign=false", "-c", "protocol.file.allow=always"])
+        .env("GIT_AUTHOR_DATE", date)
+        .env("GIT_COMMITTER_DATE", date)
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "git {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+}